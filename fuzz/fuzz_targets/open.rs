@@ -0,0 +1,11 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use rust_persist::Table;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(tbl) = Table::open_untrusted_bytes(data) {
+        for entry in tbl.iter() {
+            assert_eq!(tbl.get(entry.key), Some(entry.value));
+        }
+    }
+});
@@ -0,0 +1,308 @@
+use std::{
+    ffi::c_void,
+    io, slice,
+    sync::{Arc, Mutex},
+};
+
+use crate::{Error, Table};
+
+/// Version of the layout of [`TableVTable`], incremented whenever it changes
+pub const ABI_VERSION: u32 = 1;
+
+/// Status code of a successful operation
+pub const ABI_OK: i32 = 0;
+/// Status code of a lookup or deletion of a key that is not stored in the table
+pub const ABI_NOT_FOUND: i32 = 1;
+/// Status code of a failed operation that has no more specific code
+pub const ABI_ERROR: i32 = -1;
+/// Status code of a modification of a read-only table, see [`Error::ReadOnly`]
+pub const ABI_READ_ONLY: i32 = -2;
+/// Status code of a key or value exceeding the supported size, see [`Error::TooLarge`]
+pub const ABI_TOO_LARGE: i32 = -3;
+
+/// Callback that receives a value, which is only valid for the duration of the call
+pub type ValueCallback = unsafe extern "C" fn(user: *mut c_void, value: *const u8, value_len: usize);
+
+/// C-compatible function table behind a [`TableHandle`]
+///
+/// All functions take the context pointer of the handle as first argument. Keys and values are passed as pointer and
+/// length, the functions returning an `i32` return one of the `ABI_*` status codes.
+#[repr(C)]
+pub struct TableVTable {
+    /// Layout version of this function table, see [`ABI_VERSION`]
+    pub version: u32,
+    /// Increments the reference count of the context
+    pub retain: unsafe extern "C" fn(ctx: *const c_void),
+    /// Decrements the reference count of the context and closes the table when it drops to zero
+    pub release: unsafe extern "C" fn(ctx: *const c_void),
+    /// Passes the value of the key to the callback or returns [`ABI_NOT_FOUND`]
+    pub get: unsafe extern "C" fn(
+        ctx: *const c_void,
+        key: *const u8,
+        key_len: usize,
+        callback: ValueCallback,
+        user: *mut c_void,
+    ) -> i32,
+    /// Stores the value for the key
+    pub set: unsafe extern "C" fn(
+        ctx: *const c_void,
+        key: *const u8,
+        key_len: usize,
+        value: *const u8,
+        value_len: usize,
+    ) -> i32,
+    /// Deletes the key or returns [`ABI_NOT_FOUND`]
+    pub delete: unsafe extern "C" fn(ctx: *const c_void, key: *const u8, key_len: usize) -> i32,
+    /// Returns the number of entries
+    pub len: unsafe extern "C" fn(ctx: *const c_void) -> usize,
+    /// Writes all pending changes to disk
+    pub flush: unsafe extern "C" fn(ctx: *const c_void) -> i32,
+}
+
+type Shared = Mutex<Table>;
+
+#[inline]
+fn status(result: Result<bool, Error>) -> i32 {
+    match result {
+        Ok(true) => ABI_OK,
+        Ok(false) => ABI_NOT_FOUND,
+        Err(Error::ReadOnly) => ABI_READ_ONLY,
+        Err(Error::TooLarge) => ABI_TOO_LARGE,
+        Err(_) => ABI_ERROR,
+    }
+}
+
+#[inline]
+fn check_status(status: i32) -> Result<bool, Error> {
+    match status {
+        ABI_OK => Ok(true),
+        ABI_NOT_FOUND => Ok(false),
+        ABI_READ_ONLY => Err(Error::ReadOnly),
+        ABI_TOO_LARGE => Err(Error::TooLarge),
+        _ => Err(Error::Io(io::Error::other("table operation failed"))),
+    }
+}
+
+#[inline]
+unsafe fn bytes<'a>(ptr: *const u8, len: usize) -> &'a [u8] {
+    if len == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(ptr, len)
+    }
+}
+
+/// Runs the operation on the locked table, a table poisoned by a panic is reported as [`ABI_ERROR`]
+#[inline]
+unsafe fn with_table<F: FnOnce(&mut Table) -> Result<bool, Error>>(ctx: *const c_void, op: F) -> i32 {
+    let shared = &*(ctx as *const Shared);
+    match shared.lock() {
+        Ok(mut table) => status(op(&mut table)),
+        Err(_) => ABI_ERROR,
+    }
+}
+
+unsafe extern "C" fn abi_retain(ctx: *const c_void) {
+    Arc::increment_strong_count(ctx as *const Shared);
+}
+
+unsafe extern "C" fn abi_release(ctx: *const c_void) {
+    Arc::decrement_strong_count(ctx as *const Shared);
+}
+
+unsafe extern "C" fn abi_get(
+    ctx: *const c_void, key: *const u8, key_len: usize, callback: ValueCallback, user: *mut c_void,
+) -> i32 {
+    with_table(ctx, |table| match table.get(bytes(key, key_len)) {
+        Some(value) => {
+            callback(user, value.as_ptr(), value.len());
+            Ok(true)
+        }
+        None => Ok(false),
+    })
+}
+
+unsafe extern "C" fn abi_set(
+    ctx: *const c_void, key: *const u8, key_len: usize, value: *const u8, value_len: usize,
+) -> i32 {
+    with_table(ctx, |table| table.set(bytes(key, key_len), bytes(value, value_len)).map(|_| true))
+}
+
+unsafe extern "C" fn abi_delete(ctx: *const c_void, key: *const u8, key_len: usize) -> i32 {
+    with_table(ctx, |table| table.delete(bytes(key, key_len)).map(|old| old.is_some()))
+}
+
+unsafe extern "C" fn abi_len(ctx: *const c_void) -> usize {
+    let shared = &*(ctx as *const Shared);
+    shared.lock().map_or(0, |table| table.len())
+}
+
+unsafe extern "C" fn abi_flush(ctx: *const c_void) -> i32 {
+    with_table(ctx, |table| table.flush().map(|_| true))
+}
+
+static VTABLE: TableVTable = TableVTable {
+    version: ABI_VERSION,
+    retain: abi_retain,
+    release: abi_release,
+    get: abi_get,
+    set: abi_set,
+    delete: abi_delete,
+    len: abi_len,
+    flush: abi_flush,
+};
+
+unsafe extern "C" fn collect_value(user: *mut c_void, value: *const u8, value_len: usize) {
+    *(user as *mut Option<Vec<u8>>) = Some(bytes(value, value_len).to_vec());
+}
+
+/// Opaque, reference-counted handle to a table that can be passed to dynamically loaded plugins
+///
+/// The handle consists of a context pointer and a pointer to a [`TableVTable`] with C calling convention and has a
+/// C-compatible layout, so it can cross the boundary to code built with another compiler or in another language
+/// without exposing Rust generics. All operations go through the function table, so they are executed by the code
+/// that created the handle. Operations are serialized via a lock, so the handle can be shared between threads.
+///
+/// Cloning the handle increments the reference count, the table is closed when the last handle is dropped. Values
+/// passed to a [`ValueCallback`] must not be used after the callback returns and the callback must not call back
+/// into the handle.
+///
+/// ```
+/// use rust_persist::{Table, TableHandle};
+///
+/// let table = Table::create("example_abi.tbl").unwrap();
+/// let handle = TableHandle::new(table);
+/// let plugin_handle = handle.clone();
+/// plugin_handle.set("hello".as_bytes(), "world".as_bytes()).unwrap();
+/// assert_eq!(handle.get("hello".as_bytes()).unwrap(), Some("world".as_bytes().to_vec()));
+/// ```
+#[repr(C)]
+pub struct TableHandle {
+    ctx: *const c_void,
+    vtable: *const TableVTable,
+}
+
+// The context is only accessed via the function table, which serializes all operations
+unsafe impl Send for TableHandle {}
+unsafe impl Sync for TableHandle {}
+
+impl TableHandle {
+    /// Creates a handle that owns the given table
+    pub fn new(table: Table) -> Self {
+        let ctx = Arc::into_raw(Arc::new(Mutex::new(table))) as *const c_void;
+        TableHandle { ctx, vtable: &VTABLE }
+    }
+
+    /// Returns the function table of the handle
+    #[inline]
+    pub fn vtable(&self) -> &TableVTable {
+        unsafe { &*self.vtable }
+    }
+
+    /// Returns the context pointer that has to be passed to the functions of the function table
+    #[inline]
+    pub fn context(&self) -> *const c_void {
+        self.ctx
+    }
+
+    /// Returns a copy of the value associated with the given key
+    pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        let mut value: Option<Vec<u8>> = None;
+        let user = &mut value as *mut Option<Vec<u8>> as *mut c_void;
+        check_status(unsafe { (self.vtable().get)(self.ctx, key.as_ptr(), key.len(), collect_value, user) })?;
+        Ok(value)
+    }
+
+    /// Stores the given key/value pair
+    pub fn set(&self, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        check_status(unsafe { (self.vtable().set)(self.ctx, key.as_ptr(), key.len(), value.as_ptr(), value.len()) })
+            .map(|_| ())
+    }
+
+    /// Deletes the entry with the given key and returns whether an entry has been deleted
+    pub fn delete(&self, key: &[u8]) -> Result<bool, Error> {
+        check_status(unsafe { (self.vtable().delete)(self.ctx, key.as_ptr(), key.len()) })
+    }
+
+    /// Returns the number of entries in the table
+    #[inline]
+    pub fn len(&self) -> usize {
+        unsafe { (self.vtable().len)(self.ctx) }
+    }
+
+    /// Returns whether the table is empty
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Writes all pending changes to disk
+    pub fn flush(&self) -> Result<(), Error> {
+        check_status(unsafe { (self.vtable().flush)(self.ctx) }).map(|_| ())
+    }
+}
+
+impl Clone for TableHandle {
+    #[inline]
+    fn clone(&self) -> Self {
+        unsafe { (self.vtable().retain)(self.ctx) };
+        TableHandle { ctx: self.ctx, vtable: self.vtable }
+    }
+}
+
+impl Drop for TableHandle {
+    #[inline]
+    fn drop(&mut self) {
+        unsafe { (self.vtable().release)(self.ctx) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    unsafe extern "C" fn count_bytes(user: *mut c_void, _value: *const u8, value_len: usize) {
+        *(user as *mut usize) += value_len;
+    }
+
+    #[test]
+    fn test_table_handle() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let handle = TableHandle::new(Table::create(file.path()).unwrap());
+        assert_eq!(handle.vtable().version, ABI_VERSION);
+        let threads: Vec<_> = (0u32..4)
+            .map(|t| {
+                let handle = handle.clone();
+                std::thread::spawn(move || {
+                    for i in 0u32..100 {
+                        handle.set(&(t * 100 + i).to_le_bytes(), &i.to_le_bytes()).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(handle.len(), 400);
+        assert_eq!(handle.get(&105u32.to_le_bytes()).unwrap(), Some(5u32.to_le_bytes().to_vec()));
+        assert!(handle.delete(&105u32.to_le_bytes()).unwrap());
+        assert!(!handle.delete(&105u32.to_le_bytes()).unwrap());
+        assert_eq!(handle.get(&105u32.to_le_bytes()).unwrap(), None);
+        handle.flush().unwrap();
+        // Plugins only use the context pointer and the function table
+        let (ctx, vtable) = (handle.context(), handle.vtable());
+        let mut total = 0usize;
+        let key = 1u32.to_le_bytes();
+        let status = unsafe { (vtable.get)(ctx, key.as_ptr(), key.len(), count_bytes, &mut total as *mut _ as _) };
+        assert_eq!((status, total), (ABI_OK, 4));
+        let status = unsafe { (vtable.delete)(ctx, key.as_ptr(), key.len()) };
+        assert_eq!(status, ABI_OK);
+        let status = unsafe { (vtable.delete)(ctx, key.as_ptr(), key.len()) };
+        assert_eq!(status, ABI_NOT_FOUND);
+        assert_eq!(unsafe { (vtable.len)(ctx) }, 398);
+        drop(handle);
+        // The table is closed once the last handle is gone
+        let tbl = Table::open(file.path()).unwrap();
+        assert_eq!(tbl.len(), 398);
+    }
+}
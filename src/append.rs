@@ -0,0 +1,179 @@
+use std::cmp;
+
+use crate::{
+    checksum::{self, ChecksumHasher, CHECKSUM_SIZE},
+    index::{Hash, IndexEntryData, MAX_BLOCK_SIZE, MAX_BLOCK_SIZE_V1},
+    memmngr::Size,
+    table::{hash_key, match_key},
+    Entry, Error, Table, FLAG_CHECKSUM,
+};
+
+impl Table {
+    /// Appends the given bytes to the value associated with the given key and returns the new length of the value.
+    ///
+    /// If the free space directly after the data block of the value is big enough, the block is extended in place so
+    /// that only the appended bytes have to be written. Otherwise, the value is moved to a new block. If no entry with
+    /// the given key is stored in the table, the bytes are stored as a new value.
+    ///
+    /// If the table uses a write-ahead log, the whole new value is logged, see [`Table::set`].
+    ///
+    /// ```
+    /// use rust_persist::Table;
+    ///
+    /// let mut table = Table::create("example_append.tbl").unwrap();
+    /// table.append("log".as_bytes(), "line1\n".as_bytes()).unwrap();
+    /// assert_eq!(table.append("log".as_bytes(), "line2\n".as_bytes()).unwrap(), 12);
+    /// assert_eq!(table.get("log".as_bytes()), Some("line1\nline2\n".as_bytes()));
+    /// ```
+    pub fn append(&mut self, key: &[u8], bytes: &[u8]) -> Result<u64, Error> {
+        self.check_writable()?;
+        let hash = hash_key(key);
+        self.check_plain_key(hash, key)?;
+        if bytes.is_empty() {
+            return Ok(self.get(key).map_or(0, |value| value.len() as u64));
+        }
+        let old = match self.index.index_get(hash, |e| match_key(e, self.data, self.data_start, key)) {
+            Some(old) => old,
+            None => {
+                self.set_entry(Entry { key, value: bytes, flags: 0 })?;
+                return Ok(bytes.len() as u64);
+            }
+        };
+        let old_checksum = if old.flags & FLAG_CHECKSUM > 0 { Some(self.stored_checksum(&old)) } else { None };
+        let content_size = old.size() - if old_checksum.is_some() { CHECKSUM_SIZE as Size } else { 0 };
+        let checksum_size = if self.options.checksums { CHECKSUM_SIZE as Size } else { 0 };
+        let size = content_size
+            .checked_add(bytes.len() as Size)
+            .and_then(|size| size.checked_add(checksum_size))
+            .filter(|&size| size <= MAX_BLOCK_SIZE)
+            .ok_or(Error::TooLarge)?;
+        self.maybe_evict(size.saturating_sub(old.size()), Some(key))?;
+        if self.wal.is_some() {
+            let value =
+                [self.get_data(old.position() + old.key_size as u64, content_size - old.key_size as u64), bytes]
+                    .concat();
+            self.log_set(key, &value, old.flags)?;
+        }
+        let position = self.grow_block(hash, &old, content_size, size)?;
+        self.get_data_mut(position + content_size, bytes.len() as Size).copy_from_slice(bytes);
+        let mut flags = old.flags & !FLAG_CHECKSUM;
+        if self.options.checksums {
+            let checksum = match old_checksum {
+                // The checksum of the old content is continued, so the old content does not have to be read again
+                Some(old_checksum) => {
+                    let mut hasher = ChecksumHasher::new_with_initial(old_checksum);
+                    hasher.update(bytes);
+                    hasher.finalize()
+                }
+                None => checksum::checksum(self.get_data(position, content_size + bytes.len() as Size)),
+            };
+            self.get_data_mut(position + size - checksum_size, checksum_size).copy_from_slice(&checksum.to_le_bytes());
+            flags |= FLAG_CHECKSUM;
+        }
+        if size > MAX_BLOCK_SIZE_V1 {
+            self.header.set_wide_sizes(true);
+        }
+        let entry = match self.index.index_get_mut(hash, |e| e.position() == old.position()) {
+            Some(entry) => entry,
+            None => invariant_violated!("Entry must still be in the index"),
+        };
+        *entry = IndexEntryData::new(position, size, old.key_size, flags);
+        let new = *entry;
+        self.run_hooks(|hooks, tbl| {
+            let value = tbl.entry_from_index_data(new).value;
+            hooks.set(key, Some(&value[..value.len() - bytes.len()]), value)
+        });
+        debug_assert!(self.is_valid(), "Invalid after append");
+        self.maybe_flush()?;
+        Ok(size - checksum_size - old.key_size as u64)
+    }
+
+    /// Returns the checksum stored at the end of the data block
+    fn stored_checksum(&self, entry: &IndexEntryData) -> u32 {
+        let mut buf = [0; CHECKSUM_SIZE as usize];
+        buf.copy_from_slice(
+            self.get_data(entry.position() + entry.size() - CHECKSUM_SIZE as Size, CHECKSUM_SIZE as Size),
+        );
+        u32::from_le_bytes(buf)
+    }
+
+    /// Grows the data block of the entry to the given size and returns its new position.
+    ///
+    /// The first `content_size` bytes of the block are kept, the rest of the block is uninitialized.
+    fn grow_block(&mut self, hash: Hash, old: &IndexEntryData, content_size: Size, size: Size) -> Result<u64, Error> {
+        let position = old.position();
+        if self.mem.grow(position, size) {
+            return Ok(position);
+        }
+        let block_end = position + cmp::max(old.size(), 1);
+        if block_end + self.mem.free_tail() == self.mem.end() {
+            // The block is the last one, so the data section is extended behind it. It is extended by at least the
+            // size of the block, so that repeated appends do not resize the file every time.
+            let missing = position + size - self.mem.end();
+            self.extend_data(cmp::max(missing, size))?;
+            invariant!(self.mem.grow(position, size), "Block must grow after extending");
+            return Ok(position);
+        }
+        let new_position = self.allocate_data(hash, size)?;
+        let data_start = self.data_start;
+        safemem::copy_over(
+            self.data,
+            (position - data_start) as usize,
+            (new_position - data_start) as usize,
+            content_size as usize,
+        );
+        self.free_data(position);
+        Ok(new_position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wal::wal_path;
+    use std::fs;
+
+    #[test]
+    fn test_append() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::options().checksums(true).create(file.path()).unwrap();
+        let mut expected = Vec::new();
+        for i in 0u32..1000 {
+            let chunk = i.to_le_bytes();
+            expected.extend_from_slice(&chunk);
+            assert_eq!(tbl.append("log".as_bytes(), &chunk).unwrap(), expected.len() as u64);
+            if i % 100 == 0 {
+                tbl.set(&i.to_ne_bytes(), &[0; 100]).unwrap();
+            }
+        }
+        assert!(tbl.is_valid());
+        assert!(tbl.verify_checksums().is_empty());
+        assert_eq!(tbl.get("log".as_bytes()), Some(&expected[..]));
+        tbl.set("plain".as_bytes(), "abc".as_bytes()).unwrap();
+        tbl.set("other".as_bytes(), "xyz".as_bytes()).unwrap();
+        assert_eq!(tbl.append("plain".as_bytes(), "def".as_bytes()).unwrap(), 6);
+        assert_eq!(tbl.append("plain".as_bytes(), &[]).unwrap(), 6);
+        assert_eq!(tbl.get("plain".as_bytes()), Some("abcdef".as_bytes()));
+        assert_eq!(tbl.get("other".as_bytes()), Some("xyz".as_bytes()));
+        assert!(tbl.verify_checksums().is_empty());
+        tbl.close();
+        let tbl = Table::open(file.path()).unwrap();
+        assert_eq!(tbl.get("log".as_bytes()), Some(&expected[..]));
+        assert!(tbl.is_valid());
+    }
+
+    #[test]
+    fn test_append_wal() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::options().wal(true).create(file.path()).unwrap();
+        tbl.append("key".as_bytes(), "abc".as_bytes()).unwrap();
+        tbl.append("key".as_bytes(), "def".as_bytes()).unwrap();
+        let wal = fs::read(wal_path(file.path())).unwrap();
+        tbl.close();
+        // Simulate a crash where none of the changes reached the table file
+        Table::create(file.path()).unwrap().close();
+        fs::write(wal_path(file.path()), &wal).unwrap();
+        let tbl = Table::options().wal(true).open(file.path()).unwrap();
+        assert_eq!(tbl.get("key".as_bytes()), Some("abcdef".as_bytes()));
+    }
+}
@@ -0,0 +1,147 @@
+use std::{io::Write, iter, sync::Arc};
+
+use arrow_array::{
+    builder::{ArrayBuilder, BinaryBuilder, LargeBinaryBuilder, UInt16Builder, UInt64Builder},
+    ArrayRef, RecordBatch,
+};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use parquet::arrow::ArrowWriter;
+
+use crate::{index::is_namespaced, Error, Table, FLAG_CHECKSUM};
+
+/// Builders for the columns of one record batch
+struct Columns {
+    keys: BinaryBuilder,
+    values: LargeBinaryBuilder,
+    flags: UInt16Builder,
+    sizes: UInt64Builder,
+}
+
+impl Columns {
+    fn finish(mut self) -> RecordBatch {
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(self.keys.finish()),
+            Arc::new(self.values.finish()),
+            Arc::new(self.flags.finish()),
+            Arc::new(self.sizes.finish()),
+        ];
+        RecordBatch::try_new(Table::arrow_schema(), columns).expect("Columns must match the schema")
+    }
+}
+
+impl Table {
+    /// Returns the schema of the record batches returned by [`Table::arrow_batches`].
+    ///
+    /// The schema has the columns `key` (binary), `value` (large binary), `flags` (16-bit unsigned integer) and `size`
+    /// (64-bit unsigned integer). The size is the size of the data block in the table, i.e. it includes the key and
+    /// the checksum (if any).
+    pub fn arrow_schema() -> SchemaRef {
+        Arc::new(Schema::new(vec![
+            Field::new("key", DataType::Binary, false),
+            Field::new("value", DataType::LargeBinary, false),
+            Field::new("flags", DataType::UInt16, false),
+            Field::new("size", DataType::UInt64, false),
+        ]))
+    }
+
+    /// Returns an iterator over all entries of the table as Arrow record batches of at most `chunk_size` rows.
+    ///
+    /// The batches are built while iterating, so only one chunk of entries is copied at a time. See
+    /// [`Table::arrow_schema`] for the columns. Internal entry flags like [`FLAG_CHECKSUM`](crate::FLAG_CHECKSUM)
+    /// are not included.
+    ///
+    /// This method is only available with the `arrow` feature. Tables created via
+    /// [`HashKeyTable`](crate::HashKeyTable) are not supported.
+    pub fn arrow_batches(&self, chunk_size: usize) -> Result<impl Iterator<Item = RecordBatch> + '_, Error> {
+        self.check_byte_keys()?;
+        if chunk_size == 0 {
+            return Err(Error::InvalidOptions("chunk size must not be zero"));
+        }
+        let mut entries =
+            self.index.get_entries().iter().filter(|entry| entry.is_used() && !is_namespaced(&entry.data));
+        Ok(iter::from_fn(move || {
+            let mut columns = Columns {
+                keys: BinaryBuilder::new(),
+                values: LargeBinaryBuilder::new(),
+                flags: UInt16Builder::with_capacity(chunk_size),
+                sizes: UInt64Builder::with_capacity(chunk_size),
+            };
+            for index_entry in entries.by_ref().take(chunk_size) {
+                let entry = self.entry_from_index_data(index_entry.data);
+                columns.keys.append_value(entry.key);
+                columns.values.append_value(entry.value);
+                columns.flags.append_value(entry.flags & !FLAG_CHECKSUM);
+                columns.sizes.append_value(index_entry.data.size());
+            }
+            if columns.flags.is_empty() {
+                return None;
+            }
+            Some(columns.finish())
+        }))
+    }
+
+    /// Writes all entries of the table to the given writer as a Parquet file.
+    ///
+    /// The file uses the schema of [`Table::arrow_schema`] and is written in row groups of at most `chunk_size`
+    /// entries, so the memory usage is bounded by the size of one chunk. Returns the number of exported entries.
+    ///
+    /// This method is only available with the `arrow` feature.
+    ///
+    /// ```
+    /// use rust_persist::Table;
+    ///
+    /// let mut table = Table::create("example_parquet.tbl").unwrap();
+    /// table.set("key1".as_bytes(), "value1".as_bytes()).unwrap();
+    /// let file = std::fs::File::create("example_parquet.parquet").unwrap();
+    /// assert_eq!(table.export_parquet(file, 10_000).unwrap(), 1);
+    /// ```
+    pub fn export_parquet<W: Write + Send>(&self, writer: W, chunk_size: usize) -> Result<usize, Error> {
+        let batches = self.arrow_batches(chunk_size)?;
+        let mut out = ArrowWriter::try_new(writer, Self::arrow_schema(), None).map_err(Error::Parquet)?;
+        let mut count = 0;
+        for batch in batches {
+            count += batch.num_rows();
+            out.write(&batch).map_err(Error::Parquet)?;
+            // Every chunk is written as a row group, so that the writer does not buffer more than one chunk
+            out.flush().map_err(Error::Parquet)?;
+        }
+        out.close().map_err(Error::Parquet)?;
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_array::{cast::AsArray, types::UInt64Type};
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    #[test]
+    fn test_arrow() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::options().checksums(true).create(file.path()).unwrap();
+        for i in 0u16..250 {
+            tbl.set(&i.to_ne_bytes(), &vec![1; i as usize]).unwrap();
+        }
+        let batches: Vec<_> = tbl.arrow_batches(100).unwrap().collect();
+        assert_eq!(batches.iter().map(RecordBatch::num_rows).collect::<Vec<_>>(), [100, 100, 50]);
+        let batch = &batches[0];
+        assert_eq!(batch.schema(), Table::arrow_schema());
+        let keys = batch.column(0).as_binary::<i32>();
+        let values = batch.column(1).as_binary::<i64>();
+        let sizes = batch.column(3).as_primitive::<UInt64Type>();
+        for row in 0..batch.num_rows() {
+            assert_eq!(tbl.get(keys.value(row)), Some(values.value(row)));
+            assert_eq!(sizes.value(row), 2 + values.value(row).len() as u64 + crate::checksum::CHECKSUM_SIZE as u64);
+        }
+        assert!(matches!(tbl.arrow_batches(0), Err(Error::InvalidOptions(_))));
+        let parquet = tempfile::NamedTempFile::new().unwrap();
+        assert_eq!(tbl.export_parquet(parquet.reopen().unwrap(), 100).unwrap(), 250);
+        let reader = ParquetRecordBatchReaderBuilder::try_new(parquet.reopen().unwrap()).unwrap();
+        assert_eq!(reader.metadata().num_row_groups(), 3);
+        let rows: usize = reader.build().unwrap().map(|batch| batch.unwrap().num_rows()).sum();
+        assert_eq!(rows, 250);
+        tbl.clear().unwrap();
+        assert_eq!(tbl.arrow_batches(100).unwrap().count(), 0);
+    }
+}
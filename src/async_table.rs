@@ -0,0 +1,182 @@
+use std::{
+    io,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use tokio::task::{self, JoinError};
+
+use crate::{Error, Table, TableOptions};
+
+/// Error of operations on a table that has been poisoned by a panic
+const POISONED: Error = Error::Corrupt("table has been poisoned by a panic");
+
+/// Error of a blocking task that did not complete
+#[inline]
+fn join_error(err: JoinError) -> Error {
+    if err.is_panic() {
+        POISONED
+    } else {
+        Error::Io(io::Error::other("the runtime is shutting down"))
+    }
+}
+
+/// A handle to a table for use in async code with tokio
+///
+/// Every operation runs on the blocking thread pool of tokio via [`task::spawn_blocking`], so page faults and flushes
+/// of the memory map do not block the async reactor. The table is protected by a mutex, so the handle can be cloned
+/// and shared between tasks, but operations are executed one after another. Values are returned as copies, as
+/// references into the table would have to keep its lock.
+///
+/// If an operation panics, all further operations fail with [`Error::Corrupt`].
+///
+/// ```
+/// use rust_persist::AsyncTable;
+///
+/// let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+/// runtime.block_on(async {
+///     let table = AsyncTable::create("example_async.tbl").await.unwrap();
+///     table.set("hello".as_bytes(), "world".as_bytes()).await.unwrap();
+///     assert_eq!(table.get("hello".as_bytes()).await.unwrap(), Some("world".as_bytes().to_vec()));
+///     table.flush().await.unwrap();
+/// });
+/// ```
+#[derive(Clone)]
+pub struct AsyncTable {
+    table: Arc<Mutex<Table>>,
+}
+
+impl AsyncTable {
+    /// Opens an existing table, see [`Table::open`].
+    pub async fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Self::open_with(path, Table::options()).await
+    }
+
+    /// Opens an existing table with the given options, see [`TableOptions::open`].
+    pub async fn open_with<P: AsRef<Path>>(path: P, options: TableOptions) -> Result<Self, Error> {
+        let path = path.as_ref().to_path_buf();
+        task::spawn_blocking(move || options.open(path)).await.map_err(join_error)?.map(Self::from)
+    }
+
+    /// Creates a new table, see [`Table::create`].
+    pub async fn create<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Self::create_with(path, Table::options()).await
+    }
+
+    /// Creates a new table with the given options, see [`TableOptions::create`].
+    pub async fn create_with<P: AsRef<Path>>(path: P, options: TableOptions) -> Result<Self, Error> {
+        let path = path.as_ref().to_path_buf();
+        task::spawn_blocking(move || options.create(path)).await.map_err(join_error)?.map(Self::from)
+    }
+
+    /// Runs the closure with exclusive access to the table on the blocking thread pool and returns its result
+    ///
+    /// This can be used for operations that have no async counterpart or to combine several operations, e.g. to
+    /// modify a value without other tasks interfering.
+    pub async fn with<R, F>(&self, f: F) -> Result<R, Error>
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut Table) -> Result<R, Error> + Send + 'static,
+    {
+        let table = self.table.clone();
+        task::spawn_blocking(move || f(&mut *table.lock().map_err(|_| POISONED)?)).await.map_err(join_error)?
+    }
+
+    /// Returns a copy of the value of the given key
+    pub async fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        let key = key.to_vec();
+        self.with(move |tbl| Ok(tbl.get(&key).map(<[u8]>::to_vec))).await
+    }
+
+    /// Returns whether the given key exists in the table
+    pub async fn contains(&self, key: &[u8]) -> Result<bool, Error> {
+        let key = key.to_vec();
+        self.with(move |tbl| Ok(tbl.contains(&key))).await
+    }
+
+    /// Stores the value for the given key, see [`Table::set`].
+    pub async fn set(&self, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        let (key, value) = (key.to_vec(), value.to_vec());
+        self.with(move |tbl| tbl.set(&key, &value).map(|_| ())).await
+    }
+
+    /// Deletes the given key and returns its value (if any), see [`Table::take`].
+    pub async fn delete(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        let key = key.to_vec();
+        self.with(move |tbl| tbl.take(&key)).await
+    }
+
+    /// Returns the number of entries in the table
+    pub async fn len(&self) -> Result<usize, Error> {
+        self.with(|tbl| Ok(tbl.len())).await
+    }
+
+    /// Returns whether the table is empty
+    pub async fn is_empty(&self) -> Result<bool, Error> {
+        self.with(|tbl| Ok(tbl.is_empty())).await
+    }
+
+    /// Flushes the table to disk, see [`Table::flush`].
+    pub async fn flush(&self) -> Result<(), Error> {
+        self.with(|tbl| tbl.flush()).await
+    }
+
+    /// Returns the underlying table or `None` if other clones of this handle still exist
+    pub fn into_inner(self) -> Result<Option<Table>, Error> {
+        match Arc::try_unwrap(self.table) {
+            Ok(table) => table.into_inner().map(Some).map_err(|_| POISONED),
+            Err(_) => Ok(None),
+        }
+    }
+}
+
+impl From<Table> for AsyncTable {
+    #[inline]
+    fn from(table: Table) -> Self {
+        Self { table: Arc::new(Mutex::new(table)) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_async_table() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        let tbl = runtime.block_on(async {
+            let tbl = AsyncTable::create_with(file.path(), Table::options().checksums(true)).await.unwrap();
+            assert!(tbl.is_empty().await.unwrap());
+            let tasks: Vec<_> = (0u32..8)
+                .map(|t| {
+                    let tbl = tbl.clone();
+                    tokio::spawn(async move {
+                        for i in 0u32..50 {
+                            let key = (t * 1000 + i).to_le_bytes();
+                            tbl.set(&key, &i.to_le_bytes()).await.unwrap();
+                            assert_eq!(tbl.get(&key).await.unwrap(), Some(i.to_le_bytes().to_vec()));
+                        }
+                    })
+                })
+                .collect();
+            for task in tasks {
+                task.await.unwrap();
+            }
+            assert_eq!(tbl.len().await.unwrap(), 400);
+            assert_eq!(tbl.delete(&1005u32.to_le_bytes()).await.unwrap(), Some(5u32.to_le_bytes().to_vec()));
+            assert!(!tbl.contains(&1005u32.to_le_bytes()).await.unwrap());
+            assert_eq!(tbl.with(|tbl| Ok(tbl.iter().count())).await.unwrap(), 399);
+            tbl.flush().await.unwrap();
+            let clone = tbl.clone();
+            assert!(clone.with(|_| -> Result<(), Error> { panic!("injected panic") }).await.is_err());
+            assert!(matches!(tbl.len().await, Err(Error::Corrupt(_))));
+            assert!(tbl.into_inner().unwrap().is_none());
+            clone.into_inner().is_err()
+        });
+        assert!(tbl);
+        let tbl = runtime.block_on(AsyncTable::open(file.path())).unwrap();
+        assert_eq!(runtime.block_on(tbl.len()).unwrap(), 399);
+        assert!(tbl.into_inner().unwrap().unwrap().is_valid());
+    }
+}
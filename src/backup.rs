@@ -0,0 +1,770 @@
+use std::{
+    cmp,
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, BufWriter, Read, Write},
+    mem,
+    path::{Path, PathBuf},
+    ptr,
+};
+
+use crate::{
+    batch::{checksum, encode_ops, read_bytes, read_u32, read_u64, sibling_path},
+    checksum::{ChecksumHasher, CHECKSUM_SIZE},
+    index::{IndexEntry, IndexEntryData, MAX_BLOCK_SIZE_V1},
+    mmap::{self, MMap},
+    redact::redact,
+    resize,
+    table::{total_size, Header},
+    Entry, Error, Redaction, Table, TableOptions, WriteBatch, FLAG_CHECKSUM, FLAG_PINNED, INDEX_HEADER,
+};
+
+const MANIFEST_HEADER: [u8; 16] = *b"rust-persist-m1\n";
+
+/// Returns the path of the manifest that belongs to the backup at the given path
+#[inline]
+fn manifest_path(path: &Path) -> PathBuf {
+    sibling_path(path, ".manifest")
+}
+
+/// Writes the file via a temporary file, so that the given path never contains partial data
+pub(crate) fn write_atomic(path: &Path, data: &[u8]) -> Result<(), Error> {
+    let tmp = sibling_path(path, ".tmp");
+    let mut fd = File::create(&tmp).map_err(Error::Io)?;
+    fd.write_all(data).map_err(Error::Io)?;
+    fd.sync_all().map_err(Error::Io)?;
+    fs::rename(&tmp, path).map_err(Error::Io)
+}
+
+/// Syncs the directory containing the given path, so that a rename is persisted
+#[cfg(unix)]
+pub(crate) fn sync_parent(path: &Path) -> Result<(), Error> {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => File::open(dir).and_then(|dir| dir.sync_all()).map_err(Error::Io),
+        _ => File::open(".").and_then(|dir| dir.sync_all()).map_err(Error::Io),
+    }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn sync_parent(_path: &Path) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Copies exactly `len` bytes from the reader to the writer, feeding them to the hasher if given
+fn copy_exact<R: Read, W: Write>(
+    reader: &mut R, writer: &mut W, mut len: u64, buf: &mut [u8], mut hasher: Option<&mut ChecksumHasher>,
+) -> Result<(), Error> {
+    while len > 0 {
+        let size = cmp::min(len, buf.len() as u64) as usize;
+        let chunk = &mut buf[..size];
+        reader.read_exact(chunk).map_err(Error::Io)?;
+        if let Some(hasher) = hasher.as_mut() {
+            hasher.update(chunk);
+        }
+        writer.write_all(chunk).map_err(Error::Io)?;
+        len -= chunk.len() as u64;
+    }
+    Ok(())
+}
+
+/// Reads the header and the index of a table file from the reader and returns them with the used index entries
+fn read_table_head<R: Read>(reader: &mut R) -> Result<(Vec<u8>, Vec<IndexEntryData>), Error> {
+    let mut head = vec![0; mem::size_of::<Header>()];
+    reader.read_exact(&mut head).map_err(Error::Io)?;
+    // This is safe, the header only consists of integers and byte arrays
+    let header = unsafe { ptr::read_unaligned(head.as_ptr() as *const Header) };
+    if header.header != INDEX_HEADER {
+        return Err(Error::WrongHeader);
+    }
+    let swapped = !header.has_correct_endianness();
+    let index_capacity = if swapped { header.index_capacity.to_be().to_le() } else { header.index_capacity };
+    if !index_capacity.is_power_of_two() {
+        return Err(Error::Corrupt("index capacity is not a power of two"));
+    }
+    head.resize(total_size(index_capacity as usize, 0)? as usize, 0);
+    reader.read_exact(&mut head[mem::size_of::<Header>()..]).map_err(Error::Io)?;
+    let mut blocks = Vec::new();
+    for raw in head[mem::size_of::<Header>()..].chunks_exact(mem::size_of::<IndexEntry>()) {
+        // This is safe, index entries only consist of integers
+        let mut entry = unsafe { ptr::read_unaligned(raw.as_ptr() as *const IndexEntry) };
+        if swapped {
+            entry.fix_endianness();
+        }
+        if entry.is_used() {
+            blocks.push(entry.data);
+        }
+    }
+    blocks.sort_by_key(IndexEntryData::position);
+    Ok((head, blocks))
+}
+
+/// Describes a chain of backups created by [`Table::backup_full`] and [`Table::backup_incremental`]
+///
+/// The manifest of a backup is stored next to it as `<path>.manifest`. Besides the chain of backup files, it
+/// contains a hash of every entry at the time of the backup, so that the next incremental backup only has to contain
+/// the changed entries.
+///
+/// ```
+/// use rust_persist::{BackupManifest, Table};
+///
+/// let mut table = Table::create("example_backup.tbl").unwrap();
+/// table.set("key1".as_bytes(), "value1".as_bytes()).unwrap();
+/// table.backup_full("example_backup_full.tbl").unwrap();
+/// table.set("key2".as_bytes(), "value2".as_bytes()).unwrap();
+/// table.backup_incremental("example_backup_full.tbl.manifest", "example_backup_inc1.tbl").unwrap();
+/// let manifest = BackupManifest::load("example_backup_inc1.tbl.manifest").unwrap();
+/// let restored = manifest.restore("example_backup_restored.tbl").unwrap();
+/// assert_eq!(restored.get("key2".as_bytes()), Some("value2".as_bytes()));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct BackupManifest {
+    files: Vec<PathBuf>,
+    entries: HashMap<Vec<u8>, u64>,
+}
+
+impl BackupManifest {
+    /// Loads the manifest from the given path.
+    ///
+    /// If the file is not a valid manifest, [`Error::Corrupt`] is returned.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let data = fs::read(path).map_err(Error::Io)?;
+        Self::decode(&data).ok_or(Error::Corrupt("invalid backup manifest"))
+    }
+
+    /// Returns the files of the backup chain, starting with the full backup followed by all increments
+    #[inline]
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    /// Restores the backup chain to a new table at the given path and returns it.
+    ///
+    /// The full backup is copied to the path and all increments are applied in order. If the file exists, it will
+    /// be overwritten.
+    pub fn restore<P: AsRef<Path>>(&self, path: P) -> Result<Table, Error> {
+        let path = path.as_ref();
+        let (full, increments) = self.files.split_first().ok_or(Error::Corrupt("empty backup chain"))?;
+        fs::copy(full, path).map_err(Error::Io)?;
+        let mut tbl = Table::open(path)?;
+        for increment in increments {
+            let data = fs::read(increment).map_err(Error::Io)?;
+            let batch = WriteBatch::decode(&mut &data[..]).ok_or(Error::Corrupt("invalid backup increment"))?;
+            tbl.apply_ops(&batch)??;
+        }
+        tbl.flush()?;
+        Ok(tbl)
+    }
+
+    fn save(&self, path: &Path) -> Result<(), Error> {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&MANIFEST_HEADER);
+        buf.extend_from_slice(&(self.files.len() as u32).to_le_bytes());
+        for file in &self.files {
+            let name = file.to_str().ok_or_else(|| {
+                Error::Io(io::Error::new(io::ErrorKind::InvalidInput, "backup path is not valid UTF-8"))
+            })?;
+            buf.extend_from_slice(&(name.len() as u32).to_le_bytes());
+            buf.extend_from_slice(name.as_bytes());
+        }
+        buf.extend_from_slice(&(self.entries.len() as u64).to_le_bytes());
+        for (key, hash) in &self.entries {
+            buf.extend_from_slice(&(key.len() as u32).to_le_bytes());
+            buf.extend_from_slice(key);
+            buf.extend_from_slice(&hash.to_le_bytes());
+        }
+        let checksum = checksum(&buf);
+        buf.extend_from_slice(&checksum.to_le_bytes());
+        write_atomic(path, &buf)
+    }
+
+    fn decode(content: &[u8]) -> Option<Self> {
+        let data = &mut &content[..];
+        if read_bytes(data, MANIFEST_HEADER.len())? != MANIFEST_HEADER {
+            return None;
+        }
+        let mut manifest = Self::default();
+        for _ in 0..read_u32(data)? {
+            let len = read_u32(data)? as usize;
+            manifest.files.push(PathBuf::from(String::from_utf8(read_bytes(data, len)?).ok()?));
+        }
+        for _ in 0..read_u64(data)? {
+            let len = read_u32(data)? as usize;
+            let key = read_bytes(data, len)?;
+            manifest.entries.insert(key, read_u64(data)?);
+        }
+        let content = &content[..content.len() - data.len()];
+        if read_u64(data)? != checksum(content) || !data.is_empty() {
+            return None;
+        }
+        Some(manifest)
+    }
+}
+
+impl Table {
+    /// Writes a consistent snapshot of the table to the given path while the table stays open.
+    ///
+    /// The snapshot is a compacted copy of the table: its index is sized for the current number of entries and its
+    /// data section contains no free space, with pinned entries placed at the front. The snapshot is first written to
+    /// `<path>.tmp` and then renamed, so the given path never contains a partial copy. If the file exists, it will be
+    /// overwritten. The path must not be the path of the table itself.
+    ///
+    /// As the snapshot is taken from the in-memory state, it also contains changes that have not been flushed yet.
+    #[inline]
+    pub fn backup_to<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        self.write_compacted(path.as_ref(), TableOptions::default().index_capacity, |_| Redaction::Keep)
+    }
+
+    /// Writes a snapshot of the table to the given path like [`Table::backup_to`], with entries filtered by `filter`.
+    ///
+    /// The filter is called for every entry and decides via [`Redaction`] whether it is written as it is, left out
+    /// or written with another value, e.g. to produce a backup without sensitive data. The table itself is not
+    /// modified.
+    ///
+    /// ```
+    /// use rust_persist::{Redaction, Table};
+    ///
+    /// let mut table = Table::create("example_backup_redacted.tbl").unwrap();
+    /// table.set("user:1".as_bytes(), "alice".as_bytes()).unwrap();
+    /// table.set("secret:1".as_bytes(), "hunter2".as_bytes()).unwrap();
+    /// table
+    ///     .backup_redacted_to("example_backup_redacted_copy.tbl", |entry| {
+    ///         if entry.key.starts_with(b"secret:") {
+    ///             Redaction::Drop
+    ///         } else {
+    ///             Redaction::Keep
+    ///         }
+    ///     })
+    ///     .unwrap();
+    /// let copy = Table::open("example_backup_redacted_copy.tbl").unwrap();
+    /// assert_eq!(copy.len(), 1);
+    /// ```
+    #[inline]
+    pub fn backup_redacted_to<P: AsRef<Path>, F: FnMut(&Entry<'_>) -> Redaction>(
+        &self, path: P, filter: F,
+    ) -> Result<(), Error> {
+        self.write_compacted(path.as_ref(), TableOptions::default().index_capacity, filter)
+    }
+
+    /// Streams a consistent snapshot of the table to the given writer, e.g. to upload it to an object store.
+    ///
+    /// The written bytes form a table file with the same content as a snapshot written by [`Table::backup_to`], so
+    /// they can be stored to a file and opened directly. Only the index of the snapshot is built in memory, the data
+    /// is copied straight from the table, so no temporary file is needed. Returns the number of written bytes.
+    ///
+    /// ```
+    /// use rust_persist::Table;
+    ///
+    /// let mut table = Table::create("example_stream.tbl").unwrap();
+    /// table.set("key1".as_bytes(), "value1".as_bytes()).unwrap();
+    /// let mut snapshot = Vec::new();
+    /// table.backup_to_writer(&mut snapshot).unwrap();
+    /// std::fs::write("example_stream_copy.tbl", &snapshot).unwrap();
+    /// let copy = Table::open("example_stream_copy.tbl").unwrap();
+    /// assert_eq!(copy.get("key1".as_bytes()), Some("value1".as_bytes()));
+    /// ```
+    pub fn backup_to_writer<W: Write>(&self, writer: W) -> Result<u64, Error> {
+        let mut blocks = Vec::with_capacity(self.len());
+        for pinned in &[true, false] {
+            for block in self.mem.get_used() {
+                match self.index.get_block(block.hash, block.start) {
+                    Some(data) if (data.flags & FLAG_PINNED > 0) == *pinned => blocks.push((block.hash, data)),
+                    _ => (),
+                }
+            }
+        }
+        let defaults = TableOptions::default();
+        let index_capacity = resize::index_capacity_for(defaults.index_capacity, blocks.len(), defaults.max_usage);
+        let options = defaults.index_capacity(index_capacity).data_size(0);
+        let mmap = MMap::map_anon(total_size(index_capacity, 0)? as usize).map_err(Error::Io)?;
+        let mut snapshot = Table::from_opened(mmap::map_table(None, mmap, true, &options)?, true, options)?;
+        let mut position = snapshot.data_start;
+        for (hash, data) in &blocks {
+            let entry = IndexEntryData::new(position, data.size(), data.key_size, data.flags);
+            snapshot.index.index_set(*hash, |_| false, entry);
+            // Empty blocks occupy one byte, just like in the table
+            position += cmp::max(data.size(), 1);
+        }
+        snapshot.header.created = self.header.created;
+        snapshot.header.last_close = self.now();
+        snapshot.header.set_hash_keys(self.header.has_hash_keys());
+        snapshot.header.set_wide_sizes(blocks.iter().any(|(_, data)| data.size() > MAX_BLOCK_SIZE_V1));
+        snapshot.header.set_open(false);
+        let mut out = BufWriter::new(writer);
+        out.write_all(&snapshot.mmap).map_err(Error::Io)?;
+        for (_, data) in &blocks {
+            out.write_all(self.get_data(data.position(), data.size())).map_err(Error::Io)?;
+            if data.size() == 0 {
+                out.write_all(&[0]).map_err(Error::Io)?;
+            }
+        }
+        out.flush().map_err(Error::Io)?;
+        Ok(position)
+    }
+
+    /// Restores a table from a snapshot stream written by [`Table::backup_to_writer`] to the given path and opens it.
+    ///
+    /// The stream is written to `<path>.tmp` while the checksums of all entries that have one are validated, so a
+    /// damaged stream is detected before the table is opened. Only the index of the table is kept in memory. The
+    /// file is renamed to the given path when the complete stream has been validated, overwriting an existing file.
+    ///
+    /// If the checksum of an entry does not match, [`Error::ChecksumMismatch`] is returned.
+    ///
+    /// ```
+    /// use rust_persist::Table;
+    ///
+    /// let mut table = Table::options().checksums(true).create("example_restore.tbl").unwrap();
+    /// table.set("key1".as_bytes(), "value1".as_bytes()).unwrap();
+    /// let mut snapshot = Vec::new();
+    /// table.backup_to_writer(&mut snapshot).unwrap();
+    /// let restored = Table::restore_from_reader("example_restore_copy.tbl", &snapshot[..]).unwrap();
+    /// assert_eq!(restored.get("key1".as_bytes()), Some("value1".as_bytes()));
+    /// ```
+    pub fn restore_from_reader<P: AsRef<Path>, R: Read>(path: P, reader: R) -> Result<Table, Error> {
+        let path = path.as_ref();
+        let tmp = sibling_path(path, ".tmp");
+        let result = Self::write_validated(&tmp, reader).and_then(|_| fs::rename(&tmp, path).map_err(Error::Io));
+        if result.is_err() {
+            fs::remove_file(&tmp).ok();
+        }
+        result?;
+        sync_parent(path)?;
+        Table::open(path)
+    }
+
+    /// Copies the table file from the reader to the given path and validates the checksums of all entries
+    fn write_validated<R: Read>(path: &Path, mut reader: R) -> Result<(), Error> {
+        let (head, blocks) = read_table_head(&mut reader)?;
+        let fd = File::create(path).map_err(Error::Io)?;
+        let mut out = BufWriter::new(&fd);
+        out.write_all(&head).map_err(Error::Io)?;
+        let mut buf = vec![0; 64 * 1024];
+        let mut position = head.len() as u64;
+        for block in &blocks {
+            if block.position() < position {
+                return Err(Error::Corrupt("entries overlap"));
+            }
+            // Free space and empty blocks are copied as they are
+            copy_exact(&mut reader, &mut out, block.position() - position, &mut buf, None)?;
+            if block.flags & FLAG_CHECKSUM > 0 {
+                let data_size =
+                    block.size().checked_sub(CHECKSUM_SIZE as u64).ok_or(Error::Corrupt("entry too small"))?;
+                let mut hasher = ChecksumHasher::new();
+                copy_exact(&mut reader, &mut out, data_size, &mut buf, Some(&mut hasher))?;
+                let mut stored = [0; CHECKSUM_SIZE as usize];
+                reader.read_exact(&mut stored).map_err(Error::Io)?;
+                if hasher.finalize() != u32::from_le_bytes(stored) {
+                    return Err(Error::ChecksumMismatch);
+                }
+                out.write_all(&stored).map_err(Error::Io)?;
+            } else {
+                copy_exact(&mut reader, &mut out, block.size(), &mut buf, None)?;
+            }
+            position = block.position() + block.size();
+        }
+        io::copy(&mut reader, &mut out).map_err(Error::Io)?;
+        out.flush().map_err(Error::Io)?;
+        drop(out);
+        fd.sync_all().map_err(Error::Io)
+    }
+
+    /// Writes a fully defragmented, minimally sized copy of the table to the given path, leaving the table untouched.
+    ///
+    /// In contrast to [`Table::defragment`], no data is moved in the table file itself, so a failure while writing
+    /// cannot damage the table. The copy is synced to disk before it is renamed to the given path, so it can safely
+    /// replace the table file after the table has been closed.
+    ///
+    /// By default, the index of the copy is sized for the current number of entries. If `index_capacity` is given,
+    /// the index is created with at least this capacity, e.g. to make room for expected growth.
+    ///
+    /// See [`Table::backup_to`] for details on the copy.
+    ///
+    /// ```
+    /// use rust_persist::Table;
+    ///
+    /// let mut table = Table::create("example_vacuum.tbl").unwrap();
+    /// table.set("key1".as_bytes(), "value1".as_bytes()).unwrap();
+    /// table.vacuum_into("example_vacuum_copy.tbl", Some(4096)).unwrap();
+    /// table.close();
+    /// std::fs::rename("example_vacuum_copy.tbl", "example_vacuum.tbl").unwrap();
+    /// ```
+    #[inline]
+    pub fn vacuum_into<P: AsRef<Path>>(&self, path: P, index_capacity: Option<usize>) -> Result<(), Error> {
+        let defaults = TableOptions::default();
+        let index_capacity =
+            index_capacity.map_or(defaults.index_capacity, |capacity| defaults.index_capacity(capacity).index_capacity);
+        self.write_compacted(path.as_ref(), index_capacity, |_| Redaction::Keep)
+    }
+
+    /// Duplicates the table to the given path and opens the copy, while this table stays open.
+    ///
+    /// The copy is an independent table with the same entries that is written like [`Table::backup_to`] and opened
+    /// with the options of this table (but writable). If the file exists, it will be overwritten.
+    ///
+    /// ```
+    /// use rust_persist::Table;
+    ///
+    /// let mut production = Table::create("example_production.tbl").unwrap();
+    /// production.set("key1".as_bytes(), "value1".as_bytes()).unwrap();
+    /// let mut staging = production.clone_to("example_staging.tbl").unwrap();
+    /// staging.set("key1".as_bytes(), "value2".as_bytes()).unwrap();
+    /// assert_eq!(production.get("key1".as_bytes()), Some("value1".as_bytes()));
+    /// ```
+    pub fn clone_to<P: AsRef<Path>>(&self, path: P) -> Result<Table, Error> {
+        let path = path.as_ref();
+        self.backup_to(path)?;
+        self.options.clone().read_only(false).open(path)
+    }
+
+    /// Rebuilds the table file and continues with the rebuilt table.
+    ///
+    /// The table is written to a compacted copy next to it (see [`Table::vacuum_into`]), which is synced to disk and
+    /// atomically renamed over the table file before the table is remapped. If the process crashes, the path contains
+    /// either the old or the rebuilt table, never a partial one. This recovers from heavy fragmentation and rewrites
+    /// the table in the current file format.
+    ///
+    /// Pending changes are flushed first. The index keeps at least the capacity configured via the options.
+    ///
+    /// ```
+    /// use rust_persist::Table;
+    ///
+    /// let mut table = Table::create("example_rebuild.tbl").unwrap();
+    /// table.set("key1".as_bytes(), "value1".as_bytes()).unwrap();
+    /// table.rebuild().unwrap();
+    /// assert_eq!(table.get("key1".as_bytes()), Some("value1".as_bytes()));
+    /// ```
+    pub fn rebuild(&mut self) -> Result<(), Error> {
+        self.check_writable()?;
+        let path = self.path.clone().ok_or(Error::InvalidOptions("table has no path"))?;
+        self.sync()?;
+        self.write_compacted(&path, self.options.index_capacity, |_| Redaction::Keep)?;
+        // Shared readers notice the new generation of the replaced file and switch to the rebuilt one
+        drop(self.readers.take());
+        let mut rebuilt = self.options.clone().open(&path)?;
+        rebuilt.clock = self.clock.clone();
+        // The old table only refers to the replaced file from now on and is closed
+        drop(std::mem::replace(self, rebuilt));
+        Ok(())
+    }
+
+    /// Writes a compacted copy of the table with at least the given index capacity via a temporary file
+    fn write_compacted<F: FnMut(&Entry<'_>) -> Redaction>(
+        &self, path: &Path, min_index_capacity: usize, mut filter: F,
+    ) -> Result<(), Error> {
+        let tmp = sibling_path(path, ".tmp");
+        let mut entries = Vec::with_capacity(self.len());
+        let mut data_size = 0u64;
+        for pinned in &[true, false] {
+            for block in self.mem.get_used() {
+                // The stored hash is reused, as the keys of hash key tables cannot be hashed
+                match self.index.get_block(block.hash, block.start) {
+                    Some(data) if (data.flags & FLAG_PINNED > 0) == *pinned => {
+                        let entry = self.entry_from_index_data(data);
+                        if let Some(value) = redact(&mut filter, &entry) {
+                            data_size += cmp::max(self.block_size(entry.key, &value)?, 1) as u64;
+                            entries.push((block.hash, entry.key, value, data.flags));
+                        }
+                    }
+                    _ => (),
+                }
+            }
+        }
+        let defaults = TableOptions::default();
+        let index_capacity = resize::index_capacity_for(min_index_capacity, entries.len(), defaults.max_usage);
+        let mut snapshot = defaults
+            .clone()
+            .index_capacity(index_capacity)
+            .data_size(data_size)
+            .checksums(self.options.checksums)
+            .create(&tmp)?;
+        snapshot.options = defaults.checksums(self.options.checksums);
+        snapshot.header.created = self.header.created;
+        snapshot.header.set_hash_keys(self.header.has_hash_keys());
+        for (hash, key, value, flags) in &entries {
+            snapshot.insert_entry_hashed(*hash, Entry { key, value, flags: *flags })?;
+        }
+        debug_assert!(snapshot.is_valid(), "Invalid after backup");
+        snapshot.flush()?;
+        if let Some(fd) = &snapshot.fd {
+            fd.sync_all().map_err(Error::Io)?;
+        }
+        snapshot.close();
+        fs::rename(&tmp, path).map_err(Error::Io)?;
+        sync_parent(path)
+    }
+
+    /// Writes a full backup of the table to the given path and starts a new backup chain.
+    ///
+    /// The backup is a snapshot as written by [`Table::backup_to`], its manifest is written to `<path>.manifest`
+    /// and returned. Subsequent backups can be written via [`Table::backup_incremental`].
+    ///
+    /// Tables created via [`HashKeyTable`](crate::HashKeyTable) are not supported, use [`Table::backup_to`] instead.
+    pub fn backup_full<P: AsRef<Path>>(&self, path: P) -> Result<BackupManifest, Error> {
+        self.check_byte_keys()?;
+        let path = path.as_ref();
+        self.backup_to(path)?;
+        let entries = self.iter().map(|entry| (entry.key.to_vec(), checksum(entry.value))).collect();
+        let manifest = BackupManifest { files: vec![path.to_path_buf()], entries };
+        manifest.save(&manifest_path(path))?;
+        Ok(manifest)
+    }
+
+    /// Writes all changes since the backup described by the given manifest to the given path.
+    ///
+    /// The changed and deleted entries are determined by comparing all entries against the hashes in the base
+    /// manifest, so only the changes are written but all entries are read. The manifest of the extended backup chain
+    /// is written to `<path>.manifest` and returned, it can be used to restore the table via
+    /// [`BackupManifest::restore`] or as base for the next increment.
+    pub fn backup_incremental<P: AsRef<Path>, Q: AsRef<Path>>(
+        &self, base_manifest: P, path: Q,
+    ) -> Result<BackupManifest, Error> {
+        self.check_byte_keys()?;
+        let path = path.as_ref();
+        let base = BackupManifest::load(base_manifest)?;
+        let mut entries = HashMap::with_capacity(self.len());
+        let mut ops = Vec::new();
+        for entry in self.iter() {
+            let hash = checksum(entry.value);
+            if base.entries.get(entry.key) != Some(&hash) {
+                ops.push((entry.key, Some((entry.value, entry.flags))));
+            }
+            entries.insert(entry.key.to_vec(), hash);
+        }
+        for key in base.entries.keys() {
+            if !entries.contains_key(key) {
+                ops.push((key, None));
+            }
+        }
+        write_atomic(path, &encode_ops(ops.into_iter()))?;
+        let mut files = base.files;
+        files.push(path.to_path_buf());
+        let manifest = BackupManifest { files, entries };
+        manifest.save(&manifest_path(path))?;
+        Ok(manifest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backup_to() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let backup = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::options().checksums(true).create(file.path()).unwrap();
+        for i in 0u16..100 {
+            tbl.set(&i.to_ne_bytes(), &i.to_be_bytes()).unwrap();
+        }
+        for i in 0u16..50 {
+            tbl.delete(&i.to_ne_bytes()).unwrap();
+        }
+        assert!(tbl.pin_front(&99u16.to_ne_bytes()).unwrap());
+        tbl.backup_to(backup.path()).unwrap();
+        tbl.set(&1u16.to_ne_bytes(), &[]).unwrap();
+        tbl.delete(&60u16.to_ne_bytes()).unwrap();
+        let copy = Table::open(backup.path()).unwrap();
+        assert!(copy.is_valid());
+        assert_eq!(copy.len(), 50);
+        assert_eq!(copy.info().created, tbl.info().created);
+        assert!(copy.size() < tbl.size());
+        assert!(copy.verify_checksums().is_empty());
+        assert!(copy.get(&1u16.to_ne_bytes()).is_none());
+        assert_eq!(copy.get(&60u16.to_ne_bytes()), Some(&60u16.to_be_bytes()[..]));
+        let first = copy.iter_by_position().next().unwrap();
+        assert_eq!(first.key, 99u16.to_ne_bytes());
+        assert_eq!(first.flags & FLAG_PINNED, FLAG_PINNED);
+        assert!(!sibling_path(backup.path(), ".tmp").exists());
+    }
+
+    #[test]
+    fn test_backup_redacted_to() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let backup = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::options().checksums(true).create(file.path()).unwrap();
+        for i in 0u16..100 {
+            tbl.set(&i.to_ne_bytes(), &i.to_be_bytes()).unwrap();
+        }
+        assert!(tbl.pin_front(&98u16.to_ne_bytes()).unwrap());
+        tbl.backup_redacted_to(backup.path(), |entry| match entry.key[0] % 3 {
+            0 => Redaction::Drop,
+            1 => Redaction::Replace(vec![0; 1000]),
+            _ => Redaction::Keep,
+        })
+        .unwrap();
+        let copy = Table::open(backup.path()).unwrap();
+        assert!(copy.is_valid());
+        assert!(copy.verify_checksums().is_empty());
+        for entry in tbl.iter() {
+            match entry.key[0] % 3 {
+                0 => assert!(copy.get(entry.key).is_none()),
+                1 => assert_eq!(copy.get(entry.key), Some(&[0; 1000][..])),
+                _ => assert_eq!(copy.get(entry.key), Some(entry.value)),
+            }
+        }
+        assert_eq!(copy.iter_by_position().next().unwrap().key, 98u16.to_ne_bytes());
+        assert_eq!(tbl.len(), 100);
+    }
+
+    #[test]
+    fn test_backup_to_writer() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let backup = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::options().checksums(true).create(file.path()).unwrap();
+        for i in 0u16..100 {
+            tbl.set(&i.to_ne_bytes(), &i.to_be_bytes()).unwrap();
+        }
+        for i in 0u16..50 {
+            tbl.delete(&i.to_ne_bytes()).unwrap();
+        }
+        tbl.set(&[], &[]).unwrap();
+        assert!(tbl.pin_front(&99u16.to_ne_bytes()).unwrap());
+        let mut stream = Vec::new();
+        assert_eq!(tbl.backup_to_writer(&mut stream).unwrap(), stream.len() as u64);
+        // Apart from the header, the stream is identical to a snapshot written to a file
+        tbl.backup_to(backup.path()).unwrap();
+        let header_size = std::mem::size_of::<crate::table::Header>();
+        assert_eq!(stream[header_size..], fs::read(backup.path()).unwrap()[header_size..]);
+        fs::write(backup.path(), &stream).unwrap();
+        let copy = Table::open(backup.path()).unwrap();
+        assert!(copy.is_valid());
+        assert_eq!(copy.len(), 51);
+        assert_eq!(copy.info().created, tbl.info().created);
+        assert!(copy.verify_checksums().is_empty());
+        assert!(copy.diff(&tbl).next().is_none());
+        assert_eq!(copy.iter_by_position().next().unwrap().key, 99u16.to_ne_bytes());
+    }
+
+    #[test]
+    fn test_restore_from_reader() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let restored = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::options().checksums(true).create(file.path()).unwrap();
+        for i in 0u16..100 {
+            tbl.set(&i.to_ne_bytes(), &i.to_be_bytes()).unwrap();
+        }
+        tbl.set(&[], &[]).unwrap();
+        let mut stream = Vec::new();
+        tbl.backup_to_writer(&mut stream).unwrap();
+        let copy = Table::restore_from_reader(restored.path(), &stream[..]).unwrap();
+        assert!(copy.is_valid());
+        assert!(copy.diff(&tbl).next().is_none());
+        copy.close();
+        // A table file with free space can be restored as well
+        tbl.delete(&5u16.to_ne_bytes()).unwrap();
+        tbl.flush().unwrap();
+        let copy = Table::restore_from_reader(restored.path(), File::open(file.path()).unwrap()).unwrap();
+        assert!(copy.diff(&tbl).next().is_none());
+        copy.close();
+        let last = stream.len() - 5;
+        stream[last] ^= 1;
+        assert!(matches!(Table::restore_from_reader(restored.path(), &stream[..]), Err(Error::ChecksumMismatch)));
+        assert!(!sibling_path(restored.path(), ".tmp").exists());
+        assert!(matches!(Table::restore_from_reader(restored.path(), &stream[..100]), Err(Error::Io(_))));
+        assert!(matches!(Table::restore_from_reader(restored.path(), &[0; 100][..]), Err(Error::WrongHeader)));
+    }
+
+    #[test]
+    fn test_clone_to() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let copy_file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::options().checksums(true).create(file.path()).unwrap();
+        for i in 0u16..100 {
+            tbl.set(&i.to_ne_bytes(), &i.to_be_bytes()).unwrap();
+        }
+        let mut copy = tbl.clone_to(copy_file.path()).unwrap();
+        assert!(copy.diff(&tbl).next().is_none());
+        copy.set(&1u16.to_ne_bytes(), &[]).unwrap();
+        tbl.delete(&2u16.to_ne_bytes()).unwrap();
+        assert_eq!(tbl.get(&1u16.to_ne_bytes()), Some(&1u16.to_be_bytes()[..]));
+        assert_eq!(copy.get(&2u16.to_ne_bytes()), Some(&2u16.to_be_bytes()[..]));
+        assert!(copy.get_entry(&3u16.to_ne_bytes()).unwrap().flags & crate::FLAG_CHECKSUM != 0);
+        assert!(copy.verify_checksums().is_empty());
+        copy.close();
+        tbl.close();
+        let ro = Table::open_read_only(file.path()).unwrap();
+        let copy = ro.clone_to(copy_file.path()).unwrap();
+        assert!(!copy.is_read_only());
+    }
+
+    #[test]
+    fn test_rebuild() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::options().wal(true).create(file.path()).unwrap();
+        for i in 0u16..1000 {
+            tbl.set(&i.to_ne_bytes(), &[i as u8; 100]).unwrap();
+        }
+        for i in 0u16..900 {
+            tbl.delete(&i.to_ne_bytes()).unwrap();
+        }
+        let size = tbl.size();
+        tbl.rebuild().unwrap();
+        assert!(tbl.size() < size);
+        assert_eq!(tbl.len(), 100);
+        assert_eq!(tbl.stats().data_free, 0);
+        assert_eq!(tbl.get(&950u16.to_ne_bytes()), Some(&[950u16 as u8; 100][..]));
+        tbl.set(&1u16.to_ne_bytes(), &[1]).unwrap();
+        tbl.close();
+        let tbl = Table::open(file.path()).unwrap();
+        assert_eq!(tbl.len(), 101);
+        assert!(!tbl.info().unclean_shutdown);
+        assert!(!sibling_path(file.path(), ".tmp").exists());
+        tbl.close();
+        let mut ro = Table::options().read_only(true).open(file.path()).unwrap();
+        assert!(matches!(ro.rebuild(), Err(Error::ReadOnly)));
+    }
+
+    #[test]
+    fn test_vacuum_into() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let copy_file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::create(file.path()).unwrap();
+        for i in 0u16..1000 {
+            tbl.set(&i.to_ne_bytes(), &[0; 100]).unwrap();
+        }
+        for i in 0u16..990 {
+            tbl.delete(&i.to_ne_bytes()).unwrap();
+        }
+        let size = tbl.size();
+        tbl.vacuum_into(copy_file.path(), None).unwrap();
+        assert_eq!(tbl.size(), size);
+        let copy = Table::open(copy_file.path()).unwrap();
+        assert_eq!(copy.index.capacity(), TableOptions::default().index_capacity);
+        assert_eq!(copy.stats().data_free, 0);
+        assert_eq!(copy.len(), 10);
+        copy.close();
+        tbl.vacuum_into(copy_file.path(), Some(1000)).unwrap();
+        let copy = Table::open(copy_file.path()).unwrap();
+        assert_eq!(copy.index.capacity(), 1024);
+        assert!(copy.diff(&tbl).next().is_none());
+    }
+
+    #[test]
+    fn test_backup_incremental() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::create(file.path()).unwrap();
+        for i in 0u16..100 {
+            tbl.set(&i.to_ne_bytes(), &[0; 100]).unwrap();
+        }
+        tbl.backup_full(dir.path().join("full")).unwrap();
+        tbl.set(&1u16.to_ne_bytes(), &[1; 100]).unwrap();
+        tbl.delete(&2u16.to_ne_bytes()).unwrap();
+        tbl.backup_incremental(dir.path().join("full.manifest"), dir.path().join("inc1")).unwrap();
+        assert!(fs::metadata(dir.path().join("inc1")).unwrap().len() < 200);
+        tbl.set(&2u16.to_ne_bytes(), &[2; 100]).unwrap();
+        tbl.set(&100u16.to_ne_bytes(), &[]).unwrap();
+        let manifest = tbl.backup_incremental(dir.path().join("inc1.manifest"), dir.path().join("inc2")).unwrap();
+        assert_eq!(manifest.files().len(), 3);
+        tbl.delete(&3u16.to_ne_bytes()).unwrap();
+        let manifest = BackupManifest::load(dir.path().join("inc2.manifest")).unwrap();
+        let restored = manifest.restore(dir.path().join("restored")).unwrap();
+        assert!(restored.is_valid());
+        assert_eq!(restored.len(), 101);
+        assert_eq!(restored.get(&1u16.to_ne_bytes()), Some(&[1; 100][..]));
+        assert_eq!(restored.get(&2u16.to_ne_bytes()), Some(&[2; 100][..]));
+        assert_eq!(restored.get(&3u16.to_ne_bytes()), Some(&[0; 100][..]));
+        assert_eq!(restored.get(&100u16.to_ne_bytes()), Some(&[][..]));
+        fs::write(dir.path().join("inc2.manifest"), b"rust-persist-m1\n").unwrap();
+        assert!(matches!(BackupManifest::load(dir.path().join("inc2.manifest")), Err(Error::Corrupt(_))));
+    }
+}
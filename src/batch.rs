@@ -0,0 +1,411 @@
+use std::{
+    ffi::OsString,
+    fs::{self, File},
+    hash::Hasher,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use siphasher::sip::SipHasher13;
+
+use crate::{
+    table::{entry_size, hash_key},
+    Entry, Error, Table,
+};
+
+/// Header of records without entry flags, which are still read
+const JOURNAL_HEADER_V1: [u8; 16] = *b"rust-persist-j1\n";
+const JOURNAL_HEADER: [u8; 16] = *b"rust-persist-j2\n";
+const DELETE_MARKER: u32 = u32::MAX;
+
+/// A change of a record: the key and either the value and the entry flags to store or `None` to delete the entry
+pub(crate) type Op<'a> = (&'a [u8], Option<(&'a [u8], u16)>);
+
+/// An owned [`Op`]
+type OwnedOp = (Vec<u8>, Option<(Vec<u8>, u16)>);
+
+/// A set of changes that is applied to a table atomically via [`Table::apply`].
+///
+/// ```
+/// use rust_persist::{Table, WriteBatch};
+///
+/// let mut table = Table::create("example_batch.tbl").unwrap();
+/// let mut batch = WriteBatch::new();
+/// batch.set("key1".as_bytes(), "value1".as_bytes());
+/// batch.delete("key2".as_bytes());
+/// table.apply(batch).unwrap();
+/// assert_eq!(table.get("key1".as_bytes()), Some("value1".as_bytes()));
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct WriteBatch {
+    ops: Vec<OwnedOp>,
+}
+
+impl WriteBatch {
+    /// Creates a new empty batch
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stages storing the given key/value pair.
+    #[inline]
+    pub fn set(&mut self, key: &[u8], value: &[u8]) {
+        self.ops.push((key.to_vec(), Some((value.to_vec(), 0))))
+    }
+
+    /// Stages deleting the entry with the given key.
+    #[inline]
+    pub fn delete(&mut self, key: &[u8]) {
+        self.ops.push((key.to_vec(), None))
+    }
+
+    /// Returns the number of staged changes
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    /// Returns whether no changes are staged
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    /// Removes all staged changes
+    #[inline]
+    pub fn clear(&mut self) {
+        self.ops.clear()
+    }
+
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        encode_ops(self.ops.iter().map(|(key, value)| {
+            (&key[..], value.as_ref().map(|(value, flags)| (&value[..], *flags)))
+        }))
+    }
+
+    /// Decodes a single record from the front of `data` and advances it.
+    ///
+    /// Returns `None` if the record is incomplete or damaged.
+    pub(crate) fn decode(data: &mut &[u8]) -> Option<Self> {
+        let content = *data;
+        let with_flags = match read_bytes(data, JOURNAL_HEADER.len())? {
+            header if header == JOURNAL_HEADER => true,
+            header if header == JOURNAL_HEADER_V1 => false,
+            _ => return None,
+        };
+        let count = read_u64(data)?;
+        let mut ops = Vec::new();
+        for _ in 0..count {
+            let key_len = read_u32(data)? as usize;
+            let value_len = read_u32(data)?;
+            let flags = if with_flags { read_u16(data)? } else { 0 };
+            let key = read_bytes(data, key_len)?;
+            let value = match value_len {
+                DELETE_MARKER => None,
+                _ => Some((read_bytes(data, value_len as usize)?, flags)),
+            };
+            ops.push((key, value));
+        }
+        let content = &content[..content.len() - data.len()];
+        if read_u64(data)? != checksum(content) {
+            return None;
+        }
+        Some(Self { ops })
+    }
+}
+
+/// Encodes the given operations as one record that can be decoded via [`WriteBatch::decode`]
+pub(crate) fn encode_ops<'a, I: ExactSizeIterator<Item = Op<'a>>>(ops: I) -> Vec<u8> {
+    let mut buf = Vec::new();
+    buf.extend_from_slice(&JOURNAL_HEADER);
+    buf.extend_from_slice(&(ops.len() as u64).to_le_bytes());
+    for (key, value) in ops {
+        buf.extend_from_slice(&(key.len() as u32).to_le_bytes());
+        let (value_len, flags) = match value {
+            Some((value, flags)) => (value.len() as u32, flags),
+            None => (DELETE_MARKER, 0),
+        };
+        buf.extend_from_slice(&value_len.to_le_bytes());
+        buf.extend_from_slice(&flags.to_le_bytes());
+        buf.extend_from_slice(key);
+        if let Some((value, _)) = value {
+            buf.extend_from_slice(value);
+        }
+    }
+    let checksum = checksum(&buf);
+    buf.extend_from_slice(&checksum.to_le_bytes());
+    buf
+}
+
+/// Checks that the entry can be stored in the table and in a record, whose value lengths are limited to 32 bits
+pub(crate) fn check_record_size(key: &[u8], value: &[u8]) -> Result<(), Error> {
+    entry_size(key, value)?;
+    if value.len() as u64 >= DELETE_MARKER as u64 {
+        return Err(Error::TooLarge);
+    }
+    Ok(())
+}
+
+/// Returns whether the error rejects a change independently of the state of the file system, so that applying the
+/// change again fails the same way
+pub(crate) fn is_rejected(err: &Error) -> bool {
+    matches!(err, Error::InvalidOptions(_) | Error::TooLarge | Error::DataLimit { .. })
+}
+
+pub(crate) fn checksum(data: &[u8]) -> u64 {
+    let mut hasher = SipHasher13::new();
+    hasher.write(data);
+    hasher.finish()
+}
+
+pub(crate) fn read_bytes(data: &mut &[u8], len: usize) -> Option<Vec<u8>> {
+    if data.len() < len {
+        return None;
+    }
+    let (bytes, rest) = data.split_at(len);
+    *data = rest;
+    Some(bytes.to_vec())
+}
+
+pub(crate) fn read_u16(data: &mut &[u8]) -> Option<u16> {
+    let mut buf = [0; 2];
+    buf.copy_from_slice(&read_bytes(data, 2)?);
+    Some(u16::from_le_bytes(buf))
+}
+
+pub(crate) fn read_u32(data: &mut &[u8]) -> Option<u32> {
+    let mut buf = [0; 4];
+    buf.copy_from_slice(&read_bytes(data, 4)?);
+    Some(u32::from_le_bytes(buf))
+}
+
+pub(crate) fn read_u64(data: &mut &[u8]) -> Option<u64> {
+    let mut buf = [0; 8];
+    buf.copy_from_slice(&read_bytes(data, 8)?);
+    Some(u64::from_le_bytes(buf))
+}
+
+/// Returns the path of the file with the given extension that belongs to the table at the given path
+pub(crate) fn sibling_path(path: &Path, extension: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(extension);
+    PathBuf::from(name)
+}
+
+/// Returns the path of the journal that belongs to the table at the given path
+#[inline]
+pub(crate) fn journal_path(path: &Path) -> PathBuf {
+    sibling_path(path, ".journal")
+}
+
+impl Table {
+    /// Applies all changes of the batch to the table.
+    ///
+    /// Before the table is modified, the batch is written to a journal file next to the table (`<path>.journal`)
+    /// and synced to disk. After all changes have been applied, the table is flushed and the journal is removed.
+    /// If the process crashes in between, the journal is replayed when the table is opened the next time. So either
+    /// all or none of the changes become durable.
+    ///
+    /// In write-ahead log mode (see [`TableOptions::wal`](crate::TableOptions::wal)), the batch is written to the
+    /// log as a single record instead.
+    ///
+    /// If any key or value is too large (also for [`TableOptions::max_data_size`](crate::TableOptions::max_data_size)),
+    /// [`Error::TooLarge`] is returned before the table is modified, the same holds for [`Error::InvalidOptions`] if a
+    /// key is used by a namespace. If applying the changes fails otherwise, the changes applied so far are undone
+    /// and the error is returned. Only if undoing them fails as well, some of the changes might already be visible.
+    /// Reopening the table will then replay the complete batch.
+    pub fn apply(&mut self, batch: WriteBatch) -> Result<(), Error> {
+        self.check_writable()?;
+        // Changes that would be rejected are detected before the batch is recorded, as replaying it would fail again
+        for (key, value) in &batch.ops {
+            check_record_size(key, value.as_ref().map(|(value, _)| &value[..]).unwrap_or_default())?;
+            self.check_plain_key(hash_key(key), key)?;
+            if let (Some((value, _)), Some(limit)) = (value, self.options.max_data_size) {
+                if self.block_size(key, value)? > limit {
+                    return Err(Error::TooLarge);
+                }
+            }
+        }
+        if batch.is_empty() {
+            return Ok(());
+        }
+        if self.wal.is_some() {
+            self.log_batch(&batch)?;
+            let result = self.without_wal(|tbl| tbl.apply_ops(&batch))?;
+            if result.is_err() {
+                // The batch has been undone, so its record must not be replayed
+                self.sync()?;
+            }
+            return result;
+        }
+        let journal = self.path.as_deref().map(journal_path);
+        if let Some(journal) = &journal {
+            let mut fd = File::create(journal).map_err(Error::Io)?;
+            fd.write_all(&batch.encode()).map_err(Error::Io)?;
+            fd.sync_all().map_err(Error::Io)?;
+        }
+        let result = self.apply_ops(&batch)?;
+        if let Some(journal) = journal {
+            self.sync()?;
+            fs::remove_file(journal).map_err(Error::Io)?;
+        }
+        result
+    }
+
+    /// Applies all or none of the changes of the batch.
+    ///
+    /// The changes are applied with their entry flags and also to entries of namespaces, as they have been checked
+    /// when they were recorded. If a change fails, the changes applied so far are undone and the error is returned as
+    /// `Ok(Err(_))`. If undoing them fails as well, that error is returned and the batch is applied partially.
+    pub(crate) fn apply_ops(&mut self, batch: &WriteBatch) -> Result<Result<(), Error>, Error> {
+        if let Err(err) = self.reserve_index(batch.ops.iter().filter(|(_, value)| value.is_some()).count()) {
+            return Ok(Err(err));
+        }
+        let old: Vec<_> =
+            batch.ops.iter().map(|(key, _)| self.get_raw_entry(key).map(|e| (e.value.to_vec(), e.flags))).collect();
+        for (i, (key, value)) in batch.ops.iter().enumerate() {
+            if let Err(err) = self.apply_op(key, value.as_ref()) {
+                // The failed change is undone as well, as it might have been made before the error occurred
+                for ((key, _), old) in batch.ops[..=i].iter().zip(&old).rev() {
+                    self.apply_op(key, old.as_ref())?;
+                }
+                return Ok(Err(err));
+            }
+        }
+        Ok(Ok(()))
+    }
+
+    fn apply_op(&mut self, key: &[u8], value: Option<&(Vec<u8>, u16)>) -> Result<(), Error> {
+        match value {
+            Some((value, flags)) => self.set_raw_entry(Entry { key, value, flags: *flags }).map(|_| ()),
+            None => self.delete_raw_entry(key).map(|_| ()),
+        }
+    }
+
+    /// Replays a complete journal left by a crash during [`Table::apply`] and removes it.
+    pub(crate) fn replay_journal(&mut self) -> Result<(), Error> {
+        let journal = match self.path.as_deref() {
+            Some(path) => journal_path(path),
+            None => return Ok(()),
+        };
+        let data = match fs::read(&journal) {
+            Ok(data) => data,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(Error::Io(err)),
+        };
+        // An incomplete journal means that the table has not been touched yet
+        if let Some(batch) = WriteBatch::decode(&mut &data[..]) {
+            match self.apply_ops(&batch)? {
+                // A batch that is rejected has been undone, it would not have been applied by `Table::apply` either
+                Err(err) if !is_rejected(&err) => return Err(err),
+                _ => self.sync()?,
+            }
+        }
+        fs::remove_file(journal).map_err(Error::Io)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::create(file.path()).unwrap();
+        tbl.set("key1".as_bytes(), "value1".as_bytes()).unwrap();
+        let mut batch = WriteBatch::new();
+        batch.set("key2".as_bytes(), "value2".as_bytes());
+        batch.delete("key1".as_bytes());
+        batch.set(&[0; u16::MAX as usize + 1], &[]);
+        assert!(matches!(tbl.apply(batch.clone()), Err(Error::TooLarge)));
+        assert_eq!(tbl.len(), 1);
+        batch.ops.pop();
+        tbl.apply(batch).unwrap();
+        assert!(tbl.get("key1".as_bytes()).is_none());
+        assert_eq!(tbl.get("key2".as_bytes()), Some("value2".as_bytes()));
+        assert!(!journal_path(file.path()).exists());
+    }
+
+    #[test]
+    fn test_apply_failed() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::create(file.path()).unwrap();
+        tbl.set("key1".as_bytes(), "old".as_bytes()).unwrap();
+        tbl.namespace("ns").unwrap().set("key".as_bytes(), &[1]).unwrap();
+        // The scoped key of the entry of the namespace: the length of the name, the name and the key
+        let scoped = b"\x02nskey";
+        // Rejected changes are detected before anything is recorded
+        let mut batch = WriteBatch::new();
+        batch.set("key1".as_bytes(), "new".as_bytes());
+        batch.delete(scoped);
+        assert!(matches!(tbl.apply(batch.clone()), Err(Error::InvalidOptions(_))));
+        assert!(!journal_path(file.path()).exists());
+        tbl.close();
+        // A journal with a batch that is rejected does not keep the table from being opened
+        let mut batch = WriteBatch::new();
+        batch.set("key1".as_bytes(), "new".as_bytes());
+        batch.set(&[0; u16::MAX as usize + 1], &[]);
+        fs::write(journal_path(file.path()), batch.encode()).unwrap();
+        let tbl = Table::open(file.path()).unwrap();
+        assert_eq!(tbl.get("key1".as_bytes()), Some("old".as_bytes()));
+        assert!(tbl.get_raw_entry(scoped).is_some());
+        assert!(!journal_path(file.path()).exists());
+        tbl.close();
+        let options = Table::options().max_data_size(Some(1000));
+        let mut tbl = options.clone().open(file.path()).unwrap();
+        tbl.set("pinned".as_bytes(), &[0; 700]).unwrap();
+        tbl.pin_front("pinned".as_bytes()).unwrap();
+        let mut batch = WriteBatch::new();
+        batch.set("key1".as_bytes(), "new".as_bytes());
+        batch.set("key2".as_bytes(), &[0; 1000]);
+        assert!(matches!(tbl.apply(batch), Err(Error::TooLarge)));
+        // The pinned entry cannot be evicted, so the second change fails after the first one has been applied
+        let mut batch = WriteBatch::new();
+        batch.set("key1".as_bytes(), "new".as_bytes());
+        batch.set("key2".as_bytes(), &[0; 500]);
+        assert!(matches!(tbl.apply(batch), Err(Error::DataLimit { .. })));
+        assert_eq!(tbl.get("key1".as_bytes()), Some("old".as_bytes()));
+        assert!(!tbl.contains("key2".as_bytes()));
+        assert!(!journal_path(file.path()).exists());
+        tbl.close();
+        // In write-ahead log mode, the record of the undone batch is not replayed
+        let mut tbl = options.clone().wal(true).open(file.path()).unwrap();
+        let mut batch = WriteBatch::new();
+        batch.set("key1".as_bytes(), "new".as_bytes());
+        batch.set("key2".as_bytes(), &[0; 500]);
+        assert!(matches!(tbl.apply(batch), Err(Error::DataLimit { .. })));
+        assert_eq!(fs::metadata(crate::wal::wal_path(file.path())).unwrap().len(), 0);
+        tbl.close();
+        let tbl = options.wal(true).open(file.path()).unwrap();
+        assert_eq!(tbl.get("key1".as_bytes()), Some("old".as_bytes()));
+        assert!(!tbl.contains("key2".as_bytes()));
+        assert!(tbl.is_valid());
+    }
+
+    #[test]
+    fn test_replay_journal() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::create(file.path()).unwrap();
+        tbl.set("key1".as_bytes(), "value1".as_bytes()).unwrap();
+        tbl.close();
+        let mut batch = WriteBatch::new();
+        batch.set("key2".as_bytes(), "value2".as_bytes());
+        batch.delete("key1".as_bytes());
+        let journal = batch.encode();
+        // Crash before the journal was completely written
+        fs::write(journal_path(file.path()), &journal[..journal.len() - 1]).unwrap();
+        let tbl = Table::open(file.path()).unwrap();
+        assert_eq!(tbl.get("key1".as_bytes()), Some("value1".as_bytes()));
+        assert!(tbl.get("key2".as_bytes()).is_none());
+        assert!(!journal_path(file.path()).exists());
+        tbl.close();
+        // Crash after the journal was written
+        fs::write(journal_path(file.path()), &journal).unwrap();
+        let tbl = Table::open(file.path()).unwrap();
+        assert!(tbl.get("key1".as_bytes()).is_none());
+        assert_eq!(tbl.get("key2".as_bytes()), Some("value2".as_bytes()));
+        assert!(!journal_path(file.path()).exists());
+    }
+}
@@ -0,0 +1,378 @@
+use std::{
+    env::args,
+    fs::File,
+    io::{stdin, stdout, BufWriter, Write},
+    path::Path,
+    process::exit,
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use rust_persist::{CheckLevel, Entry, Error, LockMode, Table};
+#[cfg(feature = "msgpack")]
+use serde::de::{Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+
+fn usage() {
+    eprintln!("Usage: persist CMD PATH [ARGS]");
+    eprintln!();
+    eprintln!("Commands:");
+    eprintln!(" - dump PATH [--hex]:    Print all entries as escaped (or hex encoded) key/value pairs");
+    eprintln!(" - stats PATH:           Print table information and statistics");
+    eprintln!(" - verify PATH [--full]: Check the table for inconsistencies (including data with --full)");
+    eprintln!(" - compact PATH:         Defragment the table to reclaim free space");
+    eprintln!(" - export PATH [FILE]:   Write all entries in the streaming export format to FILE or stdout");
+    eprintln!(" - import PATH [FILE]:   Read entries in the streaming export format from FILE or stdin");
+    eprintln!(" - watch PATH [SECONDS]: Print load factor, fragmentation and entry changes every SECONDS (default 1)");
+    eprintln!("                         Samples wait while another process has the table open for writing");
+    eprintln!(" - query PATH [PRED...] [--hex] [--count]:");
+    eprintln!("                         Print (or count) the entries matching all predicates:");
+    eprintln!("                           prefix=TEXT       key starts with TEXT (escaped like the dump output)");
+    eprintln!("                           min-size=N        value has at least N bytes");
+    eprintln!("                           max-size=N        value has at most N bytes");
+    eprintln!("                           flags=MASK        entry has all flags in MASK (decimal or 0x hex)");
+    if cfg!(feature = "msgpack") {
+        eprintln!("                           field.PATH=VALUE  field of the MessagePack value equals VALUE, PATH");
+        eprintln!("                                             consists of map keys and array indices separated by .");
+    }
+}
+
+fn format_bytes(data: &[u8], hex: bool) -> String {
+    if hex {
+        data.iter().map(|b| format!("{:02x}", b)).collect()
+    } else {
+        data.iter().flat_map(|&b| std::ascii::escape_default(b)).map(char::from).collect()
+    }
+}
+
+/// Reverses the escaping of [`format_bytes`]
+fn unescape(text: &str) -> Option<Vec<u8>> {
+    let mut bytes = text.bytes();
+    let mut data = Vec::with_capacity(text.len());
+    while let Some(byte) = bytes.next() {
+        if byte != b'\\' {
+            data.push(byte);
+            continue;
+        }
+        data.push(match bytes.next()? {
+            b't' => b'\t',
+            b'n' => b'\n',
+            b'r' => b'\r',
+            b'x' => u8::from_str_radix(std::str::from_utf8(&[bytes.next()?, bytes.next()?]).ok()?, 16).ok()?,
+            byte => byte,
+        });
+    }
+    Some(data)
+}
+
+/// A decoded MessagePack value, used to compare fields in queries
+#[cfg(feature = "msgpack")]
+enum Value {
+    Nil,
+    Bool(bool),
+    Int(i128),
+    Float(f64),
+    Str(String),
+    Bytes(Vec<u8>),
+    Array(Vec<Value>),
+    Map(Vec<(Value, Value)>),
+}
+
+#[cfg(feature = "msgpack")]
+impl Value {
+    /// Returns the textual form of scalar values
+    fn to_text(&self) -> Option<String> {
+        match self {
+            Value::Nil => Some("nil".to_string()),
+            Value::Bool(val) => Some(val.to_string()),
+            Value::Int(val) => Some(val.to_string()),
+            Value::Float(val) => Some(val.to_string()),
+            Value::Str(val) => Some(val.clone()),
+            Value::Bytes(val) => Some(format_bytes(val, false)),
+            Value::Array(_) | Value::Map(_) => None,
+        }
+    }
+
+    /// Returns the nested value at the given path of map keys and array indices
+    fn field(&self, path: &[String]) -> Option<&Value> {
+        let mut value = self;
+        for name in path {
+            value = match value {
+                Value::Map(entries) => &entries.iter().find(|(key, _)| key.to_text().as_ref() == Some(name))?.1,
+                Value::Array(items) => items.get(name.parse::<usize>().ok()?)?,
+                _ => return None,
+            };
+        }
+        Some(value)
+    }
+}
+
+#[cfg(feature = "msgpack")]
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(ValueVisitor)
+    }
+}
+
+#[cfg(feature = "msgpack")]
+struct ValueVisitor;
+
+#[cfg(feature = "msgpack")]
+impl<'de> Visitor<'de> for ValueVisitor {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("a MessagePack value")
+    }
+
+    fn visit_unit<E>(self) -> Result<Value, E> {
+        Ok(Value::Nil)
+    }
+
+    fn visit_none<E>(self) -> Result<Value, E> {
+        Ok(Value::Nil)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+        Value::deserialize(deserializer)
+    }
+
+    fn visit_bool<E>(self, val: bool) -> Result<Value, E> {
+        Ok(Value::Bool(val))
+    }
+
+    fn visit_i64<E>(self, val: i64) -> Result<Value, E> {
+        Ok(Value::Int(val.into()))
+    }
+
+    fn visit_u64<E>(self, val: u64) -> Result<Value, E> {
+        Ok(Value::Int(val.into()))
+    }
+
+    fn visit_f64<E>(self, val: f64) -> Result<Value, E> {
+        Ok(Value::Float(val))
+    }
+
+    fn visit_str<E>(self, val: &str) -> Result<Value, E> {
+        Ok(Value::Str(val.to_string()))
+    }
+
+    fn visit_bytes<E>(self, val: &[u8]) -> Result<Value, E> {
+        Ok(Value::Bytes(val.to_vec()))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        let mut items = Vec::new();
+        while let Some(item) = seq.next_element()? {
+            items.push(item);
+        }
+        Ok(Value::Array(items))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+        let mut entries = Vec::new();
+        while let Some(entry) = map.next_entry()? {
+            entries.push(entry);
+        }
+        Ok(Value::Map(entries))
+    }
+}
+
+/// A condition on entries given to the query command
+enum Predicate {
+    Prefix(Vec<u8>),
+    MinSize(usize),
+    MaxSize(usize),
+    Flags(u16),
+    #[cfg(feature = "msgpack")]
+    Field(Vec<String>, String),
+}
+
+impl Predicate {
+    fn parse(arg: &str) -> Option<Self> {
+        let (name, value) = arg.split_once('=')?;
+        match name {
+            "prefix" => unescape(value).map(Predicate::Prefix),
+            "min-size" => value.parse().ok().map(Predicate::MinSize),
+            "max-size" => value.parse().ok().map(Predicate::MaxSize),
+            "flags" => match value.strip_prefix("0x") {
+                Some(hex) => u16::from_str_radix(hex, 16).ok(),
+                None => value.parse().ok(),
+            }
+            .map(Predicate::Flags),
+            #[cfg(feature = "msgpack")]
+            _ => {
+                let path = name.strip_prefix("field.")?.split('.').map(str::to_string).collect();
+                Some(Predicate::Field(path, value.to_string()))
+            }
+            #[cfg(not(feature = "msgpack"))]
+            _ => None,
+        }
+    }
+
+    fn matches(&self, entry: &Entry) -> bool {
+        match self {
+            Predicate::Prefix(prefix) => entry.key.starts_with(prefix),
+            Predicate::MinSize(size) => entry.value.len() >= *size,
+            Predicate::MaxSize(size) => entry.value.len() <= *size,
+            Predicate::Flags(mask) => entry.flags & mask == *mask,
+            #[cfg(feature = "msgpack")]
+            Predicate::Field(path, expected) => match rust_persist::deserialize::<Value>(entry.value) {
+                Ok(value) => value.field(path).and_then(Value::to_text).as_ref() == Some(expected),
+                Err(_) => false,
+            },
+        }
+    }
+}
+
+fn cmd_dump(path: &Path, hex: bool) -> Result<(), Error> {
+    let table = Table::open_read_only(path)?;
+    let mut out = BufWriter::new(stdout());
+    for entry in table.iter() {
+        writeln!(out, "{}\t{}", format_bytes(entry.key, hex), format_bytes(entry.value, hex)).map_err(Error::Io)?;
+    }
+    out.flush().map_err(Error::Io)
+}
+
+fn cmd_stats(path: &Path) -> Result<(), Error> {
+    let table = Table::open_read_only(path)?;
+    println!("{:#?}", table.info());
+    println!("{:#?}", table.stats());
+    Ok(())
+}
+
+fn cmd_verify(path: &Path, full: bool) -> Result<bool, Error> {
+    let table = Table::open_read_only(path)?;
+    let report = table.verify(if full { CheckLevel::Full } else { CheckLevel::Quick });
+    for finding in &report.findings {
+        println!("{:?}", finding);
+    }
+    if report.is_ok() {
+        eprintln!("Table is consistent");
+    } else {
+        eprintln!("Found {} inconsistencies", report.findings.len());
+    }
+    Ok(report.is_ok())
+}
+
+fn cmd_compact(path: &Path) -> Result<(), Error> {
+    let mut table = Table::open(path)?;
+    let before = table.stats();
+    table.defragment()?;
+    let after = table.stats();
+    eprintln!("Table size: {} -> {} bytes", before.size, after.size);
+    Ok(())
+}
+
+fn cmd_export(path: &Path, file: Option<&str>) -> Result<(), Error> {
+    let table = Table::open_read_only(path)?;
+    let count = match file {
+        Some(file) => table.export_to(File::create(file).map_err(Error::Io)?)?,
+        None => table.export_to(stdout().lock())?,
+    };
+    eprintln!("Exported {} entries", count);
+    Ok(())
+}
+
+fn cmd_import(path: &Path, file: Option<&str>) -> Result<(), Error> {
+    let mut table = Table::open_or_create(path)?;
+    let count = match file {
+        Some(file) => table.import_from(File::open(file).map_err(Error::Io)?)?,
+        None => table.import_from(stdin().lock())?,
+    };
+    table.flush()?;
+    eprintln!("Imported {} entries", count);
+    Ok(())
+}
+
+fn cmd_query(path: &Path, predicates: &[Predicate], hex: bool, count_only: bool) -> Result<(), Error> {
+    let table = Table::open_read_only(path)?;
+    let mut out = BufWriter::new(stdout());
+    let mut count = 0;
+    for entry in table.iter().filter(|entry| predicates.iter().all(|pred| pred.matches(entry))) {
+        count += 1;
+        if !count_only {
+            writeln!(out, "{}\t{}", format_bytes(entry.key, hex), format_bytes(entry.value, hex)).map_err(Error::Io)?;
+        }
+    }
+    if count_only {
+        writeln!(out, "{}", count).map_err(Error::Io)?;
+    }
+    out.flush().map_err(Error::Io)
+}
+
+fn cmd_watch(path: &Path, interval: Duration) -> Result<(), Error> {
+    // The table is reopened with a shared lock for every sample and closed right after, so writers only have to wait
+    // while a sample is taken. Reading the mapping without a lock could crash if a writer shrinks the file meanwhile.
+    let options = Table::options().read_only(true).lock(LockMode::Wait);
+    let start = Instant::now();
+    let mut last_entries = None;
+    println!("{:>8} {:>12} {:>10} {:>6} {:>14} {:>6}", "time", "entries", "change", "load", "data size", "frag");
+    loop {
+        match options.clone().open(path) {
+            Ok(table) => {
+                let stats = table.quick_stats();
+                let change = last_entries.map_or(0, |last| stats.entries as i64 - last as i64);
+                last_entries = Some(stats.entries);
+                println!(
+                    "{:>7.1}s {:>12} {:>+10} {:>5.1}% {:>14} {:>5.1}%",
+                    start.elapsed().as_secs_f64(),
+                    stats.entries,
+                    change,
+                    stats.load_factor * 100.0,
+                    stats.data_size,
+                    stats.fragmentation * 100.0
+                );
+            }
+            Err(err) => eprintln!("{}", err),
+        }
+        sleep(interval);
+    }
+}
+
+pub fn main() -> Result<(), Error> {
+    let args: Vec<String> = args().skip(1).collect();
+    if args.len() < 2 {
+        usage();
+        exit(2);
+    }
+    let path = Path::new(&args[1]);
+    let extra = args.get(2).map(String::as_str);
+    match (&args[0] as &str, extra) {
+        ("dump", None) => cmd_dump(path, false),
+        ("dump", Some("--hex")) => cmd_dump(path, true),
+        ("stats", None) => cmd_stats(path),
+        ("verify", None) | ("verify", Some("--full")) => {
+            if !cmd_verify(path, extra.is_some())? {
+                exit(1);
+            }
+            Ok(())
+        }
+        ("compact", None) => cmd_compact(path),
+        ("export", file) => cmd_export(path, file),
+        ("import", file) => cmd_import(path, file),
+        ("query", _) => {
+            let (flags, predicates): (Vec<_>, Vec<_>) =
+                args[2..].iter().map(String::as_str).partition(|arg| arg.starts_with("--"));
+            match predicates.into_iter().map(Predicate::parse).collect::<Option<Vec<_>>>() {
+                Some(predicates) if flags.iter().all(|flag| ["--hex", "--count"].contains(flag)) => {
+                    cmd_query(path, &predicates, flags.contains(&"--hex"), flags.contains(&"--count"))
+                }
+                _ => {
+                    usage();
+                    exit(2);
+                }
+            }
+        }
+        ("watch", interval) => match interval.map_or(Some(1.0), |s| s.parse::<f64>().ok()) {
+            Some(secs) if secs > 0.0 && secs.is_finite() => cmd_watch(path, Duration::from_secs_f64(secs)),
+            _ => {
+                usage();
+                exit(2);
+            }
+        },
+        _ => {
+            usage();
+            exit(2);
+        }
+    }
+}
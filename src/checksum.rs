@@ -0,0 +1,82 @@
+use crate::{index::IndexEntryData, table::hash_key, table::match_key, Error, Table, FLAG_CHECKSUM};
+
+/// Size of the checksum at the end of data blocks with [`FLAG_CHECKSUM`]
+pub(crate) const CHECKSUM_SIZE: u32 = 4;
+
+#[inline]
+pub(crate) fn checksum(data: &[u8]) -> u32 {
+    crc32fast::hash(data)
+}
+
+/// Computes the same checksum as [`checksum`] incrementally
+pub(crate) type ChecksumHasher = crc32fast::Hasher;
+
+impl Table {
+    pub(crate) fn verify_block(&self, entry: &IndexEntryData) -> Result<(), Error> {
+        if entry.flags & FLAG_CHECKSUM == 0 {
+            return Ok(());
+        }
+        let data = self.get_data(entry.position(), entry.size());
+        let (data, stored) = data.split_at(data.len() - CHECKSUM_SIZE as usize);
+        let mut buf = [0; CHECKSUM_SIZE as usize];
+        buf.copy_from_slice(stored);
+        if checksum(data) != u32::from_le_bytes(buf) {
+            return Err(Error::ChecksumMismatch);
+        }
+        Ok(())
+    }
+
+    /// Retrieves the value associated with the given key and verifies its checksum.
+    ///
+    /// If the entry has been stored with a checksum (see [`TableOptions::checksums`](crate::TableOptions::checksums))
+    /// that does not match the data, [`Error::ChecksumMismatch`] is returned.
+    /// If no entry with the given key is stored in the table, `None` is returned.
+    pub fn get_checked(&self, key: &[u8]) -> Result<Option<&[u8]>, Error> {
+        let hash = hash_key(key);
+        match self.index.index_get(hash, |e| match_key(e, self.data, self.data_start, key)) {
+            Some(entry) => {
+                self.verify_block(&entry)?;
+                Ok(Some(self.entry_from_index_data(entry).value))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Verifies the checksums of all entries in the table and returns the keys of all entries that do not match.
+    pub fn verify_checksums(&self) -> Vec<Vec<u8>> {
+        let mut failed = Vec::new();
+        for entry in self.index.get_entries() {
+            if entry.is_used() && self.verify_block(&entry.data).is_err() {
+                failed.push(self.entry_from_index_data(entry.data).key.to_vec());
+            }
+        }
+        failed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Error, Table};
+
+    #[test]
+    fn test_checksums() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::options().checksums(true).create(file.path()).unwrap();
+        tbl.set("key1".as_bytes(), "value1".as_bytes()).unwrap();
+        tbl.set("key2".as_bytes(), "value2".as_bytes()).unwrap();
+        assert_eq!(tbl.get("key1".as_bytes()), Some("value1".as_bytes()));
+        assert_eq!(tbl.get_checked("key1".as_bytes()).unwrap(), Some("value1".as_bytes()));
+        assert_eq!(tbl.iter().find(|e| e.key == b"key2").unwrap().value, b"value2");
+        assert!(tbl.verify_checksums().is_empty());
+        tbl.get_mut("key1".as_bytes()).unwrap()[0] = b'X';
+        assert!(matches!(tbl.get_checked("key1".as_bytes()), Err(Error::ChecksumMismatch)));
+        assert_eq!(tbl.verify_checksums(), vec!["key1".as_bytes().to_vec()]);
+        tbl.close();
+        // Existing checksums are still recognized without the option
+        let mut tbl = Table::open(file.path()).unwrap();
+        assert_eq!(tbl.get("key2".as_bytes()), Some("value2".as_bytes()));
+        tbl.set("key3".as_bytes(), "value3".as_bytes()).unwrap();
+        assert_eq!(tbl.get_checked("key3".as_bytes()).unwrap(), Some("value3".as_bytes()));
+        assert_eq!(tbl.verify_checksums().len(), 1);
+    }
+}
@@ -0,0 +1,100 @@
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Source of time for all time-based features of a table
+///
+/// Times are given as seconds since the UNIX epoch.
+/// By default, tables use the [`SystemClock`]. A different clock can be set via
+/// [`TableOptions::clock`](crate::TableOptions::clock) or [`Table::set_clock`](crate::Table::set_clock), e.g. a
+/// [`ManualClock`] to control time deterministically in tests.
+pub trait Clock: Send + Sync {
+    /// Returns the current time in seconds since the UNIX epoch
+    fn now(&self) -> u64;
+}
+
+/// Clock using the system time
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    #[inline]
+    fn now(&self) -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default()
+    }
+}
+
+/// Clock stored in the options of a table
+#[derive(Clone)]
+pub(crate) struct OptionsClock(pub(crate) Arc<dyn Clock>);
+
+impl Default for OptionsClock {
+    #[inline]
+    fn default() -> Self {
+        Self(Arc::new(SystemClock))
+    }
+}
+
+impl fmt::Debug for OptionsClock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Clock(now: {})", self.0.now())
+    }
+}
+
+/// Clock that only changes when explicitly told to
+#[derive(Debug, Default)]
+pub struct ManualClock(AtomicU64);
+
+impl ManualClock {
+    /// Creates a new clock set to the given time
+    #[inline]
+    pub fn new(now: u64) -> Self {
+        Self(AtomicU64::new(now))
+    }
+
+    /// Sets the clock to the given time
+    #[inline]
+    pub fn set(&self, now: u64) {
+        self.0.store(now, Ordering::SeqCst)
+    }
+
+    /// Moves the clock forward by the given number of seconds
+    #[inline]
+    pub fn advance(&self, secs: u64) {
+        self.0.fetch_add(secs, Ordering::SeqCst);
+    }
+}
+
+impl Clock for ManualClock {
+    #[inline]
+    fn now(&self) -> u64 {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Table;
+
+    #[test]
+    fn test_manual_clock() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::create(file.path()).unwrap();
+        assert!(tbl.now() > 1_600_000_000);
+        let clock = Arc::new(ManualClock::new(1000));
+        tbl.set_clock(clock.clone());
+        assert_eq!(tbl.now(), 1000);
+        clock.advance(10);
+        assert_eq!(tbl.now(), 1010);
+        // The clock of the options is already used when the table is created
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let tbl = Table::options().clock(clock.clone()).create(file.path()).unwrap();
+        assert_eq!((tbl.now(), tbl.info().created), (1010, 1010));
+    }
+}
@@ -0,0 +1,101 @@
+use crate::Error;
+
+/// Encoding of the keys or values of a [`TypedTable`](crate::TypedTable)
+///
+/// Keys and values use separate codecs, so keys can be encoded in an order-preserving way while values use a
+/// general-purpose format.
+///
+/// ```
+/// use rust_persist::{BigEndian, MsgPack, Table, TypedTable};
+///
+/// let table = Table::create("example_codec.tbl").unwrap();
+/// let mut table = TypedTable::<u64, String, _, BigEndian, MsgPack>::new(table);
+/// table.set(&1, &"value1".to_string()).unwrap();
+/// assert_eq!(table.inner().get(&1u64.to_be_bytes()).is_some(), true);
+/// ```
+pub trait Codec<T> {
+    /// Encodes the given value to bytes
+    fn encode(value: &T) -> Result<Vec<u8>, Error>;
+
+    /// Decodes a value from the given bytes
+    fn decode(data: &[u8]) -> Result<T, Error>;
+}
+
+/// Codec that stores byte vectors and strings as they are
+pub struct Raw;
+
+impl Codec<Vec<u8>> for Raw {
+    #[inline]
+    fn encode(value: &Vec<u8>) -> Result<Vec<u8>, Error> {
+        Ok(value.clone())
+    }
+
+    #[inline]
+    fn decode(data: &[u8]) -> Result<Vec<u8>, Error> {
+        Ok(data.to_vec())
+    }
+}
+
+impl Codec<String> for Raw {
+    #[inline]
+    fn encode(value: &String) -> Result<Vec<u8>, Error> {
+        Ok(value.as_bytes().to_vec())
+    }
+
+    #[inline]
+    fn decode(data: &[u8]) -> Result<String, Error> {
+        String::from_utf8(data.to_vec()).map_err(|_| Error::Corrupt("string is not valid UTF-8"))
+    }
+}
+
+/// Codec that encodes integers as fixed-size big-endian bytes
+///
+/// The byte order of encoded values matches the numeric order (for signed integers, the sign bit is flipped), so
+/// this codec is suitable for keys that should be ordered.
+pub struct BigEndian;
+
+macro_rules! big_endian_codec {
+    ($($ty:ty => $flip:expr),*) => {
+        $(
+            impl Codec<$ty> for BigEndian {
+                #[inline]
+                fn encode(value: &$ty) -> Result<Vec<u8>, Error> {
+                    Ok((*value ^ $flip).to_be_bytes().to_vec())
+                }
+
+                #[inline]
+                fn decode(data: &[u8]) -> Result<$ty, Error> {
+                    let mut buf = [0; std::mem::size_of::<$ty>()];
+                    if data.len() != buf.len() {
+                        return Err(Error::Corrupt("integer has wrong size"));
+                    }
+                    buf.copy_from_slice(data);
+                    Ok(<$ty>::from_be_bytes(buf) ^ $flip)
+                }
+            }
+        )*
+    };
+}
+
+big_endian_codec!(
+    u8 => 0, u16 => 0, u32 => 0, u64 => 0, u128 => 0,
+    i8 => i8::MIN, i16 => i16::MIN, i32 => i32::MIN, i64 => i64::MIN, i128 => i128::MIN
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_big_endian_order() {
+        let values = [i64::MIN, -1000, -1, 0, 1, 1000, i64::MAX];
+        let encoded: Vec<_> = values.iter().map(|v| BigEndian::encode(v).unwrap()).collect();
+        assert!(encoded.windows(2).all(|w| w[0] < w[1]));
+        for (value, data) in values.iter().zip(&encoded) {
+            assert_eq!(<BigEndian as Codec<i64>>::decode(data).unwrap(), *value);
+        }
+        assert!(<BigEndian as Codec<u32>>::decode(&[1, 2]).is_err());
+        assert_eq!(<Raw as Codec<String>>::decode(b"abc").unwrap(), "abc");
+        assert!(<Raw as Codec<String>>::decode(&[0xff]).is_err());
+    }
+}
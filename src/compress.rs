@@ -1,8 +1,16 @@
-use std::{marker::PhantomData, path::Path};
+use std::{
+    borrow::Cow,
+    cmp,
+    convert::{TryFrom, TryInto},
+    path::Path,
+};
 
 use serde::{Serialize, de::DeserializeOwned};
 
-use crate::{Entry, Error, Table, Stats, serialize, deserialize};
+use crate::{
+    traits::AsTable, BorrowedEntries, Codec, Entries, Error, MsgPack, Table, TableRead, TableWrite, TypedTable,
+    serialize, deserialize,
+};
 
 /// Method used internally to compress data
 #[inline]
@@ -10,10 +18,149 @@ pub fn compress(val: &[u8]) -> Vec<u8> {
     lz4_flex::compress_prepend_size(val)
 }
 
+/// LZ4 cannot compress data by more than this factor, so larger original sizes in the size prefix are bogus
+const MAX_COMPRESSION_RATIO: usize = 256;
+
+/// Marker following the (zero) size prefix of data compressed in chunks, which an empty LZ4 block never starts with
+const CHUNKED_MARKER: [u8; 4] = *b"\xffCHK";
+
+/// Size of the fixed part of data compressed in chunks: size prefix, marker, chunk size, original size, chunk count
+const CHUNKED_HEADER_SIZE: usize = 24;
+
+/// Compresses the data in independently compressed chunks of the given size.
+///
+/// In contrast to [`compress`], parts of the data can be decompressed without decompressing everything before them,
+/// see [`CompressedTable::get_range`]. The result starts with a zero size prefix followed by a marker, so that
+/// [`decompress`] can tell both formats apart. It is followed by the chunk size, the original size, the number of
+/// chunks and the compressed size of each chunk, so that any chunk can be located without touching the others.
+pub fn compress_chunked(val: &[u8], chunk_size: usize) -> Vec<u8> {
+    let chunk_size = cmp::max(chunk_size, 1);
+    let chunks: Vec<_> = val.chunks(chunk_size).map(lz4_flex::block::compress).collect();
+    let size = CHUNKED_HEADER_SIZE + 4 * chunks.len() + chunks.iter().map(Vec::len).sum::<usize>();
+    let mut data = Vec::with_capacity(size);
+    data.extend_from_slice(&0u32.to_le_bytes());
+    data.extend_from_slice(&CHUNKED_MARKER);
+    data.extend_from_slice(&(chunk_size as u32).to_le_bytes());
+    data.extend_from_slice(&(val.len() as u64).to_le_bytes());
+    data.extend_from_slice(&(chunks.len() as u32).to_le_bytes());
+    for chunk in &chunks {
+        data.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+    }
+    for chunk in &chunks {
+        data.extend_from_slice(chunk);
+    }
+    data
+}
+
+/// Layout of data compressed via [`compress_chunked`]
+struct Chunked<'a> {
+    chunk_size: usize,
+    original: usize,
+    /// Compressed chunks
+    chunks: Vec<&'a [u8]>,
+}
+
+impl<'a> Chunked<'a> {
+    /// Returns the layout of the data or `None` if it has been compressed via [`compress`]
+    fn parse(data: &'a [u8]) -> Result<Option<Self>, Error> {
+        if data.len() < CHUNKED_HEADER_SIZE || data[..4] != [0; 4] || data[4..8] != CHUNKED_MARKER {
+            return Ok(None);
+        }
+        let u32_at = |pos: usize| data[pos..pos + 4].try_into().map(u32::from_le_bytes).unwrap_or_default() as usize;
+        let chunk_size = u32_at(8);
+        let original = data[12..20].try_into().map(u64::from_le_bytes).unwrap_or_default();
+        let count = u32_at(20);
+        let original = usize::try_from(original).map_err(|_| Error::TooLarge)?;
+        let sizes_end = count.checked_mul(4).and_then(|size| size.checked_add(CHUNKED_HEADER_SIZE));
+        let sizes_fit = sizes_end.filter(|&end| end <= data.len()).is_some();
+        if chunk_size == 0 || !sizes_fit || count != original.div_ceil(chunk_size) {
+            return Err(Error::Corrupt("invalid chunked compressed value"));
+        }
+        let mut pos = CHUNKED_HEADER_SIZE + 4 * count;
+        let mut chunks = Vec::with_capacity(count);
+        for i in 0..count {
+            let size = u32_at(CHUNKED_HEADER_SIZE + 4 * i);
+            match data.get(pos..pos + size) {
+                Some(chunk) => chunks.push(chunk),
+                None => return Err(Error::Corrupt("invalid chunked compressed value")),
+            }
+            pos += size;
+        }
+        Ok(Some(Self { chunk_size, original, chunks }))
+    }
+
+    /// Decompresses the chunk with the given number
+    fn decompress_chunk(&self, chunk: usize) -> Result<Vec<u8>, Error> {
+        let size = cmp::min(self.chunk_size, self.original - chunk * self.chunk_size);
+        lz4_flex::block::decompress(self.chunks[chunk], size).map_err(Error::Decompress)
+    }
+}
+
 /// Method used internally to decompress data
+///
+/// Data claiming an original size that cannot result from compressing it is rejected before allocating any memory
+/// for it, see [`decompress_limited`].
 #[inline]
 pub fn decompress(data: &[u8]) -> Result<Vec<u8>, Error> {
-    lz4_flex::decompress_size_prepended(data).map_err(Error::Decompress)
+    decompress_limited(data, usize::MAX)
+}
+
+/// Decompresses the data unless its original size exceeds the given limit.
+///
+/// The size is taken from the size prefix of the data, so this protects against decompression bombs in untrusted
+/// tables. If the size exceeds the limit or cannot result from compressing the data, [`Error::DecompressLimit`] is
+/// returned.
+pub fn decompress_limited(data: &[u8], limit: usize) -> Result<Vec<u8>, Error> {
+    let size = decompressed_size(data)?;
+    let limit = cmp::min(limit, data.len().saturating_sub(4).saturating_mul(MAX_COMPRESSION_RATIO));
+    if size > limit {
+        return Err(Error::DecompressLimit { size: size as u64, limit: limit as u64 });
+    }
+    match Chunked::parse(data)? {
+        Some(chunked) => {
+            let mut val = Vec::with_capacity(size);
+            for chunk in 0..chunked.chunks.len() {
+                val.extend_from_slice(&chunked.decompress_chunk(chunk)?);
+            }
+            Ok(val)
+        }
+        None => lz4_flex::decompress_size_prepended(data).map_err(Error::Decompress),
+    }
+}
+
+/// Returns the original size of compressed data without decompressing it
+#[inline]
+pub fn decompressed_size(data: &[u8]) -> Result<usize, Error> {
+    if let Some(chunked) = Chunked::parse(data)? {
+        return Ok(chunked.original);
+    }
+    lz4_flex::block::uncompressed_size(data).map(|(size, _)| size).map_err(Error::Decompress)
+}
+
+/// Original and stored size of a compressed value
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde_derive::Serialize)]
+pub struct CompressedSize {
+    /// Size of the serialized value before compression
+    pub original: u64,
+
+    /// Size of the value as stored in the table (including the size prefix)
+    pub stored: u64,
+}
+
+/// Struct containing compression statistics of a table
+#[derive(Debug, Default, serde_derive::Serialize)]
+pub struct CompressionStats {
+    /// Number of compressed entries
+    pub entries: usize,
+
+    /// Total size of all values before compression
+    pub original_size: u64,
+
+    /// Total size of all values as stored in the table
+    pub stored_size: u64,
+
+    /// Fraction of stored size and original size (lower is better)
+    pub ratio: f32,
 }
 
 impl Table {
@@ -64,201 +211,376 @@ impl Table {
 }
 
 
-/// Internal iterator over all entries in the typed table
-struct Iter<K, V, I> {
-    inner: I,
-    _key: PhantomData<K>,
-    _value: PhantomData<V>,
+/// A layer that compresses all values stored in the wrapped table.
+///
+/// Keys are stored uncompressed, so lookups do not need to decompress anything. This layer works on raw bytes and can
+/// be wrapped in a [`TypedTable`] to store typed data, see [`CompressedTypedTable`].
+pub struct CompressedTable<T = Table> {
+    inner: T,
+    max_decompressed_size: usize,
+    chunk_size: Option<usize>,
 }
 
-impl<'a, K: DeserializeOwned, V: DeserializeOwned, I: Iterator<Item = Entry<'a>>> Iterator for Iter<K, V, I> {
-    type Item = Result<(K, V), Error>;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        self.inner.next().map(|entry| Ok((deserialize(entry.key)?, deserialize(&decompress(entry.value)?)?)))
+impl<T> CompressedTable<T> {
+    /// Wraps the given table or layer.
+    #[inline]
+    pub fn new(inner: T) -> Self {
+        Self { inner, max_decompressed_size: usize::MAX, chunk_size: None }
     }
-}
-
 
-/// Internal iterator over all keys in the typed table
-struct KeyIter<K, I> {
-    inner: I,
-    _key: PhantomData<K>,
-}
-
-impl<'a, K: DeserializeOwned, I: Iterator<Item = Entry<'a>>> Iterator for KeyIter<K, I> {
-    type Item = Result<K, Error>;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        self.inner.next().map(|entry| deserialize(entry.key))
+    /// Sets the maximum original size of a single value.
+    ///
+    /// Values claiming a larger size are not decompressed and [`Error::DecompressLimit`] is returned instead. This
+    /// should be set when reading untrusted tables. By default, sizes are not limited.
+    #[inline]
+    pub fn max_decompressed_size(mut self, limit: usize) -> Self {
+        self.max_decompressed_size = limit;
+        self
     }
-}
-
 
-/// A typed version of the table with compressed values.
-///
-/// This struct wraps the normal [`Table`] and ensures that keys and values have a certain type.
-///
-/// ## On serialization
-///
-/// This functionality requires the feature `msgpack`.
-///
-/// For encoding/decoding data, the MessagePack format is used. In particular,
-/// a compressed encoding is used that skips the field names and encodes structs as arrays.
-/// Please see [`serde`], [`rmp_serde`] and [MessagePack](https://msgpack.org) for more info.
-///
-/// If you want to enable serialization for custom structs and types, you can either implement
-/// [`serde::Serialize`] and [`serde::Deserialize`] directly or use [the `derive` feature of `serde`](https://serde.rs/derive.html).
-///
-/// If any key or value cannot be encoded or decoded, [`Error::Serialize`] or [`Error::Deserialize`] is thrown.
-pub struct CompressedTypedTable<K, V> {
-    inner: Table,
-    _key: PhantomData<K>,
-    _value: PhantomData<V>,
-}
-
-impl<K: Serialize + DeserializeOwned, V: Serialize + DeserializeOwned> CompressedTypedTable<K, V> {
-    /// Opens an existing typed table from the given path.
+    /// Compresses values larger than the given size in chunks of that size, see [`compress_chunked`].
+    ///
+    /// This allows reading parts of large values via [`CompressedTable::get_range`] by decompressing only the chunks
+    /// containing the requested range. Smaller chunks make partial reads cheaper but compress worse. By default,
+    /// values are compressed as a whole.
     #[inline]
-    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
-        Ok(Self { inner: Table::open(path)?, _key: PhantomData, _value: PhantomData })
+    pub fn chunk_size(mut self, size: Option<usize>) -> Self {
+        self.chunk_size = size;
+        self
     }
 
-    /// Creates a new typed table at the given path (overwriting an existing table).
     #[inline]
-    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
-        Ok(Self { inner: Table::create(path)?, _key: PhantomData, _value: PhantomData })
+    fn compress(&self, value: &[u8]) -> Vec<u8> {
+        match self.chunk_size {
+            Some(chunk_size) if value.len() > chunk_size => compress_chunked(value, chunk_size),
+            // The size prefix of the plain format only has 32 bits
+            _ if value.len() >= u32::MAX as usize => compress_chunked(value, u32::MAX as usize),
+            _ => compress(value),
+        }
     }
 
-    /// Opens an existing or creates a new typed table at the given path.
     #[inline]
-    pub fn open_or_create<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
-        let path = path.as_ref();
-        if path.exists() {
-            Self::open(path)
-        } else {
-            Self::create(path)
-        }
+    fn decompress(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        decompress_limited(data, self.max_decompressed_size)
     }
 
-    /// Returns a reference to the wrapped [`Table`].
-    /// 
+    /// Returns a reference to the wrapped table or layer.
+    ///
     /// Beware that the inner table will expose the raw compressed data
     #[inline]
-    pub fn inner(&self) -> &Table {
+    pub fn inner(&self) -> &T {
         &self.inner
     }
 
-    /// Returns the wrapped [`Table`].
-    /// 
+    /// Returns the wrapped table or layer.
+    ///
     /// Beware that the inner table will expose the raw compressed data
     #[inline]
-    pub fn into_inner(self) -> Table {
+    pub fn into_inner(self) -> T {
         self.inner
     }
+}
 
-    /// Returns whether an entry is associated with the given key.
-    #[inline]
-    pub fn contains(&self, key: &K) -> Result<bool, Error> {
-        self.inner.contains_obj(key)
+impl<T: TableRead<Key = [u8], OwnedKey = Vec<u8>, Value = [u8], OwnedValue = Vec<u8>>> CompressedTable<T> {
+    /// Returns the original and stored size of the value associated with the given key.
+    ///
+    /// If no entry with the given key exists in the table, `None` is returned.
+    /// The value is not decompressed for this.
+    pub fn entry_size(&self, key: &[u8]) -> Result<Option<CompressedSize>, Error> {
+        match self.inner.get(key)? {
+            Some(v) => Ok(Some(CompressedSize { original: decompressed_size(&v)? as u64, stored: v.len() as u64 })),
+            None => Ok(None),
+        }
     }
 
-    /// Loads and returns the value stored with the given key.
+    /// Returns `len` bytes of the value associated with the given key, starting at `offset`.
     ///
-    /// See [`Table::get_obj`] for more info
-    #[inline]
-    pub fn get(&self, key: &K) -> Result<Option<V>, Error> {
-        self.inner.get_compressed_obj(key)
+    /// For values compressed in chunks (see [`CompressedTable::chunk_size`]), only the chunks containing the range
+    /// are decompressed, other values are decompressed completely. If no entry with the given key exists in the table
+    /// or the range exceeds the value, `None` is returned.
+    ///
+    /// ```
+    /// use rust_persist::{CompressedTable, Table, TableWrite};
+    ///
+    /// let mut table = CompressedTable::new(Table::create("example_chunked.tbl").unwrap()).chunk_size(Some(4096));
+    /// let value: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+    /// table.set("key".as_bytes(), &value).unwrap();
+    /// assert_eq!(table.get_range("key".as_bytes(), 50_000, 10).unwrap(), Some(value[50_000..50_010].to_vec()));
+    /// ```
+    pub fn get_range(&self, key: &[u8], offset: u64, len: u64) -> Result<Option<Vec<u8>>, Error> {
+        let data = match self.inner.get(key)? {
+            Some(data) => data,
+            None => return Ok(None),
+        };
+        let chunked = match Chunked::parse(&data)? {
+            Some(chunked) => chunked,
+            None => {
+                let value = self.decompress(&data)?;
+                let range = offset.checked_add(len).filter(|&end| end <= value.len() as u64).map(|end| offset..end);
+                return Ok(range.map(|range| value[range.start as usize..range.end as usize].to_vec()));
+            }
+        };
+        let end = match offset.checked_add(len).filter(|&end| end <= chunked.original as u64) {
+            Some(end) => end as usize,
+            None => return Ok(None),
+        };
+        let (offset, chunk_size) = (offset as usize, chunked.chunk_size);
+        let mut range = Vec::with_capacity(len as usize);
+        for chunk in offset / chunk_size..end.div_ceil(chunk_size) {
+            let chunk_start = chunk * chunk_size;
+            let decompressed = chunked.decompress_chunk(chunk)?;
+            let from = offset.saturating_sub(chunk_start);
+            let to = cmp::min(end - chunk_start, decompressed.len());
+            range.extend_from_slice(&decompressed[from..to]);
+        }
+        Ok(Some(range))
     }
 
-    /// Stores the given key/value pair in the table.
+    /// Returns compression statistics over all entries of the table.
     ///
-    /// See [`Table::set_obj`] for more info
+    /// This method has to scan all entries but does not decompress any values.
+    pub fn compression_stats(&self) -> Result<CompressionStats, Error> {
+        let mut stats = CompressionStats::default();
+        for entry in self.inner.entries() {
+            let (_, value) = entry?;
+            stats.entries += 1;
+            stats.original_size += decompressed_size(&value)? as u64;
+            stats.stored_size += value.len() as u64;
+        }
+        if stats.original_size > 0 {
+            stats.ratio = stats.stored_size as f32 / stats.original_size as f32;
+        }
+        Ok(stats)
+    }
+}
+
+impl<T: TableRead<Key = [u8], OwnedKey = Vec<u8>, Value = [u8], OwnedValue = Vec<u8>>> TableRead
+    for CompressedTable<T>
+{
+    type Key = [u8];
+    type OwnedKey = Vec<u8>;
+    type Value = [u8];
+    type OwnedValue = Vec<u8>;
+
     #[inline]
-    pub fn set(&mut self, key: &K, value: &V) -> Result<bool, Error> {
-        self.inner.set_compressed_obj(key, value)
+    fn contains(&self, key: &[u8]) -> Result<bool, Error> {
+        self.inner.contains(key)
     }
 
-    /// Deletes the entry with the given key from the table.
-    ///
-    /// See [`Table::delete_obj`] for more info
     #[inline]
-    pub fn delete(&mut self, key: &K) -> Result<bool, Error> {
-        self.inner.delete_obj(key)
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        match self.inner.get(key)? {
+            Some(v) => Ok(Some(self.decompress(&v)?)),
+            None => Ok(None),
+        }
     }
 
-    /// Deletes and return the entry with the given key from the table.
-    ///
-    /// See [`Table::take_obj`] for more info
     #[inline]
-    pub fn take(&mut self, key: &K) -> Result<Option<V>, Error> {
-        self.inner.take_compressed_obj(key)
+    fn entries(&self) -> Entries<'_, Vec<u8>, Vec<u8>> {
+        Box::new(self.inner.entries().map(move |entry| {
+            let (key, value) = entry?;
+            Ok((key, self.decompress(&value)?))
+        }))
     }
 
+    #[inline]
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
 
-    /// Iterate over all entries in the typed table
     #[inline]
-    pub fn iter(&self) -> impl Iterator<Item = Result<(K, V), Error>> + '_ {
-        Iter { inner: self.inner.iter(), _key: PhantomData, _value: PhantomData }
+    fn entries_borrowed(&self) -> BorrowedEntries<'_, [u8], [u8]> {
+        Box::new(self.inner.entries_borrowed().map(move |entry| {
+            let (key, value) = entry?;
+            Ok((key, Cow::Owned(self.decompress(&value)?)))
+        }))
     }
+}
 
-    /// Iterate over all entries in the typed table
+impl<T: AsTable> AsTable for CompressedTable<T> {
     #[inline]
-    pub fn keys(&self) -> impl Iterator<Item = Result<K, Error>> + '_ {
-        KeyIter { inner: self.inner.iter(), _key: PhantomData }
+    fn table(&self) -> &Table {
+        self.inner.table()
     }
+}
 
-    /// Return the number of entries in the table
+impl<T: TableWrite<Key = [u8], OwnedKey = Vec<u8>, Value = [u8], OwnedValue = Vec<u8>>> TableWrite
+    for CompressedTable<T>
+{
     #[inline]
-    pub fn len(&self) -> usize {
-        self.inner.len()
+    fn set(&mut self, key: &[u8], value: &[u8]) -> Result<bool, Error> {
+        self.inner.set(key, &self.compress(value))
+    }
+
+    #[inline]
+    fn delete(&mut self, key: &[u8]) -> Result<bool, Error> {
+        self.inner.delete(key)
     }
 
-    /// Return the raw size of the table in bytes
     #[inline]
-    pub fn size(&self) -> u64 {
-        self.inner.size() as u64
+    fn take(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        match self.inner.take(key)? {
+            Some(v) => Ok(Some(self.decompress(&v)?)),
+            None => Ok(None),
+        }
     }
 
-    /// Return whether the table is empty
     #[inline]
-    pub fn is_empty(&self) -> bool {
-        self.inner.len() == 0
+    fn clear(&mut self) -> Result<(), Error> {
+        self.inner.clear()
     }
 
-    /// Forces to write all pending changes to disk
     #[inline]
-    pub fn flush(&mut self) -> Result<(), Error> {
+    fn flush(&mut self) -> Result<(), Error> {
         self.inner.flush()
     }
 
-    /// Forces defragmentation of the data section.
-    ///
-    /// See [`Table::defragment`] for more info.
     #[inline]
-    pub fn defragment(&mut self) -> Result<(), Error> {
+    fn defragment(&mut self) -> Result<(), Error> {
         self.inner.defragment()
     }
+}
 
-    /// Explicitly closes the table.
+/// A typed version of the table with compressed values.
+///
+/// This is a [`TypedTable`] layered over a [`CompressedTable`], so values are serialized and then compressed.
+/// Keys are serialized but not compressed.
+///
+/// See [TypedTable](TypedTable#on-serialization) for more info on serialization.
+pub type CompressedTypedTable<K, V, T = Table, KC = MsgPack, VC = MsgPack> = TypedTable<K, V, CompressedTable<T>, KC, VC>;
+
+impl<K, V, KC: Codec<K>, VC: Codec<V>> CompressedTypedTable<K, V, Table, KC, VC> {
+    /// Opens an existing typed table from the given path.
+    #[inline]
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Ok(Self::new(CompressedTable::new(Table::open(path)?)))
+    }
+
+    /// Creates a new typed table at the given path (overwriting an existing table).
+    #[inline]
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Ok(Self::new(CompressedTable::new(Table::create(path)?)))
+    }
+
+    /// Opens an existing or creates a new typed table at the given path.
+    #[inline]
+    pub fn open_or_create<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        if path.exists() {
+            Self::open(path)
+        } else {
+            Self::create(path)
+        }
+    }
+}
+
+impl<K, V, T, KC, VC> CompressedTypedTable<K, V, T, KC, VC>
+where
+    KC: Codec<K>,
+    VC: Codec<V>,
+    T: TableRead<Key = [u8], OwnedKey = Vec<u8>, Value = [u8], OwnedValue = Vec<u8>>,
+{
+    /// Returns the original and stored size of the value associated with the given key.
     ///
-    /// Normally this method does not need to be called.
+    /// If no entry with the given key exists in the table, `None` is returned.
+    /// The value is not decompressed for this.
     #[inline]
-    pub fn close(self) {
-        self.inner.close()
+    pub fn entry_size(&self, key: &K) -> Result<Option<CompressedSize>, Error> {
+        self.inner().entry_size(&KC::encode(key)?)
     }
 
-    /// Deletes all entries in the table
+    /// Returns compression statistics over all entries of the table.
     ///
-    /// This method essentially resets the table to its state after creation.
+    /// This method has to scan all entries but does not decompress any values.
     #[inline]
-    pub fn clear(&mut self) -> Result<(), Error> {
-        self.inner.clear()
+    pub fn compression_stats(&self) -> Result<CompressionStats, Error> {
+        self.inner().compression_stats()
+    }
+
+    /// Sets the maximum original size of a single value, see [`CompressedTable::max_decompressed_size`]
+    #[inline]
+    pub fn max_decompressed_size(self, limit: usize) -> Self {
+        Self::new(self.into_inner().max_decompressed_size(limit))
     }
+}
 
-    /// Return a statistics struct
-    pub fn stats(&self) -> Stats {
-        self.inner.stats()
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compression_stats() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = CompressedTypedTable::<usize, String>::create(file.path()).unwrap();
+        tbl.set(&1, &"a".repeat(1000)).unwrap();
+        tbl.set(&2, &"b".repeat(1000)).unwrap();
+        assert_eq!(tbl.get(&1).unwrap(), Some("a".repeat(1000)));
+        let size = tbl.entry_size(&1).unwrap().unwrap();
+        assert_eq!(size.original, serialize("a".repeat(1000)).unwrap().len() as u64);
+        assert!(size.stored < size.original);
+        assert_eq!(tbl.entry_size(&3).unwrap(), None);
+        let stats = tbl.compression_stats().unwrap();
+        assert_eq!(stats.entries, 2);
+        assert_eq!(stats.original_size, 2 * size.original);
+        assert!(stats.ratio > 0.0 && stats.ratio < 0.5);
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn test_layering() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = CompressedTypedTable::<usize, String>::create(file.path()).unwrap();
+        tbl.set(&1, &"a".repeat(100)).unwrap();
+        tbl.set(&2, &"b".repeat(100)).unwrap();
+        assert_eq!(tbl.take(&2).unwrap(), Some("b".repeat(100)));
+        tbl.close();
+        let ro = Table::open_read_only(file.path()).unwrap();
+        assert_eq!(ro.get_compressed_obj(1usize).unwrap(), Some("a".repeat(100)));
+        let tbl = TypedTable::<usize, String, _>::new(CompressedTable::new(ro));
+        assert_eq!(tbl.get(&1).unwrap(), Some("a".repeat(100)));
+        assert_eq!(tbl.iter().map(Result::unwrap).collect::<Vec<_>>(), vec![(1, "a".repeat(100))]);
+        assert_eq!(tbl.compression_stats().unwrap().entries, 1);
+    }
+
+    #[test]
+    fn test_chunked() {
+        let value: Vec<u8> = (0..10_000u32).map(|i| (i % 7) as u8).collect();
+        let data = compress_chunked(&value, 1000);
+        assert_eq!(decompressed_size(&data).unwrap(), value.len());
+        assert_eq!(decompress(&data).unwrap(), value);
+        assert!(matches!(decompress_limited(&data, 9_999), Err(Error::DecompressLimit { .. })));
+        assert_eq!(decompress(&compress_chunked(&[], 1000)).unwrap(), Vec::<u8>::new());
+        assert!(matches!(decompress(&data[..data.len() - 1]), Err(Error::Corrupt(_))));
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = CompressedTable::new(Table::create(file.path()).unwrap()).chunk_size(Some(1000));
+        tbl.set("chunked".as_bytes(), &value).unwrap();
+        tbl.set("small".as_bytes(), &value[..100]).unwrap();
+        assert_eq!(tbl.get("chunked".as_bytes()).unwrap(), Some(value.clone()));
+        assert!(Chunked::parse(tbl.inner().get("chunked".as_bytes()).unwrap()).unwrap().is_some());
+        assert!(Chunked::parse(tbl.inner().get("small".as_bytes()).unwrap()).unwrap().is_none());
+        for &(offset, len) in &[(0, 0), (0, 1000), (999, 2), (2500, 5000), (9_990, 10), (0, 10_000)] {
+            let expected = Some(value[offset as usize..(offset + len) as usize].to_vec());
+            assert_eq!(tbl.get_range("chunked".as_bytes(), offset, len).unwrap(), expected);
+        }
+        assert_eq!(tbl.get_range("chunked".as_bytes(), 9_990, 11).unwrap(), None);
+        assert_eq!(tbl.get_range("small".as_bytes(), 10, 20).unwrap(), Some(value[10..30].to_vec()));
+        assert_eq!(tbl.get_range("small".as_bytes(), 90, 20).unwrap(), None);
+        assert_eq!(tbl.get_range("missing".as_bytes(), 0, 0).unwrap(), None);
+        assert_eq!(tbl.entry_size("chunked".as_bytes()).unwrap().unwrap().original, 10_000);
+    }
+
+    #[test]
+    fn test_decompress_limit() {
+        let data = compress(&[0; 100_000]);
+        assert_eq!(decompress_limited(&data, 100_000).unwrap().len(), 100_000);
+        assert!(matches!(decompress_limited(&data, 99_999), Err(Error::DecompressLimit { size: 100_000, .. })));
+        // A size prefix claiming 4 GiB for a few bytes of data
+        let bomb = [0xff, 0xff, 0xff, 0xff, 0x10, 0x00];
+        assert!(matches!(decompress(&bomb), Err(Error::DecompressLimit { size: 0xffff_ffff, limit: 512 })));
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = CompressedTypedTable::<usize, String>::create(file.path()).unwrap().max_decompressed_size(100);
+        tbl.set(&1, &"a".repeat(10)).unwrap();
+        tbl.set(&2, &"b".repeat(1000)).unwrap();
+        assert_eq!(tbl.get(&1).unwrap(), Some("a".repeat(10)));
+        assert!(matches!(tbl.get(&2), Err(Error::DecompressLimit { .. })));
+    }
+}
@@ -0,0 +1,290 @@
+use std::{
+    path::Path,
+    sync::{
+        mpsc::{self, Receiver},
+        RwLock, RwLockReadGuard, RwLockWriteGuard,
+    },
+};
+
+use crate::{
+    sharded::shard_for, table::hash_key, ChangeEvent, Entries, Error, ShardedTable, Table, TableOptions, TableRead,
+    TableWrite,
+};
+
+/// Error of operations on a shard whose lock has been poisoned by a panic
+#[inline]
+fn poisoned<T>(_: T) -> Error {
+    Error::Corrupt("shard has been poisoned by a panic")
+}
+
+/// A table that can be shared between threads and modified via `&self`
+///
+/// The table is a [`ShardedTable`] with a read-write lock per shard, so readers of a shard run in parallel and
+/// writers only block operations on the same shard. Values are returned as copies (or passed to a closure via
+/// [`ConcurrentTable::get_with`]), as references into a shard would have to keep its lock.
+///
+/// If an operation panics while holding the lock of a shard, all further operations on that shard fail with
+/// [`Error::Corrupt`].
+///
+/// ```
+/// use std::{sync::Arc, thread};
+/// use rust_persist::ConcurrentTable;
+///
+/// let table = Arc::new(ConcurrentTable::create("example_concurrent", 4).unwrap());
+/// let workers: Vec<_> = (0u32..4)
+///     .map(|worker| {
+///         let table = table.clone();
+///         thread::spawn(move || table.set(&worker.to_le_bytes(), "done".as_bytes()).unwrap())
+///     })
+///     .collect();
+/// for worker in workers {
+///     worker.join().unwrap();
+/// }
+/// assert_eq!(table.len().unwrap(), 4);
+/// assert_eq!(table.get(&0u32.to_le_bytes()).unwrap(), Some("done".as_bytes().to_vec()));
+/// ```
+pub struct ConcurrentTable {
+    shards: Vec<RwLock<Table>>,
+}
+
+impl ConcurrentTable {
+    /// Opens an existing sharded table from the given directory, see [`ShardedTable::open`].
+    #[inline]
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self, Error> {
+        ShardedTable::open(dir).map(Self::from)
+    }
+
+    /// Opens an existing sharded table from the given directory with the given options, see
+    /// [`ShardedTable::open_with`].
+    #[inline]
+    pub fn open_with<P: AsRef<Path>>(dir: P, options: TableOptions) -> Result<Self, Error> {
+        ShardedTable::open_with(dir, options).map(Self::from)
+    }
+
+    /// Creates a new sharded table with the given number of shards in the given directory, see
+    /// [`ShardedTable::create`].
+    ///
+    /// More shards allow more writers to work in parallel.
+    #[inline]
+    pub fn create<P: AsRef<Path>>(dir: P, shards: usize) -> Result<Self, Error> {
+        ShardedTable::create(dir, shards).map(Self::from)
+    }
+
+    /// Creates a new sharded table with the given options, see [`ShardedTable::create_with`].
+    #[inline]
+    pub fn create_with<P: AsRef<Path>>(dir: P, shards: usize, options: TableOptions) -> Result<Self, Error> {
+        ShardedTable::create_with(dir, shards, options).map(Self::from)
+    }
+
+    #[inline]
+    fn shard_of(&self, key: &[u8]) -> &RwLock<Table> {
+        &self.shards[shard_for(hash_key(key), self.shards.len())]
+    }
+
+    #[inline]
+    fn read(shard: &RwLock<Table>) -> Result<RwLockReadGuard<'_, Table>, Error> {
+        shard.read().map_err(poisoned)
+    }
+
+    #[inline]
+    fn write(shard: &RwLock<Table>) -> Result<RwLockWriteGuard<'_, Table>, Error> {
+        shard.write().map_err(poisoned)
+    }
+
+    /// Passes the value of the given key (if any) to the closure and returns its result
+    ///
+    /// The shard of the key is locked for reading while the closure runs, so it should not take long and must not
+    /// modify the table.
+    #[inline]
+    pub fn get_with<R, F: FnOnce(Option<&[u8]>) -> R>(&self, key: &[u8], f: F) -> Result<R, Error> {
+        Ok(f(Self::read(self.shard_of(key))?.get(key)))
+    }
+
+    /// Returns a copy of the value of the given key
+    #[inline]
+    pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        self.get_with(key, |value| value.map(|v| v.to_vec()))
+    }
+
+    /// Returns whether the given key exists in the table
+    #[inline]
+    pub fn contains(&self, key: &[u8]) -> Result<bool, Error> {
+        Ok(Self::read(self.shard_of(key))?.contains(key))
+    }
+
+    /// Stores the value for the given key, see [`Table::set`].
+    #[inline]
+    pub fn set(&self, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        Self::write(self.shard_of(key))?.set(key, value).map(|_| ())
+    }
+
+    /// Deletes the given key and returns its value (if any), see [`Table::take`].
+    #[inline]
+    pub fn delete(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        Self::write(self.shard_of(key))?.take(key)
+    }
+
+    /// Replaces the value of the given key with the result of the closure, see [`Table::update`].
+    ///
+    /// The shard of the key stays locked between reading and writing, so concurrent updates of a key are not lost.
+    #[inline]
+    pub fn update<F: FnOnce(Option<&[u8]>) -> Option<Vec<u8>>>(&self, key: &[u8], update: F) -> Result<(), Error> {
+        Self::write(self.shard_of(key))?.update(key, update)
+    }
+
+    /// Returns a receiver of the changes of all entries whose key starts with the given prefix, see [`Table::watch`].
+    ///
+    /// Background threads can use the receiver to react to changes made by other threads without polling. Changes of
+    /// different shards might be received in a different order than they have been made.
+    pub fn watch(&self, prefix: &[u8]) -> Result<Receiver<ChangeEvent>, Error> {
+        let (sender, receiver) = mpsc::channel();
+        for shard in &self.shards {
+            Self::write(shard)?.add_watcher(prefix, sender.clone());
+        }
+        Ok(receiver)
+    }
+
+    /// Returns the number of entries in all shards
+    ///
+    /// The shards are counted one after another, so concurrent modifications might be partially included.
+    pub fn len(&self) -> Result<usize, Error> {
+        self.shards.iter().map(|shard| Self::read(shard).map(|tbl| tbl.len())).sum()
+    }
+
+    /// Returns whether all shards are empty
+    pub fn is_empty(&self) -> Result<bool, Error> {
+        for shard in &self.shards {
+            if !Self::read(shard)?.is_empty() {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Flushes all shards
+    pub fn flush(&self) -> Result<(), Error> {
+        self.shards.iter().try_for_each(|shard| Self::read(shard)?.flush())
+    }
+
+    /// Returns the underlying sharded table, e.g. to iterate over all entries
+    pub fn into_inner(self) -> Result<ShardedTable, Error> {
+        let shards = self.shards.into_iter().map(|shard| shard.into_inner().map_err(poisoned));
+        shards.collect::<Result<_, _>>().map(ShardedTable::from_shards)
+    }
+}
+
+impl TableRead for ConcurrentTable {
+    type Key = [u8];
+    type OwnedKey = Vec<u8>;
+    type Value = [u8];
+    type OwnedValue = Vec<u8>;
+
+    #[inline]
+    fn contains(&self, key: &[u8]) -> Result<bool, Error> {
+        ConcurrentTable::contains(self, key)
+    }
+
+    #[inline]
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        ConcurrentTable::get(self, key)
+    }
+
+    /// Returns copies of all entries, shard by shard
+    ///
+    /// Each shard is locked while its entries are copied, so concurrent modifications of other shards might be
+    /// partially included.
+    fn entries(&self) -> Entries<'_, Vec<u8>, Vec<u8>> {
+        Box::new(self.shards.iter().flat_map(|shard| match Self::read(shard) {
+            Ok(tbl) => tbl.iter_owned().map(Ok).collect(),
+            Err(err) => vec![Err(err)],
+        }))
+    }
+
+    /// Returns the number of entries in all shards, including shards that have been poisoned
+    #[inline]
+    fn len(&self) -> usize {
+        self.shards.iter().map(|shard| shard.read().unwrap_or_else(|err| err.into_inner()).len()).sum()
+    }
+}
+
+impl TableWrite for ConcurrentTable {
+    #[inline]
+    fn set(&mut self, key: &[u8], value: &[u8]) -> Result<bool, Error> {
+        Self::write(self.shard_of(key))?.set(key, value).map(|old| old.is_some())
+    }
+
+    #[inline]
+    fn delete(&mut self, key: &[u8]) -> Result<bool, Error> {
+        ConcurrentTable::delete(self, key).map(|old| old.is_some())
+    }
+
+    #[inline]
+    fn take(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        ConcurrentTable::delete(self, key)
+    }
+
+    fn clear(&mut self) -> Result<(), Error> {
+        self.shards.iter().try_for_each(|shard| Self::write(shard)?.clear())
+    }
+
+    #[inline]
+    fn flush(&mut self) -> Result<(), Error> {
+        ConcurrentTable::flush(self)
+    }
+
+    fn defragment(&mut self) -> Result<(), Error> {
+        self.shards.iter().try_for_each(|shard| Self::write(shard)?.defragment())
+    }
+}
+
+impl From<ShardedTable> for ConcurrentTable {
+    #[inline]
+    fn from(table: ShardedTable) -> Self {
+        Self { shards: table.into_shards().into_iter().map(RwLock::new).collect() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{convert::TryInto, sync::Arc, thread};
+
+    #[test]
+    fn test_concurrent() {
+        let dir = tempfile::tempdir().unwrap();
+        let tbl = Arc::new(ConcurrentTable::create(dir.path(), 4).unwrap());
+        let threads: Vec<_> = (0u32..8)
+            .map(|t| {
+                let tbl = tbl.clone();
+                thread::spawn(move || {
+                    for i in 0u32..200 {
+                        let key = (t * 1000 + i).to_le_bytes();
+                        tbl.set(&key, &i.to_le_bytes()).unwrap();
+                        assert_eq!(tbl.get(&key).unwrap(), Some(i.to_le_bytes().to_vec()));
+                        tbl.update("counter".as_bytes(), |old| {
+                            let count = old.map_or(0, |v| u32::from_le_bytes(v.try_into().unwrap()));
+                            Some((count + 1).to_le_bytes().to_vec())
+                        })
+                        .unwrap();
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(tbl.get("counter".as_bytes()).unwrap(), Some(1600u32.to_le_bytes().to_vec()));
+        assert_eq!(tbl.len().unwrap(), 1601);
+        assert_eq!(tbl.delete(&1005u32.to_le_bytes()).unwrap(), Some(5u32.to_le_bytes().to_vec()));
+        assert!(!tbl.contains(&1005u32.to_le_bytes()).unwrap());
+        assert_eq!(tbl.get_with(&1006u32.to_le_bytes(), |v| v.map(<[u8]>::len)).unwrap(), Some(4));
+        tbl.flush().unwrap();
+        let tbl = Arc::try_unwrap(tbl).ok().unwrap().into_inner().unwrap();
+        assert_eq!(tbl.len(), 1600);
+        assert!(tbl.shards().iter().all(Table::is_valid));
+        tbl.close();
+        let tbl = ConcurrentTable::open(dir.path()).unwrap();
+        assert_eq!(tbl.len().unwrap(), 1600);
+        assert!(!tbl.is_empty().unwrap());
+    }
+}
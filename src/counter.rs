@@ -0,0 +1,121 @@
+use crate::{
+    checksum::{self, CHECKSUM_SIZE},
+    memmngr::Size,
+    table::{hash_key, match_key},
+    Entry, Error, Table, FLAG_CHECKSUM,
+};
+
+/// Size of a counter value in bytes
+const COUNTER_SIZE: Size = 8;
+
+impl Table {
+    /// Adds `delta` to the counter stored under the given key and returns the new value of the counter.
+    ///
+    /// The value is treated as a 64-bit signed integer in little-endian byte order. If no entry with the given key is
+    /// stored in the table, the counter is created with `delta` as its value. Existing counters are updated in place
+    /// (including their checksum), so no new data block is allocated.
+    ///
+    /// Fails with [`Error::InvalidOptions`] if the stored value does not have 8 bytes and with [`Error::TooLarge`] if
+    /// the counter would overflow. In both cases, the value is not changed.
+    ///
+    /// ```
+    /// use rust_persist::Table;
+    ///
+    /// let mut table = Table::create("example_counter.tbl").unwrap();
+    /// assert_eq!(table.increment("visits".as_bytes(), 1).unwrap(), 1);
+    /// assert_eq!(table.increment("visits".as_bytes(), 5).unwrap(), 6);
+    /// assert_eq!(table.decrement("visits".as_bytes(), 2).unwrap(), 4);
+    /// assert_eq!(table.get("visits".as_bytes()), Some(&4i64.to_le_bytes()[..]));
+    /// ```
+    pub fn increment(&mut self, key: &[u8], delta: i64) -> Result<i64, Error> {
+        self.check_writable()?;
+        let hash = hash_key(key);
+        self.check_plain_key(hash, key)?;
+        let entry = match self.index.index_get(hash, |e| match_key(e, self.data, self.data_start, key)) {
+            Some(entry) => entry,
+            None => {
+                self.set_entry(Entry { key, value: &delta.to_le_bytes(), flags: 0 })?;
+                return Ok(delta);
+            }
+        };
+        let has_checksum = entry.flags & FLAG_CHECKSUM != 0;
+        let content_size = entry.size() - if has_checksum { CHECKSUM_SIZE as Size } else { 0 };
+        if content_size - entry.key_size as Size != COUNTER_SIZE {
+            return Err(Error::InvalidOptions("value is not a 64-bit integer"));
+        }
+        let value_pos = entry.position() + entry.key_size as u64;
+        let mut buf = [0; COUNTER_SIZE as usize];
+        buf.copy_from_slice(self.get_data(value_pos, COUNTER_SIZE));
+        let value = i64::from_le_bytes(buf).checked_add(delta).ok_or(Error::TooLarge)?;
+        if self.options.checksums && !has_checksum {
+            // The block has no room for a checksum, so the counter is moved to a new block
+            self.set_entry(Entry { key, value: &value.to_le_bytes(), flags: entry.flags })?;
+            return Ok(value);
+        }
+        self.log_set(key, &value.to_le_bytes(), entry.flags)?;
+        self.get_data_mut(value_pos, COUNTER_SIZE).copy_from_slice(&value.to_le_bytes());
+        if has_checksum {
+            let checksum = checksum::checksum(self.get_data(entry.position(), content_size));
+            self.get_data_mut(entry.position() + content_size, CHECKSUM_SIZE as Size)
+                .copy_from_slice(&checksum.to_le_bytes());
+        }
+        self.maybe_flush()?;
+        Ok(value)
+    }
+
+    /// Subtracts `delta` from the counter stored under the given key and returns the new value of the counter.
+    ///
+    /// See [`Table::increment`] for details.
+    #[inline]
+    pub fn decrement(&mut self, key: &[u8], delta: i64) -> Result<i64, Error> {
+        self.increment(key, delta.checked_neg().ok_or(Error::TooLarge)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wal::wal_path;
+    use std::fs;
+
+    #[test]
+    fn test_increment() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::create(file.path()).unwrap();
+        assert_eq!(tbl.increment("a".as_bytes(), 5).unwrap(), 5);
+        assert_eq!(tbl.increment("a".as_bytes(), -7).unwrap(), -2);
+        assert_eq!(tbl.decrement("a".as_bytes(), 3).unwrap(), -5);
+        assert_eq!(tbl.get("a".as_bytes()), Some(&(-5i64).to_le_bytes()[..]));
+        tbl.set("max".as_bytes(), &i64::MAX.to_le_bytes()).unwrap();
+        assert!(matches!(tbl.increment("max".as_bytes(), 1), Err(Error::TooLarge)));
+        assert!(matches!(tbl.decrement("a".as_bytes(), i64::MIN), Err(Error::TooLarge)));
+        assert_eq!(tbl.get("max".as_bytes()), Some(&i64::MAX.to_le_bytes()[..]));
+        tbl.set("text".as_bytes(), "abc".as_bytes()).unwrap();
+        assert!(matches!(tbl.increment("text".as_bytes(), 1), Err(Error::InvalidOptions(_))));
+        assert_eq!(tbl.get("text".as_bytes()), Some("abc".as_bytes()));
+        tbl.close();
+        // Counters written without checksums get one when the table uses checksums
+        let mut tbl = Table::options().checksums(true).open(file.path()).unwrap();
+        assert_eq!(tbl.increment("a".as_bytes(), 10).unwrap(), 5);
+        assert_eq!(tbl.increment("a".as_bytes(), 10).unwrap(), 15);
+        assert_eq!(tbl.get_checked("a".as_bytes()).unwrap(), Some(&15i64.to_le_bytes()[..]));
+        assert!(tbl.verify_checksums().is_empty());
+        assert!(tbl.is_valid());
+    }
+
+    #[test]
+    fn test_increment_wal() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::options().wal(true).create(file.path()).unwrap();
+        for _ in 0..10 {
+            tbl.increment("counter".as_bytes(), 2).unwrap();
+        }
+        let wal = fs::read(wal_path(file.path())).unwrap();
+        tbl.close();
+        // Simulate a crash where none of the changes reached the table file
+        Table::create(file.path()).unwrap().close();
+        fs::write(wal_path(file.path()), &wal).unwrap();
+        let tbl = Table::options().wal(true).open(file.path()).unwrap();
+        assert_eq!(tbl.get("counter".as_bytes()), Some(&20i64.to_le_bytes()[..]));
+    }
+}
@@ -0,0 +1,154 @@
+use crate::{index::IndexEntry, Table};
+
+/// Difference between two tables as returned by [`Table::diff`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffItem<'a> {
+    /// The entry only exists in the other table
+    Added {
+        /// Key of the entry
+        key: &'a [u8],
+        /// Value in the other table
+        value: &'a [u8],
+    },
+    /// The entry only exists in this table
+    Removed {
+        /// Key of the entry
+        key: &'a [u8],
+        /// Value in this table
+        value: &'a [u8],
+    },
+    /// The entry exists in both tables with different values
+    Changed {
+        /// Key of the entry
+        key: &'a [u8],
+        /// Value in this table
+        old: &'a [u8],
+        /// Value in the other table
+        new: &'a [u8],
+    },
+}
+
+/// Internal iterator over the differences between two tables
+struct Diff<'a> {
+    pos: usize,
+    mine: &'a Table,
+    theirs: &'a Table,
+}
+
+impl<'a> Diff<'a> {
+    /// Compares an index entry of this table (`from_mine`) or the other table with the respective other table
+    fn compare(&self, entry: &IndexEntry, from_mine: bool) -> Option<DiffItem<'a>> {
+        let (from, to) = if from_mine { (self.mine, self.theirs) } else { (self.theirs, self.mine) };
+        let data = from.entry_from_index_data(entry.data);
+        // The stored hash is used, so hash key tables can be compared as well
+        match (to.get_entry_hashed(entry.hash, data.key), from_mine) {
+            (None, true) => Some(DiffItem::Removed { key: data.key, value: data.value }),
+            (None, false) => Some(DiffItem::Added { key: data.key, value: data.value }),
+            (Some(other), true) if other.value != data.value => {
+                Some(DiffItem::Changed { key: data.key, old: data.value, new: other.value })
+            }
+            _ => None,
+        }
+    }
+}
+
+impl<'a> Iterator for Diff<'a> {
+    type Item = DiffItem<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mine = self.mine.index.get_entries();
+        let theirs = self.theirs.index.get_entries();
+        while self.pos < mine.len() + theirs.len() {
+            let pos = self.pos;
+            self.pos += 1;
+            let (entry, from_mine) =
+                if pos < mine.len() { (&mine[pos], true) } else { (&theirs[pos - mine.len()], false) };
+            if !entry.is_used() {
+                continue;
+            }
+            if let Some(item) = self.compare(entry, from_mine) {
+                return Some(item);
+            }
+        }
+        None
+    }
+}
+
+impl Table {
+    /// Returns an iterator over all differences between this table and the other table.
+    ///
+    /// Entries that only exist in the other table are returned as [`DiffItem::Added`], entries that only exist in
+    /// this table as [`DiffItem::Removed`] and entries with different values as [`DiffItem::Changed`]. The items are
+    /// returned in no particular order. Entry flags are not compared.
+    ///
+    /// Both tables are compared via lookups, so no entries are copied. Both tables need to have the same kind of keys,
+    /// i.e. either both or none of them have to be created via [`HashKeyTable`](crate::HashKeyTable).
+    ///
+    /// ```
+    /// use rust_persist::{DiffItem, Table};
+    ///
+    /// let mut old = Table::create("example_diff1.tbl").unwrap();
+    /// old.set("key1".as_bytes(), "value1".as_bytes()).unwrap();
+    /// let mut new = Table::create("example_diff2.tbl").unwrap();
+    /// new.set("key1".as_bytes(), "value2".as_bytes()).unwrap();
+    /// let diff: Vec<_> = old.diff(&new).collect();
+    /// assert_eq!(diff, [DiffItem::Changed { key: b"key1", old: b"value1", new: b"value2" }]);
+    /// ```
+    #[inline]
+    pub fn diff<'a>(&'a self, other: &'a Table) -> impl Iterator<Item = DiffItem<'a>> + 'a {
+        Diff { pos: 0, mine: self, theirs: other }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::HashKeyTable;
+
+    #[test]
+    fn test_diff() {
+        let file1 = tempfile::NamedTempFile::new().unwrap();
+        let file2 = tempfile::NamedTempFile::new().unwrap();
+        let mut mine = Table::create(file1.path()).unwrap();
+        let mut theirs = Table::create(file2.path()).unwrap();
+        assert_eq!(mine.diff(&theirs).count(), 0);
+        for i in 0u16..100 {
+            mine.set(&i.to_ne_bytes(), &[1]).unwrap();
+        }
+        for i in 50u16..200 {
+            theirs.set(&i.to_ne_bytes(), &[if i < 60 { 1 } else { 2 }]).unwrap();
+        }
+        let (mut added, mut removed, mut changed) = (0, 0, 0);
+        for item in mine.diff(&theirs) {
+            match item {
+                DiffItem::Added { key, value } => {
+                    assert!(mine.get(key).is_none());
+                    assert_eq!(value, [2]);
+                    added += 1;
+                }
+                DiffItem::Removed { key, value } => {
+                    assert!(theirs.get(key).is_none());
+                    assert_eq!(value, [1]);
+                    removed += 1;
+                }
+                DiffItem::Changed { key, old, new } => {
+                    assert_eq!((mine.get(key), theirs.get(key)), (Some(old), Some(new)));
+                    changed += 1;
+                }
+            }
+        }
+        assert_eq!((added, removed, changed), (100, 50, 40));
+        assert_eq!(theirs.diff(&mine).count(), 190);
+        assert_eq!(mine.diff(&mine).count(), 0);
+        let file3 = tempfile::NamedTempFile::new().unwrap();
+        let file4 = tempfile::NamedTempFile::new().unwrap();
+        let mut hash1 = HashKeyTable::create(file3.path()).unwrap();
+        let mut hash2 = HashKeyTable::create(file4.path()).unwrap();
+        hash1.set(0, &[1]).unwrap();
+        hash1.set(1, &[1]).unwrap();
+        hash2.set(0, &[2]).unwrap();
+        hash2.set(1, &[1]).unwrap();
+        let diff: Vec<_> = hash1.inner().diff(hash2.inner()).collect();
+        assert_eq!(diff, [DiffItem::Changed { key: &0u64.to_le_bytes(), old: &[1], new: &[2] }]);
+    }
+}
@@ -0,0 +1,265 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    backup::write_atomic,
+    batch::{checksum, journal_path, read_bytes, read_u32, read_u64},
+    mmap::lock_fd,
+    reader::readers_path,
+    wal::wal_path,
+    Error, LockMode, Table, TableOptions,
+};
+
+const REGISTRY_HEADER: [u8; 16] = *b"rust-persist-e1\n";
+
+/// Name of the file in the directory of an [`Env`] that lists its tables
+const REGISTRY_FILE: &str = "tables";
+
+/// Name of the file in the directory of an [`Env`] that is locked while the environment is open
+const LOCK_FILE: &str = "lock";
+
+/// A table registered in an [`Env`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnvTable {
+    /// Name of the table, the table is stored as `NAME.tbl` in the directory of the environment
+    pub name: String,
+    /// Application-defined identifier of the format of the keys and values, given on creation
+    pub schema: u64,
+}
+
+fn encode_registry(tables: &[EnvTable]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    buf.extend_from_slice(&REGISTRY_HEADER);
+    buf.extend_from_slice(&(tables.len() as u32).to_le_bytes());
+    for table in tables {
+        buf.extend_from_slice(&(table.name.len() as u32).to_le_bytes());
+        buf.extend_from_slice(table.name.as_bytes());
+        buf.extend_from_slice(&table.schema.to_le_bytes());
+    }
+    let checksum = checksum(&buf);
+    buf.extend_from_slice(&checksum.to_le_bytes());
+    buf
+}
+
+fn decode_registry(content: &[u8]) -> Option<Vec<EnvTable>> {
+    let data = &mut &content[..];
+    if read_bytes(data, REGISTRY_HEADER.len())? != REGISTRY_HEADER {
+        return None;
+    }
+    let count = read_u32(data)? as usize;
+    let mut tables = Vec::new();
+    for _ in 0..count {
+        let len = read_u32(data)? as usize;
+        let name = String::from_utf8(read_bytes(data, len)?).ok()?;
+        tables.push(EnvTable { name, schema: read_u64(data)? });
+    }
+    let content = &content[..content.len() - data.len()];
+    if read_u64(data)? != checksum(content) || !data.is_empty() {
+        return None;
+    }
+    Some(tables)
+}
+
+/// Returns whether the name can be used as a file name on all platforms
+fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('.')
+        && name.len() <= 200
+        && name.bytes().all(|b| b.is_ascii_alphanumeric() || b"-_.".contains(&b))
+}
+
+/// A directory of named tables with a registry of the existing tables
+///
+/// Applications that manage many table files can use an environment to create, list, open and drop tables by name
+/// instead of handling their paths. The registry is stored in the file `tables` in the directory and records the
+/// name and an application-defined schema identifier of every table, e.g. to detect tables that need a migration.
+/// The options of a table are not recorded, as the settings that determine the format are stored in the table file
+/// itself (see [`Table::info`]).
+///
+/// The registry is replaced atomically on every change. The file `lock` in the directory is locked exclusively while
+/// the environment is open, so only one [`Env`] per directory can be used at a time.
+///
+/// ```
+/// use rust_persist::{Env, Table};
+///
+/// # std::fs::remove_dir_all("example_env").ok();
+/// let mut env = Env::open("example_env").unwrap();
+/// let mut users = env.create("users", 1, Table::options()).unwrap();
+/// users.set("alice".as_bytes(), "admin".as_bytes()).unwrap();
+/// users.close();
+/// env.create("sessions", 3, Table::options()).unwrap();
+/// assert_eq!(env.list().iter().map(|table| table.name.as_str()).collect::<Vec<_>>(), ["users", "sessions"]);
+/// assert!(env.drop("sessions").unwrap());
+/// let users = env.open_table("users", Table::options()).unwrap();
+/// assert_eq!(users.get("alice".as_bytes()), Some("admin".as_bytes()));
+/// ```
+pub struct Env {
+    dir: PathBuf,
+    tables: Vec<EnvTable>,
+    _lock: File,
+}
+
+impl Env {
+    /// Opens the environment in the given directory, creating the directory if needed.
+    ///
+    /// If the environment is already open, [`Error::TableLocked`] is returned.
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self, Error> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir).map_err(Error::Io)?;
+        let lock = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(dir.join(LOCK_FILE))
+            .map_err(Error::Io)?;
+        lock_fd(&lock, false, LockMode::Exclusive)?;
+        let tables = match fs::read(dir.join(REGISTRY_FILE)) {
+            Ok(content) => decode_registry(&content).ok_or(Error::Corrupt("invalid table registry"))?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(Error::Io(err)),
+        };
+        Ok(Self { dir, tables, _lock: lock })
+    }
+
+    /// Returns all registered tables in the order of their creation
+    #[inline]
+    pub fn list(&self) -> &[EnvTable] {
+        &self.tables
+    }
+
+    /// Returns the registered table with the given name, if any
+    #[inline]
+    pub fn get(&self, name: &str) -> Option<&EnvTable> {
+        self.tables.iter().find(|table| table.name == name)
+    }
+
+    /// Returns the path of the file of the table with the given name
+    #[inline]
+    pub fn path_of(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{}.tbl", name))
+    }
+
+    fn save(&self) -> Result<(), Error> {
+        write_atomic(&self.dir.join(REGISTRY_FILE), &encode_registry(&self.tables))
+    }
+
+    /// Creates and registers a new table with the given name, schema identifier and options.
+    ///
+    /// Names may consist of ASCII letters, digits, `-`, `_` and `.` (but must not start with `.`), otherwise
+    /// [`Error::InvalidOptions`] is returned. If a table with the name is already registered, an error of the kind
+    /// [`io::ErrorKind::AlreadyExists`] is returned.
+    pub fn create(&mut self, name: &str, schema: u64, options: TableOptions) -> Result<Table, Error> {
+        if !valid_name(name) {
+            return Err(Error::InvalidOptions("table names must consist of letters, digits, '-', '_' and '.'"));
+        }
+        if self.get(name).is_some() {
+            return Err(Error::Io(io::Error::new(io::ErrorKind::AlreadyExists, "table is already registered")));
+        }
+        let table = options.create(self.path_of(name))?;
+        self.tables.push(EnvTable { name: name.to_string(), schema });
+        self.save()?;
+        Ok(table)
+    }
+
+    /// Opens the registered table with the given name using the given options.
+    ///
+    /// If no table with the name is registered, an error of the kind [`io::ErrorKind::NotFound`] is returned.
+    pub fn open_table(&self, name: &str, options: TableOptions) -> Result<Table, Error> {
+        if self.get(name).is_none() {
+            return Err(Error::Io(io::Error::new(io::ErrorKind::NotFound, "table is not registered")));
+        }
+        options.open(self.path_of(name))
+    }
+
+    /// Changes the schema identifier of the registered table with the given name, e.g. after a migration.
+    ///
+    /// Returns whether the table is registered.
+    pub fn set_schema(&mut self, name: &str, schema: u64) -> Result<bool, Error> {
+        match self.tables.iter_mut().find(|table| table.name == name) {
+            Some(table) => table.schema = schema,
+            None => return Ok(false),
+        }
+        self.save()?;
+        Ok(true)
+    }
+
+    /// Deletes the table with the given name and removes it from the registry.
+    ///
+    /// The table is locked until its files have been deleted, so [`Error::TableLocked`] is returned if it is still in
+    /// use and it cannot be opened while it is being deleted. Damaged tables are deleted as well.
+    /// Returns whether the table has been registered.
+    pub fn drop(&mut self, name: &str) -> Result<bool, Error> {
+        let pos = match self.tables.iter().position(|table| table.name == name) {
+            Some(pos) => pos,
+            None => return Ok(false),
+        };
+        let path = self.path_of(name);
+        // Only the lock is taken, so damaged tables can be dropped as well. The lock is held until the file is removed.
+        let _lock = match File::open(&path) {
+            Ok(fd) => {
+                lock_fd(&fd, false, LockMode::Exclusive)?;
+                Some(fd)
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+            Err(err) => return Err(Error::Io(err)),
+        };
+        self.tables.remove(pos);
+        self.save()?;
+        for file in &[path.clone(), wal_path(&path), journal_path(&path), readers_path(&path)] {
+            match fs::remove_file(file) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(Error::Io(err)),
+                _ => (),
+            }
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_env() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut env = Env::open(dir.path()).unwrap();
+        assert!(env.list().is_empty());
+        let mut tbl = env.create("users", 1, Table::options()).unwrap();
+        tbl.set("alice".as_bytes(), &[1]).unwrap();
+        env.create("cache-v2.tmp", 7, Table::options().wal(true)).unwrap().close();
+        assert!(matches!(env.create("users", 1, Table::options()), Err(Error::Io(_))));
+        for name in &["", ".hidden", "a/b", "a b"] {
+            assert!(matches!(env.create(name, 1, Table::options()), Err(Error::InvalidOptions(_))));
+        }
+        // The table is still open, so it cannot be dropped
+        assert!(matches!(env.drop("users"), Err(Error::TableLocked)));
+        tbl.close();
+        assert!(env.set_schema("users", 2).unwrap());
+        assert!(!env.set_schema("missing", 2).unwrap());
+        // The environment is locked while it is open
+        assert!(matches!(Env::open(dir.path()), Err(Error::TableLocked)));
+        std::mem::drop(env);
+        let env = Env::open(dir.path()).unwrap();
+        let expected = [
+            EnvTable { name: "users".to_string(), schema: 2 },
+            EnvTable { name: "cache-v2.tmp".to_string(), schema: 7 },
+        ];
+        assert_eq!(env.list(), &expected[..]);
+        let tbl = env.open_table("users", Table::options()).unwrap();
+        assert_eq!(tbl.get("alice".as_bytes()), Some(&[1][..]));
+        tbl.close();
+        assert!(matches!(env.open_table("missing", Table::options()), Err(Error::Io(_))));
+        let mut env = env;
+        assert!(env.drop("cache-v2.tmp").unwrap());
+        assert!(!env.drop("cache-v2.tmp").unwrap());
+        assert!(!env.path_of("cache-v2.tmp").exists());
+        std::mem::drop(env);
+        assert_eq!(Env::open(dir.path()).unwrap().list(), &expected[..1]);
+        fs::write(dir.path().join(REGISTRY_FILE), b"garbage").unwrap();
+        assert!(matches!(Env::open(dir.path()), Err(Error::Corrupt(_))));
+    }
+}
@@ -0,0 +1,191 @@
+use crate::{
+    history::FLAG_STATS,
+    index::is_namespaced,
+    mailbox::FLAG_MAILBOX,
+    namespace::FLAG_NAMESPACE,
+    table::{hash_key, match_key},
+    Error, Table, FLAG_PINNED,
+};
+
+/// Entry flag that marks entries that have been used since the eviction scan last passed them
+pub(crate) const FLAG_REFERENCED: u16 = 1 << 8;
+
+/// Entries with any of these flags are never evicted
+const NOT_EVICTABLE: u16 = FLAG_PINNED | FLAG_STATS | FLAG_MAILBOX;
+
+impl Table {
+    /// Returns the value of the given key and marks the entry as recently used.
+    ///
+    /// With [`TableOptions::max_data_size`](crate::TableOptions::max_data_size), entries that have not been used
+    /// recently are evicted first. Storing an entry marks it as used as well, but [`Table::get`] does not, as it
+    /// cannot modify the table. The mark is stored in the entry flags and survives reopening the table.
+    ///
+    /// ```
+    /// use rust_persist::Table;
+    ///
+    /// let options = Table::options().max_data_size(Some(1000));
+    /// let mut table = options.create("example_evict.tbl").unwrap();
+    /// for i in 0u32..100 {
+    ///     table.set(&i.to_le_bytes(), &[0; 96]).unwrap();
+    ///     // Keep the first entry by using it regularly
+    ///     table.touch(&0u32.to_le_bytes());
+    /// }
+    /// assert!(table.len() < 100);
+    /// assert!(table.contains(&0u32.to_le_bytes()));
+    /// assert!(table.contains(&99u32.to_le_bytes()));
+    /// ```
+    pub fn touch(&mut self, key: &[u8]) -> Option<&[u8]> {
+        let hash = hash_key(key);
+        let read_only = self.check_writable().is_err();
+        let (data, data_start) = (&self.data, self.data_start);
+        let entry = match self.index.index_get_mut(hash, |e| match_key(e, data, data_start, key)) {
+            Some(entry) if is_namespaced(entry) => return None,
+            Some(entry) if !read_only => {
+                entry.flags |= FLAG_REFERENCED;
+                *entry
+            }
+            Some(entry) => *entry,
+            None => return None,
+        };
+        Some(self.entry_from_index_data(entry).value)
+    }
+
+    /// Evicts entries until `size` more bytes can be stored without exceeding the configured maximum data size.
+    ///
+    /// The entries are scanned in index order like the hand of a clock: entries that have been used since the last
+    /// pass are only unmarked, the others are evicted. The entry of the given key is not evicted, as it is about to be
+    /// overwritten and its new value might already be in the write-ahead log. Entries of namespaces are evicted like
+    /// plain entries, but the registered names are kept.
+    ///
+    /// If not enough entries can be evicted, [`Error::DataLimit`] is returned. The evicted entries stay deleted.
+    pub(crate) fn maybe_evict(&mut self, size: u64, key: Option<&[u8]>) -> Result<(), Error> {
+        let limit = match self.options.max_data_size {
+            Some(limit) => limit,
+            None => return Ok(()),
+        };
+        if size > limit {
+            return Err(Error::TooLarge);
+        }
+        // Every entry is unmarked in the first pass, so two passes find all candidates
+        let mut remaining = 2 * self.index.capacity();
+        while self.mem.used_size() + size > limit && remaining > 0 {
+            remaining -= 1;
+            let slot = self.evict_hand % self.index.capacity();
+            let entry = &self.index.get_entries()[slot];
+            let (used, data) = (entry.is_used(), entry.data);
+            if !used || data.flags & NOT_EVICTABLE != 0 {
+                self.evict_hand = slot + 1;
+                continue;
+            }
+            if data.flags & FLAG_REFERENCED != 0 {
+                self.index.slot_data_mut(slot).flags &= !FLAG_REFERENCED;
+                self.evict_hand = slot + 1;
+                continue;
+            }
+            let old = self.entry_from_index_data(data);
+            // The names of namespaces are registered under keys starting with a zero byte
+            let registry = data.flags & FLAG_NAMESPACE != 0 && old.key.first() == Some(&0);
+            if Some(old.key) == key || registry {
+                self.evict_hand = slot + 1;
+                continue;
+            }
+            let old_key = old.key.to_vec();
+            self.log_delete(&old_key)?;
+            // The following entries are shifted back into this slot, so the hand stays in place
+            self.index.delete_located(slot);
+            self.free_data(data.position());
+            self.run_hooks(|hooks, tbl| hooks.delete(&old_key, tbl.entry_from_index_data(data).value));
+            self.evicted += 1;
+        }
+        if self.mem.used_size() + size > limit {
+            return Err(Error::DataLimit { needed: self.mem.used_size() + size, limit });
+        }
+        Ok(())
+    }
+
+    /// Returns the number of entries that have been evicted since the table has been opened, see
+    /// [`TableOptions::max_data_size`](crate::TableOptions::max_data_size).
+    #[inline]
+    pub fn evicted(&self) -> u64 {
+        self.evicted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CheckLevel, TableOptions};
+
+    #[test]
+    fn test_evict() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let options = TableOptions::default().max_data_size(Some(10_000)).checksums(true);
+        let mut tbl = options.clone().create(file.path()).unwrap();
+        tbl.set("pinned".as_bytes(), &[1; 100]).unwrap();
+        tbl.pin_front("pinned".as_bytes()).unwrap();
+        for i in 0u32..1000 {
+            tbl.set(&i.to_le_bytes(), &[0; 96]).unwrap();
+            if i % 10 == 0 {
+                tbl.touch(&0u32.to_le_bytes()).unwrap();
+            }
+            assert!(tbl.mem.used_size() <= 10_000);
+        }
+        assert!(tbl.evicted() > 800);
+        assert_eq!(tbl.len() as u64 + tbl.evicted(), 1001);
+        assert!(tbl.contains("pinned".as_bytes()));
+        assert!(tbl.contains(&0u32.to_le_bytes()));
+        assert!(tbl.contains(&999u32.to_le_bytes()));
+        assert!(!tbl.contains(&500u32.to_le_bytes()));
+        assert!(tbl.verify(CheckLevel::Full).is_ok());
+        // Overwriting an entry never evicts the entry itself
+        tbl.set(&999u32.to_le_bytes(), &[2; 500]).unwrap();
+        assert_eq!(tbl.get(&999u32.to_le_bytes()), Some(&[2; 500][..]));
+        assert!(matches!(tbl.set("huge".as_bytes(), &[0; 20_000]), Err(Error::TooLarge)));
+        tbl.close();
+        let mut tbl = options.open(file.path()).unwrap();
+        assert_eq!(tbl.evicted(), 0);
+        assert!(tbl.get_raw_entry(&0u32.to_le_bytes()).unwrap().flags & FLAG_REFERENCED != 0);
+        assert_eq!(tbl.get_entry(&0u32.to_le_bytes()).unwrap().flags, crate::FLAG_CHECKSUM);
+        tbl.set_many((2000u32..2050).map(|i| (i.to_le_bytes(), [0; 96]))).unwrap();
+        assert!(tbl.mem.used_size() <= 10_000);
+        assert!(tbl.contains(&2049u32.to_le_bytes()));
+        assert!(tbl.is_valid());
+    }
+
+    #[test]
+    fn test_evict_all_writes() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = TableOptions::default().max_data_size(Some(10_000)).create(file.path()).unwrap();
+        for i in 0u32..200 {
+            tbl.namespace("a").unwrap().set(&i.to_le_bytes(), &[0; 96]).unwrap();
+            assert!(tbl.mem.used_size() <= 10_000);
+        }
+        assert!(tbl.namespace("a").unwrap().len() < 200);
+        assert_eq!(tbl.namespaces(), ["a"]);
+        for i in 0u32..200 {
+            tbl.update(&i.to_le_bytes(), |_| Some(vec![1; 96])).unwrap();
+            assert!(tbl.mem.used_size() <= 10_000);
+        }
+        for _ in 0..100 {
+            tbl.append("log".as_bytes(), &[2; 50]).unwrap();
+            assert!(tbl.mem.used_size() <= 10_000);
+        }
+        assert_eq!(tbl.get("log".as_bytes()).unwrap().len(), 5000);
+        let evicted = tbl.evicted();
+        let mut writer = tbl.writer("stream".as_bytes()).unwrap();
+        std::io::Write::write_all(&mut writer, &[3; 4000]).unwrap();
+        writer.finish().unwrap();
+        assert!(tbl.mem.used_size() <= 10_000);
+        assert!(tbl.evicted() > evicted);
+        assert!(tbl.is_valid());
+        // If all entries are pinned, the limit cannot be met
+        let keys: Vec<Vec<u8>> = tbl.iter().map(|entry| entry.key.to_vec()).collect();
+        for key in &keys {
+            tbl.pin_front(key).unwrap();
+        }
+        assert!(matches!(tbl.set("new".as_bytes(), &[0; 5000]), Err(Error::DataLimit { limit: 10_000, .. })));
+        assert!(!tbl.contains("new".as_bytes()));
+        assert!(tbl.mem.used_size() <= 10_000);
+        assert!(tbl.is_valid());
+    }
+}
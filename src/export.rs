@@ -0,0 +1,227 @@
+use std::{
+    hash::Hasher,
+    io::{self, BufReader, BufWriter, Read, Write},
+};
+
+use siphasher::sip::SipHasher13;
+
+use crate::{redact::redact, Entry, Error, Redaction, Table, FLAG_CHECKSUM};
+
+const EXPORT_HEADER: [u8; 16] = *b"rust-persist-x1\n";
+const END_MARKER: u32 = u32::MAX;
+
+/// Writer that computes a checksum over all written bytes
+struct HashingWriter<W> {
+    inner: W,
+    hasher: SipHasher13,
+}
+
+impl<W: Write> HashingWriter<W> {
+    fn write_all(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.hasher.write(data);
+        self.inner.write_all(data).map_err(Error::Io)
+    }
+}
+
+/// Reader that computes a checksum over all read bytes
+struct HashingReader<R> {
+    inner: R,
+    hasher: SipHasher13,
+}
+
+impl<R: Read> HashingReader<R> {
+    fn read_bytes(&mut self, len: usize) -> Result<Vec<u8>, Error> {
+        let mut buf = vec![0; len];
+        self.inner.read_exact(&mut buf).map_err(|err| match err.kind() {
+            io::ErrorKind::UnexpectedEof => Error::Corrupt("export stream is truncated"),
+            _ => Error::Io(err),
+        })?;
+        self.hasher.write(&buf);
+        Ok(buf)
+    }
+
+    fn read_u16(&mut self) -> Result<u16, Error> {
+        let mut buf = [0; 2];
+        buf.copy_from_slice(&self.read_bytes(2)?);
+        Ok(u16::from_le_bytes(buf))
+    }
+
+    fn read_u32(&mut self) -> Result<u32, Error> {
+        let mut buf = [0; 4];
+        buf.copy_from_slice(&self.read_bytes(4)?);
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    fn read_u64(&mut self) -> Result<u64, Error> {
+        let mut buf = [0; 8];
+        buf.copy_from_slice(&self.read_bytes(8)?);
+        Ok(u64::from_le_bytes(buf))
+    }
+}
+
+impl Table {
+    /// Writes all entries of the table to the given writer in a portable stream format.
+    ///
+    /// In contrast to the table file, the stream does not depend on the memory layout, the byte order or the format
+    /// version of the table and contains no free space. It starts with a header followed by one record per entry
+    /// (little-endian key length, value length and flags followed by the key and the value) and ends with the number
+    /// of records and a checksum. Use [`Table::import_from`] to read it.
+    ///
+    /// Returns the number of exported entries. Tables created via [`HashKeyTable`](crate::HashKeyTable) are not
+    /// supported.
+    #[inline]
+    pub fn export_to<W: Write>(&self, writer: W) -> Result<usize, Error> {
+        self.export_redacted_to(writer, |_| Redaction::Keep)
+    }
+
+    /// Writes the entries of the table to the given writer like [`Table::export_to`], filtered by `filter`.
+    ///
+    /// The filter is called for every entry and decides via [`Redaction`] whether it is written as it is, left out
+    /// or written with another value, e.g. to produce a dump without sensitive data. Returns the number of exported
+    /// entries.
+    ///
+    /// ```
+    /// use rust_persist::{Redaction, Table};
+    ///
+    /// let mut table = Table::create("example_export_redacted.tbl").unwrap();
+    /// table.set("user:1".as_bytes(), "alice".as_bytes()).unwrap();
+    /// table.set("password:1".as_bytes(), "hunter2".as_bytes()).unwrap();
+    /// let mut dump = Vec::new();
+    /// table
+    ///     .export_redacted_to(&mut dump, |entry| {
+    ///         if entry.key.starts_with(b"password:") {
+    ///             Redaction::Replace(b"***".to_vec())
+    ///         } else {
+    ///             Redaction::Keep
+    ///         }
+    ///     })
+    ///     .unwrap();
+    /// ```
+    pub fn export_redacted_to<W: Write, F: FnMut(&Entry<'_>) -> Redaction>(
+        &self, writer: W, mut filter: F,
+    ) -> Result<usize, Error> {
+        self.check_byte_keys()?;
+        let mut out = HashingWriter { inner: BufWriter::new(writer), hasher: SipHasher13::new() };
+        out.write_all(&EXPORT_HEADER)?;
+        let mut count = 0;
+        for entry in self.iter() {
+            let value = match redact(&mut filter, &entry) {
+                Some(value) => value,
+                None => continue,
+            };
+            if value.len() as u64 > u32::MAX as u64 {
+                // value lengths are limited to 32 bits in the stream format
+                return Err(Error::TooLarge);
+            }
+            out.write_all(&(entry.key.len() as u32).to_le_bytes())?;
+            out.write_all(&(value.len() as u32).to_le_bytes())?;
+            out.write_all(&(entry.flags & !FLAG_CHECKSUM).to_le_bytes())?;
+            out.write_all(entry.key)?;
+            out.write_all(&value)?;
+            count += 1;
+        }
+        out.write_all(&END_MARKER.to_le_bytes())?;
+        out.write_all(&(count as u64).to_le_bytes())?;
+        let checksum = out.hasher.finish();
+        out.inner.write_all(&checksum.to_le_bytes()).map_err(Error::Io)?;
+        out.inner.flush().map_err(Error::Io)?;
+        Ok(count)
+    }
+
+    /// Reads all entries from a stream written by [`Table::export_to`] and stores them in the table.
+    ///
+    /// Existing entries with the same keys are overwritten, other entries are kept. Returns the number of imported
+    /// entries.
+    ///
+    /// The entries are stored while reading, so if the stream is damaged or truncated, [`Error::Corrupt`] is
+    /// returned and the entries read up to this point have already been stored.
+    pub fn import_from<R: Read>(&mut self, reader: R) -> Result<usize, Error> {
+        self.check_writable()?;
+        self.check_byte_keys()?;
+        let mut input = HashingReader { inner: BufReader::new(reader), hasher: SipHasher13::new() };
+        if input.read_bytes(EXPORT_HEADER.len())? != EXPORT_HEADER {
+            return Err(Error::WrongHeader);
+        }
+        let mut count = 0;
+        loop {
+            let key_len = input.read_u32()?;
+            if key_len == END_MARKER {
+                break;
+            }
+            let value_len = input.read_u32()?;
+            let flags = input.read_u16()?;
+            let key = input.read_bytes(key_len as usize)?;
+            let value = input.read_bytes(value_len as usize)?;
+            self.set_entry(Entry { key: &key, value: &value, flags })?;
+            count += 1;
+        }
+        let expected = input.read_u64()?;
+        let checksum = input.hasher.finish();
+        if input.read_u64()? != checksum || expected != count as u64 {
+            return Err(Error::Corrupt("export stream does not match its checksum"));
+        }
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FLAG_PINNED;
+
+    #[test]
+    fn test_export_import() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::options().checksums(true).create(file.path()).unwrap();
+        for i in 0u16..100 {
+            tbl.set(&i.to_ne_bytes(), &i.to_be_bytes()).unwrap();
+        }
+        tbl.set(&[], &[]).unwrap();
+        tbl.pin_front(&1u16.to_ne_bytes()).unwrap();
+        let mut stream = Vec::new();
+        assert_eq!(tbl.export_to(&mut stream).unwrap(), 101);
+        let file2 = tempfile::NamedTempFile::new().unwrap();
+        let mut copy = Table::create(file2.path()).unwrap();
+        assert_eq!(copy.import_from(&stream[..]).unwrap(), 101);
+        assert_eq!(copy.len(), 101);
+        for entry in tbl.iter() {
+            assert_eq!(copy.get(entry.key), Some(entry.value));
+        }
+        assert_eq!(copy.get_entry(&1u16.to_ne_bytes()).unwrap().flags, FLAG_PINNED);
+        assert!(copy.verify_checksums().is_empty());
+        copy.clear().unwrap();
+        let len = stream.len();
+        stream[len - 1] ^= 1;
+        assert!(matches!(copy.import_from(&stream[..]), Err(Error::Corrupt(_))));
+        assert!(matches!(copy.import_from(&stream[..len - 20]), Err(Error::Corrupt(_))));
+        assert!(matches!(copy.import_from(&stream[16..]), Err(Error::WrongHeader)));
+    }
+
+    #[test]
+    fn test_export_redacted() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::create(file.path()).unwrap();
+        tbl.set("user:1".as_bytes(), "alice".as_bytes()).unwrap();
+        tbl.set("token:1".as_bytes(), "abc".as_bytes()).unwrap();
+        tbl.set_entry(Entry { key: "user:2".as_bytes(), value: "bob".as_bytes(), flags: 1 }).unwrap();
+        let mut stream = Vec::new();
+        let exported = tbl
+            .export_redacted_to(&mut stream, |entry| {
+                if entry.flags & 1 != 0 {
+                    Redaction::Drop
+                } else if entry.key.starts_with(b"token:") {
+                    Redaction::Replace(b"REDACTED".to_vec())
+                } else {
+                    Redaction::Keep
+                }
+            })
+            .unwrap();
+        assert_eq!(exported, 2);
+        let file2 = tempfile::NamedTempFile::new().unwrap();
+        let mut copy = Table::create(file2.path()).unwrap();
+        assert_eq!(copy.import_from(&stream[..]).unwrap(), 2);
+        assert_eq!(copy.get("user:1".as_bytes()), Some("alice".as_bytes()));
+        assert_eq!(copy.get("token:1".as_bytes()), Some("REDACTED".as_bytes()));
+        assert!(copy.get("user:2".as_bytes()).is_none());
+    }
+}
@@ -0,0 +1,57 @@
+use crate::{mmap, Error, LockMode, Table, TableOptions};
+
+impl Table {
+    /// Opens a table from untrusted bytes, e.g. to exercise the parsing of the on-disk format in fuzzing harnesses.
+    ///
+    /// The bytes are copied into an anonymous memory map and the table is opened read-only, so it has no backing
+    /// file. The same validations as in [`Table::open_untrusted`] are applied.
+    ///
+    /// ```
+    /// use rust_persist::{Error, Table};
+    ///
+    /// assert!(matches!(Table::open_untrusted_bytes(&[0xff; 1024]), Err(Error::WrongHeader)));
+    /// ```
+    pub fn open_untrusted_bytes(bytes: &[u8]) -> Result<Table, Error> {
+        let options = TableOptions::default().read_only(true).lock(LockMode::None);
+        let mmap = mmap::map_bytes(bytes)?;
+        Self::open_validated(move || Self::from_opened(mmap::map_table(None, mmap, false, &options)?, false, options))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn test_open_untrusted() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::create(file.path()).unwrap();
+        for i in 0u16..100 {
+            tbl.set(&i.to_ne_bytes(), &i.to_be_bytes()).unwrap();
+        }
+        tbl.close();
+        let bytes = fs::read(file.path()).unwrap();
+        let mut tbl = Table::open_untrusted_bytes(&bytes).unwrap();
+        assert_eq!(tbl.len(), 100);
+        assert_eq!(tbl.get(&5u16.to_ne_bytes()), Some(&5u16.to_be_bytes() as &[u8]));
+        assert!(matches!(tbl.set(&[], &[]), Err(Error::ReadOnly)));
+        assert!(matches!(Table::open_untrusted_bytes(&bytes[..100]), Err(Error::Corrupt(_))));
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(42);
+        for _ in 0..1000 {
+            let mut bytes = bytes.clone();
+            for _ in 0..rng.gen_range(1..10) {
+                let pos = rng.gen_range(16..bytes.len());
+                bytes[pos] = rng.gen();
+            }
+            if let Ok(tbl) = Table::open_untrusted_bytes(&bytes) {
+                for entry in tbl.iter() {
+                    tbl.get(entry.key);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use crate::{index::Hash, memmngr::Size, Error, Table};
+
+/// Size of the guard region behind every data block, see
+/// [`TableOptions::guard_bytes`](crate::TableOptions::guard_bytes)
+pub(crate) const GUARD_SIZE: Size = 16;
+
+/// Byte the guard regions are filled with
+const GUARD_BYTE: u8 = 0xfd;
+
+/// Hash of the blocks of guard regions, which is never used by index entries
+pub(crate) const GUARD_HASH: Hash = 0;
+
+impl Table {
+    /// Turns the part of the block at the given position behind its first `size` bytes into a guard region
+    pub(crate) fn add_guard(&mut self, pos: u64, size: Size) {
+        if self.mem.split(pos, size, GUARD_HASH) {
+            self.get_data_mut(pos + size, GUARD_SIZE).fill(GUARD_BYTE);
+            self.unindexed += 1;
+        }
+    }
+
+    /// Returns whether the guard region at the given position still contains the guard bytes
+    #[inline]
+    pub(crate) fn guard_intact(&self, start: u64) -> bool {
+        self.get_data(start, GUARD_SIZE).iter().all(|&b| b == GUARD_BYTE)
+    }
+
+    /// Checks and frees the guard region at the given position
+    fn free_guard(&mut self, start: u64) {
+        let intact = self.guard_intact(start);
+        let position = start - self.data_start;
+        self.mem.check_invariant_at(intact, "Guard bytes behind a data block have been overwritten", position);
+        self.mem.free(start);
+        self.unindexed -= 1;
+    }
+
+    /// Checks and frees the guard region behind the data block at the given position, if it has one
+    pub(crate) fn remove_guard(&mut self, pos: u64) {
+        let end = match self.mem.block_at(pos) {
+            Some(block) => block.end(),
+            None => return,
+        };
+        match self.mem.next_used(end) {
+            Some(block) if block.hash == GUARD_HASH && block.size == GUARD_SIZE => {
+                let start = block.start;
+                self.free_guard(start)
+            }
+            _ => (),
+        }
+    }
+
+    /// Checks and frees all guard regions, e.g. before the data section is defragmented
+    pub(crate) fn remove_guards(&mut self) -> Result<(), Error> {
+        let guards: Vec<_> = self.mem.get_used().iter().filter(|b| b.hash == GUARD_HASH).map(|b| b.start).collect();
+        for start in guards {
+            self.free_guard(start);
+        }
+        match self.mem.violation() {
+            Some(reason) => Err(Error::Corrupt(reason)),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CheckLevel, Finding};
+    use std::panic::{self, AssertUnwindSafe};
+
+    #[test]
+    fn test_guard_bytes() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::options().guard_bytes(true).create(file.path()).unwrap();
+        for i in 0u16..200 {
+            tbl.set(&i.to_ne_bytes(), &[i as u8; 10]).unwrap();
+        }
+        for i in 0u16..200 {
+            tbl.append(&i.to_ne_bytes(), &[0; 20]).unwrap();
+        }
+        assert!(tbl.unindexed > 0);
+        assert!(tbl.verify(CheckLevel::Full).is_ok());
+        for i in 0u16..150 {
+            tbl.delete(&i.to_ne_bytes()).unwrap();
+        }
+        assert!(tbl.verify(CheckLevel::Full).is_ok());
+        tbl.defragment().unwrap();
+        assert_eq!(tbl.unindexed, 0);
+        tbl.set("key".as_bytes(), "value".as_bytes()).unwrap();
+        assert_eq!(tbl.unindexed, 1);
+        assert_eq!(tbl.get(&199u16.to_ne_bytes()).unwrap().len(), 30);
+        assert!(tbl.is_valid());
+        // Simulate a write past the end of the value
+        let entry = tbl.index.get_entries().iter().find(|e| e.is_used() && e.data.key_size == 3).unwrap().data;
+        let end = entry.position() + entry.size();
+        tbl.get_data_mut(end, 1)[0] = 0;
+        let position = end - tbl.data_start;
+        assert_eq!(tbl.verify(CheckLevel::Full).findings, vec![Finding::GuardOverwritten { position }]);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| tbl.delete("key".as_bytes()).map(|_| ())));
+        if cfg!(feature = "no-panic") {
+            assert!(result.unwrap().is_ok());
+            assert!(matches!(tbl.set("key".as_bytes(), &[]), Err(Error::Corrupt(_))));
+        } else {
+            assert!(result.is_err());
+        }
+    }
+}
@@ -0,0 +1,263 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+};
+
+use crate::{CheckLevel, Table, TableOptions};
+
+/// Operation executed by the [`Harness`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Op {
+    /// Stores the value for the key and compares the replaced value
+    Set(Vec<u8>, Vec<u8>),
+    /// Deletes the key and compares the deleted value
+    Delete(Vec<u8>),
+    /// Compares the value of the key
+    Get(Vec<u8>),
+    /// Flushes the table
+    Flush,
+    /// Defragments the table
+    Defragment,
+    /// Deletes all entries
+    Clear,
+    /// Closes and reopens the table
+    Reopen,
+}
+
+/// Failed run of the [`Harness`]
+#[derive(Debug, Clone)]
+pub struct Failure {
+    /// The operations that reproduce the failure
+    pub ops: Vec<Op>,
+    /// Index of the operation that failed, `ops.len()` if the final check failed
+    pub step: usize,
+    /// Description of the failure
+    pub message: String,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Step {} of {} failed: {}", self.step, self.ops.len(), self.message)?;
+        for (i, op) in self.ops.iter().enumerate() {
+            writeln!(f, "{:4}: {:?}", i, op)?;
+        }
+        Ok(())
+    }
+}
+
+/// Small deterministic random number generator (SplitMix64), so that sequences are reproducible from a seed
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, max: u64) -> u64 {
+        self.next() % max
+    }
+
+    fn key(&mut self) -> Vec<u8> {
+        // A small key space, so that keys are overwritten and deleted frequently
+        let key = self.below(256) as u8;
+        vec![key; self.below(4) as usize]
+    }
+
+    fn value(&mut self) -> Vec<u8> {
+        let len = if self.below(50) == 0 { self.below(20_000) } else { self.below(200) };
+        (0..len).map(|_| self.next() as u8).collect()
+    }
+}
+
+/// Differential testing harness that runs operation sequences against a [`Table`] and a [`HashMap`]
+///
+/// After every operation, the results of both are compared. After reopening and defragmenting the table and at the
+/// end of a run, the whole content is compared and the table is checked via [`Table::verify`]. Panics of the table
+/// are reported as failures.
+///
+/// Failing random sequences are shrunk to a minimal sequence of operations that still fails, which can be replayed
+/// via [`Harness::run`].
+///
+/// This harness is only available with the `test-utils` feature.
+///
+/// ```
+/// use rust_persist::{Harness, Table};
+///
+/// let harness = Harness::new("example_harness.tbl").options(Table::options().checksums(true));
+/// for seed in 0..3 {
+///     if let Err(failure) = harness.run_random(seed, 200) {
+///         panic!("{}", failure);
+///     }
+/// }
+/// ```
+pub struct Harness {
+    path: PathBuf,
+    options: TableOptions,
+}
+
+impl Harness {
+    /// Creates a harness that creates its table at the given path (overwriting an existing table).
+    #[inline]
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self { path: path.as_ref().to_path_buf(), options: TableOptions::default() }
+    }
+
+    /// Sets the options used to create and reopen the table.
+    #[inline]
+    pub fn options(mut self, options: TableOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Generates a random sequence of operations from the given seed
+    pub fn random_ops(seed: u64, count: usize) -> Vec<Op> {
+        let mut rng = Rng(seed);
+        (0..count)
+            .map(|_| match rng.below(100) {
+                0..=49 => Op::Set(rng.key(), rng.value()),
+                50..=69 => Op::Delete(rng.key()),
+                70..=89 => Op::Get(rng.key()),
+                90..=92 => Op::Flush,
+                93..=95 => Op::Defragment,
+                96 => Op::Clear,
+                _ => Op::Reopen,
+            })
+            .collect()
+    }
+
+    /// Runs a random sequence of operations and returns the shrunk sequence if it fails.
+    pub fn run_random(&self, seed: u64, count: usize) -> Result<(), Failure> {
+        let ops = Self::random_ops(seed, count);
+        if self.run(&ops).is_ok() {
+            return Ok(());
+        }
+        let ops = minimize(ops, |ops| self.run(ops).is_err());
+        self.run(&ops)
+    }
+
+    /// Runs the given operations and compares the results.
+    pub fn run(&self, ops: &[Op]) -> Result<(), Failure> {
+        let mut step = 0;
+        let result = panic::catch_unwind(AssertUnwindSafe(|| self.run_inner(ops, &mut step)));
+        let message = match result {
+            Ok(Ok(())) => return Ok(()),
+            Ok(Err(message)) => message,
+            Err(panic) => match panic.downcast_ref::<&str>() {
+                Some(message) => format!("panicked: {}", message),
+                None => format!("panicked: {}", panic.downcast_ref::<String>().map_or("unknown", |s| s)),
+            },
+        };
+        Err(Failure { ops: ops.to_vec(), step, message })
+    }
+
+    fn run_inner(&self, ops: &[Op], step: &mut usize) -> Result<(), String> {
+        let mut tbl = self.options.clone().create(&self.path).map_err(|err| format!("create failed: {}", err))?;
+        let mut map = HashMap::new();
+        for op in ops {
+            match op {
+                Op::Set(key, value) => {
+                    let old = tbl.set(key, value).map_err(|err| format!("set failed: {}", err))?.map(|v| v.to_vec());
+                    compare("replaced value", old, map.insert(key.clone(), value.clone()))?;
+                }
+                Op::Delete(key) => {
+                    let old = tbl.delete(key).map_err(|err| format!("delete failed: {}", err))?.map(|v| v.to_vec());
+                    compare("deleted value", old, map.remove(key))?;
+                }
+                Op::Get(key) => compare("value", tbl.get(key), map.get(key).map(|v| &v[..]))?,
+                Op::Flush => tbl.flush().map_err(|err| format!("flush failed: {}", err))?,
+                Op::Defragment => {
+                    tbl.defragment().map_err(|err| format!("defragment failed: {}", err))?;
+                    check_content(&tbl, &map)?;
+                }
+                Op::Clear => {
+                    tbl.clear().map_err(|err| format!("clear failed: {}", err))?;
+                    map.clear();
+                }
+                Op::Reopen => {
+                    tbl.close();
+                    tbl = self.options.clone().open(&self.path).map_err(|err| format!("open failed: {}", err))?;
+                    check_content(&tbl, &map)?;
+                }
+            }
+            *step += 1;
+        }
+        check_content(&tbl, &map)
+    }
+}
+
+fn compare<T: fmt::Debug + PartialEq>(what: &str, table: T, map: T) -> Result<(), String> {
+    if table != map {
+        return Err(format!("{} differs: table {:?}, map {:?}", what, table, map));
+    }
+    Ok(())
+}
+
+fn check_content(tbl: &Table, map: &HashMap<Vec<u8>, Vec<u8>>) -> Result<(), String> {
+    let report = tbl.verify(CheckLevel::Full);
+    if !report.is_ok() {
+        return Err(format!("table is inconsistent: {:?}", report.findings));
+    }
+    compare("length", tbl.len(), map.len())?;
+    for (key, value) in map {
+        compare("value", tbl.get(key), Some(&value[..]))?;
+    }
+    Ok(())
+}
+
+/// Removes operations from the failing sequence as long as it keeps failing
+fn minimize<F: FnMut(&[Op]) -> bool>(mut ops: Vec<Op>, mut fails: F) -> Vec<Op> {
+    let mut chunk = ops.len() / 2;
+    while chunk > 0 {
+        let mut start = 0;
+        while start < ops.len() {
+            let end = (start + chunk).min(ops.len());
+            let candidate: Vec<Op> = ops[..start].iter().chain(&ops[end..]).cloned().collect();
+            if fails(&candidate) {
+                ops = candidate;
+            } else {
+                start = end;
+            }
+        }
+        chunk /= 2;
+    }
+    ops
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_harness() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let harness = Harness::new(file.path());
+        for seed in 0..10 {
+            if let Err(failure) = harness.run_random(seed, 300) {
+                panic!("{}", failure);
+            }
+        }
+        assert_eq!(Harness::random_ops(1, 100), Harness::random_ops(1, 100));
+    }
+
+    #[test]
+    fn test_minimize() {
+        let ops = Harness::random_ops(42, 500);
+        // Fails if a set of the key [] is followed by a reopen
+        let fails = |ops: &[Op]| {
+            ops.iter()
+                .position(|op| matches!(op, Op::Set(key, _) if key.is_empty()))
+                .is_some_and(|pos| ops[pos..].contains(&Op::Reopen))
+        };
+        assert!(fails(&ops));
+        let minimal = minimize(ops, fails);
+        assert_eq!(minimal.len(), 2);
+        assert!(matches!(&minimal[0], Op::Set(key, _) if key.is_empty()));
+        assert_eq!(minimal[1], Op::Reopen);
+    }
+}
@@ -0,0 +1,357 @@
+use std::{cell::Cell, path::Path};
+
+use crate::{index::Hash, Entry, Error, Stats, Table};
+
+/// Hash under which the keys that cannot be stored as hash are indexed
+const SPECIAL_HASH: Hash = u64::MAX;
+
+/// Entry flag that marks values followed by a 32-bit verifier
+const FLAG_VERIFIER: u16 = 1 << 13;
+
+const VERIFIER_SIZE: usize = 4;
+
+/// Returns the hash and the key bytes under which the given key is stored.
+///
+/// Keys are stored directly as hash without any key bytes. As a hash of 0 marks an empty index slot, the keys 0 and
+/// `u64::MAX` are both stored under the hash `u64::MAX` and distinguished by their key bytes.
+#[inline]
+fn index_key(key: u64) -> (Hash, [u8; 8], usize) {
+    match key {
+        0 | SPECIAL_HASH => (SPECIAL_HASH, key.to_le_bytes(), 8),
+        _ => (key, [0; 8], 0),
+    }
+}
+
+/// Returns the key of an entry from its hash and key bytes or `None` if they are inconsistent
+#[inline]
+pub(crate) fn entry_key(hash: Hash, key: &[u8]) -> Option<u64> {
+    if hash != SPECIAL_HASH {
+        return Some(hash).filter(|_| key.is_empty());
+    }
+    if key.len() != 8 {
+        return None;
+    }
+    let mut buf = [0; 8];
+    buf.copy_from_slice(key);
+    Some(u64::from_le_bytes(buf)).filter(|&key| key == 0 || key == SPECIAL_HASH)
+}
+
+/// Splits a stored value into the value and its verifier (if any)
+#[inline]
+fn split_value(flags: u16, data: &[u8]) -> (&[u8], Option<u32>) {
+    if flags & FLAG_VERIFIER == 0 || data.len() < VERIFIER_SIZE {
+        return (data, None);
+    }
+    let (value, verifier) = data.split_at(data.len() - VERIFIER_SIZE);
+    let mut buf = [0; VERIFIER_SIZE];
+    buf.copy_from_slice(verifier);
+    (value, Some(u32::from_le_bytes(buf)))
+}
+
+/// Strips the verifier (if any) from a stored value
+#[inline]
+fn strip_value_mut(flags: u16, data: &mut [u8]) -> &mut [u8] {
+    if flags & FLAG_VERIFIER == 0 || data.len() < VERIFIER_SIZE {
+        return data;
+    }
+    let len = data.len() - VERIFIER_SIZE;
+    &mut data[..len]
+}
+
+/// Statistics about the risk of key collisions in a [`HashKeyTable`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct CollisionStats {
+    /// Number of entries in the table
+    pub entries: usize,
+
+    /// Number of entries stored with a verifier
+    pub verified_entries: usize,
+
+    /// Number of collisions detected via verifiers since the table has been opened
+    pub detected_collisions: u64,
+
+    /// Probability that looking up a key that is not in the table returns the value of another key
+    ///
+    /// This assumes that the keys are uniformly distributed. Entries without verifier contribute `1 / 2^64`,
+    /// entries with verifier only `1 / 2^96` (when looked up via [`HashKeyTable::get_verified`]).
+    pub false_positive_rate: f64,
+}
+
+/// A table with 64-bit keys that are used as hashes directly
+///
+/// This table is meant for caches whose keys have already been hashed by the caller. The keys are not hashed again
+/// and no key bytes are stored, so each entry only needs its index slot and the value. The keys must be well
+/// distributed, as they are used to place the entries in the index.
+///
+/// The table file is marked as hash key table, so a normal table cannot be opened as [`HashKeyTable`]. A hash key
+/// table can be opened as normal [`Table`], but its entries have no meaningful keys.
+///
+/// As only the 64-bit keys are stored, two different original keys with the same hash would be confused. Entries can
+/// optionally be stored with an additional 32-bit verifier (e.g. an independent hash of the original key) via
+/// [`HashKeyTable::set_verified`] that is checked by [`HashKeyTable::get_verified`]. This costs 4 bytes per entry and
+/// reduces the false positive rate by a factor of `2^32`. See [`HashKeyTable::collision_stats`] for the resulting
+/// rate.
+///
+/// ```
+/// use rust_persist::HashKeyTable;
+///
+/// let mut table = HashKeyTable::create("example_hashkey.tbl").unwrap();
+/// table.set(0x1234_5678_9abc_def0, "value".as_bytes()).unwrap();
+/// assert_eq!(table.get(0x1234_5678_9abc_def0), Some("value".as_bytes()));
+/// ```
+pub struct HashKeyTable {
+    inner: Table,
+    collisions: Cell<u64>,
+}
+
+impl HashKeyTable {
+    /// Opens an existing hash key table from the given path.
+    ///
+    /// If the table has not been created as hash key table, [`Error::WrongHeader`] is returned.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let inner = Table::open(path)?;
+        if !inner.header.has_hash_keys() {
+            return Err(Error::WrongHeader);
+        }
+        Ok(Self { inner, collisions: Cell::new(0) })
+    }
+
+    /// Creates a new hash key table at the given path (overwriting an existing table).
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let inner = Table::create(path)?;
+        inner.header.set_hash_keys(true);
+        Ok(Self { inner, collisions: Cell::new(0) })
+    }
+
+    /// Opens an existing or creates a new hash key table at the given path.
+    #[inline]
+    pub fn open_or_create<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        if path.exists() {
+            Self::open(path)
+        } else {
+            Self::create(path)
+        }
+    }
+
+    /// Returns a reference to the wrapped [`Table`].
+    ///
+    /// Beware that the entries of the inner table have no meaningful keys.
+    #[inline]
+    pub fn inner(&self) -> &Table {
+        &self.inner
+    }
+
+    /// Returns whether an entry is associated with the given key.
+    #[inline]
+    pub fn contains(&self, key: u64) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Retrieves and returns the value associated with the given key.
+    ///
+    /// The verifier of the entry (if any) is not checked.
+    #[inline]
+    pub fn get(&self, key: u64) -> Option<&[u8]> {
+        self.get_with_verifier(key).map(|(value, _)| value)
+    }
+
+    /// Retrieves and returns the value associated with the given key if its verifier matches.
+    ///
+    /// If the entry has been stored with a different verifier, a collision is counted and `None` is returned.
+    /// Entries stored without verifier are returned regardless of the given verifier.
+    #[inline]
+    pub fn get_verified(&self, key: u64, verifier: u32) -> Option<&[u8]> {
+        match self.get_with_verifier(key) {
+            Some((_, Some(stored))) if stored != verifier => {
+                self.collisions.set(self.collisions.get() + 1);
+                None
+            }
+            Some((value, _)) => Some(value),
+            None => None,
+        }
+    }
+
+    #[inline]
+    fn get_with_verifier(&self, key: u64) -> Option<(&[u8], Option<u32>)> {
+        let (hash, bytes, len) = index_key(key);
+        self.inner.get_entry_hashed(hash, &bytes[..len]).map(|e| split_value(e.flags, e.value))
+    }
+
+    /// Retrieves and returns the value associated with the given key for modification.
+    ///
+    /// The verifier of the entry (if any) is not checked.
+    #[inline]
+    pub fn get_mut(&mut self, key: u64) -> Option<&mut [u8]> {
+        let (hash, bytes, len) = index_key(key);
+        self.inner.get_entry_mut_hashed(hash, &bytes[..len]).map(|e| strip_value_mut(e.flags, e.value))
+    }
+
+    /// Stores the given key/value pair in the table.
+    ///
+    /// See [`Table::set`] for more info
+    #[inline]
+    pub fn set(&mut self, key: u64, value: &[u8]) -> Result<Option<&mut [u8]>, Error> {
+        let (hash, bytes, len) = index_key(key);
+        self.inner
+            .set_entry_hashed(hash, Entry { key: &bytes[..len], value, flags: 0 })
+            .map(|r| r.map(|e| strip_value_mut(e.flags, e.value)))
+    }
+
+    /// Stores the given key/value pair together with a verifier in the table.
+    ///
+    /// If an entry with a different verifier is replaced, a collision is counted. See [`Table::set`] for more info
+    pub fn set_verified(&mut self, key: u64, verifier: u32, value: &[u8]) -> Result<Option<&mut [u8]>, Error> {
+        let (hash, bytes, len) = index_key(key);
+        let mut data = Vec::with_capacity(value.len() + VERIFIER_SIZE);
+        data.extend_from_slice(value);
+        data.extend_from_slice(&verifier.to_le_bytes());
+        let collisions = &self.collisions;
+        self.inner.set_entry_hashed(hash, Entry { key: &bytes[..len], value: &data, flags: FLAG_VERIFIER }).map(|r| {
+            r.map(|e| {
+                if matches!(split_value(e.flags, e.value), (_, Some(stored)) if stored != verifier) {
+                    collisions.set(collisions.get() + 1);
+                }
+                strip_value_mut(e.flags, e.value)
+            })
+        })
+    }
+
+    /// Deletes the entry with the given key.
+    ///
+    /// See [`Table::delete`] for more info
+    #[inline]
+    pub fn delete(&mut self, key: u64) -> Result<Option<&mut [u8]>, Error> {
+        let (hash, bytes, len) = index_key(key);
+        self.inner.delete_entry_hashed(hash, &bytes[..len]).map(|r| r.map(|e| strip_value_mut(e.flags, e.value)))
+    }
+
+    /// Returns an iterator over all keys and values in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = (u64, &[u8])> {
+        self.inner.index.get_entries().iter().filter(|entry| entry.is_used()).filter_map(move |entry| {
+            let entry_data = self.inner.raw_entry_from_index_data(entry.data);
+            let (value, _) = split_value(entry_data.flags, entry_data.value);
+            entry_key(entry.hash, entry_data.key).map(|key| (key, value))
+        })
+    }
+
+    /// Returns statistics about the risk of key collisions
+    pub fn collision_stats(&self) -> CollisionStats {
+        let entries = self.len();
+        let verified_entries = self
+            .inner
+            .index
+            .get_entries()
+            .iter()
+            .filter(|entry| entry.is_used() && entry.data.flags & FLAG_VERIFIER != 0)
+            .count();
+        let unverified = (entries - verified_entries) as f64;
+        let false_positive_rate = (unverified + verified_entries as f64 / 2f64.powi(32)) / 2f64.powi(64);
+        CollisionStats { entries, verified_entries, detected_collisions: self.collisions.get(), false_positive_rate }
+    }
+
+    /// Return the number of entries in the table
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    /// Return whether the table is empty
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// Forces to write all pending changes to disk
+    #[inline]
+    pub fn flush(&self) -> Result<(), Error> {
+        self.inner.flush()
+    }
+
+    /// Deletes all entries in the table
+    #[inline]
+    pub fn clear(&mut self) -> Result<(), Error> {
+        self.inner.clear()
+    }
+
+    /// Explicitly closes the table.
+    ///
+    /// Normally this method does not need to be called.
+    #[inline]
+    pub fn close(self) {
+        // nothing to do, just drop self
+    }
+
+    /// Return a statistics struct
+    #[inline]
+    pub fn stats(&self) -> Stats {
+        self.inner.stats()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_keys() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = HashKeyTable::create(file.path()).unwrap();
+        let keys = [0, 1, 0x1234_5678_9abc_def0, u64::MAX - 1, u64::MAX];
+        for &key in &keys {
+            assert!(tbl.set(key, &key.to_be_bytes()).unwrap().is_none());
+        }
+        for i in 2..1000u64 {
+            tbl.set(i.wrapping_mul(0x9e37_79b9_7f4a_7c15), &[]).unwrap();
+        }
+        assert!(tbl.inner().is_valid());
+        for &key in &keys {
+            assert_eq!(tbl.get(key), Some(&key.to_be_bytes()[..]));
+        }
+        // Only the special keys store key bytes
+        assert_eq!(tbl.inner().iter().filter(|e| !e.key.is_empty()).count(), 2);
+        assert_eq!(tbl.iter().count(), 1003);
+        assert!(tbl.iter().any(|(key, value)| key == 0 && value == [0; 8]));
+        assert_eq!(tbl.delete(u64::MAX).unwrap(), Some(&mut u64::MAX.to_be_bytes()[..]));
+        assert_eq!(tbl.get(0), Some(&[0; 8][..]));
+        assert!(!tbl.contains(u64::MAX));
+        let backup = tempfile::NamedTempFile::new().unwrap();
+        tbl.inner().backup_to(backup.path()).unwrap();
+        let copy = HashKeyTable::open(backup.path()).unwrap();
+        assert_eq!(copy.len(), 1002);
+        assert_eq!(copy.get(0x1234_5678_9abc_def0), tbl.get(0x1234_5678_9abc_def0));
+        assert!(tbl.inner().backup_full(backup.path()).is_err());
+        tbl.close();
+        assert!(matches!(Table::open(file.path()).map(|t| t.is_empty()), Ok(false)));
+        let tbl = HashKeyTable::open(file.path()).unwrap();
+        assert_eq!(tbl.len(), 1002);
+        tbl.close();
+        Table::create(file.path()).unwrap();
+        assert!(matches!(HashKeyTable::open(file.path()), Err(Error::WrongHeader)));
+    }
+
+    #[test]
+    fn test_verifier() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = HashKeyTable::create(file.path()).unwrap();
+        tbl.set(1, "plain".as_bytes()).unwrap();
+        tbl.set_verified(2, 0xaaaa, "checked".as_bytes()).unwrap();
+        tbl.set_verified(0, 0xbbbb, &[]).unwrap();
+        assert_eq!(tbl.get(2), Some("checked".as_bytes()));
+        assert_eq!(tbl.get_verified(2, 0xaaaa), Some("checked".as_bytes()));
+        assert_eq!(tbl.get_verified(2, 0xcccc), None);
+        assert_eq!(tbl.get_verified(1, 0xcccc), Some("plain".as_bytes()));
+        assert_eq!(tbl.get_verified(0, 0xbbbb), Some(&[][..]));
+        assert_eq!(tbl.get_mut(2).unwrap().len(), 7);
+        assert!(tbl.iter().all(|(key, value)| tbl.get(key) == Some(value)));
+        let stats = tbl.collision_stats();
+        assert_eq!((stats.entries, stats.verified_entries, stats.detected_collisions), (3, 2, 1));
+        assert!(stats.false_positive_rate > 0.0 && stats.false_positive_rate < 1e-19);
+        assert_eq!(tbl.set_verified(2, 0xdddd, "other".as_bytes()).unwrap(), Some(&mut b"checked".to_vec()[..]));
+        assert_eq!(tbl.collision_stats().detected_collisions, 2);
+        assert_eq!(tbl.set(2, "plain".as_bytes()).unwrap(), Some(&mut b"other".to_vec()[..]));
+        assert_eq!(tbl.delete(0).unwrap(), Some(&mut [][..]));
+        assert_eq!(tbl.collision_stats().verified_entries, 0);
+        assert!(tbl.inner().is_valid());
+    }
+}
@@ -0,0 +1,177 @@
+use std::convert::TryInto;
+
+use serde_derive::Serialize;
+
+use crate::{Entry, Error, QuickStats, Table};
+
+/// Entry flag that marks the entry storing the statistics history
+pub(crate) const FLAG_STATS: u16 = 1 << 9;
+
+/// Key of the entry storing the statistics history, it starts with the byte `0xff` like the keys of mailbox entries
+/// but can not collide with them as topics can not be empty
+const STATS_KEY: &[u8] = b"\xff\x00stats";
+
+/// Maximum number of snapshots that are kept, older snapshots are dropped
+const STATS_HISTORY_CAPACITY: usize = 64;
+
+/// Size of a stored snapshot: the time and five 64-bit statistics
+const SNAPSHOT_SIZE: usize = 48;
+
+/// Statistics of a table at a point in time, see [`Table::stats_history`]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct StatsSnapshot {
+    /// Time of the snapshot according to the table clock in seconds since the UNIX epoch
+    pub time: u64,
+    /// Statistics of the table at that time
+    pub stats: QuickStats,
+}
+
+impl StatsSnapshot {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.time.to_le_bytes());
+        buf.extend_from_slice(&(self.stats.entries as u64).to_le_bytes());
+        buf.extend_from_slice(&(self.stats.index_capacity as u64).to_le_bytes());
+        buf.extend_from_slice(&self.stats.data_size.to_le_bytes());
+        buf.extend_from_slice(&self.stats.data_used.to_le_bytes());
+        buf.extend_from_slice(&self.stats.avg_displacement.to_le_bytes());
+    }
+
+    fn decode(data: &[u8]) -> Option<Self> {
+        let mut fields = data.chunks_exact(8).map(|field| field.try_into().ok().map(u64::from_le_bytes));
+        let mut next = || fields.next().flatten();
+        let time = next()?;
+        let entries = next()? as usize;
+        let index_capacity = next()? as usize;
+        let data_size = next()?;
+        let data_used = next()?;
+        let avg_displacement = f64::from_bits(next()?);
+        let stats = QuickStats {
+            entries,
+            index_capacity,
+            load_factor: if index_capacity == 0 { 0.0 } else { entries as f64 / index_capacity as f64 },
+            avg_displacement,
+            data_size,
+            data_used,
+            fragmentation: if data_size == 0 {
+                0.0
+            } else {
+                data_size.saturating_sub(data_used) as f64 / data_size as f64
+            },
+        };
+        Some(Self { time, stats })
+    }
+}
+
+impl Table {
+    /// Returns the snapshots of the table statistics stored in the table, oldest first.
+    ///
+    /// Snapshots are taken via [`Table::record_stats`] or automatically, see
+    /// [`TableOptions::stats_interval`](crate::TableOptions::stats_interval). Only the last 64 snapshots are kept.
+    pub fn stats_history(&self) -> Vec<StatsSnapshot> {
+        match self.get_raw_entry(STATS_KEY) {
+            Some(entry) if entry.flags & FLAG_STATS != 0 => {
+                entry.value.chunks_exact(SNAPSHOT_SIZE).filter_map(StatsSnapshot::decode).collect()
+            }
+            _ => vec![],
+        }
+    }
+
+    /// Stores a snapshot of the current [`Table::quick_stats`] in the table, dropping the oldest snapshot if 64
+    /// snapshots are stored already.
+    ///
+    /// The history is stored in the table itself, so operators can see how a table grows and fragments over time
+    /// without external monitoring. It is stored as an entry that is marked via an entry flag and whose key starts
+    /// with the byte `0xff`, this entry is included in [`Table::iter`] and [`Table::len`].
+    ///
+    /// Tables created via [`HashKeyTable`](crate::HashKeyTable) are not supported.
+    ///
+    /// ```
+    /// use rust_persist::Table;
+    ///
+    /// let mut table = Table::create("example_stats_history.tbl").unwrap();
+    /// table.record_stats().unwrap();
+    /// table.set("key".as_bytes(), "value".as_bytes()).unwrap();
+    /// table.record_stats().unwrap();
+    /// let history = table.stats_history();
+    /// assert_eq!(history.len(), 2);
+    /// assert!(history[1].stats.entries > history[0].stats.entries);
+    /// ```
+    pub fn record_stats(&mut self) -> Result<(), Error> {
+        self.check_writable()?;
+        self.check_byte_keys()?;
+        let snapshot = StatsSnapshot { time: self.now(), stats: self.quick_stats() };
+        let mut history = self.stats_history();
+        if history.len() >= STATS_HISTORY_CAPACITY {
+            history.drain(..=history.len() - STATS_HISTORY_CAPACITY);
+        }
+        history.push(snapshot);
+        let mut value = Vec::with_capacity(history.len() * SNAPSHOT_SIZE);
+        for snapshot in &history {
+            snapshot.encode(&mut value);
+        }
+        self.stats_recorded = Some(snapshot.time);
+        self.set_raw_entry(Entry { key: STATS_KEY, value: &value, flags: FLAG_STATS })?;
+        Ok(())
+    }
+
+    /// Records a snapshot if the configured interval has passed since the last one
+    pub(crate) fn maybe_record_stats(&mut self) -> Result<(), Error> {
+        let interval = match self.options.stats_interval {
+            Some(interval) if !self.header.has_hash_keys() => interval.as_secs(),
+            _ => return Ok(()),
+        };
+        let last = match self.stats_recorded {
+            Some(time) => time,
+            None => self.stats_history().last().map_or(0, |snapshot| snapshot.time),
+        };
+        self.stats_recorded = Some(last);
+        if self.now() < last.saturating_add(interval) {
+            return Ok(());
+        }
+        self.record_stats()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ManualClock;
+    use std::{sync::Arc, time::Duration};
+
+    #[test]
+    fn test_stats_history() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let options = Table::options().stats_interval(Some(Duration::from_secs(60)));
+        let mut tbl = options.clone().create(file.path()).unwrap();
+        let clock = Arc::new(ManualClock::new(1000));
+        tbl.set_clock(clock.clone());
+        assert!(tbl.stats_history().is_empty());
+        for i in 0u32..100 {
+            tbl.set(&i.to_le_bytes(), &[0; 100]).unwrap();
+            clock.advance(10);
+        }
+        let history = tbl.stats_history();
+        assert_eq!(
+            history.iter().map(|s| s.time).collect::<Vec<_>>(),
+            (0..17).map(|i| 1000 + 60 * i).collect::<Vec<_>>()
+        );
+        // Each snapshot is taken before the write that triggered it, including the history entry from then on
+        assert_eq!(history[0].stats.entries, 0);
+        assert_eq!(history[1].stats.entries, 7);
+        assert!(history.windows(2).all(|w| w[0].stats.data_used < w[1].stats.data_used));
+        tbl.close();
+        let mut tbl = options.open(file.path()).unwrap();
+        assert_eq!(tbl.stats_history(), history);
+        // The interval is continued from the last stored snapshot
+        tbl.set_clock(Arc::new(ManualClock::new(2000)));
+        tbl.delete(&0u32.to_le_bytes()).unwrap();
+        assert_eq!(tbl.stats_history().len(), 17);
+        for _ in 0..100 {
+            tbl.record_stats().unwrap();
+        }
+        let history = tbl.stats_history();
+        assert_eq!(history.len(), STATS_HISTORY_CAPACITY);
+        assert_eq!(history.last().unwrap().stats.entries, 100);
+        assert!(tbl.is_valid());
+    }
+}
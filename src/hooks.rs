@@ -0,0 +1,181 @@
+use std::mem;
+
+use crate::{watch::Watcher, ChangeEvent, Table};
+
+/// Callback that is called with the key, the old value (if any) and the new value of a stored entry
+type SetHook = Box<dyn FnMut(&[u8], Option<&[u8]>, &[u8]) + Send + Sync>;
+
+/// Callback that is called with the key and the old value of a deleted entry
+type DeleteHook = Box<dyn FnMut(&[u8], &[u8]) + Send + Sync>;
+
+/// Callbacks registered via [`Table::on_set`] and [`Table::on_delete`] and subscriptions via [`Table::watch`]
+#[derive(Default)]
+pub(crate) struct Hooks {
+    on_set: Vec<SetHook>,
+    on_delete: Vec<DeleteHook>,
+    pub(crate) watchers: Vec<Watcher>,
+}
+
+impl Hooks {
+    #[inline]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.on_set.is_empty() && self.on_delete.is_empty() && self.watchers.is_empty()
+    }
+
+    /// Calls all set hooks in the order of their registration and notifies the watchers
+    pub(crate) fn set(&mut self, key: &[u8], old: Option<&[u8]>, new: &[u8]) {
+        for hook in &mut self.on_set {
+            hook(key, old, new)
+        }
+        let event = || ChangeEvent::Set { key: key.to_vec(), value: new.to_vec() };
+        self.watchers.retain(|watcher| watcher.notify(key, event));
+    }
+
+    /// Calls all delete hooks in the order of their registration and notifies the watchers
+    pub(crate) fn delete(&mut self, key: &[u8], old: &[u8]) {
+        for hook in &mut self.on_delete {
+            hook(key, old)
+        }
+        self.watchers.retain(|watcher| watcher.notify(key, || ChangeEvent::Delete { key: key.to_vec() }));
+    }
+}
+
+impl Table {
+    /// Registers a callback that is called whenever an entry is inserted or overwritten
+    ///
+    /// The callback is called with the key, the old value (or `None` if the key is new) and the new value after the
+    /// table has been modified. Multiple callbacks are called in the order of their registration. Callbacks are not
+    /// persisted and are dropped when the table is closed.
+    ///
+    /// Hooks are called by all methods that store single entries, by [`Table::set_many`], [`Table::append`],
+    /// [`Table::update`], [`Table::apply`], [`Table::writer`] and [`Table::ingest`]. Internal entries like the
+    /// statistics history are reported as well. Methods that rewrite entries in bulk ([`Table::rename`],
+    /// [`Table::swap`], [`Table::rewrite_keys`] and [`Table::rewrite_values`]) do not call hooks.
+    ///
+    /// ```
+    /// use std::sync::{Arc, Mutex};
+    /// use rust_persist::Table;
+    ///
+    /// let changes = Arc::new(Mutex::new(Vec::new()));
+    /// let mut table = Table::create("example_hooks.tbl").unwrap();
+    /// let log = changes.clone();
+    /// table.on_set(move |key, old, _new| log.lock().unwrap().push((key.to_vec(), old.is_some())));
+    /// let log = changes.clone();
+    /// table.on_delete(move |key, _old| log.lock().unwrap().push((key.to_vec(), false)));
+    /// table.set("key".as_bytes(), "value1".as_bytes()).unwrap();
+    /// table.set("key".as_bytes(), "value2".as_bytes()).unwrap();
+    /// table.delete("key".as_bytes()).unwrap();
+    /// let key = "key".as_bytes().to_vec();
+    /// assert_eq!(*changes.lock().unwrap(), vec![(key.clone(), false), (key.clone(), true), (key, false)]);
+    /// ```
+    pub fn on_set<F: FnMut(&[u8], Option<&[u8]>, &[u8]) + Send + Sync + 'static>(&mut self, hook: F) {
+        self.hooks.on_set.push(Box::new(hook))
+    }
+
+    /// Registers a callback that is called with the key and the old value whenever an entry is deleted
+    ///
+    /// Deleting entries via [`Table::delete`], [`Table::filter`], [`Table::retain_mut`], [`Table::drain`] and
+    /// [`Table::clear`] calls the hooks, see [`Table::on_set`] for details.
+    pub fn on_delete<F: FnMut(&[u8], &[u8]) + Send + Sync + 'static>(&mut self, hook: F) {
+        self.hooks.on_delete.push(Box::new(hook))
+    }
+
+    /// Removes all callbacks registered via [`Table::on_set`] and [`Table::on_delete`]
+    ///
+    /// Subscriptions via [`Table::watch`] are kept until their receivers are dropped.
+    pub fn clear_hooks(&mut self) {
+        self.hooks.on_set.clear();
+        self.hooks.on_delete.clear();
+    }
+
+    /// Passes the registered hooks and the table to the closure, if any hooks are registered
+    ///
+    /// This must be called after the table has been modified but before the freed data of old entries can be reused.
+    #[inline]
+    pub(crate) fn run_hooks<F: FnOnce(&mut Hooks, &Table)>(&mut self, f: F) {
+        if self.hooks.is_empty() {
+            return;
+        }
+        let mut hooks = mem::take(&mut self.hooks);
+        f(&mut hooks, self);
+        self.hooks = hooks;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::WriteBatch;
+    use std::sync::{Arc, Mutex};
+
+    /// Key, old value and new value of a recorded modification
+    type Op = (Vec<u8>, Option<Vec<u8>>, Option<Vec<u8>>);
+    type Log = Arc<Mutex<Vec<Op>>>;
+
+    fn record(tbl: &mut Table) -> Log {
+        let log = Log::default();
+        let set_log = log.clone();
+        tbl.on_set(move |key, old, new| {
+            set_log.lock().unwrap().push((key.to_vec(), old.map(<[u8]>::to_vec), Some(new.to_vec())))
+        });
+        let delete_log = log.clone();
+        tbl.on_delete(move |key, old| delete_log.lock().unwrap().push((key.to_vec(), Some(old.to_vec()), None)));
+        log
+    }
+
+    fn take(log: &Log) -> Vec<Op> {
+        std::mem::take(&mut *log.lock().unwrap())
+    }
+
+    fn op(key: &[u8], old: Option<&[u8]>, new: Option<&[u8]>) -> Op {
+        (key.to_vec(), old.map(<[u8]>::to_vec), new.map(<[u8]>::to_vec))
+    }
+
+    #[test]
+    fn test_hooks() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::options().checksums(true).shred(true).create(file.path()).unwrap();
+        let log = record(&mut tbl);
+        tbl.set(b"a", b"1").unwrap();
+        tbl.set(b"a", b"2").unwrap();
+        tbl.append(b"a", b"3").unwrap();
+        tbl.update(b"b", |_| Some(b"4".to_vec())).unwrap();
+        tbl.update(b"b", |_| None).unwrap();
+        tbl.delete(b"a").unwrap();
+        tbl.delete(b"a").unwrap();
+        assert_eq!(
+            take(&log),
+            vec![
+                op(b"a", None, Some(b"1")),
+                op(b"a", Some(b"1"), Some(b"2")),
+                op(b"a", Some(b"2"), Some(b"23")),
+                op(b"b", None, Some(b"4")),
+                op(b"b", Some(b"4"), None),
+                op(b"a", Some(b"23"), None),
+            ]
+        );
+        tbl.set_many((0u8..100).map(|i| ([i], [i; 10]))).unwrap();
+        tbl.set_many((0u8..100).map(|i| ([i], [i; 5]))).unwrap();
+        let ops = take(&log);
+        assert_eq!(ops.len(), 200);
+        assert_eq!(ops[150], op(&[50], Some(&[50; 10]), Some(&[50; 5])));
+        tbl.filter(|entry| entry.key[0] >= 10).unwrap();
+        tbl.retain_mut(|entry| entry.key[0] >= 20).unwrap();
+        let mut ops = take(&log);
+        ops.sort();
+        assert_eq!(ops, (0u8..20).map(|i| op(&[i], Some(&[i; 5]), None)).collect::<Vec<_>>());
+        let mut batch = WriteBatch::new();
+        batch.set(b"x", b"y");
+        batch.delete(&[20]);
+        tbl.apply(batch).unwrap();
+        assert_eq!(take(&log), vec![op(b"x", None, Some(b"y")), op(&[20], Some(&[20; 5]), None)]);
+        tbl.drain().unwrap().take(5).count();
+        assert_eq!(take(&log).len(), 5);
+        tbl.clear().unwrap();
+        assert_eq!(take(&log).len(), 75);
+        tbl.clear_hooks();
+        tbl.set(b"a", b"1").unwrap();
+        assert!(take(&log).is_empty());
+        assert!(tbl.is_valid());
+    }
+}
@@ -1,16 +1,67 @@
 use std::mem;
 
+use crate::{namespace::FLAG_NAMESPACE, Error, Finding, FLAG_PINNED};
+
 pub(crate) type Hash = u64;
 
+/// Number of bits that store the position of a data block, the remaining upper bits store the upper bits of its size
+const POSITION_BITS: u32 = 48;
+
+/// Largest position of a data block (and thereby the largest table size)
+pub(crate) const MAX_POSITION: u64 = (1 << POSITION_BITS) - 1;
+
+/// Largest size of a data block in format v2 (32 bits in `size_low` plus 16 bits in `position_raw`)
+pub(crate) const MAX_BLOCK_SIZE: u64 = (1 << 48) - 1;
+
+/// Largest size of a data block in format v1 (without wide sizes)
+pub(crate) const MAX_BLOCK_SIZE_V1: u64 = u32::MAX as u64;
+
+/// Location and metadata of a data block as stored in the index
+///
+/// Both format versions use the same 24-byte layout. In format v1, the size is limited to 32 bits and the upper 16
+/// bits of `position_raw` are always zero. Format v2 (marked via [`Header::has_wide_sizes`](crate::table::Header))
+/// stores the upper 16 bits of the size in these bits, so entries of both formats are decoded the same way.
 #[repr(C)]
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub(crate) struct IndexEntryData {
-    pub position: u64,
-    pub size: u32,
+    position_raw: u64,
+    size_low: u32,
     pub key_size: u16,
     pub flags: u16,
 }
 
+impl IndexEntryData {
+    #[inline]
+    pub(crate) fn new(position: u64, size: u64, key_size: u16, flags: u16) -> Self {
+        debug_assert!(position <= MAX_POSITION && size <= MAX_BLOCK_SIZE);
+        Self { position_raw: position | (size >> 32) << POSITION_BITS, size_low: size as u32, key_size, flags }
+    }
+
+    /// Returns the position of the data block
+    #[inline]
+    pub(crate) fn position(&self) -> u64 {
+        self.position_raw & MAX_POSITION
+    }
+
+    /// Returns the size of the data block
+    #[inline]
+    pub(crate) fn size(&self) -> u64 {
+        (self.position_raw >> POSITION_BITS) << 32 | self.size_low as u64
+    }
+
+    #[inline]
+    pub(crate) fn set_position(&mut self, position: u64) {
+        debug_assert!(position <= MAX_POSITION);
+        self.position_raw = (self.position_raw & !MAX_POSITION) | position;
+    }
+}
+
+/// Returns whether the entry belongs to a namespace or registers one
+#[inline]
+pub(crate) fn is_namespaced(data: &IndexEntryData) -> bool {
+    data.flags & FLAG_NAMESPACE != 0
+}
+
 #[repr(C)]
 pub(crate) struct IndexEntry {
     pub(crate) hash: Hash,
@@ -30,8 +81,8 @@ impl IndexEntry {
 
     pub(crate) fn fix_endianness(&mut self) {
         self.hash = self.hash.to_le().to_be();
-        self.data.position = self.data.position.to_le().to_be();
-        self.data.size = self.data.size.to_le().to_be();
+        self.data.position_raw = self.data.position_raw.to_le().to_be();
+        self.data.size_low = self.data.size_low.to_le().to_be();
         self.data.key_size = self.data.key_size.to_le().to_be();
         self.data.flags = self.data.flags.to_le().to_be();
     }
@@ -55,6 +106,9 @@ pub struct Index {
     mask: usize,
     capacity: usize,
     count: usize,
+    namespaced: usize,
+    pinned: usize,
+    displacement: usize,
     entries: &'static mut [IndexEntry],
 }
 
@@ -63,7 +117,40 @@ impl Index {
     pub(crate) fn new(entries: &'static mut [IndexEntry], used_count: usize) -> Self {
         let capacity = entries.len();
         debug_assert_eq!(capacity.count_ones(), 1);
-        Self { mask: capacity - 1, capacity, count: used_count, entries }
+        let mut index = Self {
+            mask: capacity - 1,
+            capacity,
+            count: used_count,
+            namespaced: 0,
+            pinned: 0,
+            displacement: 0,
+            entries,
+        };
+        for pos in 0..index.capacity {
+            if index.entries[pos].is_used() {
+                let data = index.entries[pos].data;
+                index.count_flags(&data);
+            }
+        }
+        index.count_displacement();
+        index
+    }
+
+    /// Replaces the entries after the file has been mapped again, keeping the count and the displacement
+    #[inline]
+    pub(crate) fn remap(&mut self, entries: &'static mut [IndexEntry]) {
+        debug_assert_eq!(entries.len().count_ones(), 1);
+        self.capacity = entries.len();
+        self.mask = self.capacity - 1;
+        self.entries = entries;
+    }
+
+    /// Recomputes the total displacement of all entries
+    fn count_displacement(&mut self) {
+        self.displacement = (0..self.capacity)
+            .filter(|&pos| self.entries[pos].is_used())
+            .map(|pos| self.get_displacement(&self.entries[pos], pos))
+            .sum();
     }
 
     fn reinsert(&mut self, start: usize, end: usize) {
@@ -80,24 +167,27 @@ impl Index {
                 entry.clear();
             }
             self.count -= 1;
+            self.uncount_flags(&data);
             self.index_set(hash, |_| false, data);
         }
+        self.count_displacement();
     }
 
     #[inline]
-    pub(crate) fn grow_from_half(&mut self) {
-        for entry in &mut self.entries[self.capacity / 2..] {
+    pub(crate) fn grow_from(&mut self, old_capacity: usize) {
+        for entry in &mut self.entries[old_capacity..] {
             entry.clear()
         }
         self.reinsert(0, self.capacity)
     }
 
-    pub(crate) fn shrink_to_half(&mut self) {
-        assert!(self.count <= self.capacity / 2);
+    pub(crate) fn shrink_to_half(&mut self) -> Result<(), Error> {
+        invariant!(self.count <= self.capacity / 2, "Index must fit into half of its capacity");
         self.capacity /= 2;
         self.mask = self.capacity - 1;
         self.reinsert(self.capacity, 2 * self.capacity);
         self.reinsert_all();
+        Ok(())
     }
 
     #[inline]
@@ -111,6 +201,9 @@ impl Index {
             entry.clear()
         }
         self.count = 0;
+        self.namespaced = 0;
+        self.pinned = 0;
+        self.displacement = 0;
     }
 
     pub(crate) fn update_block_position(&mut self, hash: Hash, old_pos: u64, new_pos: u64) {
@@ -120,24 +213,74 @@ impl Index {
             if !entry.is_used() {
                 return;
             }
-            if entry.hash == hash && entry.data.position == old_pos {
-                entry.data.position = new_pos;
+            if entry.hash == hash && entry.data.position() == old_pos {
+                entry.data.set_position(new_pos);
                 return;
             }
             pos = (pos + 1) & self.mask;
         }
     }
 
+    pub(crate) fn get_block(&self, hash: Hash, block_pos: u64) -> Option<IndexEntryData> {
+        let mut pos = (hash & self.mask as u64) as usize;
+        loop {
+            let entry = &self.entries[pos];
+            if !entry.is_used() {
+                return None;
+            }
+            if entry.hash == hash && entry.data.position() == block_pos {
+                return Some(entry.data);
+            }
+            pos = (pos + 1) & self.mask;
+        }
+    }
+
     #[inline]
     pub fn len(&self) -> usize {
         self.count
     }
 
+    /// Returns the number of entries that belong to namespaces, see [`Table::namespace`](crate::Table::namespace)
+    #[inline]
+    pub(crate) fn namespaced(&self) -> usize {
+        self.namespaced
+    }
+
+    /// Returns the number of entries marked with [`FLAG_PINNED`]
+    #[inline]
+    pub(crate) fn pinned(&self) -> usize {
+        self.pinned
+    }
+
+    /// Adds the entry to the counters of entries with certain flags
+    #[inline]
+    fn count_flags(&mut self, data: &IndexEntryData) {
+        self.namespaced += is_namespaced(data) as usize;
+        self.pinned += (data.flags & FLAG_PINNED != 0) as usize;
+    }
+
+    /// Removes the entry from the counters of entries with certain flags
+    #[inline]
+    fn uncount_flags(&mut self, data: &IndexEntryData) {
+        self.namespaced -= is_namespaced(data) as usize;
+        self.pinned -= (data.flags & FLAG_PINNED != 0) as usize;
+    }
+
     #[inline]
     pub fn capacity(&self) -> usize {
         self.capacity
     }
 
+    /// Returns the average distance of the entries from their ideal positions
+    #[inline]
+    pub fn avg_displacement(&self) -> f64 {
+        if self.count == 0 {
+            0.0
+        } else {
+            self.displacement as f64 / self.count as f64
+        }
+    }
+
     #[inline]
     fn get_displacement(&self, entry: &IndexEntry, pos: usize) -> usize {
         (pos + self.capacity - (entry.hash as usize & self.mask)) & self.mask
@@ -187,6 +330,7 @@ impl Index {
                 }
             }
             self.entries.swap(last_pos, pos);
+            self.displacement -= 1;
         }
         self.entries[last_pos].clear();
     }
@@ -194,10 +338,21 @@ impl Index {
     pub(crate) fn index_set<F: FnMut(&IndexEntryData) -> bool>(
         &mut self, hash: Hash, match_fn: F, data: IndexEntryData,
     ) -> Option<IndexEntryData> {
-        match self.locate(hash, match_fn) {
+        let located = self.locate(hash, match_fn);
+        self.set_located(located, hash, data)
+    }
+
+    /// Stores the entry at the position returned by [`Index::locate`] for its hash, the index must not have been
+    /// modified in between
+    pub(crate) fn set_located(
+        &mut self, located: LocateResult, hash: Hash, data: IndexEntryData,
+    ) -> Option<IndexEntryData> {
+        match located {
             LocateResult::Found(pos) => {
                 let mut old = data;
                 mem::swap(&mut old, &mut self.entries[pos].data);
+                self.uncount_flags(&old);
+                self.count_flags(&data);
                 Some(old)
             }
             LocateResult::Hole(pos) => {
@@ -205,6 +360,8 @@ impl Index {
                 entry.hash = hash;
                 entry.data = data;
                 self.count += 1;
+                self.count_flags(&data);
+                self.displacement += self.get_displacement(&self.entries[pos], pos);
                 None
             }
             LocateResult::Steal(pos) => {
@@ -230,7 +387,11 @@ impl Index {
                         break;
                     }
                 }
+                // every entry after the stolen slot has been moved one slot further away from its ideal position
+                let shifted = (cur_pos + self.capacity - pos) & self.mask;
+                self.displacement += self.get_displacement(&self.entries[pos], pos) + shifted;
                 self.count += 1;
+                self.count_flags(&data);
                 None
             }
         }
@@ -246,51 +407,82 @@ impl Index {
         }
     }
 
+    #[inline]
+    pub(crate) fn index_get_mut<F: FnMut(&IndexEntryData) -> bool>(
+        &mut self, hash: Hash, match_fn: F,
+    ) -> Option<&mut IndexEntryData> {
+        match self.locate(hash, match_fn) {
+            LocateResult::Found(pos) => Some(&mut self.entries[pos].data),
+            _ => None,
+        }
+    }
+
     #[inline]
     pub(crate) fn index_delete<F: FnMut(&IndexEntryData) -> bool>(
         &mut self, hash: Hash, match_fn: F,
     ) -> Option<IndexEntryData> {
         match self.locate(hash, match_fn) {
-            LocateResult::Found(pos) => {
-                let entry = self.entries[pos].data;
-                self.backshift(pos);
-                self.count -= 1;
-                Some(entry)
-            }
+            LocateResult::Found(pos) => Some(self.delete_located(pos)),
             _ => None,
         }
     }
 
+    /// Deletes the entry at the position found via [`Index::locate`]
+    #[inline]
+    pub(crate) fn delete_located(&mut self, pos: usize) -> IndexEntryData {
+        let entry = self.entries[pos].data;
+        self.displacement -= self.get_displacement(&self.entries[pos], pos);
+        self.backshift(pos);
+        self.count -= 1;
+        self.uncount_flags(&entry);
+        entry
+    }
+
     #[inline]
     pub(crate) fn get_entries(&self) -> &[IndexEntry] {
         self.entries
     }
 
-    pub fn is_valid(&self) -> bool {
-        let mut valid = true;
+    /// Replaces the flags of the entry in the given slot
+    #[inline]
+    pub(crate) fn set_slot_flags(&mut self, slot: usize, flags: u16) {
+        let mut data = self.entries[slot].data;
+        self.uncount_flags(&data);
+        data.flags = flags;
+        self.count_flags(&data);
+        self.entries[slot].data = data;
+    }
+
+    /// Returns the data of the entry in the given slot for modification, the key, its hash and the flags
+    /// [`FLAG_NAMESPACE`] and [`FLAG_PINNED`] must not be changed
+    #[inline]
+    pub(crate) fn slot_data_mut(&mut self, slot: usize) -> &mut IndexEntryData {
+        &mut self.entries[slot].data
+    }
+
+    /// Checks the index for inconsistencies and adds all of them to `findings`
+    pub(crate) fn check(&self, findings: &mut Vec<Finding>) {
         let mut entries = 0;
+        let mut displacement = 0;
         for pos in 0..self.capacity {
             let entry = &self.entries[pos];
             if !entry.is_used() {
                 continue;
             }
-            if entry.data.key_size as u32 > entry.data.size {
-                println!("Index error: key_size > size, {:?}", entry.data);
-                valid = false;
+            displacement += self.get_displacement(entry, pos);
+            if entry.data.key_size as u64 > entry.data.size() {
+                findings.push(Finding::KeyLargerThanEntry { slot: pos });
             }
             entries += 1;
             match self.locate(entry.hash, |e| &entry.data == e) {
                 LocateResult::Found(p) if p == pos => (),
-                found => {
-                    println!("Index error: entry is at wrong position, actual: {}, expected: {:?}", pos, found);
-                    valid = false;
-                }
+                LocateResult::Found(p) => findings.push(Finding::MisplacedIndexEntry { slot: pos, expected: Some(p) }),
+                _ => findings.push(Finding::MisplacedIndexEntry { slot: pos, expected: None }),
             };
         }
         if entries != self.count {
-            println!("Index error: entry count does not match, expected: {}, actual: {}", self.count, entries);
-            valid = false;
+            findings.push(Finding::IndexCountMismatch { stored: self.count, actual: entries });
         }
-        valid
+        debug_assert_eq!(displacement, self.displacement, "Displacement must be tracked correctly");
     }
 }
@@ -0,0 +1,171 @@
+use crate::{
+    index::{Hash, IndexEntryData},
+    table::{hash_key, match_key},
+    Entry, Error, Table,
+};
+
+/// Handle for inserting many entries into a table at high throughput.
+///
+/// The data of each entry is written to the data section immediately, but the corresponding index updates are
+/// buffered in memory and applied in batches. The index is grown once per batch instead of step by step and the data
+/// section is not shrunk or defragmented while entries are pending.
+///
+/// Entries that are still pending are not visible via [`Ingest::table`]. All pending entries are applied when
+/// [`Ingest::flush`] or [`Ingest::finish`] is called, when the batch is full or when the handle is dropped.
+pub struct Ingest<'a> {
+    tbl: &'a mut Table,
+    batch_size: usize,
+    pending: Vec<(Hash, IndexEntryData)>,
+}
+
+impl<'a> Ingest<'a> {
+    /// Stores the given key/value pair in the table.
+    ///
+    /// If another value is already stored for the key, it will be replaced when the pending entries are applied.
+    /// Like [`Table::set`], this evicts entries if the table would exceed
+    /// [`TableOptions::max_data_size`](crate::TableOptions::max_data_size). Statistics snapshots (see
+    /// [`TableOptions::stats_interval`](crate::TableOptions::stats_interval)) are only taken at the start of a batch.
+    /// If the key is used by an entry of a namespace, [`Error::InvalidOptions`] is returned.
+    /// If the table file cannot be extended (e.g. due to no space on device), the method will return an `Err` result.
+    pub fn set(&mut self, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        self.tbl.check_writable()?;
+        self.tbl.check_plain_key(hash_key(key), key)?;
+        let size = self.tbl.block_size(key, value)?;
+        if self.pending.is_empty() {
+            // Recording a snapshot writes an entry which might move data blocks, so it must not happen while entries
+            // are pending
+            self.tbl.maybe_record_stats()?;
+        }
+        // Entries are evicted before the new one is logged, so replaying the log does not delete it again
+        self.tbl.maybe_evict(size, Some(key))?;
+        self.tbl.log_set(key, value, 0)?;
+        if self.pending.is_empty() {
+            self.tbl.reserve_index(self.batch_size)?;
+        }
+        let (hash, index_entry) = self.tbl.write_block(&Entry { key, value, flags: 0 })?;
+        self.tbl.unindexed += 1;
+        self.pending.push((hash, index_entry));
+        if self.pending.len() >= self.batch_size {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Applies all pending index updates to the table.
+    pub fn flush(&mut self) -> Result<(), Error> {
+        for (hash, index_entry) in self.pending.drain(..) {
+            let result = {
+                let data = &self.tbl.data;
+                let data_start = self.tbl.data_start;
+                let key_start = (index_entry.position() - data_start) as usize;
+                let key = &data[key_start..key_start + index_entry.key_size as usize];
+                self.tbl.index.index_set(hash, |e| match_key(e, data, data_start, key), index_entry)
+            };
+            self.tbl.unindexed -= 1;
+            if let Some(old) = result {
+                self.tbl.free_data(old.position());
+            }
+            self.tbl.run_hooks(|hooks, tbl| {
+                let new = tbl.entry_from_index_data(index_entry);
+                hooks.set(new.key, result.map(|old| tbl.entry_from_index_data(old).value), new.value)
+            });
+        }
+        debug_assert!(self.tbl.is_valid(), "Invalid after ingest flush");
+        self.tbl.maybe_shrink_data()
+    }
+
+    /// Applies all pending index updates and ends the ingestion.
+    #[inline]
+    pub fn finish(mut self) -> Result<(), Error> {
+        self.flush()
+    }
+
+    /// Returns the number of entries that have been written but are not yet visible in the index.
+    #[inline]
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Returns a reference to the table.
+    ///
+    /// Pending entries are not visible in this view until they are flushed.
+    #[inline]
+    pub fn table(&self) -> &Table {
+        self.tbl
+    }
+}
+
+impl<'a> Drop for Ingest<'a> {
+    fn drop(&mut self) {
+        if !self.pending.is_empty() {
+            self.flush().ok();
+        }
+    }
+}
+
+impl Table {
+    /// Starts a bulk ingestion into this table.
+    ///
+    /// Index updates are collected in batches of `batch_size` entries and applied together.
+    /// See [`Ingest`] for more info.
+    #[inline]
+    pub fn ingest(&mut self, batch_size: usize) -> Ingest<'_> {
+        Ingest { tbl: self, batch_size: batch_size.max(1), pending: Vec::with_capacity(batch_size) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ManualClock, TableOptions};
+    use std::{sync::Arc, time::Duration};
+
+    #[test]
+    fn test_ingest() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::create(file.path()).unwrap();
+        tbl.set(&5u16.to_ne_bytes(), "old".as_bytes()).unwrap();
+        tbl.namespace("ns").unwrap().set("key".as_bytes(), &[1]).unwrap();
+        {
+            let mut ingest = tbl.ingest(100);
+            // The scoped key of the entry of the namespace
+            assert!(matches!(ingest.set(b"\x02nskey", &[2]), Err(Error::InvalidOptions(_))));
+            for i in 0u16..1000 {
+                ingest.set(&i.to_ne_bytes(), &i.to_be_bytes()).unwrap();
+            }
+            assert!(ingest.pending() < 100);
+            ingest.finish().unwrap();
+        }
+        assert!(tbl.is_valid());
+        assert_eq!(tbl.len(), 1000);
+        for i in 0u16..1000 {
+            assert_eq!(tbl.get(&i.to_ne_bytes()), Some(&i.to_be_bytes() as &[u8]));
+        }
+        tbl.close();
+        let tbl = Table::open(file.path()).unwrap();
+        assert!(tbl.is_valid());
+        assert_eq!(tbl.len(), 1000);
+    }
+
+    #[test]
+    fn test_ingest_evict_and_stats() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let options = TableOptions::default().max_data_size(Some(10_000)).stats_interval(Some(Duration::from_secs(60)));
+        let mut tbl = options.create(file.path()).unwrap();
+        let clock = Arc::new(ManualClock::new(1000));
+        tbl.set_clock(clock.clone());
+        {
+            let mut ingest = tbl.ingest(10);
+            for i in 0u32..1000 {
+                ingest.set(&i.to_le_bytes(), &[0; 96]).unwrap();
+                assert!(ingest.table().mem.used_size() <= 10_000);
+                clock.advance(10);
+            }
+            ingest.finish().unwrap();
+        }
+        assert!(tbl.evicted() > 800);
+        assert!(tbl.contains(&999u32.to_le_bytes()));
+        assert!(tbl.stats_history().len() > 10);
+        assert!(tbl.is_valid());
+    }
+}
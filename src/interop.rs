@@ -0,0 +1,228 @@
+use std::io::{BufWriter, Read, Write};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{redact::redact, Entry, Error, Redaction, Table};
+
+/// Key or value as represented in JSON
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum JsonData {
+    Text(String),
+    Binary { base64: String },
+}
+
+impl JsonData {
+    fn encode(data: &[u8]) -> Self {
+        match std::str::from_utf8(data) {
+            Ok(text) => JsonData::Text(text.to_string()),
+            Err(_) => JsonData::Binary { base64: STANDARD.encode(data) },
+        }
+    }
+
+    fn decode(self) -> Result<Vec<u8>, Error> {
+        match self {
+            JsonData::Text(text) => Ok(text.into_bytes()),
+            JsonData::Binary { base64 } => decode_base64(&base64),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct JsonEntry {
+    key: JsonData,
+    value: JsonData,
+}
+
+/// Encoding of a key or value in CSV
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum CsvEncoding {
+    Utf8,
+    Base64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CsvRecord {
+    key: String,
+    key_encoding: CsvEncoding,
+    value: String,
+    value_encoding: CsvEncoding,
+}
+
+fn encode_csv(data: &[u8]) -> (String, CsvEncoding) {
+    match std::str::from_utf8(data) {
+        Ok(text) => (text.to_string(), CsvEncoding::Utf8),
+        Err(_) => (STANDARD.encode(data), CsvEncoding::Base64),
+    }
+}
+
+fn decode_csv(data: String, encoding: CsvEncoding) -> Result<Vec<u8>, Error> {
+    match encoding {
+        CsvEncoding::Utf8 => Ok(data.into_bytes()),
+        CsvEncoding::Base64 => decode_base64(&data),
+    }
+}
+
+fn decode_base64(data: &str) -> Result<Vec<u8>, Error> {
+    STANDARD.decode(data).map_err(|_| Error::Corrupt("invalid base64 data"))
+}
+
+impl Table {
+    /// Writes all entries of the table to the given writer as JSON lines.
+    ///
+    /// Every line contains an object with a `key` and a `value` field. Keys and values that are valid UTF-8 are
+    /// written as JSON strings, all others as objects with a single `base64` field, e.g.
+    /// `{"key":"name","value":{"base64":"AAEC"}}`.
+    ///
+    /// Returns the number of exported entries. Entry flags are not exported, use [`Table::export_to`] for a
+    /// lossless copy.
+    #[inline]
+    pub fn export_json<W: Write>(&self, writer: W) -> Result<usize, Error> {
+        self.export_json_redacted(writer, |_| Redaction::Keep)
+    }
+
+    /// Writes the entries of the table to the given writer as JSON lines like [`Table::export_json`], filtered by
+    /// `filter`, see [`Table::export_redacted_to`].
+    pub fn export_json_redacted<W: Write, F: FnMut(&Entry<'_>) -> Redaction>(
+        &self, writer: W, mut filter: F,
+    ) -> Result<usize, Error> {
+        self.check_byte_keys()?;
+        let mut out = BufWriter::new(writer);
+        let mut count = 0;
+        for entry in self.iter() {
+            let value = match redact(&mut filter, &entry) {
+                Some(value) => value,
+                None => continue,
+            };
+            let record = JsonEntry { key: JsonData::encode(entry.key), value: JsonData::encode(&value) };
+            serde_json::to_writer(&mut out, &record).map_err(Error::Json)?;
+            out.write_all(b"\n").map_err(Error::Io)?;
+            count += 1;
+        }
+        out.flush().map_err(Error::Io)?;
+        Ok(count)
+    }
+
+    /// Reads entries written by [`Table::export_json`] and stores them in the table.
+    ///
+    /// Existing entries with the same keys are overwritten. Returns the number of imported entries. The entries are
+    /// stored while reading, so on errors, the entries read up to this point have already been stored.
+    pub fn import_json<R: Read>(&mut self, reader: R) -> Result<usize, Error> {
+        self.check_writable()?;
+        self.check_byte_keys()?;
+        let mut count = 0;
+        for record in serde_json::Deserializer::from_reader(reader).into_iter::<JsonEntry>() {
+            let record = record.map_err(Error::Json)?;
+            self.set(&record.key.decode()?, &record.value.decode()?)?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Writes all entries of the table to the given writer as CSV.
+    ///
+    /// The file has a header line and the columns `key`, `key_encoding`, `value` and `value_encoding`. Keys and
+    /// values that are valid UTF-8 are written as they are with the encoding `utf8`, all others are written base64
+    /// encoded with the encoding `base64`.
+    ///
+    /// Returns the number of exported entries. Entry flags are not exported, use [`Table::export_to`] for a
+    /// lossless copy.
+    #[inline]
+    pub fn export_csv<W: Write>(&self, writer: W) -> Result<usize, Error> {
+        self.export_csv_redacted(writer, |_| Redaction::Keep)
+    }
+
+    /// Writes the entries of the table to the given writer as CSV like [`Table::export_csv`], filtered by `filter`,
+    /// see [`Table::export_redacted_to`].
+    pub fn export_csv_redacted<W: Write, F: FnMut(&Entry<'_>) -> Redaction>(
+        &self, writer: W, mut filter: F,
+    ) -> Result<usize, Error> {
+        self.check_byte_keys()?;
+        let mut out = csv::Writer::from_writer(writer);
+        let mut count = 0;
+        for entry in self.iter() {
+            let value = match redact(&mut filter, &entry) {
+                Some(value) => value,
+                None => continue,
+            };
+            let (key, key_encoding) = encode_csv(entry.key);
+            let (value, value_encoding) = encode_csv(&value);
+            out.serialize(CsvRecord { key, key_encoding, value, value_encoding }).map_err(Error::Csv)?;
+            count += 1;
+        }
+        out.flush().map_err(Error::Io)?;
+        Ok(count)
+    }
+
+    /// Reads entries written by [`Table::export_csv`] and stores them in the table.
+    ///
+    /// Existing entries with the same keys are overwritten. Returns the number of imported entries. The entries are
+    /// stored while reading, so on errors, the entries read up to this point have already been stored.
+    pub fn import_csv<R: Read>(&mut self, reader: R) -> Result<usize, Error> {
+        self.check_writable()?;
+        self.check_byte_keys()?;
+        let mut count = 0;
+        for record in csv::Reader::from_reader(reader).into_deserialize::<CsvRecord>() {
+            let record = record.map_err(Error::Csv)?;
+            let key = decode_csv(record.key, record.key_encoding)?;
+            let value = decode_csv(record.value, record.value_encoding)?;
+            self.set(&key, &value)?;
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fill(tbl: &mut Table) {
+        tbl.set("text".as_bytes(), "value, with \"quotes\"\nand lines".as_bytes()).unwrap();
+        tbl.set(&[0xff, 0], &[0x80, 1, 2]).unwrap();
+        tbl.set(&[], &[]).unwrap();
+    }
+
+    #[test]
+    fn test_json() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::create(file.path()).unwrap();
+        fill(&mut tbl);
+        let mut json = Vec::new();
+        assert_eq!(tbl.export_json(&mut json).unwrap(), 3);
+        let text = String::from_utf8(json.clone()).unwrap();
+        assert_eq!(text.lines().count(), 3);
+        assert!(text.contains(r#"{"key":{"base64":"/wA="},"value":{"base64":"gAEC"}}"#));
+        tbl.clear().unwrap();
+        assert_eq!(tbl.import_json(&json[..]).unwrap(), 3);
+        assert_eq!(tbl.get(&[0xff, 0]), Some(&[0x80, 1, 2][..]));
+        assert_eq!(tbl.get(&[]), Some(&[][..]));
+        assert!(matches!(tbl.import_json(&b"{\"key\": 1}"[..]), Err(Error::Json(_))));
+        assert!(matches!(
+            tbl.import_json(&b"{\"key\": \"a\", \"value\": {\"base64\": \"!\"}}"[..]),
+            Err(Error::Corrupt(_))
+        ));
+    }
+
+    #[test]
+    fn test_csv() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::create(file.path()).unwrap();
+        fill(&mut tbl);
+        let mut csv = Vec::new();
+        assert_eq!(tbl.export_csv(&mut csv).unwrap(), 3);
+        let text = String::from_utf8(csv.clone()).unwrap();
+        assert!(text.starts_with("key,key_encoding,value,value_encoding\n"));
+        assert!(text.contains("/wA=,base64,gAEC,base64\n"));
+        tbl.clear().unwrap();
+        assert_eq!(tbl.import_csv(&csv[..]).unwrap(), 3);
+        assert_eq!(tbl.get("text".as_bytes()), Some("value, with \"quotes\"\nand lines".as_bytes()));
+        assert_eq!(tbl.get(&[0xff, 0]), Some(&[0x80, 1, 2][..]));
+        assert!(matches!(
+            tbl.import_csv(&b"key,key_encoding,value,value_encoding\na,hex,b,utf8\n"[..]),
+            Err(Error::Csv(_))
+        ));
+    }
+}
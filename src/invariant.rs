@@ -0,0 +1,52 @@
+//! Checks of internal invariants
+//!
+//! This is the only place that panics on violated invariants. With the `no-panic` feature, the macros return
+//! [`Error::Corrupt`](crate::Error::Corrupt) from the calling function instead, so they can only be used in functions
+//! returning `Result<_, Error>`.
+
+/// Fails because the internal invariant described by the reason is violated
+macro_rules! invariant_violated {
+    ($reason:literal) => {{
+        #[cfg(feature = "no-panic")]
+        return Err(crate::Error::Corrupt($reason));
+        #[cfg(not(feature = "no-panic"))]
+        panic!($reason)
+    }};
+}
+
+/// Fails if the condition does not hold, see [`invariant_violated`]
+macro_rules! invariant {
+    ($cond:expr, $reason:literal) => {
+        if !$cond {
+            invariant_violated!($reason)
+        }
+    };
+}
+
+/// Checks an invariant in code that cannot return an error
+///
+/// With the `no-panic` feature, the first violation is recorded in `slot` to be reported later.
+#[inline]
+#[track_caller]
+pub(crate) fn check(slot: &mut Option<&'static str>, ok: bool, reason: &'static str) {
+    if !ok {
+        if cfg!(feature = "no-panic") {
+            slot.get_or_insert(reason);
+        } else {
+            panic!("{}", reason);
+        }
+    }
+}
+
+/// Checks an invariant like [`check`], naming the position of the violation in the panic message
+#[inline]
+#[track_caller]
+pub(crate) fn check_at(slot: &mut Option<&'static str>, ok: bool, reason: &'static str, position: u64) {
+    if !ok {
+        if cfg!(feature = "no-panic") {
+            slot.get_or_insert(reason);
+        } else {
+            panic!("{} at position {}", reason, position);
+        }
+    }
+}
@@ -1,8 +1,20 @@
-use crate::{index::IndexEntry, Entry, EntryMut, Error, Table};
+use std::{
+    collections::{btree_set, HashMap},
+    slice,
+};
 
-/// Internal iterator over all entries in a table
+use crate::{
+    checksum::{self, CHECKSUM_SIZE},
+    index::{is_namespaced, IndexEntry, IndexEntryData},
+    memmngr::{Size, Used},
+    Entry, EntryMut, Error, Table, FLAG_CHECKSUM, RESERVED_FLAGS,
+};
+
+/// Iterator over all entries in a table, see [`Table::iter`]
 pub struct Iter<'a> {
     pos: usize,
+    end: usize,
+    remaining: usize,
     entries: &'a [IndexEntry],
     tbl: &'a Table,
 }
@@ -11,28 +23,270 @@ impl<'a> Iterator for Iter<'a> {
     type Item = Entry<'a>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        loop {
-            if self.pos >= self.entries.len() {
-                return None;
-            }
+        while self.pos < self.end {
             let entry = &self.entries[self.pos];
             self.pos += 1;
-            if !entry.is_used() {
+            if !entry.is_used() || is_namespaced(&entry.data) {
                 continue;
             }
+            self.remaining -= 1;
             return Some(self.tbl.entry_from_index_data(entry.data));
         }
+        None
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a> DoubleEndedIterator for Iter<'a> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        while self.end > self.pos {
+            self.end -= 1;
+            let entry = &self.entries[self.end];
+            if !entry.is_used() || is_namespaced(&entry.data) {
+                continue;
+            }
+            self.remaining -= 1;
+            return Some(self.tbl.entry_from_index_data(entry.data));
+        }
+        None
+    }
+}
+
+impl<'a> ExactSizeIterator for Iter<'a> {}
+
+impl<'a> IntoIterator for &'a Table {
+    type Item = Entry<'a>;
+    type IntoIter = Iter<'a>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        let entries = self.index.get_entries();
+        Iter { pos: 0, end: entries.len(), remaining: self.len(), entries, tbl: self }
+    }
+}
+
+impl<K: AsRef<[u8]>, V: AsRef<[u8]>> Extend<(K, V)> for Table {
+    /// Stores all given key/value pairs in the table via [`Table::set_many`].
+    ///
+    /// # Panics
+    /// Panics if the pairs cannot be stored, also with the `no-panic` feature, use [`Table::set_many`] to handle
+    /// errors.
+    #[inline]
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        if let Err(err) = self.set_many(iter) {
+            panic!("Failed to extend table: {}", err)
+        }
+    }
+}
+
+/// Internal iterator over all entries in a table with mutable values, see [`Table::iter_mut`]
+pub struct IterMut<'a> {
+    pos: usize,
+    entries: &'a [IndexEntry],
+    data: &'a mut [u8],
+    data_start: u64,
+}
+
+impl<'a> Iterator for IterMut<'a> {
+    type Item = EntryMut<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pos < self.entries.len() {
+            let entry = &self.entries[self.pos];
+            self.pos += 1;
+            if !entry.is_used() || is_namespaced(&entry.data) {
+                continue;
+            }
+            let data = entry.data;
+            let checksum_size = if data.flags & FLAG_CHECKSUM > 0 { CHECKSUM_SIZE as Size } else { 0 };
+            let start = (data.position() - self.data_start) as usize;
+            let block = &mut self.data[start..start + (data.size() - checksum_size) as usize];
+            // Every entry has its own data block and is returned only once, so the returned slices never overlap
+            let block = unsafe { slice::from_raw_parts_mut(block.as_mut_ptr(), block.len()) };
+            let (key, value) = block.split_at_mut(data.key_size as usize);
+            return Some(EntryMut { key, value, flags: data.flags & !RESERVED_FLAGS });
+        }
+        None
+    }
+}
+
+/// Internal iterator over all entries in a table in the order of their data position
+pub struct PositionIter<'a> {
+    blocks: btree_set::Iter<'a, Used>,
+    tbl: &'a Table,
+}
+
+impl<'a> Iterator for PositionIter<'a> {
+    type Item = Entry<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for block in &mut self.blocks {
+            match self.tbl.index.get_block(block.hash, block.start) {
+                Some(data) if !is_namespaced(&data) => return Some(self.tbl.entry_from_index_data(data)),
+                _ => (),
+            }
+        }
+        None
+    }
+}
+
+/// Internal iterator removing all entries from a table, see [`Table::drain`]
+pub struct Drain<'a> {
+    pos: usize,
+    tbl: &'a mut Table,
+}
+
+impl<'a> Iterator for Drain<'a> {
+    type Item = (Vec<u8>, Vec<u8>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pos < self.tbl.index.capacity() {
+            let entry = &self.tbl.index.get_entries()[self.pos];
+            if !entry.is_used() || is_namespaced(&entry.data) {
+                self.pos += 1;
+                continue;
+            }
+            let (hash, data) = (entry.hash, entry.data);
+            let item = {
+                let entry = self.tbl.entry_from_index_data(data);
+                (entry.key.to_vec(), entry.value.to_vec())
+            };
+            // The following entries are shifted back into this position, so the position is not advanced
+            self.tbl.index.index_delete(hash, |e| e.position() == data.position());
+            self.tbl.free_data(data.position());
+            self.tbl.run_hooks(|hooks, _| hooks.delete(&item.0, &item.1));
+            return Some(item);
+        }
+        None
+    }
+}
+
+impl<'a> Drop for Drain<'a> {
+    fn drop(&mut self) {
+        // Shrinking only fails if the file cannot be resized, the table stays valid in this case
+        while let Ok(true) = self.tbl.maybe_shrink_index() {}
+        self.tbl.maybe_shrink_data().ok();
     }
 }
 
 impl Table {
     /// Returns an iterator over all entries in the table
     ///
-    /// Each entry will be returned exactly once but in no particular order.
+    /// Each entry will be returned exactly once but in no particular order. Entries of namespaces are not included,
+    /// see [`Table::namespace`].
     /// The entries are returned as tuples of key and value.
+    ///
+    /// The iterator knows the number of remaining entries and can also be iterated from the back.
+    #[inline]
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = Entry<'_>> + ExactSizeIterator {
+        self.into_iter()
+    }
+
+    /// Returns an iterator over all entries in the table with mutable values
+    ///
+    /// Each entry will be returned exactly once but in no particular order. Changes to the values will be directly
+    /// reflected in the table, like with [`Table::each_mut`]. Use [`Table::retain_mut`] if checksums or the
+    /// write-ahead log have to be updated.
+    ///
+    /// ```
+    /// use rust_persist::Table;
+    ///
+    /// let mut table = Table::create("example_iter_mut.tbl").unwrap();
+    /// table.set("key1".as_bytes(), "value1".as_bytes()).unwrap();
+    /// for entry in table.iter_mut() {
+    ///     entry.value.make_ascii_uppercase();
+    /// }
+    /// assert_eq!(table.get("key1".as_bytes()), Some("VALUE1".as_bytes()));
+    /// ```
+    ///
+    /// In [`LockMode::Shared`](crate::LockMode::Shared), no entries are returned if the readers cannot be locked out,
+    /// use [`Table::try_iter_mut`] to get the error instead.
+    #[inline]
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = EntryMut<'_>> {
+        // Without the lock of a shared writer, the values must not be modified, so no entries are returned
+        let entries = if self.lock_readers().is_ok() { self.index.get_entries() } else { &[] };
+        IterMut { pos: 0, entries, data: &mut *self.data, data_start: self.data_start }
+    }
+
+    /// Returns an iterator over all entries with mutable values like [`Table::iter_mut`], but returns an error if the
+    /// readers cannot be locked out in [`LockMode::Shared`](crate::LockMode::Shared).
+    #[inline]
+    pub fn try_iter_mut(&mut self) -> Result<impl Iterator<Item = EntryMut<'_>>, Error> {
+        self.lock_readers()?;
+        Ok(IterMut { pos: 0, entries: self.index.get_entries(), data: &mut *self.data, data_start: self.data_start })
+    }
+
+    /// Returns an iterator over all entries in the table including their internal flags
+    pub(crate) fn raw_iter(&self) -> impl Iterator<Item = Entry<'_>> {
+        let entries = self.index.get_entries().iter().filter(|entry| entry.is_used());
+        entries.map(move |entry| self.raw_entry_from_index_data(entry.data))
+    }
+
+    /// Returns an iterator over all entries in the table ordered by their position in the data section
+    ///
+    /// As the data is read sequentially, this is the fastest way to scan all values of large tables.
+    #[inline]
+    pub fn iter_by_position(&self) -> impl Iterator<Item = Entry<'_>> {
+        PositionIter { blocks: self.mem.get_used().iter(), tbl: self }
+    }
+
+    /// Returns an iterator over copies of all entries in the table
+    ///
+    /// In contrast to [`Table::iter`], the keys and values are copied, so they can be kept after the table has been
+    /// modified or closed, or sent to other threads.
+    #[inline]
+    pub fn iter_owned(&self) -> impl Iterator<Item = (Vec<u8>, Vec<u8>)> + '_ {
+        self.iter().map(|entry| (entry.key.to_vec(), entry.value.to_vec()))
+    }
+
+    /// Returns an iterator that removes all entries from the table and yields copies of them
+    ///
+    /// Every entry is removed when it is returned, so entries that have not been returned when the iterator is dropped
+    /// stay in the table, like the entries of namespaces. The index and the data section are shrunk when the iterator
+    /// is dropped.
+    ///
+    /// If the table uses a write-ahead log, it is flushed first and the removals are not logged, like in
+    /// [`Table::clear`].
+    ///
+    /// ```
+    /// use rust_persist::Table;
+    ///
+    /// let mut table = Table::create("example_drain.tbl").unwrap();
+    /// table.set("key1".as_bytes(), "value1".as_bytes()).unwrap();
+    /// let entries: Vec<_> = table.drain().unwrap().collect();
+    /// assert_eq!(entries, vec![("key1".as_bytes().to_vec(), "value1".as_bytes().to_vec())]);
+    /// assert!(table.is_empty());
+    /// ```
+    pub fn drain(&mut self) -> Result<impl Iterator<Item = (Vec<u8>, Vec<u8>)> + '_, Error> {
+        self.check_writable()?;
+        if self.wal.is_some() {
+            // Changes in the write-ahead log must not be replayed onto the drained table
+            self.sync()?;
+        }
+        Ok(Drain { pos: 0, tbl: self })
+    }
+
+    /// Copies all entries of the table into the given collection, e.g. a [`HashMap`] or [`BTreeMap`](std::collections::BTreeMap)
     #[inline]
-    pub fn iter(&self) -> impl Iterator<Item = Entry<'_>> {
-        Iter { pos: 0, entries: self.index.get_entries(), tbl: self }
+    pub fn collect_into<M: Extend<(Vec<u8>, Vec<u8>)>>(&self, map: &mut M) {
+        map.extend(self.iter_owned())
+    }
+
+    /// Copies all entries of the table into a new [`HashMap`]
+    ///
+    /// If the keys and values of the table are larger than `max_size` bytes in total, [`Error::TooLarge`] is returned
+    /// without copying anything.
+    pub fn to_hashmap(&self, max_size: u64) -> Result<HashMap<Vec<u8>, Vec<u8>>, Error> {
+        if self.mem.used_size() > max_size {
+            return Err(Error::TooLarge);
+        }
+        let mut map = HashMap::with_capacity(self.len());
+        self.collect_into(&mut map);
+        Ok(map)
     }
 
     /// Execute the given method for all entries in the table
@@ -49,16 +303,12 @@ impl Table {
     ///
     /// The method will be executed once for each entry in the table.
     /// Changes to the values will be directy reflected in the table.
+    /// Like [`Table::iter_mut`], this does nothing if the readers cannot be locked out in
+    /// [`LockMode::Shared`](crate::LockMode::Shared).
+    #[inline]
     pub fn each_mut<F: FnMut(EntryMut<'_>)>(&mut self, mut f: F) {
-        for pos in 0..self.index.capacity() {
-            let entry_data = {
-                let entry = &self.index.get_entries()[pos];
-                if !entry.is_used() {
-                    continue;
-                }
-                entry.data
-            };
-            f(self.entry_mut_from_index_data(entry_data))
+        for entry in self.iter_mut() {
+            f(entry)
         }
     }
 
@@ -66,6 +316,7 @@ impl Table {
     ///
     /// If the predicate `f` returns `true` for a key/value pair, the entry will remain in the table, otherwise it will be removed.
     pub fn filter<F: FnMut(Entry<'_>) -> bool>(&mut self, mut f: F) -> Result<(), Error> {
+        self.check_writable()?;
         let mut pos = 0;
         loop {
             if pos >= self.index.capacity() {
@@ -73,27 +324,102 @@ impl Table {
             }
             let entry_data = {
                 let entry = &self.index.get_entries()[pos];
-                if !entry.is_used() {
+                if !entry.is_used() || is_namespaced(&entry.data) {
                     pos += 1;
                     continue;
                 }
                 entry.data
             };
             let key = {
-                let data = self.get_data(entry_data.position, entry_data.size);
-                let (key, value) = data.split_at(entry_data.key_size as usize);
-                if f(Entry { key, value, flags: entry_data.flags }) {
+                let Entry { key, value, flags } = self.entry_from_index_data(entry_data);
+                if f(Entry { key, value, flags }) {
                     pos += 1;
                     continue;
                 }
                 key.to_vec()
             };
+            self.log_delete(&key)?;
+            self.delete_entry_no_shrink(&key);
+        }
+        self.maybe_shrink_index()?;
+        self.maybe_shrink_data()?;
+        Ok(())
+    }
+
+    /// Filters the entries in the table according to the given predicate, which can also modify the values.
+    ///
+    /// If the predicate `f` returns `false` for an entry, the entry will be removed from the table, see
+    /// [`Table::filter`]. Changes to the values of kept entries are directly reflected in the table. In contrast to
+    /// [`Table::each_mut`], the checksums of changed values are updated and changed values are written to the
+    /// write-ahead log.
+    ///
+    /// ```
+    /// use rust_persist::Table;
+    ///
+    /// let mut table = Table::create("example_retain_mut.tbl").unwrap();
+    /// table.set("key1".as_bytes(), &[1]).unwrap();
+    /// table.set("key2".as_bytes(), &[5]).unwrap();
+    /// // Decrement the remaining lifetime stored in the value and drop expired entries
+    /// table
+    ///     .retain_mut(|entry| {
+    ///         entry.value[0] -= 1;
+    ///         entry.value[0] > 0
+    ///     })
+    ///     .unwrap();
+    /// assert_eq!(table.get("key2".as_bytes()), Some(&[4][..]));
+    /// assert_eq!(table.len(), 1);
+    /// ```
+    pub fn retain_mut<F: FnMut(EntryMut<'_>) -> bool>(&mut self, mut f: F) -> Result<(), Error> {
+        self.check_writable()?;
+        let mut pos = 0;
+        while pos < self.index.capacity() {
+            let entry_data = {
+                let entry = &self.index.get_entries()[pos];
+                if !entry.is_used() || is_namespaced(&entry.data) {
+                    pos += 1;
+                    continue;
+                }
+                entry.data
+            };
+            // The old value is only needed to detect changes that have to be checksummed or logged
+            let old_value = if self.wal.is_some() || entry_data.flags & FLAG_CHECKSUM != 0 {
+                Some(self.entry_from_index_data(entry_data).value.to_vec())
+            } else {
+                None
+            };
+            if f(self.entry_mut_from_index_data(entry_data)) {
+                if let Some(old_value) = old_value {
+                    self.commit_value_change(entry_data, &old_value)?;
+                }
+                pos += 1;
+                continue;
+            }
+            let key = self.entry_from_index_data(entry_data).key.to_vec();
+            self.log_delete(&key)?;
             self.delete_entry_no_shrink(&key);
         }
         self.maybe_shrink_index()?;
         self.maybe_shrink_data()?;
         Ok(())
     }
+
+    /// Updates the checksum of the entry and logs its value if the value differs from the given old value
+    fn commit_value_change(&mut self, entry: IndexEntryData, old_value: &[u8]) -> Result<(), Error> {
+        {
+            let Entry { key, value, .. } = self.entry_from_index_data(entry);
+            if value == old_value {
+                return Ok(());
+            }
+            self.log_set(key, value, entry.flags)?;
+        }
+        if entry.flags & FLAG_CHECKSUM != 0 {
+            let content_size = entry.size() - CHECKSUM_SIZE as Size;
+            let checksum = checksum::checksum(self.get_data(entry.position(), content_size));
+            self.get_data_mut(entry.position() + content_size, CHECKSUM_SIZE as Size)
+                .copy_from_slice(&checksum.to_le_bytes());
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -107,5 +433,166 @@ mod tests {
         tbl.set("key1".as_bytes(), "value1".as_bytes()).unwrap();
         tbl.set("key2".as_bytes(), "value2".as_bytes()).unwrap();
         assert_eq!(tbl.iter().count(), 2);
+        for i in 0u16..100 {
+            tbl.set(&i.to_ne_bytes(), &i.to_be_bytes()).unwrap();
+        }
+        let mut iter = tbl.iter();
+        assert_eq!(iter.len(), 102);
+        let first = iter.next().unwrap().key;
+        let last = iter.next_back().unwrap().key;
+        assert_eq!(iter.len(), 100);
+        let rest: Vec<_> = iter.by_ref().rev().map(|e| e.key).collect();
+        assert_eq!(iter.len(), 0);
+        assert!(iter.next().is_none() && iter.next_back().is_none());
+        let forward: Vec<_> = tbl.iter().map(|e| e.key).collect();
+        let mut backward: Vec<_> = tbl.iter().rev().map(|e| e.key).collect();
+        backward.reverse();
+        assert_eq!(forward, backward);
+        assert_eq!((forward[0], forward[101]), (first, last));
+        assert!(forward[1..101].iter().eq(rest.iter().rev()));
+    }
+
+    #[test]
+    fn test_collection_traits() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("table.tbl");
+        let mut tbl = Table::create_from_iter(&path, (0u16..100).map(|i| (i.to_ne_bytes(), i.to_be_bytes()))).unwrap();
+        assert_eq!(tbl.len(), 100);
+        tbl.extend(vec![(b"key".to_vec(), b"value".to_vec()), (0u16.to_ne_bytes().to_vec(), vec![])]);
+        assert_eq!(tbl.len(), 101);
+        assert_eq!(tbl.get(&0u16.to_ne_bytes()), Some(&[][..]));
+        let mut count = 0;
+        for entry in &tbl {
+            assert_eq!(tbl.get(entry.key), Some(entry.value));
+            count += 1;
+        }
+        assert_eq!(count, 101);
+        assert!(tbl.is_valid());
+    }
+
+    #[test]
+    fn test_iter_mut() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::options().checksums(true).create(file.path()).unwrap();
+        for i in 0u8..100 {
+            tbl.set(&[i], &[0; 8]).unwrap();
+        }
+        tbl.set(&[], &[]).unwrap();
+        // All entries can be held at the same time
+        let mut entries: Vec<_> = tbl.iter_mut().collect();
+        assert_eq!(entries.len(), 101);
+        for entry in &mut entries {
+            entry.value.fill(entry.key.first().copied().unwrap_or_default());
+        }
+        let entry = tbl.iter_mut().find(|entry| entry.key == [42]).unwrap();
+        entry.value[0] = 0xff;
+        assert_eq!(tbl.get(&[7]), Some(&[7; 8][..]));
+        assert_eq!(tbl.get(&[42]), Some(&[0xff, 42, 42, 42, 42, 42, 42, 42][..]));
+        assert_eq!(tbl.get(&[]), Some(&[][..]));
+        assert!(tbl.is_valid());
+    }
+
+    #[test]
+    fn test_iter_owned() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::create(file.path()).unwrap();
+        tbl.set("key1".as_bytes(), "value1".as_bytes()).unwrap();
+        let mut entries: Vec<_> = tbl.iter_owned().collect();
+        tbl.close();
+        let entry = std::thread::spawn(move || entries.pop().unwrap()).join().unwrap();
+        assert_eq!(entry, ("key1".as_bytes().to_vec(), "value1".as_bytes().to_vec()));
+    }
+
+    #[test]
+    fn test_to_hashmap() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::create(file.path()).unwrap();
+        tbl.set("key1".as_bytes(), "value1".as_bytes()).unwrap();
+        tbl.set("key2".as_bytes(), "value2".as_bytes()).unwrap();
+        let map = tbl.to_hashmap(1024).unwrap();
+        assert_eq!(map.len(), 2);
+        assert_eq!(map[&b"key2"[..]], b"value2");
+        assert!(matches!(tbl.to_hashmap(10), Err(Error::TooLarge)));
+        let mut map = std::collections::BTreeMap::new();
+        tbl.collect_into(&mut map);
+        assert_eq!(map.len(), 2);
+    }
+
+    #[test]
+    fn test_iter_by_position() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::create(file.path()).unwrap();
+        for i in 0u16..200 {
+            tbl.set(&i.to_ne_bytes(), &i.to_be_bytes()).unwrap();
+        }
+        tbl.set(&[], &[]).unwrap();
+        let mut last = 0;
+        let mut count = 0;
+        for entry in tbl.iter_by_position() {
+            if !entry.key.is_empty() {
+                let pos = entry.key.as_ptr() as usize;
+                assert!(pos >= last);
+                last = pos;
+            }
+            count += 1;
+        }
+        assert_eq!(count, tbl.len());
+    }
+
+    #[test]
+    fn test_retain_mut() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::options().checksums(true).wal(true).create(file.path()).unwrap();
+        for i in 0u8..200 {
+            tbl.set(&[i], &[i, 0]).unwrap();
+        }
+        tbl.flush().unwrap();
+        tbl.retain_mut(|entry| {
+            if entry.key[0] % 2 == 1 {
+                entry.value[1] = 1;
+            }
+            entry.key[0] < 100
+        })
+        .unwrap();
+        assert_eq!(tbl.len(), 100);
+        assert_eq!(tbl.get(&[1]), Some(&[1, 1][..]));
+        assert_eq!(tbl.get(&[2]), Some(&[2, 0][..]));
+        assert!(tbl.verify_checksums().is_empty());
+        assert!(tbl.is_valid());
+        let wal = std::fs::read(crate::wal::wal_path(file.path())).unwrap();
+        tbl.close();
+        // Simulate a crash where none of the changes reached the table file
+        let mut tbl = Table::create(file.path()).unwrap();
+        for i in 0u8..200 {
+            tbl.set(&[i], &[i, 0]).unwrap();
+        }
+        tbl.close();
+        std::fs::write(crate::wal::wal_path(file.path()), &wal).unwrap();
+        let tbl = Table::options().wal(true).open(file.path()).unwrap();
+        assert_eq!(tbl.len(), 100);
+        assert_eq!(tbl.get(&[1]), Some(&[1, 1][..]));
+    }
+
+    #[test]
+    fn test_drain() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::create(file.path()).unwrap();
+        for i in 0u16..1000 {
+            tbl.set(&i.to_ne_bytes(), &[i as u8; 100]).unwrap();
+        }
+        let capacity = tbl.index.capacity();
+        let mut drained = HashMap::new();
+        for (key, value) in tbl.drain().unwrap().take(600) {
+            drained.insert(key, value);
+        }
+        assert_eq!(tbl.len(), 400);
+        assert!(tbl.is_valid());
+        drained.extend(tbl.drain().unwrap());
+        assert_eq!(drained.len(), 1000);
+        assert_eq!(drained[&7u16.to_ne_bytes()[..]], [7; 100]);
+        assert!(tbl.is_empty());
+        assert!(tbl.index.capacity() < capacity);
+        assert_eq!(tbl.mem.used_size(), 0);
+        assert!(tbl.is_valid());
     }
 }
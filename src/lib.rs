@@ -3,10 +3,20 @@
 //!
 //! The hash table can store keys and values as `&[u8]` of arbitrary length.
 //! With the `msgpack` feature enabled, any type that can be (de-)serialized with serde/msgpack can be stored.
+//! With the `interop` feature enabled, tables can be exported to and imported from JSON lines and CSV.
+//! With the `arrow` feature enabled, tables can be exported as Arrow record batches and Parquet files.
+//! With the `abi` feature enabled, tables can be handed to dynamically loaded plugins via a C-compatible handle.
+//! With the `async` feature enabled, tables can be used from async code via [`AsyncTable`], which runs operations on
+//! the blocking thread pool of tokio.
+//! With the `rayon` feature enabled, tables can be scanned in parallel via [`Table::par_iter`].
+//! With the `no-panic` feature enabled, violated internal invariants are reported as [`Error::Corrupt`] instead of
+//! panicking. The [`Extend`] implementation of [`Table`] still panics if the entries cannot be stored, as it cannot
+//! return errors, use [`Table::set_many`] instead.
 //!
 //! The hash table consists of two parts:
 //! 1) an actual hash table that stores the hash of the key and the position and size of the key/value data.
 //! 2) a memory-managed data section where keys and values are stored.
+//!
 //! Both parts grow and shrink automatically depending on usage.
 //!
 //! The used algorithms are optimized for performance so that the data storage should be faster that a regular
@@ -49,26 +59,128 @@ use std::io;
 
 use index::{Hash, IndexEntry};
 
+#[macro_use]
+mod invariant;
+
+#[cfg(feature = "abi")]
+mod abi;
+mod append;
+#[cfg(feature = "arrow")]
+mod arrow;
+#[cfg(feature = "async")]
+mod async_table;
+mod backup;
+mod batch;
+mod checksum;
+mod clock;
+mod codec;
+mod concurrent;
+mod counter;
+mod diff;
+mod env;
+mod evict;
+mod export;
+#[cfg(feature = "fuzz")]
+mod fuzz;
+mod guard;
+#[cfg(feature = "test-utils")]
+mod harness;
+mod hashkey;
+mod history;
+mod hooks;
 mod index;
+#[cfg(feature = "interop")]
+mod interop;
+mod ingest;
 mod iter;
+mod mailbox;
 mod memmngr;
+mod merge;
 mod mmap;
 #[cfg(feature = "msgpack")]
 mod msgpack;
+mod namespace;
+mod options;
+#[cfg(feature = "rayon")]
+mod parallel;
+mod prehashed;
+mod reader;
+mod readonly;
+mod redact;
+mod repair;
 #[cfg(feature = "compress")]
 mod compress;
 mod resize;
+mod rewrite;
+mod sharded;
+mod shred;
+mod slowlog;
+mod stream;
 mod table;
+mod traits;
+mod verify;
+mod wal;
+mod watch;
+mod window;
 #[cfg(test)]
 mod tests;
 
 #[cfg(feature = "msgpack")]
-pub use msgpack::{deserialize, serialize, TypedTable};
+pub use msgpack::{deserialize, serialize, MsgPack, TypedTable};
 #[cfg(feature = "compress")]
-pub use compress::{compress, decompress, CompressedTypedTable};
-pub use table::{Entry, EntryMut, Table, Stats};
+pub use compress::{
+    compress, compress_chunked, decompress, decompress_limited, decompressed_size, CompressedSize, CompressedTable,
+    CompressedTypedTable, CompressionStats,
+};
+#[cfg(feature = "abi")]
+pub use abi::{
+    TableHandle, TableVTable, ValueCallback, ABI_ERROR, ABI_NOT_FOUND, ABI_OK, ABI_READ_ONLY, ABI_TOO_LARGE,
+    ABI_VERSION,
+};
+#[cfg(feature = "async")]
+pub use async_table::AsyncTable;
+pub use backup::BackupManifest;
+pub use batch::WriteBatch;
+pub use clock::{Clock, ManualClock, SystemClock};
+pub use codec::{BigEndian, Codec, Raw};
+pub use concurrent::ConcurrentTable;
+pub use diff::DiffItem;
+pub use env::{Env, EnvTable};
+#[cfg(feature = "test-utils")]
+pub use harness::{Failure, Harness, Op};
+pub use hashkey::{CollisionStats, HashKeyTable};
+pub use history::StatsSnapshot;
+pub use ingest::Ingest;
+pub use iter::Iter;
+pub use merge::MergeDecision;
+pub use namespace::Namespace;
+pub use options::{FlushMode, GrowFill, LockMode, TableOptions};
+pub use reader::{ReadGuard, SharedReader};
+pub use readonly::ReadOnlyTable;
+pub use redact::Redaction;
+pub use repair::{DegradedTable, DiscardReason, DiscardedEntry, RepairReport};
+pub use sharded::ShardedTable;
+pub use slowlog::{SlowOp, SlowOpKind};
+pub use stream::ValueWriter;
+pub use table::{Entry, EntryMut, QuickStats, Stats, Table, TableInfo};
+pub use traits::{BorrowedEntries, Entries, TableRead, TableWrite};
+pub use verify::{CheckLevel, Finding, IntegrityReport};
+pub use watch::ChangeEvent;
+pub use window::WindowedTable;
+
+const INDEX_HEADER: [u8; 16] = *b"rust-persist-02\n";
+
+/// Entry flag that marks entries pinned to the front of the data section, see [`Table::pin_front`]
+pub const FLAG_PINNED: u16 = 1 << 15;
 
-const INDEX_HEADER: [u8; 16] = *b"rust-persist-01\n";
+/// Entry flag that marks entries whose data block ends with a checksum, see [`TableOptions::checksums`]
+pub const FLAG_CHECKSUM: u16 = 1 << 14;
+
+/// Entry flags that are used internally, i.e. bits 8 to 13
+///
+/// These bits are cleared in the flags of entries passed to [`Table::set_entry`] and in the flags of all entries
+/// returned by the table, the lower 8 bits are free for use by applications.
+pub const RESERVED_FLAGS: u16 = 0x3f00;
 
 const MAX_USAGE: f64 = 0.9;
 const MIN_USAGE: f64 = 0.35;
@@ -84,6 +196,45 @@ pub enum Error {
     WrongHeader,
     /// The table is locked by another process
     TableLocked,
+    /// The given table options are invalid
+    InvalidOptions(&'static str),
+    /// A key, value or the table itself exceeds the supported size
+    TooLarge,
+    /// The table has been opened read-only
+    ReadOnly,
+    /// The table file is inconsistent, the reason is given
+    Corrupt(&'static str),
+    /// The data of an entry does not match its checksum
+    ChecksumMismatch,
+    /// The table file cannot be grown as the file system does not have enough free space
+    ///
+    /// The table has not been modified and stays usable.
+    OutOfSpace {
+        /// Number of bytes the file would have grown
+        needed: u64,
+        /// Number of bytes available on the file system
+        available: u64,
+    },
+    /// The table file would exceed the maximum size of the memory map set via [`TableOptions::max_map_size`]
+    ///
+    /// The table has not been modified and stays usable.
+    MapLimit {
+        /// Number of bytes that would have been mapped
+        needed: u64,
+        /// The configured maximum size
+        limit: u64,
+    },
+    /// The stored entries would exceed [`TableOptions::max_data_size`] and not enough entries can be evicted, as the
+    /// remaining entries are pinned or used internally
+    ///
+    /// Modifications that store new data have not been made, but other entries might have been evicted. Modifications
+    /// that only evict after they completed, e.g. [`Table::rename`], have been made.
+    DataLimit {
+        /// Number of bytes that would have been stored
+        needed: u64,
+        /// The configured maximum size
+        limit: u64,
+    },
     #[cfg(feature = "msgpack")]
     /// A key or value could not be deserialized
     Deserialize(rmp_serde::decode::Error),
@@ -92,7 +243,25 @@ pub enum Error {
     Serialize(rmp_serde::encode::Error),
     /// Failed to decompress data
     #[cfg(feature = "compress")]
-    Decompress(lz4_flex::block::DecompressError)
+    Decompress(lz4_flex::block::DecompressError),
+    /// A compressed value claims a larger original size than allowed, see
+    /// [`CompressedTable::max_decompressed_size`]
+    #[cfg(feature = "compress")]
+    DecompressLimit {
+        /// Original size claimed by the value
+        size: u64,
+        /// The maximum allowed size
+        limit: u64,
+    },
+    /// Failed to read or write JSON
+    #[cfg(feature = "interop")]
+    Json(serde_json::Error),
+    /// Failed to read or write CSV
+    #[cfg(feature = "interop")]
+    Csv(csv::Error),
+    /// Failed to write Parquet
+    #[cfg(feature = "arrow")]
+    Parquet(parquet::errors::ParquetError),
 }
 
 impl std::fmt::Display for Error {
@@ -104,6 +273,20 @@ impl std::fmt::Display for Error {
             }
             Error::WrongHeader => f.write_str("Persistence error: File has wrong header"),
             Error::TableLocked => f.write_str("Persistence error: Table is locked"),
+            Error::ChecksumMismatch => f.write_str("Persistence error: Checksum mismatch"),
+            Error::OutOfSpace { needed, available } => {
+                write!(f, "Persistence error: Not enough space, needed {} bytes, available {}", needed, available)
+            }
+            Error::MapLimit { needed, limit } => {
+                write!(f, "Persistence error: Memory map limit exceeded, needed {} bytes, limit {}", needed, limit)
+            }
+            Error::DataLimit { needed, limit } => {
+                write!(f, "Persistence error: Data size limit exceeded, needed {} bytes, limit {}", needed, limit)
+            }
+            Error::Corrupt(reason) => write!(f, "Persistence error: Table is corrupt: {}", reason),
+            Error::ReadOnly => f.write_str("Persistence error: Table is read-only"),
+            Error::TooLarge => f.write_str("Persistence error: Size limit exceeded"),
+            Error::InvalidOptions(reason) => write!(f, "Persistence error: Invalid options: {}", reason),
             Error::Deserialize(err) => {
                 f.write_str("Persistence error: Failed to deserialize data:")?;
                 err.fmt(f)
@@ -116,6 +299,25 @@ impl std::fmt::Display for Error {
                 f.write_str("Persistence error: Failed to decrompress data:")?;
                 err.fmt(f)
             }
+            #[cfg(feature = "compress")]
+            Error::DecompressLimit { size, limit } => {
+                write!(f, "Persistence error: Decompressed size of {} bytes exceeds limit of {}", size, limit)
+            }
+            #[cfg(feature = "interop")]
+            Error::Json(err) => {
+                f.write_str("Persistence error: Failed to process JSON:")?;
+                err.fmt(f)
+            }
+            #[cfg(feature = "interop")]
+            Error::Csv(err) => {
+                f.write_str("Persistence error: Failed to process CSV:")?;
+                err.fmt(f)
+            }
+            #[cfg(feature = "arrow")]
+            Error::Parquet(err) => {
+                f.write_str("Persistence error: Failed to write Parquet:")?;
+                err.fmt(f)
+            }
         }
     }
 }
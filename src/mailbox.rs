@@ -0,0 +1,200 @@
+use std::cmp;
+
+use crate::{Entry, Error, Table};
+
+/// Entry flag that marks the messages and the sequence counters of mailbox topics
+pub(crate) const FLAG_MAILBOX: u16 = 1 << 10;
+
+/// First byte of all keys of mailbox entries
+const MAILBOX_PREFIX: u8 = 0xff;
+
+/// Maximum length of a topic name in bytes
+const MAX_TOPIC_LEN: usize = 255;
+
+/// Returns the key of the sequence counter of the topic: the prefix, the length of the topic and the topic.
+#[inline]
+fn topic_key(topic: &str) -> Vec<u8> {
+    let mut key = Vec::with_capacity(2 + topic.len() + 8);
+    key.push(MAILBOX_PREFIX);
+    key.push(topic.len() as u8);
+    key.extend_from_slice(topic.as_bytes());
+    key
+}
+
+/// Returns the key of the message with the given sequence number: the key of the topic followed by the number.
+#[inline]
+fn message_key(topic: &str, seq: u64) -> Vec<u8> {
+    let mut key = topic_key(topic);
+    key.extend_from_slice(&seq.to_be_bytes());
+    key
+}
+
+fn check_topic(topic: &str) -> Result<(), Error> {
+    if topic.is_empty() || topic.len() > MAX_TOPIC_LEN {
+        return Err(Error::InvalidOptions("topic must have 1 to 255 bytes"));
+    }
+    Ok(())
+}
+
+impl Table {
+    /// Returns whether an entry that is not part of a mailbox is stored under the given key
+    fn is_foreign_entry(&self, key: &[u8]) -> bool {
+        matches!(self.get_raw_entry(key), Some(entry) if entry.flags & FLAG_MAILBOX == 0)
+    }
+
+    /// Returns the sequence numbers of the first and the last stored message of the topic
+    fn topic_range(&self, topic: &str) -> Option<(u64, u64)> {
+        check_topic(topic).ok()?;
+        let entry = self.get_raw_entry(&topic_key(topic))?;
+        if entry.flags & FLAG_MAILBOX == 0 || entry.value.len() != 16 {
+            return None;
+        }
+        let mut first = [0; 8];
+        let mut last = [0; 8];
+        first.copy_from_slice(&entry.value[..8]);
+        last.copy_from_slice(&entry.value[8..]);
+        Some((u64::from_le_bytes(first), u64::from_le_bytes(last)))
+    }
+
+    fn set_topic_range(&mut self, topic: &str, first: u64, last: u64) -> Result<(), Error> {
+        let mut value = [0; 16];
+        value[..8].copy_from_slice(&first.to_le_bytes());
+        value[8..].copy_from_slice(&last.to_le_bytes());
+        self.set_raw_entry(Entry { key: &topic_key(topic), value: &value, flags: FLAG_MAILBOX })?;
+        Ok(())
+    }
+
+    /// Appends the message to the given topic and returns its sequence number.
+    ///
+    /// Together with [`Table::poll`], this forms a small persistent mailbox, so that processes sharing a table (e.g.
+    /// via [`LockMode::Wait`](crate::LockMode::Wait)) can exchange events. The messages of a topic are numbered
+    /// starting with 1 and numbers are never reused, even after [`Table::trim_topic`]. Topics must have 1 to 255 bytes.
+    ///
+    /// Messages and the sequence counter of a topic are stored as entries of the table that are marked via an entry
+    /// flag and whose keys start with the byte `0xff`. They are included in [`Table::iter`] and [`Table::len`]. If
+    /// other entries are stored under these keys, [`Error::InvalidOptions`] is returned instead of replacing them.
+    ///
+    /// Tables created via [`HashKeyTable`](crate::HashKeyTable) are not supported.
+    ///
+    /// ```
+    /// use rust_persist::Table;
+    ///
+    /// let mut table = Table::create("example_mailbox.tbl").unwrap();
+    /// assert_eq!(table.post("events", "started".as_bytes()).unwrap(), 1);
+    /// assert_eq!(table.post("events", "stopped".as_bytes()).unwrap(), 2);
+    /// assert_eq!(table.poll("events", 1), [(2, "stopped".as_bytes())]);
+    /// ```
+    pub fn post(&mut self, topic: &str, msg: &[u8]) -> Result<u64, Error> {
+        self.check_byte_keys()?;
+        check_topic(topic)?;
+        let (first, last) = self.topic_range(topic).unwrap_or((1, 0));
+        let seq = last.checked_add(1).ok_or(Error::TooLarge)?;
+        if self.is_foreign_entry(&topic_key(topic)) || self.is_foreign_entry(&message_key(topic, seq)) {
+            return Err(Error::InvalidOptions("key of the topic is used by another entry"));
+        }
+        // The counter is updated last, so a message only becomes visible once it has been stored completely
+        self.set_raw_entry(Entry { key: &message_key(topic, seq), value: msg, flags: FLAG_MAILBOX })?;
+        self.set_topic_range(topic, first, seq)?;
+        Ok(seq)
+    }
+
+    /// Returns all stored messages of the topic with a sequence number greater than `since_seq` in order.
+    ///
+    /// Subscribers remember the sequence number of the last message they have seen and pass it to the next call, or
+    /// `0` to receive all stored messages. If the topic does not exist, an empty list is returned.
+    pub fn poll(&self, topic: &str, since_seq: u64) -> Vec<(u64, &[u8])> {
+        let (first, last) = match self.topic_range(topic) {
+            Some(range) => range,
+            None => return Vec::new(),
+        };
+        (cmp::max(first, since_seq.saturating_add(1))..=last)
+            .filter_map(|seq| match self.get_raw_entry(&message_key(topic, seq)) {
+                Some(entry) if entry.flags & FLAG_MAILBOX != 0 => Some((seq, entry.value)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Returns the sequence number of the last message posted to the topic, or `0` if nothing has been posted.
+    ///
+    /// New subscribers can pass this to [`Table::poll`] to only receive messages posted from now on.
+    #[inline]
+    pub fn topic_seq(&self, topic: &str) -> u64 {
+        self.topic_range(topic).map_or(0, |(_, last)| last)
+    }
+
+    /// Deletes all messages of the topic with a sequence number up to `up_to_seq` and returns their number.
+    ///
+    /// Messages are never deleted otherwise, so topics should be trimmed once all subscribers have seen them.
+    pub fn trim_topic(&mut self, topic: &str, up_to_seq: u64) -> Result<usize, Error> {
+        let (first, last) = match self.topic_range(topic) {
+            Some(range) => range,
+            None => return Ok(0),
+        };
+        let end = cmp::min(up_to_seq, last);
+        if end < first {
+            return Ok(0);
+        }
+        // The counter is updated first, so that no deleted message is visible if this is interrupted
+        self.set_topic_range(topic, end + 1, last)?;
+        let mut deleted = 0;
+        for seq in first..=end {
+            let key = message_key(topic, seq);
+            if !self.is_foreign_entry(&key) && self.delete(&key)?.is_some() {
+                deleted += 1;
+            }
+        }
+        Ok(deleted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mailbox() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::create(file.path()).unwrap();
+        assert!(tbl.poll("a", 0).is_empty());
+        assert_eq!(tbl.topic_seq("a"), 0);
+        for i in 1u64..=10 {
+            assert_eq!(tbl.post("a", &i.to_le_bytes()).unwrap(), i);
+        }
+        tbl.post("b", "other".as_bytes()).unwrap();
+        assert!(matches!(tbl.post("", &[]), Err(Error::InvalidOptions(_))));
+        assert!(matches!(tbl.post(&"x".repeat(256), &[]), Err(Error::InvalidOptions(_))));
+        assert_eq!(tbl.poll("a", 0).len(), 10);
+        assert_eq!(tbl.poll("a", 8), [(9, &9u64.to_le_bytes()[..]), (10, &10u64.to_le_bytes()[..])]);
+        assert!(tbl.poll("a", u64::MAX).is_empty());
+        assert_eq!(tbl.poll("b", 0), [(1, "other".as_bytes())]);
+        assert_eq!(tbl.trim_topic("a", 5).unwrap(), 5);
+        assert_eq!(tbl.trim_topic("a", 3).unwrap(), 0);
+        assert_eq!(tbl.poll("a", 0).iter().map(|(seq, _)| *seq).collect::<Vec<_>>(), [6, 7, 8, 9, 10]);
+        assert_eq!(tbl.trim_topic("a", u64::MAX).unwrap(), 5);
+        assert!(tbl.poll("a", 0).is_empty());
+        tbl.close();
+        // Another process opening the table later sees the messages and continues the numbering
+        let mut tbl = Table::open(file.path()).unwrap();
+        assert_eq!(tbl.topic_seq("a"), 10);
+        assert_eq!(tbl.post("a", &[]).unwrap(), 11);
+        assert_eq!(tbl.poll("a", 0), [(11, &[][..])]);
+        assert_eq!(tbl.len(), 2 + 2);
+        // A plain entry with the key of the counter is not a topic
+        tbl.set(&topic_key("c"), &[0; 16]).unwrap();
+        assert_eq!(tbl.topic_seq("c"), 0);
+        // Plain entries are never replaced or deleted by the mailbox
+        assert!(matches!(tbl.post("c", &[1]), Err(Error::InvalidOptions(_))));
+        assert_eq!(tbl.get(&topic_key("c")), Some(&[0; 16][..]));
+        tbl.set(&message_key("a", 12), &[2]).unwrap();
+        assert!(matches!(tbl.post("a", &[1]), Err(Error::InvalidOptions(_))));
+        assert_eq!(tbl.get(&message_key("a", 12)), Some(&[2][..]));
+        assert_eq!(tbl.topic_seq("a"), 11);
+        tbl.delete(&message_key("a", 12)).unwrap();
+        assert_eq!(tbl.post("a", &[1]).unwrap(), 12);
+        tbl.set(&message_key("a", 11), &[3]).unwrap();
+        assert_eq!(tbl.trim_topic("a", u64::MAX).unwrap(), 1);
+        assert_eq!(tbl.get(&message_key("a", 11)), Some(&[3][..]));
+        assert!(tbl.is_valid());
+    }
+}
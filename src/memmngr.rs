@@ -1,9 +1,12 @@
 use std::{cmp, collections::BTreeSet, ops::Bound};
 
-use crate::Hash;
+use crate::{
+    invariant::{check, check_at},
+    Finding, Hash,
+};
 
 pub(crate) type Pos = u64;
-pub(crate) type Size = u32;
+pub(crate) type Size = u64;
 
 #[derive(Ord, PartialEq, PartialOrd, Eq, Clone, Debug)]
 pub struct Used {
@@ -36,6 +39,7 @@ pub struct MemoryManagment {
     used: BTreeSet<Used>,
     free: BTreeSet<Free>,
     used_size: u64,
+    violation: Option<&'static str>,
 }
 
 impl MemoryManagment {
@@ -45,7 +49,7 @@ impl MemoryManagment {
         if start != end {
             free.insert(Free { start, size: (end - start) as Size });
         }
-        Self { start, end, used: BTreeSet::new(), free, used_size: 0 }
+        Self { start, end, used: BTreeSet::new(), free, used_size: 0, violation: None }
     }
 
     #[inline]
@@ -53,12 +57,24 @@ impl MemoryManagment {
         self.used.insert(Used { start, size: cmp::max(size, 1), hash });
     }
 
+    /// Returns whether any of the used blocks overlap, which [`MemoryManagment::fix_up`] cannot handle
+    pub(crate) fn has_overlaps(&self) -> bool {
+        let mut last_end = self.start;
+        for used in &self.used {
+            if used.start < last_end {
+                return true;
+            }
+            last_end = used.end();
+        }
+        false
+    }
+
     pub(crate) fn fix_up(&mut self) {
         self.free.clear();
         self.used_size = 0;
         let mut last_end = self.start;
         for used in &self.used {
-            self.used_size += used.size as u64;
+            self.used_size += used.size;
             if used.start != last_end {
                 self.free.insert(Free { size: (used.start - last_end) as Size, start: last_end });
             }
@@ -75,36 +91,74 @@ impl MemoryManagment {
         let best = candidates.min_by_key(|cand| {
             (cand.size - size).next_power_of_two().trailing_zeros() + cand.start.next_power_of_two().trailing_zeros()
         });
-        if let Some(free) = best.cloned() {
-            assert!(self.free.remove(&free));
-            debug_assert!(free.size >= size);
-            if free.size > size {
-                self.free.insert(Free { size: free.size - size, start: free.start + size as Pos });
-            }
-            self.used.insert(Used { start: free.start, size, hash });
-            self.used_size += size as u64;
-            Some(free.start)
-        } else {
-            None
+        best.cloned().map(|free| self.allocate_in(free, size, hash))
+    }
+
+    /// Allocates a block in the free region with the lowest position that is big enough
+    pub fn allocate_lowest(&mut self, mut size: Size, hash: Hash) -> Option<Pos> {
+        size = cmp::max(size, 1);
+        let candidates = self.free.range((Bound::Included(Free { size, start: 0 }), Bound::Unbounded));
+        let best = candidates.min_by_key(|cand| cand.start);
+        best.cloned().map(|free| self.allocate_in(free, size, hash))
+    }
+
+    fn allocate_in(&mut self, free: Free, size: Size, hash: Hash) -> Pos {
+        check(&mut self.violation, self.free.remove(&free), "allocated free block must be tracked");
+        debug_assert!(free.size >= size);
+        if free.size > size {
+            self.free.insert(Free { size: free.size - size, start: free.start + size as Pos });
         }
+        self.used.insert(Used { start: free.start, size, hash });
+        self.used_size += size;
+        free.start
     }
 
-    pub fn free(&mut self, pos: Pos) -> bool {
-        let used = if let Some(used) = self
-            .used
+    /// Returns the used block starting at the given position
+    pub(crate) fn block_at(&self, pos: Pos) -> Option<&Used> {
+        self.used
             .range((
                 Bound::Included(Used { start: pos, size: 0, hash: 0 }),
                 Bound::Excluded(Used { start: pos + 1, size: 0, hash: 0 }),
             ))
             .next()
-            .cloned()
-        {
-            used
-        } else {
-            return false;
+    }
+
+    /// Returns the first used block starting at or after the given position
+    pub(crate) fn next_used(&self, pos: Pos) -> Option<&Used> {
+        self.used.range(Used { start: pos, size: 0, hash: 0 }..).next()
+    }
+
+    /// Returns the parts of the given range that lie within the managed space and are not covered by used blocks
+    pub(crate) fn unused_parts(&self, start: Pos, end: Pos) -> Vec<(Pos, Pos)> {
+        let end = cmp::min(end, self.end);
+        let mut pos = cmp::max(start, self.start);
+        let first = Used { start: pos, size: 0, hash: 0 };
+        if let Some(before) = self.used.range(..&first).next_back() {
+            pos = cmp::max(pos, before.end());
+        }
+        let mut parts = vec![];
+        for used in self.used.range(&first..) {
+            if used.start >= end {
+                break;
+            }
+            if used.start > pos {
+                parts.push((pos, used.start));
+            }
+            pos = cmp::max(pos, used.end());
+        }
+        if pos < end {
+            parts.push((pos, end));
+        }
+        parts
+    }
+
+    pub fn free(&mut self, pos: Pos) -> bool {
+        let used = match self.block_at(pos) {
+            Some(used) => used.clone(),
+            None => return false,
         };
-        assert!(self.used.remove(&used));
-        self.used_size -= used.size as u64;
+        check(&mut self.violation, self.used.remove(&used), "used block must be tracked");
+        self.used_size -= used.size;
         let mut free = Free { start: used.start, size: used.size };
         let free_before = if let Some(before) = self.used.range((Bound::Unbounded, Bound::Excluded(&used))).last() {
             Free { start: before.end(), size: (free.start - before.end()) as Size }
@@ -112,7 +166,7 @@ impl MemoryManagment {
             Free { start: self.start, size: (pos - self.start) as Size }
         };
         if free_before.size > 0 {
-            assert!(self.free.remove(&free_before));
+            check(&mut self.violation, self.free.remove(&free_before), "free block before must be tracked");
             free.start = free_before.start;
             free.size += free_before.size;
         }
@@ -122,13 +176,110 @@ impl MemoryManagment {
             Free { start: used.end(), size: (self.end - used.end()) as Size }
         };
         if free_after.size > 0 {
-            assert!(self.free.remove(&free_after));
+            check(&mut self.violation, self.free.remove(&free_after), "free block after must be tracked");
             free.size += free_after.size;
         }
         self.free.insert(free);
         true
     }
 
+    /// Shrinks the used block at the given position to the given size, the rest of the block becomes free
+    pub fn shrink(&mut self, pos: Pos, mut size: Size) -> bool {
+        size = cmp::max(size, 1);
+        let used = match self
+            .used
+            .range((
+                Bound::Included(Used { start: pos, size: 0, hash: 0 }),
+                Bound::Excluded(Used { start: pos + 1, size: 0, hash: 0 }),
+            ))
+            .next()
+            .cloned()
+        {
+            Some(used) if used.size >= size => used,
+            _ => return false,
+        };
+        if used.size == size {
+            return true;
+        }
+        check(&mut self.violation, self.used.remove(&used), "used block must be tracked");
+        let shrunk = Used { start: pos, size, hash: used.hash };
+        let free_end =
+            self.used.range((Bound::Excluded(&shrunk), Bound::Unbounded)).next().map_or(self.end, |u| u.start);
+        if free_end > used.end() {
+            let removed = self.free.remove(&Free { start: used.end(), size: free_end - used.end() });
+            check(&mut self.violation, removed, "free block after must be tracked");
+        }
+        self.free.insert(Free { start: shrunk.end(), size: free_end - shrunk.end() });
+        self.used_size -= used.size - size;
+        self.used.insert(shrunk);
+        true
+    }
+
+    /// Grows the used block at the given position to the given size, taking the space from the free region directly
+    /// after it. Returns `false` and leaves the block unchanged if that region is too small.
+    pub fn grow(&mut self, pos: Pos, size: Size) -> bool {
+        let used = match self
+            .used
+            .range((
+                Bound::Included(Used { start: pos, size: 0, hash: 0 }),
+                Bound::Excluded(Used { start: pos + 1, size: 0, hash: 0 }),
+            ))
+            .next()
+            .cloned()
+        {
+            Some(used) if used.size <= size => used,
+            _ => return false,
+        };
+        if used.size == size {
+            return true;
+        }
+        let free_end = self.used.range((Bound::Excluded(&used), Bound::Unbounded)).next().map_or(self.end, |u| u.start);
+        if free_end < pos + size {
+            return false;
+        }
+        let removed = self.free.remove(&Free { start: used.end(), size: free_end - used.end() });
+        check(&mut self.violation, removed, "free block after must be tracked");
+        let grown = Used { start: pos, size, hash: used.hash };
+        if free_end > grown.end() {
+            self.free.insert(Free { start: grown.end(), size: free_end - grown.end() });
+        }
+        self.used_size += size - used.size;
+        check(&mut self.violation, self.used.remove(&used), "used block must be tracked");
+        self.used.insert(grown);
+        true
+    }
+
+    /// Splits the used block at the given position after `size` bytes, the rest becomes a block with the given hash
+    pub fn split(&mut self, pos: Pos, size: Size, hash: Hash) -> bool {
+        let used = match self.block_at(pos) {
+            Some(used) if size > 0 && used.size > size => used.clone(),
+            _ => return false,
+        };
+        check(&mut self.violation, self.used.remove(&used), "used block must be tracked");
+        self.used.insert(Used { start: pos, size, hash: used.hash });
+        self.used.insert(Used { start: pos + size, size: used.size - size, hash });
+        true
+    }
+
+    /// Changes the hash of the used block at the given position, e.g. when the block now belongs to a different key
+    pub fn rehash(&mut self, pos: Pos, hash: Hash) -> bool {
+        let used = match self
+            .used
+            .range((
+                Bound::Included(Used { start: pos, size: 0, hash: 0 }),
+                Bound::Excluded(Used { start: pos + 1, size: 0, hash: 0 }),
+            ))
+            .next()
+            .cloned()
+        {
+            Some(used) => used,
+            None => return false,
+        };
+        check(&mut self.violation, self.used.remove(&used), "used block must be tracked");
+        self.used.insert(Used { hash, ..used });
+        true
+    }
+
     pub fn set_end(&mut self, end: Pos) -> Vec<Used> {
         let mut evicted = vec![];
         if end <= self.end {
@@ -146,10 +297,10 @@ impl MemoryManagment {
             Free { start: self.start, size: (self.end - self.start) as Size }
         };
         if last_free.size > 0 {
-            assert!(self.free.remove(&last_free));
+            check(&mut self.violation, self.free.remove(&last_free), "last free block must be tracked");
         }
         self.end = end;
-        assert!(last_free.start <= self.end);
+        check(&mut self.violation, last_free.start <= self.end, "end must not cut used blocks");
         last_free.size = (self.end - last_free.start) as Size;
         if last_free.size > 0 {
             self.free.insert(last_free);
@@ -174,10 +325,10 @@ impl MemoryManagment {
             Free { start: self.start, size: (self.end - self.start) as Size }
         };
         if first_free.size > 0 {
-            assert!(self.free.remove(&first_free));
+            check(&mut self.violation, self.free.remove(&first_free), "first free block must be tracked");
         }
         self.start = start;
-        assert!(first_free.end() >= self.start);
+        check(&mut self.violation, first_free.end() >= self.start, "start must not cut used blocks");
         first_free.size = (first_free.end() - self.start) as Size;
         first_free.start = self.start;
         if first_free.size > 0 {
@@ -196,6 +347,12 @@ impl MemoryManagment {
         self.used.iter().last()
     }
 
+    /// Returns the size of the free space after the last used block
+    #[inline]
+    pub(crate) fn free_tail(&self) -> u64 {
+        self.end - self.last_used().map(|u| u.end()).unwrap_or(self.start)
+    }
+
     #[inline]
     pub(crate) fn get_used(&self) -> &BTreeSet<Used> {
         &self.used
@@ -206,6 +363,26 @@ impl MemoryManagment {
         self.used_size
     }
 
+    /// Returns the first violated invariant, which is only recorded with the `no-panic` feature
+    #[inline]
+    pub fn violation(&self) -> Option<&'static str> {
+        self.violation
+    }
+
+    /// Records a violated invariant of the table that is detected in code that cannot return an error
+    #[inline]
+    #[track_caller]
+    pub fn check_invariant(&mut self, ok: bool, reason: &'static str) {
+        check(&mut self.violation, ok, reason)
+    }
+
+    /// Records a violated invariant like [`MemoryManagment::check_invariant`], naming the position of the violation
+    #[inline]
+    #[track_caller]
+    pub fn check_invariant_at(&mut self, ok: bool, reason: &'static str, position: Pos) {
+        check_at(&mut self.violation, ok, reason, position)
+    }
+
     #[inline]
     pub fn start(&self) -> Pos {
         self.start
@@ -216,13 +393,6 @@ impl MemoryManagment {
         self.end
     }
 
-    #[inline]
-    pub(crate) fn clear(&mut self) {
-        self.used.clear();
-        self.free.clear();
-        self.used_size = 0;
-    }
-
     #[inline]
     pub(crate) fn take_used(self) -> BTreeSet<Used> {
         self.used
@@ -232,51 +402,63 @@ impl MemoryManagment {
         self.free.iter().last().map(|v| v.size).unwrap_or_default()
     }
 
-    pub(crate) fn is_valid(&self) -> bool {
-        let mut valid = true;
+    /// Checks the block accounting for inconsistencies and adds all of them to `findings`.
+    ///
+    /// All positions are reported relative to `base`.
+    pub(crate) fn check(&self, base: Pos, findings: &mut Vec<Finding>) {
         let mut blocks = Vec::with_capacity(self.used.len() + self.free.len());
         let mut used_size = 0;
         for used in &self.used {
             blocks.push((used.start, used.size, true));
-            used_size += used.size as u64;
+            used_size += used.size;
         }
         for free in &self.free {
             blocks.push((free.start, free.size, false))
         }
         if used_size != self.used_size {
-            println!("Used size wrong: {} vs {}", used_size, self.used_size);
-            valid = false;
+            findings.push(Finding::UsedSizeMismatch { stored: self.used_size, actual: used_size });
         }
-        if !blocks.is_empty() {
-            blocks.sort_by_key(|&(p, ..)| p);
-            let mut last = self.start;
-            let mut used = !blocks[0].2;
-            for &(p, l, u) in &blocks {
-                if l == 0 {
-                    println!("Zero-size block: (pos: {}, len:{}, used: {})", p, l, u);
-                    valid = false;
-                }
-                if p != last || !u && !used {
-                    println!(
-                        "Non-sequential blocks: (end of last block: {}, used: {}) -> (pos: {}, len: {}, used: {})",
-                        last, used, p, l, u
-                    );
-                    valid = false;
-                }
-                used = u;
-                last = p + l as u64;
+        if blocks.is_empty() {
+            return;
+        }
+        blocks.sort_by_key(|&(p, ..)| p);
+        let mut last = self.start;
+        let mut used = !blocks[0].2;
+        for &(p, l, u) in &blocks {
+            let position = p.wrapping_sub(base);
+            if l == 0 {
+                findings.push(Finding::ZeroSizeBlock { position });
             }
-            if last != self.end {
-                println!("Last block does not end at end: {} vs {}", last, self.end);
-                valid = false
+            if p < last {
+                findings.push(Finding::OverlappingBlocks { position, previous_end: last.wrapping_sub(base) });
+            } else if p > last {
+                findings.push(Finding::UntrackedSpace { start: last.wrapping_sub(base), end: position });
+            } else if !u && !used {
+                findings.push(Finding::AdjacentFreeBlocks { position });
             }
+            used = u;
+            last = p + l;
+        }
+        if last < self.end {
+            findings.push(Finding::UntrackedSpace { start: last.wrapping_sub(base), end: self.end.wrapping_sub(base) });
+        } else if last > self.end {
+            findings.push(Finding::BlockBeyondEnd { end: last.wrapping_sub(base) });
         }
-        if !valid {
+    }
+
+    #[cfg(test)]
+    pub(crate) fn is_valid(&self) -> bool {
+        let mut findings = Vec::new();
+        self.check(self.start, &mut findings);
+        if !findings.is_empty() {
+            for finding in &findings {
+                println!("Memory error: {:?}", finding);
+            }
             println!("Start: {}, end: {}, used_size: {}", self.start, self.end, self.used_size);
             println!("Used: {:?}", self.used);
             println!("Free: {:?}", self.free);
         }
-        valid
+        findings.is_empty()
     }
 }
 
@@ -288,6 +470,9 @@ mod tests {
     enum Op {
         Alloc { size: Size, hash: Hash, result: Option<Pos> },
         Free { pos: Pos, result: bool },
+        Shrink { pos: Pos, size: Size, result: bool },
+        Grow { pos: Pos, size: Size, result: bool },
+        Rehash { pos: Pos, hash: Hash, result: bool },
         SetStart { start: Pos, result: Vec<Used> },
         SetEnd { end: Pos, result: Vec<Used> },
     }
@@ -300,6 +485,9 @@ mod tests {
             match *op {
                 Op::Alloc { size, hash, result } => assert_eq!(mem.allocate(size, hash), result),
                 Op::Free { pos, result } => assert_eq!(mem.free(pos), result),
+                Op::Shrink { pos, size, result } => assert_eq!(mem.shrink(pos, size), result),
+                Op::Grow { pos, size, result } => assert_eq!(mem.grow(pos, size), result),
+                Op::Rehash { pos, hash, result } => assert_eq!(mem.rehash(pos, hash), result),
                 Op::SetStart { start, ref result } => assert_eq!(&mem.set_start(start), result),
                 Op::SetEnd { end, ref result } => assert_eq!(&mem.set_end(end), result),
             };
@@ -348,6 +536,65 @@ mod tests {
         )
     }
 
+    #[test]
+    fn shrink() {
+        let mut mem = MemoryManagment::new(1000, 2000);
+        run_ops(
+            &mut mem,
+            &[
+                Op::Alloc { size: 400, hash: 0, result: Some(1000) },
+                Op::Alloc { size: 100, hash: 0, result: Some(1400) },
+                Op::Alloc { size: 100, hash: 0, result: Some(1500) },
+                Op::Free { pos: 1500, result: true },
+                Op::Shrink { pos: 1000, size: 500, result: false },
+                Op::Shrink { pos: 1100, size: 50, result: false },
+                Op::Shrink { pos: 1000, size: 400, result: true },
+                Op::Shrink { pos: 1000, size: 300, result: true },
+                Op::Shrink { pos: 1400, size: 0, result: true },
+                Op::Alloc { size: 599, hash: 0, result: Some(1401) },
+                Op::Alloc { size: 100, hash: 0, result: Some(1300) },
+            ],
+        );
+        assert_eq!(mem.used_size(), 1000);
+    }
+
+    #[test]
+    fn grow() {
+        let mut mem = MemoryManagment::new(1000, 2000);
+        run_ops(
+            &mut mem,
+            &[
+                Op::Alloc { size: 400, hash: 0, result: Some(1000) },
+                Op::Alloc { size: 100, hash: 0, result: Some(1400) },
+                Op::Alloc { size: 100, hash: 0, result: Some(1500) },
+                Op::Free { pos: 1400, result: true },
+                Op::Grow { pos: 1000, size: 501, result: false },
+                Op::Grow { pos: 1100, size: 500, result: false },
+                Op::Grow { pos: 1000, size: 300, result: false },
+                Op::Grow { pos: 1000, size: 450, result: true },
+                Op::Grow { pos: 1000, size: 500, result: true },
+                Op::Grow { pos: 1500, size: 500, result: true },
+                Op::Grow { pos: 1500, size: 501, result: false },
+                Op::Alloc { size: 1, hash: 0, result: None },
+            ],
+        );
+        assert_eq!(mem.used_size(), 1000);
+    }
+
+    #[test]
+    fn rehash() {
+        let mut mem = MemoryManagment::new(1000, 2000);
+        run_ops(
+            &mut mem,
+            &[
+                Op::Alloc { size: 400, hash: 1, result: Some(1000) },
+                Op::Rehash { pos: 1000, hash: 2, result: true },
+                Op::Rehash { pos: 1400, hash: 2, result: false },
+            ],
+        );
+        assert_eq!(mem.get_used().iter().map(|u| u.hash).collect::<Vec<_>>(), [2]);
+    }
+
     #[test]
     fn allocate_prefers_start() {
         let mut mem = MemoryManagment::new(1000, 2000);
@@ -387,6 +634,25 @@ mod tests {
         )
     }
 
+    #[test]
+    fn allocate_lowest() {
+        let mut mem = MemoryManagment::new(1000, 2000);
+        run_ops(
+            &mut mem,
+            &[
+                Op::Alloc { size: 100, hash: 0, result: Some(1000) },
+                Op::Alloc { size: 100, hash: 0, result: Some(1100) },
+                Op::Alloc { size: 300, hash: 0, result: Some(1200) },
+                Op::Alloc { size: 100, hash: 0, result: Some(1500) },
+                Op::Free { pos: 1100, result: true },
+                Op::Free { pos: 1200, result: true },
+            ],
+        );
+        assert_eq!(mem.allocate_lowest(50, 0), Some(1100));
+        assert_eq!(mem.allocate_lowest(400, 0), Some(1600));
+        assert!(mem.is_valid());
+    }
+
     #[test]
     fn increase_end() {
         let mut mem = MemoryManagment::new(1000, 2000);
@@ -414,4 +680,33 @@ mod tests {
             ],
         )
     }
+
+    #[test]
+    fn unused_parts() {
+        let mut mem = MemoryManagment::new(1000, 2000);
+        assert_eq!(mem.allocate(100, 0), Some(1000));
+        assert_eq!(mem.allocate(100, 0), Some(1100));
+        assert_eq!(mem.allocate(100, 0), Some(1200));
+        assert!(mem.free(1100));
+        assert_eq!(mem.unused_parts(0, 3000), vec![(1100, 1200), (1300, 2000)]);
+        assert_eq!(mem.unused_parts(1050, 1250), vec![(1100, 1200)]);
+        assert_eq!(mem.unused_parts(1150, 1350), vec![(1150, 1200), (1300, 1350)]);
+        assert!(mem.unused_parts(1000, 1100).is_empty());
+    }
+
+    #[test]
+    fn split() {
+        let mut mem = MemoryManagment::new(1000, 2000);
+        assert_eq!(mem.allocate(100, 1), Some(1000));
+        assert!(!mem.split(1000, 100, 2));
+        assert!(!mem.split(1050, 10, 2));
+        assert!(mem.split(1000, 90, 2));
+        assert_eq!(mem.next_used(1000), Some(&Used { start: 1000, size: 90, hash: 1 }));
+        assert_eq!(mem.next_used(1001), Some(&Used { start: 1090, size: 10, hash: 2 }));
+        assert_eq!(mem.used_size(), 100);
+        assert!(mem.is_valid());
+        assert!(mem.free(1090));
+        assert_eq!(mem.next_used(1001), None);
+        assert!(mem.is_valid());
+    }
 }
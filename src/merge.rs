@@ -0,0 +1,113 @@
+use crate::{Error, Table};
+
+/// Decides how a key that exists in both tables is merged, see [`Table::merge_from`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MergeDecision {
+    /// Keep the value of this table
+    KeepMine,
+    /// Take the value of the other table
+    TakeTheirs,
+    /// Store the given value instead of both
+    Merged(Vec<u8>),
+    /// Delete the entry from this table
+    Delete,
+}
+
+impl Table {
+    /// Merges all entries of the other table into this table.
+    ///
+    /// Entries whose keys only exist in the other table are copied. For keys that exist in both tables with different
+    /// values, the `resolve` closure is called with the key, the value of this table and the value of the other table
+    /// and decides via [`MergeDecision`] which value is stored. Entries with identical values are left as they are.
+    ///
+    /// The index is grown up front for all copied entries. Returns the number of entries of this table that have been
+    /// added, replaced or deleted. Tables created via [`HashKeyTable`](crate::HashKeyTable) are not supported.
+    ///
+    /// ```
+    /// use rust_persist::{MergeDecision, Table};
+    ///
+    /// let mut mine = Table::create("example_merge1.tbl").unwrap();
+    /// mine.set("key1".as_bytes(), "a".as_bytes()).unwrap();
+    /// let mut theirs = Table::create("example_merge2.tbl").unwrap();
+    /// theirs.set("key1".as_bytes(), "b".as_bytes()).unwrap();
+    /// theirs.set("key2".as_bytes(), "c".as_bytes()).unwrap();
+    /// mine.merge_from(&theirs, |_key, mine, theirs| MergeDecision::Merged([mine, theirs].concat())).unwrap();
+    /// assert_eq!(mine.get("key1".as_bytes()), Some("ab".as_bytes()));
+    /// assert_eq!(mine.get("key2".as_bytes()), Some("c".as_bytes()));
+    /// ```
+    pub fn merge_from<F: FnMut(&[u8], &[u8], &[u8]) -> MergeDecision>(
+        &mut self, other: &Table, mut resolve: F,
+    ) -> Result<usize, Error> {
+        self.check_writable()?;
+        self.check_byte_keys()?;
+        other.check_byte_keys()?;
+        let new_entries = other.iter().filter(|entry| !self.contains(entry.key)).count();
+        self.reserve_index(new_entries)?;
+        let mut changed = 0;
+        for theirs in other.iter() {
+            let decision = match self.get(theirs.key) {
+                None => MergeDecision::TakeTheirs,
+                Some(mine) if mine == theirs.value => MergeDecision::KeepMine,
+                Some(mine) => resolve(theirs.key, mine, theirs.value),
+            };
+            match decision {
+                MergeDecision::KeepMine => continue,
+                MergeDecision::TakeTheirs => {
+                    self.set_entry(theirs)?;
+                }
+                MergeDecision::Merged(value) => {
+                    self.set(theirs.key, &value)?;
+                }
+                MergeDecision::Delete => {
+                    self.delete(theirs.key)?;
+                }
+            }
+            changed += 1;
+        }
+        Ok(changed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FLAG_PINNED;
+
+    #[test]
+    fn test_merge_from() {
+        let file1 = tempfile::NamedTempFile::new().unwrap();
+        let file2 = tempfile::NamedTempFile::new().unwrap();
+        let mut mine = Table::create(file1.path()).unwrap();
+        let mut theirs = Table::create(file2.path()).unwrap();
+        for i in 0u16..100 {
+            mine.set(&i.to_ne_bytes(), &[1]).unwrap();
+        }
+        for i in 50u16..300 {
+            theirs.set(&i.to_ne_bytes(), &[if i < 60 { 1 } else { 2 }]).unwrap();
+        }
+        theirs.pin_front(&200u16.to_ne_bytes()).unwrap();
+        let mut calls = 0;
+        let changed = mine
+            .merge_from(&theirs, |key, mine, theirs| {
+                calls += 1;
+                assert_eq!((mine, theirs), (&[1][..], &[2][..]));
+                match u16::from_ne_bytes([key[0], key[1]]) {
+                    60..=69 => MergeDecision::KeepMine,
+                    70..=79 => MergeDecision::TakeTheirs,
+                    80..=89 => MergeDecision::Delete,
+                    _ => MergeDecision::Merged(vec![3]),
+                }
+            })
+            .unwrap();
+        assert_eq!(calls, 40);
+        assert_eq!(changed, 30 + 200);
+        assert_eq!(mine.len(), 300 - 10);
+        assert_eq!(mine.get(&65u16.to_ne_bytes()), Some(&[1][..]));
+        assert_eq!(mine.get(&75u16.to_ne_bytes()), Some(&[2][..]));
+        assert_eq!(mine.get(&85u16.to_ne_bytes()), None);
+        assert_eq!(mine.get(&95u16.to_ne_bytes()), Some(&[3][..]));
+        assert_eq!(mine.get(&250u16.to_ne_bytes()), Some(&[2][..]));
+        assert_eq!(mine.get_entry(&200u16.to_ne_bytes()).unwrap().flags, FLAG_PINNED);
+        assert!(mine.is_valid());
+    }
+}
@@ -1,37 +1,168 @@
-use std::fs::OpenOptions;
-use std::io;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
 use std::path::Path;
 use std::{fs::File, mem, slice};
 
 use fs2::FileExt;
-use memmap::MmapMut;
+use memmap::{MmapMut, MmapOptions};
 
 pub type MMap = MmapMut;
 
+use crate::backup::sync_parent;
+use crate::batch::sibling_path;
 use crate::table::{total_size, Header};
-use crate::{Error, IndexEntry, INDEX_HEADER, INITIAL_DATA_SIZE, INITIAL_INDEX_CAPACITY};
+use crate::{Error, GrowFill, IndexEntry, LockMode, TableOptions, INDEX_HEADER};
+
+/// Magic header of format 01, which only stored the magic, the flags and the index capacity in the header
+const INDEX_HEADER_V1: [u8; 16] = *b"rust-persist-01\n";
+
+/// Size of the header of format 01
+const HEADER_SIZE_V1: usize = 36;
+
+/// Header, index entries, data start and data section of a mapped table
+type MappedParts = (&'static mut Header, &'static mut [IndexEntry], usize, &'static mut [u8]);
 
 /// This method is unsafe as it potentially creates references to uninitialized memory
-pub(crate) unsafe fn mmap_as_ref(
-    mmap: &mut MMap, index_capacity: usize,
-) -> (&'static mut Header, &'static mut [IndexEntry], usize, &'static mut [u8]) {
-    if (mmap.len() as u64) < total_size(index_capacity, 0) {
-        panic!("Memory map too small");
-    }
+pub(crate) unsafe fn mmap_as_ref(mmap: &mut MMap, index_capacity: usize) -> Result<MappedParts, Error> {
+    let data_start = match total_size(index_capacity, 0) {
+        Ok(size) if size <= mmap.len() as u64 => size as usize,
+        _ => invariant_violated!("Memory map too small"),
+    };
     let header = &mut *(mmap.as_mut_ptr() as *mut Header);
     let ptr = mmap.as_mut_ptr().add(mem::size_of::<Header>()) as *mut IndexEntry;
     let entries = slice::from_raw_parts_mut(ptr, index_capacity);
-    let data_start = total_size(index_capacity, 0) as usize;
     let data = slice::from_raw_parts_mut(mmap.as_mut_ptr().add(data_start), mmap.len() - data_start);
-    (header, entries, data_start, data)
+    Ok((header, entries, data_start, data))
+}
+
+/// Returns the number of bytes available to unprivileged users on the file system of the given file.
+#[cfg(unix)]
+pub(crate) fn available_space(fd: &File) -> Result<Option<u64>, Error> {
+    use std::os::unix::io::AsRawFd;
+    let mut stat: libc::statvfs = unsafe { mem::zeroed() };
+    if unsafe { libc::fstatvfs(fd.as_raw_fd(), &mut stat) } != 0 {
+        return Err(Error::Io(io::Error::last_os_error()));
+    }
+    Ok(Some((stat.f_bavail as u64).saturating_mul(stat.f_frsize as u64)))
+}
+
+#[cfg(not(unix))]
+pub(crate) fn available_space(_fd: &File) -> Result<Option<u64>, Error> {
+    Ok(None)
+}
+
+/// Fails with [`Error::OutOfSpace`] if the file cannot grow to the given size due to missing free space.
+///
+/// Files are grown sparsely, so without this check, running out of space would only be noticed when writing to the
+/// memory map, which crashes the process.
+pub(crate) fn check_space(fd: &File, size: u64) -> Result<(), Error> {
+    let current = fd.metadata().map_err(Error::Io)?.len();
+    if size <= current {
+        return Ok(());
+    }
+    let needed = size - current;
+    match available_space(fd)? {
+        Some(available) if needed > available => Err(Error::OutOfSpace { needed, available }),
+        _ => Ok(()),
+    }
+}
+
+/// Fails with [`Error::MapLimit`] if the memory map of the given size would exceed the configured maximum size.
+pub(crate) fn check_map_size(options: &TableOptions, size: u64) -> Result<(), Error> {
+    match options.max_map_size {
+        Some(limit) if size > limit => Err(Error::MapLimit { needed: size, limit }),
+        _ => Ok(()),
+    }
+}
+
+/// Sets the length of the file after checking the free space via [`check_space`]
+pub(crate) fn set_len(fd: &File, size: u64) -> Result<(), Error> {
+    check_space(fd, size)?;
+    fd.set_len(size).map_err(Error::Io)
+}
+
+/// Initializes a newly created or grown region of the mapping according to the fill mode
+pub(crate) fn fill_grown(region: &mut [u8], fill: GrowFill) {
+    match fill {
+        GrowFill::Sparse => (),
+        GrowFill::Zero => region.fill(0),
+        GrowFill::Pattern(byte) => {
+            if cfg!(debug_assertions) {
+                region.fill(byte)
+            }
+        }
+    }
 }
 
 pub(crate) fn map_fd(fd: &File) -> Result<MMap, Error> {
     unsafe { MMap::map_mut(fd).map_err(Error::Io) }
 }
 
+/// Hints the kernel that the given range of the mapping is accessed randomly and can be reclaimed early.
+#[cfg(unix)]
+pub(crate) fn advise_cold(mmap: &MMap, start: usize, len: usize) -> Result<(), Error> {
+    if len == 0 {
+        return Ok(());
+    }
+    let (ptr, len) = page_range(mmap, start, len);
+    if unsafe { libc::madvise(ptr, len, libc::MADV_RANDOM) } != 0 {
+        return Err(Error::Io(io::Error::last_os_error()));
+    }
+    #[cfg(target_os = "linux")]
+    {
+        // MADV_COLD is only available since Linux 5.4, older kernels reject it which is fine for a hint
+        unsafe { libc::madvise(ptr, len, libc::MADV_COLD) };
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub(crate) fn advise_cold(_mmap: &MMap, _start: usize, _len: usize) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Returns the number of bytes of the mapping that are currently resident in memory.
+#[cfg(unix)]
+pub(crate) fn resident_bytes(mmap: &MMap) -> Result<u64, Error> {
+    if mmap.is_empty() {
+        return Ok(0);
+    }
+    let page_size = page_size();
+    let (ptr, len) = page_range(mmap, 0, mmap.len());
+    let mut pages = vec![0u8; len.div_ceil(page_size)];
+    if unsafe { libc::mincore(ptr, len, pages.as_mut_ptr() as *mut _) } != 0 {
+        return Err(Error::Io(io::Error::last_os_error()));
+    }
+    let resident = pages.iter().filter(|&&p| p & 1 == 1).count() as u64 * page_size as u64;
+    Ok(std::cmp::min(resident, mmap.len() as u64))
+}
+
+#[cfg(not(unix))]
+pub(crate) fn resident_bytes(mmap: &MMap) -> Result<u64, Error> {
+    Ok(mmap.len() as u64)
+}
+
+#[cfg(unix)]
+pub(crate) fn page_size() -> usize {
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn page_size() -> usize {
+    4096
+}
+
+/// Extends the given range to page boundaries as required by madvise and mincore
+#[cfg(unix)]
+fn page_range(mmap: &MMap, start: usize, len: usize) -> (*mut libc::c_void, usize) {
+    let page_size = page_size();
+    let aligned_start = start - start % page_size;
+    let ptr = unsafe { mmap.as_ptr().add(aligned_start) } as *mut libc::c_void;
+    (ptr, len + start - aligned_start)
+}
+
 pub(crate) struct OpenFdResult {
-    pub fd: File,
+    pub fd: Option<File>,
     pub mmap: MMap,
     pub header: &'static mut Header,
     pub index_entries: &'static mut [IndexEntry],
@@ -39,29 +170,157 @@ pub(crate) struct OpenFdResult {
     pub data: &'static mut [u8],
 }
 
-pub(crate) fn open_fd(path: &Path, create: bool) -> Result<OpenFdResult, Error> {
-    let fd = OpenOptions::new().read(true).write(true).create(create).open(path).map_err(Error::Io)?;
-    match fd.try_lock_exclusive() {
-        Ok(()) => (),
-        Err(err) if err.kind() == io::ErrorKind::WouldBlock => return Err(Error::TableLocked),
-        Err(err) => return Err(Error::Io(err)),
+impl OpenFdResult {
+    /// Converts the header and all index entries to the native byte order if needed
+    pub fn fix_endianness(&mut self) {
+        if !self.header.has_correct_endianness() {
+            for entry in self.index_entries.iter_mut() {
+                entry.fix_endianness()
+            }
+            self.header.fix_endianness();
+            self.header.set_correct_endianness();
+        }
+    }
+}
+
+/// Maps the file copy-on-write, so that modifications never reach the file
+pub(crate) fn map_fd_private(fd: &File) -> Result<MMap, Error> {
+    unsafe { MmapOptions::new().map_copy(fd).map_err(Error::Io) }
+}
+
+/// Locks the file according to the lock mode, shared if `shared` is set and exclusively otherwise
+pub(crate) fn lock_fd(fd: &File, shared: bool, mode: LockMode) -> Result<(), Error> {
+    let try_lock = if shared { FileExt::try_lock_shared } else { FileExt::try_lock_exclusive };
+    let lock = if shared { FileExt::lock_shared } else { FileExt::lock_exclusive };
+    match mode {
+        LockMode::Exclusive | LockMode::Shared => match try_lock(fd) {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => Err(Error::TableLocked),
+            Err(err) => Err(Error::Io(err)),
+        },
+        LockMode::Wait => lock(fd).map_err(Error::Io),
+        LockMode::None => Ok(()),
     }
-    fd.try_lock_exclusive().unwrap();
-    fd.lock_exclusive().map_err(Error::Io)?;
+}
+
+pub(crate) fn open_fd(path: &Path, create: bool, options: &TableOptions) -> Result<OpenFdResult, Error> {
+    let read_only = options.read_only;
+    let mut fd = OpenOptions::new().read(true).write(!read_only).create(create).open(path).map_err(Error::Io)?;
+    lock_fd(&fd, read_only, options.lock)?;
+    if create {
+        let size = total_size(options.index_capacity, options.data_size)?;
+        check_map_size(options, size)?;
+        set_len(&fd, size)?;
+    }
+    let mut mmap = if read_only { map_fd_private(&fd)? } else { map_fd(&fd)? };
+    if !create && mmap.len() >= HEADER_SIZE_V1 && mmap[..INDEX_HEADER_V1.len()] == INDEX_HEADER_V1 {
+        let upgraded = upgrade_v1(&mmap)?;
+        mmap = if read_only {
+            upgraded
+        } else {
+            drop(mmap);
+            fd = replace_file(path, &upgraded, options.lock)?;
+            map_fd(&fd)?
+        };
+    }
+    let result = map_table(Some(fd), mmap, create, options)?;
     if create {
-        fd.set_len(total_size(INITIAL_INDEX_CAPACITY, INITIAL_DATA_SIZE as u64)).map_err(Error::Io)?;
+        // The index is cleared when the table is loaded, the data section might still contain an old table
+        fill_grown(result.data, options.grow_fill);
+    }
+    Ok(result)
+}
+
+/// Converts a table of format 01 to the current header layout and returns the converted table in memory.
+///
+/// The header of format 01 is smaller, so the index and the data section are moved back and the positions of all
+/// entries are adjusted. Writable tables are converted by replacing the file via [`replace_file`], so an interrupted
+/// conversion leaves the old file intact.
+fn upgrade_v1(old: &MMap) -> Result<MMap, Error> {
+    let old_len = old.len();
+    let shift = mem::size_of::<Header>() - HEADER_SIZE_V1;
+    let mut mmap = MMap::map_anon(old_len + shift).map_err(Error::Io)?;
+    mmap[..old_len].copy_from_slice(old);
+    mmap.copy_within(HEADER_SIZE_V1..old_len, mem::size_of::<Header>());
+    let (header, ..) = unsafe { mmap_as_ref(&mut mmap, 0)? };
+    let correct_endianness = header.has_correct_endianness();
+    let page_size = page_size() as u32;
+    header.page_size = if correct_endianness { page_size } else { page_size.to_be().to_le() };
+    header.created = 0;
+    header.last_close = 0;
+    header.version = [0; 16];
+    let mut index_capacity = header.index_capacity;
+    if !correct_endianness {
+        index_capacity = index_capacity.to_be().to_le();
+    }
+    if !index_capacity.is_power_of_two() || total_size(index_capacity as usize, 0)? > mmap.len() as u64 {
+        return Err(Error::Corrupt("invalid index capacity"));
+    }
+    let (header, entries, ..) = unsafe { mmap_as_ref(&mut mmap, index_capacity as usize)? };
+    for entry in entries.iter_mut().filter(|entry| entry.is_used()) {
+        if !correct_endianness {
+            entry.fix_endianness();
+        }
+        entry.data.set_position(entry.data.position() + shift as u64);
+        if !correct_endianness {
+            entry.fix_endianness();
+        }
+    }
+    header.header = INDEX_HEADER;
+    Ok(mmap)
+}
+
+/// Writes the content to a new file that replaces the file at the given path and returns the new file, locked like
+/// the replaced one.
+///
+/// The new file is locked before it is renamed, so no other process can open it in between.
+fn replace_file(path: &Path, content: &[u8], lock: LockMode) -> Result<File, Error> {
+    let tmp = sibling_path(path, ".upgrade");
+    let mut fd =
+        OpenOptions::new().read(true).write(true).create(true).truncate(true).open(&tmp).map_err(Error::Io)?;
+    lock_fd(&fd, false, lock)?;
+    fd.write_all(content).map_err(Error::Io)?;
+    fd.sync_all().map_err(Error::Io)?;
+    fs::rename(&tmp, path).map_err(Error::Io)?;
+    sync_parent(path)?;
+    Ok(fd)
+}
+
+/// Copies the given bytes into an anonymous memory map
+#[cfg(feature = "fuzz")]
+pub(crate) fn map_bytes(bytes: &[u8]) -> Result<MMap, Error> {
+    if bytes.len() < mem::size_of::<Header>() {
+        return Err(Error::WrongHeader);
     }
-    let mut mmap = map_fd(&fd)?;
+    let mut mmap = MMap::map_anon(bytes.len()).map_err(Error::Io)?;
+    mmap.copy_from_slice(bytes);
+    Ok(mmap)
+}
+
+pub(crate) fn map_table(
+    fd: Option<File>, mut mmap: MMap, create: bool, options: &TableOptions,
+) -> Result<OpenFdResult, Error> {
     if mmap.len() < mem::size_of::<Header>() {
         return Err(Error::WrongHeader);
     }
-    let (header, ..) = unsafe { mmap_as_ref(&mut mmap, INITIAL_INDEX_CAPACITY as usize) };
+    check_map_size(options, mmap.len() as u64)?;
+    let (header, ..) = unsafe { mmap_as_ref(&mut mmap, 0)? };
     if create {
         // This is safe, nothing in header is Drop
         header.header = INDEX_HEADER;
-        header.index_capacity = INITIAL_INDEX_CAPACITY as u32;
+        header.index_capacity = options.index_capacity as u32;
         header.set_correct_endianness();
     }
+    let index_capacity = check_header(header, mmap.len() as u64)?;
+    let (header, index_entries, data_start, data) = unsafe { mmap_as_ref(&mut mmap, index_capacity as usize)? };
+    Ok(OpenFdResult { fd, mmap, header, index_entries, data_start, data })
+}
+
+/// Checks the header of a table file of the given size and returns its index capacity
+///
+/// If a resize of the index has been interrupted before the file was grown, the capacity in the header is rolled
+/// back.
+pub(crate) fn check_header(header: &mut Header, file_size: u64) -> Result<u32, Error> {
     if header.header != INDEX_HEADER {
         return Err(Error::WrongHeader);
     }
@@ -69,6 +328,20 @@ pub(crate) fn open_fd(path: &Path, create: bool) -> Result<OpenFdResult, Error>
     if !header.has_correct_endianness() {
         index_capacity = index_capacity.to_be().to_le();
     }
-    let (header, index_entries, data_start, data) = unsafe { mmap_as_ref(&mut mmap, index_capacity as usize) };
-    Ok(OpenFdResult { fd, mmap, header, index_entries, data_start, data })
+    if let (true, Some(origin)) = (header.is_dirty(), header.resize_origin()) {
+        if total_size(index_capacity as usize, 0)? > file_size {
+            // The new capacity has been stored but the file has not been grown, so the resize is rolled back. The
+            // entries have not been moved yet as this happens after growing the file.
+            index_capacity = origin;
+            header.index_capacity =
+                if header.has_correct_endianness() { origin } else { origin.to_be().to_le() };
+        }
+    }
+    if !index_capacity.is_power_of_two() {
+        return Err(Error::Corrupt("index capacity is not a power of two"));
+    }
+    if total_size(index_capacity as usize, 0)? > file_size {
+        return Err(Error::Corrupt("file is smaller than the index"));
+    }
+    Ok(index_capacity)
 }
@@ -1,8 +1,8 @@
-use std::{marker::PhantomData, path::Path};
+use std::{borrow::Cow, marker::PhantomData, path::Path};
 
 use serde::{de::DeserializeOwned, Serialize};
 
-use crate::{Entry, Error, Table, Stats};
+use crate::{traits::AsTable, Codec, Entries, Error, Table, TableRead, TableWrite, Stats};
 
 /// Method used internally to serialize values to bytes
 #[inline]
@@ -16,6 +16,23 @@ pub fn deserialize<T: DeserializeOwned>(data: &[u8]) -> Result<T, Error> {
     rmp_serde::from_read(data).map_err(Error::Deserialize)
 }
 
+/// Codec that encodes keys and values in the MessagePack format via [`serialize`] and [`deserialize`]
+///
+/// This is the default codec of [`TypedTable`].
+pub struct MsgPack;
+
+impl<T: Serialize + DeserializeOwned> Codec<T> for MsgPack {
+    #[inline]
+    fn encode(value: &T) -> Result<Vec<u8>, Error> {
+        serialize(value)
+    }
+
+    #[inline]
+    fn decode(data: &[u8]) -> Result<T, Error> {
+        deserialize(data)
+    }
+}
+
 impl Table {
     /// Returns whether an entry is associated with the given key.
     ///
@@ -88,40 +105,49 @@ impl Table {
 }
 
 /// Internal iterator over all entries in the typed table
-struct Iter<K, V, I> {
+struct Iter<K, V, KC, VC, I> {
     inner: I,
-    _key: PhantomData<K>,
-    _value: PhantomData<V>,
+    _key: PhantomData<(K, KC)>,
+    _value: PhantomData<(V, VC)>,
 }
 
-impl<'a, K: DeserializeOwned, V: DeserializeOwned, I: Iterator<Item = Entry<'a>>> Iterator for Iter<K, V, I> {
+impl<'a, K, V, KC: Codec<K>, VC: Codec<V>, I: Iterator<Item = Result<(Cow<'a, [u8]>, Cow<'a, [u8]>), Error>>> Iterator
+    for Iter<K, V, KC, VC, I>
+{
     type Item = Result<(K, V), Error>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.inner.next().map(|entry| Ok((deserialize(entry.key)?, deserialize(entry.value)?)))
+        self.inner.next().map(|entry| {
+            let (key, value) = entry?;
+            Ok((KC::decode(&key)?, VC::decode(&value)?))
+        })
     }
 }
 
 
 /// Internal iterator over all keys in the typed table
-struct KeyIter<K, I> {
+struct KeyIter<K, KC, I> {
     inner: I,
-    _key: PhantomData<K>,
+    _key: PhantomData<(K, KC)>,
 }
 
-impl<'a, K: DeserializeOwned, I: Iterator<Item = Entry<'a>>> Iterator for KeyIter<K, I> {
+impl<'a, K, KC: Codec<K>, I: Iterator<Item = Result<(Cow<'a, [u8]>, Cow<'a, [u8]>), Error>>> Iterator
+    for KeyIter<K, KC, I>
+{
     type Item = Result<K, Error>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.inner.next().map(|entry| deserialize(entry.key))
+        self.inner.next().map(|entry| KC::decode(&entry?.0))
     }
 }
 
-
 /// A typed version of the table.
 ///
 /// This struct wraps the normal [`Table`] and ensures that keys and values have a certain type.
 ///
+/// Instead of a [`Table`], any other byte-level layer implementing [`TableRead`] and [`TableWrite`] can be wrapped
+/// via [`TypedTable::new`], e.g. a [`CompressedTable`](crate::CompressedTable).
+///
 /// ## On serialization
 ///
 /// This functionality requires the feature `msgpack`.
@@ -134,23 +160,28 @@ impl<'a, K: DeserializeOwned, I: Iterator<Item = Entry<'a>>> Iterator for KeyIte
 /// [`serde::Serialize`] and [`serde::Deserialize`] directly or use [the `derive` feature of `serde`](https://serde.rs/derive.html).
 ///
 /// If any key or value cannot be encoded or decoded, [`Error::Serialize`] or [`Error::Deserialize`] is thrown.
-pub struct TypedTable<K, V> {
-    inner: Table,
-    _key: PhantomData<K>,
-    _value: PhantomData<V>,
+///
+/// Other encodings can be used for keys and values by specifying a [`Codec`] for each of them, e.g. [`BigEndian`]
+/// for integer keys whose encoding should preserve their order.
+///
+/// [`BigEndian`]: crate::BigEndian
+pub struct TypedTable<K, V, T = Table, KC = MsgPack, VC = MsgPack> {
+    inner: T,
+    _key: PhantomData<(K, KC)>,
+    _value: PhantomData<(V, VC)>,
 }
 
-impl<K: Serialize + DeserializeOwned, V: Serialize + DeserializeOwned> TypedTable<K, V> {
+impl<K, V, KC: Codec<K>, VC: Codec<V>> TypedTable<K, V, Table, KC, VC> {
     /// Opens an existing typed table from the given path.
     #[inline]
     pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
-        Ok(Self { inner: Table::open(path)?, _key: PhantomData, _value: PhantomData })
+        Ok(Self::new(Table::open(path)?))
     }
 
     /// Creates a new typed table at the given path (overwriting an existing table).
     #[inline]
     pub fn create<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
-        Ok(Self { inner: Table::create(path)?, _key: PhantomData, _value: PhantomData })
+        Ok(Self::new(Table::create(path)?))
     }
 
     /// Opens an existing or creates a new typed table at the given path.
@@ -163,23 +194,46 @@ impl<K: Serialize + DeserializeOwned, V: Serialize + DeserializeOwned> TypedTabl
             Self::create(path)
         }
     }
+}
 
-    /// Returns a reference to the wrapped [`Table`].
+impl<K, V, T, KC, VC> TypedTable<K, V, T, KC, VC> {
+    /// Wraps the given table or layer.
     #[inline]
-    pub fn inner(&self) -> &Table {
+    pub fn new(inner: T) -> Self {
+        Self { inner, _key: PhantomData, _value: PhantomData }
+    }
+
+    /// Returns a reference to the wrapped table or layer.
+    #[inline]
+    pub fn inner(&self) -> &T {
         &self.inner
     }
 
-    /// Returns the wrapped [`Table`].
+    /// Returns the wrapped table or layer.
     #[inline]
-    pub fn into_inner(self) -> Table {
+    pub fn into_inner(self) -> T {
         self.inner
     }
 
+    /// Explicitly closes the table.
+    ///
+    /// Normally this method does not need to be called.
+    #[inline]
+    pub fn close(self) {
+        // nothing to do, just drop self
+    }
+}
+
+impl<K, V, T, KC, VC> TypedTable<K, V, T, KC, VC>
+where
+    KC: Codec<K>,
+    VC: Codec<V>,
+    T: TableRead<Key = [u8], OwnedKey = Vec<u8>, Value = [u8], OwnedValue = Vec<u8>>,
+{
     /// Returns whether an entry is associated with the given key.
     #[inline]
     pub fn contains(&self, key: &K) -> Result<bool, Error> {
-        self.inner.contains_obj(key)
+        self.inner.contains(&KC::encode(key)?)
     }
 
     /// Loads and returns the value stored with the given key.
@@ -187,15 +241,62 @@ impl<K: Serialize + DeserializeOwned, V: Serialize + DeserializeOwned> TypedTabl
     /// See [`Table::get_obj`] for more info
     #[inline]
     pub fn get(&self, key: &K) -> Result<Option<V>, Error> {
-        self.inner.get_obj(key)
+        match self.inner.get(&KC::encode(key)?)? {
+            Some(v) => Ok(Some(VC::decode(&v)?)),
+            None => Ok(None),
+        }
     }
 
+    /// Iterate over all entries in the typed table
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = Result<(K, V), Error>> + '_ {
+        Iter::<K, V, KC, VC, _> { inner: self.inner.entries_borrowed(), _key: PhantomData, _value: PhantomData }
+    }
+
+    /// Iterate over all entries in the typed table
+    #[inline]
+    pub fn keys(&self) -> impl Iterator<Item = Result<K, Error>> + '_ {
+        KeyIter::<K, KC, _> { inner: self.inner.entries_borrowed(), _key: PhantomData }
+    }
+
+    /// Return the number of entries in the table
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    /// Return whether the table is empty
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.inner.len() == 0
+    }
+}
+
+impl<K, V, T: AsTable, KC, VC> TypedTable<K, V, T, KC, VC> {
+    /// Return the raw size of the table in bytes
+    #[inline]
+    pub fn size(&self) -> u64 {
+        self.inner.table().size()
+    }
+
+    /// Return a statistics struct
+    pub fn stats(&self) -> Stats {
+        self.inner.table().stats()
+    }
+}
+
+impl<K, V, T, KC, VC> TypedTable<K, V, T, KC, VC>
+where
+    KC: Codec<K>,
+    VC: Codec<V>,
+    T: TableWrite<Key = [u8], OwnedKey = Vec<u8>, Value = [u8], OwnedValue = Vec<u8>>,
+{
     /// Stores the given key/value pair in the table.
     ///
     /// See [`Table::set_obj`] for more info
     #[inline]
     pub fn set(&mut self, key: &K, value: &V) -> Result<bool, Error> {
-        self.inner.set_obj(key, value)
+        self.inner.set(&KC::encode(key)?, &VC::encode(value)?)
     }
 
     /// Deletes the entry with the given key from the table.
@@ -203,7 +304,7 @@ impl<K: Serialize + DeserializeOwned, V: Serialize + DeserializeOwned> TypedTabl
     /// See [`Table::delete_obj`] for more info
     #[inline]
     pub fn delete(&mut self, key: &K) -> Result<bool, Error> {
-        self.inner.delete_obj(key)
+        self.inner.delete(&KC::encode(key)?)
     }
 
     /// Deletes and return the entry with the given key from the table.
@@ -211,75 +312,109 @@ impl<K: Serialize + DeserializeOwned, V: Serialize + DeserializeOwned> TypedTabl
     /// See [`Table::take_obj`] for more info
     #[inline]
     pub fn take(&mut self, key: &K) -> Result<Option<V>, Error> {
-        self.inner.take_obj(key)
+        match self.inner.take(&KC::encode(key)?)? {
+            Some(v) => Ok(Some(VC::decode(&v)?)),
+            None => Ok(None),
+        }
     }
 
+    /// Forces to write all pending changes to disk
+    #[inline]
+    pub fn flush(&mut self) -> Result<(), Error> {
+        self.inner.flush()
+    }
 
-    /// Iterate over all entries in the typed table
+    /// Forces defragmentation of the data section.
+    ///
+    /// See [`Table::defragment`] for more info.
     #[inline]
-    pub fn iter(&self) -> impl Iterator<Item = Result<(K, V), Error>> + '_ {
-        Iter { inner: self.inner.iter(), _key: PhantomData, _value: PhantomData }
+    pub fn defragment(&mut self) -> Result<(), Error> {
+        self.inner.defragment()
     }
 
-    /// Iterate over all entries in the typed table
+    /// Deletes all entries in the table
+    ///
+    /// This method essentially resets the table to its state after creation.
     #[inline]
-    pub fn keys(&self) -> impl Iterator<Item = Result<K, Error>> + '_ {
-        KeyIter { inner: self.inner.iter(), _key: PhantomData }
+    pub fn clear(&mut self) -> Result<(), Error> {
+        self.inner.clear()
     }
+}
+
+impl<K, V, T, KC, VC> TableRead for TypedTable<K, V, T, KC, VC>
+where
+    KC: Codec<K>,
+    VC: Codec<V>,
+    T: TableRead<Key = [u8], OwnedKey = Vec<u8>, Value = [u8], OwnedValue = Vec<u8>>,
+{
+    type Key = K;
+    type OwnedKey = K;
+    type Value = V;
+    type OwnedValue = V;
 
-    /// Return the number of entries in the table
     #[inline]
-    pub fn len(&self) -> usize {
-        self.inner.len()
+    fn contains(&self, key: &K) -> Result<bool, Error> {
+        TypedTable::contains(self, key)
     }
 
-    /// Return the raw size of the table in bytes
     #[inline]
-    pub fn size(&self) -> u64 {
-        self.inner.size() as u64
+    fn get(&self, key: &K) -> Result<Option<V>, Error> {
+        TypedTable::get(self, key)
     }
 
-    /// Return whether the table is empty
     #[inline]
-    pub fn is_empty(&self) -> bool {
-        self.inner.len() == 0
+    fn entries(&self) -> Entries<'_, K, V> {
+        Box::new(self.iter())
     }
 
-    /// Forces to write all pending changes to disk
     #[inline]
-    pub fn flush(&mut self) -> Result<(), Error> {
-        self.inner.flush()
+    fn len(&self) -> usize {
+        TypedTable::len(self)
     }
+}
 
-    /// Forces defragmentation of the data section.
-    ///
-    /// See [`Table::defragment`] for more info.
+impl<K, V, T: AsTable, KC, VC> AsTable for TypedTable<K, V, T, KC, VC> {
     #[inline]
-    pub fn defragment(&mut self) -> Result<(), Error> {
-        self.inner.defragment()
+    fn table(&self) -> &Table {
+        self.inner.table()
     }
+}
 
-    /// Explicitly closes the table.
-    ///
-    /// Normally this method does not need to be called.
+impl<K, V, T, KC, VC> TableWrite for TypedTable<K, V, T, KC, VC>
+where
+    KC: Codec<K>,
+    VC: Codec<V>,
+    T: TableWrite<Key = [u8], OwnedKey = Vec<u8>, Value = [u8], OwnedValue = Vec<u8>>,
+{
     #[inline]
-    pub fn close(self) {
-        self.inner.close()
+    fn set(&mut self, key: &K, value: &V) -> Result<bool, Error> {
+        TypedTable::set(self, key, value)
     }
 
-    /// Deletes all entries in the table
-    ///
-    /// This method essentially resets the table to its state after creation.
     #[inline]
-    pub fn clear(&mut self) -> Result<(), Error> {
-        self.inner.clear()
+    fn delete(&mut self, key: &K) -> Result<bool, Error> {
+        TypedTable::delete(self, key)
     }
 
-    /// Return a statistics struct
-    pub fn stats(&self) -> Stats {
-        self.inner.stats()
+    #[inline]
+    fn take(&mut self, key: &K) -> Result<Option<V>, Error> {
+        TypedTable::take(self, key)
     }
 
+    #[inline]
+    fn clear(&mut self) -> Result<(), Error> {
+        TypedTable::clear(self)
+    }
+
+    #[inline]
+    fn flush(&mut self) -> Result<(), Error> {
+        TypedTable::flush(self)
+    }
+
+    #[inline]
+    fn defragment(&mut self) -> Result<(), Error> {
+        TypedTable::defragment(self)
+    }
 }
 
 #[cfg(test)]
@@ -291,22 +426,22 @@ mod tests {
         let file = tempfile::NamedTempFile::new().unwrap();
         let mut tbl = Table::create(file.path()).unwrap();
         tbl.set_obj("key1", "value1").unwrap();
-        tbl.set_obj(&("key2", 1usize), &(1usize, true)).unwrap();
+        tbl.set_obj(("key2", 1usize), (1usize, true)).unwrap();
         assert!(tbl.is_valid());
         assert_eq!(tbl.len(), 2);
         assert_eq!(tbl.get_obj("key1").unwrap(), Some("value1".to_string()));
-        assert_eq!(tbl.get_obj(&("key2", 1)).unwrap(), Some((1, true)));
+        assert_eq!(tbl.get_obj(("key2", 1)).unwrap(), Some((1, true)));
         tbl.set_obj("key1", "value3").unwrap();
         assert!(tbl.is_valid());
         assert_eq!(tbl.len(), 2);
         assert_eq!(tbl.get_obj("key1").unwrap(), Some("value3".to_string()));
-        assert_eq!(tbl.get_obj(&("key2", 1)).unwrap(), Some((1, true)));
+        assert_eq!(tbl.get_obj(("key2", 1)).unwrap(), Some((1, true)));
         assert!(tbl.delete_obj("key1").unwrap());
-        assert!(tbl.delete_obj(&("key2", 1)).unwrap());
+        assert!(tbl.delete_obj(("key2", 1)).unwrap());
         assert!(tbl.is_valid());
         assert_eq!(tbl.len(), 0);
         assert_eq!(tbl.get_obj("key1").unwrap(), Option::<bool>::None);
-        assert_eq!(tbl.get_obj(&("key2", 1)).unwrap(), Option::<bool>::None);
+        assert_eq!(tbl.get_obj(("key2", 1)).unwrap(), Option::<bool>::None);
     }
 
     #[test]
@@ -0,0 +1,327 @@
+use std::borrow::Cow;
+
+use crate::{BorrowedEntries, Entries, Entry, EntryMut, Error, Table, TableRead, TableWrite, RESERVED_FLAGS};
+
+/// Entry flag that marks entries of namespaces and the registered namespace names
+pub(crate) const FLAG_NAMESPACE: u16 = 1 << 12;
+
+/// Maximum length of a namespace name in bytes
+const MAX_NAME_LEN: usize = 255;
+
+/// Returns the key under which the key of the namespace is stored: the length of the name, the name and the key.
+#[inline]
+fn scoped_key(name: &[u8], key: &[u8]) -> Vec<u8> {
+    let mut scoped = Vec::with_capacity(1 + name.len() + key.len());
+    scoped.push(name.len() as u8);
+    scoped.extend_from_slice(name);
+    scoped.extend_from_slice(key);
+    scoped
+}
+
+/// Returns the key under which the name of the namespace is registered.
+///
+/// As names are not empty, this key never collides with the key of an entry of a namespace.
+#[inline]
+fn registry_key(name: &[u8]) -> Vec<u8> {
+    scoped_key(&[], name)
+}
+
+fn check_name(name: &str) -> Result<(), Error> {
+    if name.is_empty() || name.len() > MAX_NAME_LEN {
+        return Err(Error::InvalidOptions("namespace name must have 1 to 255 bytes"));
+    }
+    Ok(())
+}
+
+/// A named namespace within a table as returned by [`Table::namespace`]
+///
+/// All operations of the namespace are scoped, i.e. they only see the entries that have been stored via the
+/// namespace, and entries of different namespaces never conflict.
+///
+/// The entries of a namespace are stored as normal entries of the table, marked via an entry flag and with the key
+/// prefixed by the length of the namespace name and the name itself. They are not visible to the methods of the
+/// table like [`Table::get`], [`Table::iter`] and [`Table::len`], and modifying a key of this form directly in the
+/// table returns [`Error::InvalidOptions`] instead of replacing the entry of the namespace.
+pub struct Namespace<'a> {
+    table: &'a mut Table,
+    name: String,
+}
+
+impl<'a> Namespace<'a> {
+    /// Returns the name of the namespace
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the entry with the given key in this namespace
+    pub fn get_entry(&self, key: &[u8]) -> Option<Entry<'_>> {
+        let prefix = 1 + self.name.len();
+        let entry = self.table.get_raw_entry(&scoped_key(self.name.as_bytes(), key))?;
+        if entry.flags & FLAG_NAMESPACE == 0 {
+            return None;
+        }
+        Some(Entry { key: &entry.key[prefix..], value: entry.value, flags: entry.flags }.without_reserved())
+    }
+
+    /// Returns the value of the given key in this namespace
+    #[inline]
+    pub fn get(&self, key: &[u8]) -> Option<&[u8]> {
+        self.get_entry(key).map(|entry| entry.value)
+    }
+
+    /// Returns whether the given key exists in this namespace
+    #[inline]
+    pub fn contains(&self, key: &[u8]) -> bool {
+        self.get_entry(key).is_some()
+    }
+
+    /// Stores the given entry in this namespace and returns the previous entry (if any).
+    ///
+    /// See [`Table::set_entry`] for details, the flags of the entry are kept in the same way. If an entry that is not
+    /// part of the namespace is stored under the key of the entry in the table, [`Error::InvalidOptions`] is returned
+    /// instead of replacing it.
+    pub fn set_entry<'b>(&mut self, entry: Entry<'b>) -> Result<Option<EntryMut<'_>>, Error> {
+        let prefix = 1 + self.name.len();
+        let scoped = scoped_key(self.name.as_bytes(), entry.key);
+        if matches!(self.table.get_raw_entry(&scoped), Some(old) if old.flags & FLAG_NAMESPACE == 0) {
+            return Err(Error::InvalidOptions("key is used by another entry"));
+        }
+        let flags = entry.flags & !RESERVED_FLAGS | FLAG_NAMESPACE;
+        let old = self.table.set_raw_entry(Entry { key: &scoped, value: entry.value, flags })?;
+        Ok(old.filter(|old| old.flags & FLAG_NAMESPACE != 0).map(|old| {
+            EntryMut { key: &old.key[prefix..], value: old.value, flags: old.flags }.without_reserved()
+        }))
+    }
+
+    /// Stores the value for the given key in this namespace and returns the previous value (if any).
+    ///
+    /// See [`Table::set`] for details.
+    #[inline]
+    pub fn set(&mut self, key: &[u8], value: &[u8]) -> Result<Option<&mut [u8]>, Error> {
+        self.set_entry(Entry { key, value, flags: 0 }).map(|old| old.map(|old| old.value))
+    }
+
+    /// Deletes the given key from this namespace and returns the deleted value (if any).
+    ///
+    /// See [`Table::delete`] for details.
+    pub fn delete(&mut self, key: &[u8]) -> Result<Option<&mut [u8]>, Error> {
+        if !self.contains(key) {
+            return Ok(None);
+        }
+        Ok(self.table.delete_raw_entry(&scoped_key(self.name.as_bytes(), key))?.map(|old| old.value))
+    }
+
+    /// Returns an iterator over all entries in this namespace in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = Entry<'_>> {
+        let name = self.name.as_bytes();
+        self.table.raw_iter().filter_map(move |entry| {
+            if entry.flags & FLAG_NAMESPACE == 0 || entry.key.first() != Some(&(name.len() as u8)) {
+                return None;
+            }
+            let key = entry.key[1..].strip_prefix(name)?;
+            Some(Entry { key, value: entry.value, flags: entry.flags }.without_reserved())
+        })
+    }
+
+    /// Returns the number of entries in this namespace.
+    ///
+    /// This iterates over the whole table.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    /// Returns whether this namespace contains no entries
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+
+    /// Deletes all entries of this namespace and returns their number. The namespace stays registered.
+    pub fn clear(&mut self) -> Result<usize, Error> {
+        let keys: Vec<Vec<u8>> = self.iter().map(|entry| entry.key.to_vec()).collect();
+        for key in &keys {
+            self.table.delete_raw_entry(&scoped_key(self.name.as_bytes(), key))?;
+        }
+        Ok(keys.len())
+    }
+}
+
+impl<'a> TableRead for Namespace<'a> {
+    type Key = [u8];
+    type OwnedKey = Vec<u8>;
+    type Value = [u8];
+    type OwnedValue = Vec<u8>;
+
+    #[inline]
+    fn contains(&self, key: &[u8]) -> Result<bool, Error> {
+        Ok(Namespace::contains(self, key))
+    }
+
+    #[inline]
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        Ok(Namespace::get(self, key).map(|value| value.to_vec()))
+    }
+
+    #[inline]
+    fn entries(&self) -> Entries<'_, Vec<u8>, Vec<u8>> {
+        Box::new(self.iter().map(|entry| Ok((entry.key.to_vec(), entry.value.to_vec()))))
+    }
+
+    #[inline]
+    fn entries_borrowed(&self) -> BorrowedEntries<'_, [u8], [u8]> {
+        Box::new(self.iter().map(|entry| Ok((Cow::Borrowed(entry.key), Cow::Borrowed(entry.value)))))
+    }
+
+    #[inline]
+    fn len(&self) -> usize {
+        Namespace::len(self)
+    }
+}
+
+impl<'a> TableWrite for Namespace<'a> {
+    #[inline]
+    fn set(&mut self, key: &[u8], value: &[u8]) -> Result<bool, Error> {
+        Namespace::set(self, key, value).map(|old| old.is_some())
+    }
+
+    #[inline]
+    fn delete(&mut self, key: &[u8]) -> Result<bool, Error> {
+        Namespace::delete(self, key).map(|old| old.is_some())
+    }
+
+    #[inline]
+    fn clear(&mut self) -> Result<(), Error> {
+        Namespace::clear(self).map(|_| ())
+    }
+
+    #[inline]
+    fn flush(&mut self) -> Result<(), Error> {
+        self.table.flush()
+    }
+
+    /// Forces defragmentation of the data section of the whole table, see [`Table::defragment`].
+    #[inline]
+    fn defragment(&mut self) -> Result<(), Error> {
+        self.table.defragment()
+    }
+}
+
+impl Table {
+    /// Returns a handle to the namespace with the given name, registering the name in the table if needed.
+    ///
+    /// Namespaces allow storing several independent maps in one table file, see [`Namespace`]. Names must have 1 to
+    /// 255 bytes. Registering a name modifies the table, so namespaces of read-only tables must have been registered
+    /// before. If another entry is stored under the key that registers the name, [`Error::InvalidOptions`] is returned.
+    ///
+    /// Tables created via [`HashKeyTable`](crate::HashKeyTable) are not supported.
+    ///
+    /// ```
+    /// use rust_persist::Table;
+    ///
+    /// let mut table = Table::create("example_namespace.tbl").unwrap();
+    /// table.namespace("users").unwrap().set("1".as_bytes(), "alice".as_bytes()).unwrap();
+    /// table.namespace("groups").unwrap().set("1".as_bytes(), "admins".as_bytes()).unwrap();
+    /// assert_eq!(table.namespace("users").unwrap().get("1".as_bytes()), Some("alice".as_bytes()));
+    /// assert_eq!(table.namespaces(), ["groups", "users"]);
+    /// ```
+    pub fn namespace(&mut self, name: &str) -> Result<Namespace<'_>, Error> {
+        self.check_byte_keys()?;
+        check_name(name)?;
+        let registry = registry_key(name.as_bytes());
+        match self.get_raw_entry(&registry) {
+            Some(entry) if entry.flags & FLAG_NAMESPACE != 0 => (),
+            Some(_) => return Err(Error::InvalidOptions("key of the namespace is used by another entry")),
+            None => {
+                self.set_raw_entry(Entry { key: &registry, value: &[], flags: FLAG_NAMESPACE })?;
+            }
+        }
+        Ok(Namespace { table: self, name: name.to_string() })
+    }
+
+    /// Returns the sorted names of all namespaces registered in the table.
+    ///
+    /// This iterates over the whole table.
+    pub fn namespaces(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .raw_iter()
+            .filter(|entry| entry.flags & FLAG_NAMESPACE != 0 && entry.key.len() > 1 && entry.key[0] == 0)
+            .filter_map(|entry| String::from_utf8(entry.key[1..].to_vec()).ok())
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// Deletes all entries of the namespace with the given name and unregisters it.
+    ///
+    /// Returns the number of deleted entries.
+    pub fn drop_namespace(&mut self, name: &str) -> Result<usize, Error> {
+        let deleted = self.namespace(name)?.clear()?;
+        self.delete_raw_entry(&registry_key(name.as_bytes()))?;
+        Ok(deleted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::HashKeyTable;
+
+    #[test]
+    fn test_namespace() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::options().checksums(true).create(file.path()).unwrap();
+        tbl.set("1".as_bytes(), "plain".as_bytes()).unwrap();
+        for i in 0u16..100 {
+            tbl.namespace("a").unwrap().set(&i.to_ne_bytes(), &[1]).unwrap();
+        }
+        let mut b = tbl.namespace("b").unwrap();
+        assert_eq!(b.set("1".as_bytes(), &[2]).unwrap(), None);
+        assert_eq!(b.set("1".as_bytes(), &[3]).unwrap(), Some(&mut [2][..]));
+        assert_eq!(b.get("2".as_bytes()), None);
+        assert_eq!(b.len(), 1);
+        // Flags given by the application are kept, internal ones are cleared
+        b.set_entry(Entry { key: "2".as_bytes(), value: &[5], flags: 3 | crate::RESERVED_FLAGS }).unwrap();
+        assert_eq!(b.get_entry("2".as_bytes()).unwrap().flags, 3 | crate::FLAG_CHECKSUM);
+        assert_eq!(tbl.get("1".as_bytes()), Some("plain".as_bytes()));
+        // Entries of namespaces are not visible to the table and cannot be modified directly
+        assert_eq!(tbl.len(), 1);
+        assert_eq!(tbl.iter().count(), 1);
+        assert_eq!(tbl.get(&scoped_key(b"b", b"1")), None);
+        assert!(!tbl.contains(&registry_key(b"b")));
+        assert!(matches!(tbl.set(&scoped_key(b"b", b"1"), &[4]), Err(Error::InvalidOptions(_))));
+        assert!(matches!(tbl.delete(&registry_key(b"b")), Err(Error::InvalidOptions(_))));
+        assert!(matches!(tbl.rename("1".as_bytes(), &scoped_key(b"b", b"2")), Err(Error::InvalidOptions(_))));
+        assert_eq!(tbl.namespace("b").unwrap().get("1".as_bytes()), Some(&[3][..]));
+        // A plain entry with the key of a namespace entry is not part of the namespace
+        tbl.set(&scoped_key(b"c", b"1"), &[4]).unwrap();
+        let mut c = tbl.namespace("c").unwrap();
+        assert_eq!(c.get("1".as_bytes()), None);
+        assert_eq!(c.delete("1".as_bytes()).unwrap(), None);
+        assert!(matches!(c.set("1".as_bytes(), &[5]), Err(Error::InvalidOptions(_))));
+        assert!(c.is_empty());
+        assert!(tbl.contains(&scoped_key(b"c", b"1")));
+        assert_eq!(tbl.namespaces(), ["a", "b", "c"]);
+        tbl.close();
+        let mut tbl = Table::open(file.path()).unwrap();
+        let mut a = tbl.namespace("a").unwrap();
+        assert_eq!(a.len(), 100);
+        assert!(a.iter().all(|entry| entry.key.len() == 2 && entry.value == [1]));
+        assert_eq!(a.get_entry(&5u16.to_ne_bytes()).unwrap().flags, crate::FLAG_CHECKSUM);
+        assert_eq!(a.delete(&5u16.to_ne_bytes()).unwrap(), Some(&mut [1][..]));
+        assert_eq!(tbl.drop_namespace("a").unwrap(), 99);
+        assert_eq!(tbl.namespaces(), ["b", "c"]);
+        assert_eq!(tbl.namespace("b").unwrap().get("1".as_bytes()), Some(&[3][..]));
+        assert_eq!(tbl.len(), 2);
+        assert_eq!(tbl.stats().entries, 6);
+        assert!(matches!(tbl.namespace(""), Err(Error::InvalidOptions(_))));
+        tbl.set(&registry_key(b"d"), &[]).unwrap();
+        assert!(matches!(tbl.namespace("d"), Err(Error::InvalidOptions(_))));
+        assert!(matches!(tbl.namespace(&"x".repeat(256)), Err(Error::InvalidOptions(_))));
+        let file = tempfile::NamedTempFile::new().unwrap();
+        HashKeyTable::create(file.path()).unwrap().close();
+        let mut tbl = Table::open(file.path()).unwrap();
+        assert!(matches!(tbl.namespace("a"), Err(Error::InvalidOptions(_))));
+    }
+}
@@ -0,0 +1,503 @@
+use std::{cmp, path::Path, sync::Arc, time::Duration};
+
+use crate::{
+    checksum::CHECKSUM_SIZE, clock::OptionsClock, resize::index_capacity_for, table::total_size, Clock, Error, Table,
+    SharedReader, WindowedTable,
+    INITIAL_DATA_SIZE, INITIAL_INDEX_CAPACITY, MAX_USAGE, MIN_USAGE,
+};
+
+/// Determines when changes are explicitly written to disk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlushMode {
+    /// Changes are only written when [`Table::flush`] is called or when the operating system decides to
+    Manual,
+    /// Changes are written when the table is closed
+    OnClose,
+    /// Changes are written after every modification (slow)
+    EveryWrite,
+}
+
+/// Determines how the table file is locked
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockMode {
+    /// Take an exclusive lock and fail with [`Error::TableLocked`] if the table is locked by another process
+    ///
+    /// Read-only tables take a shared lock instead.
+    Exclusive,
+    /// Take an exclusive lock and wait until the table is no longer locked by another process
+    ///
+    /// Read-only tables take a shared lock instead.
+    Wait,
+    /// Do not lock the table file
+    ///
+    /// Opening a table from multiple processes at the same time will corrupt it.
+    None,
+    /// Take an exclusive lock like [`LockMode::Exclusive`], but let readers opened via
+    /// [`TableOptions::open_reader`] access the table while it is open
+    ///
+    /// Readers are locked out from the first modification until the changes are published via [`Table::flush`] or
+    /// the table is closed, so they only see complete changes. Changes made since the last flush are not visible to
+    /// readers and hold them off, so the writer should flush regularly. Modifications that cannot report errors, e.g.
+    /// [`Table::get_mut`], do nothing if the readers cannot be locked out, their fallible variants like
+    /// [`Table::try_get_mut`] return the error instead. Read-only tables do not support this mode.
+    Shared,
+}
+
+/// Determines how the space is initialized when the table file grows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GrowFill {
+    /// Leave the new space to the file system, which creates a sparse region that reads as zeros
+    ///
+    /// This is the fastest mode, but the disk space is only allocated when the region is written. If the disk runs
+    /// full in the meantime, writing to the memory map crashes the process.
+    Sparse,
+    /// Explicitly write zeros to the new space, so that it is allocated on disk right away
+    ///
+    /// This costs write bandwidth when growing the file, but guarantees that no stale data of the file system or of
+    /// a previous table at the same path is ever exposed.
+    Zero,
+    /// Fill the new space and every newly allocated data block with the given byte in debug builds
+    ///
+    /// This makes reads of bytes that have never been written visible when debugging. In release builds, this
+    /// behaves like [`GrowFill::Sparse`].
+    Pattern(u8),
+}
+
+/// Options to configure how a table is opened or created
+///
+/// ```
+/// use rust_persist::{FlushMode, Table};
+///
+/// let table = Table::options().index_capacity(1024).flush(FlushMode::OnClose).create("options.tbl").unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct TableOptions {
+    pub(crate) index_capacity: usize,
+    pub(crate) data_size: u64,
+    pub(crate) min_usage: f64,
+    pub(crate) max_usage: f64,
+    pub(crate) target_displacement: Option<f64>,
+    pub(crate) flush: FlushMode,
+    pub(crate) lock: LockMode,
+    pub(crate) read_only: bool,
+    pub(crate) wal: bool,
+    pub(crate) checksums: bool,
+    pub(crate) max_map_size: Option<u64>,
+    pub(crate) slow_op_threshold: Option<Duration>,
+    pub(crate) stats_interval: Option<Duration>,
+    pub(crate) max_data_size: Option<u64>,
+    pub(crate) grow_fill: GrowFill,
+    pub(crate) shred: bool,
+    pub(crate) guard_bytes: bool,
+    pub(crate) clock: OptionsClock,
+}
+
+impl Default for TableOptions {
+    fn default() -> Self {
+        Self {
+            index_capacity: INITIAL_INDEX_CAPACITY,
+            data_size: INITIAL_DATA_SIZE as u64,
+            min_usage: MIN_USAGE,
+            max_usage: MAX_USAGE,
+            target_displacement: None,
+            flush: FlushMode::Manual,
+            lock: LockMode::Exclusive,
+            read_only: false,
+            wal: false,
+            checksums: false,
+            max_map_size: None,
+            slow_op_threshold: None,
+            stats_interval: None,
+            max_data_size: None,
+            grow_fill: GrowFill::Sparse,
+            shred: false,
+            guard_bytes: false,
+            clock: OptionsClock::default(),
+        }
+    }
+}
+
+impl TableOptions {
+    /// Sets the initial capacity of the index when creating a table (rounded up to a power of two).
+    ///
+    /// The index will not be shrunk below this capacity.
+    #[inline]
+    pub fn index_capacity(mut self, capacity: usize) -> Self {
+        self.index_capacity = capacity.max(2).next_power_of_two();
+        self
+    }
+
+    /// Sets the initial size of the data section when creating a table.
+    ///
+    /// The data section is not shrunk automatically below this size, but [`Table::defragment`] truncates it.
+    #[inline]
+    pub fn data_size(mut self, size: u64) -> Self {
+        self.data_size = size;
+        self
+    }
+
+    /// Sets the usage fractions of the index that trigger shrinking (`min`) and growing (`max`) it.
+    ///
+    /// The defaults are 35% and 90%.
+    #[inline]
+    pub fn index_usage(mut self, min: f64, max: f64) -> Self {
+        self.min_usage = min;
+        self.max_usage = max;
+        self
+    }
+
+    /// Sizes the index adaptively to keep the average displacement of its entries (the extra slots probed per
+    /// lookup) close to the given target.
+    ///
+    /// The index is grown when the average displacement exceeds the target and shrunk when the displacement expected
+    /// after shrinking is below half of the target, so the lookup cost stays stable even if the hashes of the keys are
+    /// not evenly distributed. The bounds set via [`TableOptions::index_usage`] still apply: the index always grows
+    /// above the maximum usage and is never resized to a usage below the minimum, so low targets require a low
+    /// minimum usage. By default (`None`), the index is only resized based on its usage.
+    ///
+    /// ```
+    /// use rust_persist::Table;
+    ///
+    /// let options = Table::options().index_usage(0.1, 0.9).target_displacement(Some(0.5));
+    /// let table = options.create("example_adaptive.tbl").unwrap();
+    /// ```
+    #[inline]
+    pub fn target_displacement(mut self, target: Option<f64>) -> Self {
+        self.target_displacement = target;
+        self
+    }
+
+    /// Sets when changes are explicitly written to disk.
+    #[inline]
+    pub fn flush(mut self, flush: FlushMode) -> Self {
+        self.flush = flush;
+        self
+    }
+
+    /// Sets how the table file is locked.
+    #[inline]
+    pub fn lock(mut self, lock: LockMode) -> Self {
+        self.lock = lock;
+        self
+    }
+
+    /// Opens the table read-only.
+    ///
+    /// The file is opened read-only and only a shared lock is taken, so that multiple processes can read the same
+    /// table at the same time. All modifying methods will return [`Error::ReadOnly`].
+    ///
+    /// The file is mapped copy-on-write, so values modified via [`Table::get_mut`] or [`Table::each_mut`] are
+    /// never written to the file. Use [`Table::open_read_only`] to prevent modifications at compile time instead.
+    #[inline]
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// Enables the write-ahead log mode.
+    ///
+    /// In this mode, all sets and deletes are written to a log file next to the table (`<path>.wal`) and synced to
+    /// disk before the table itself is modified. When the table is opened, the log is replayed, so that no
+    /// successful change is lost even if the changes to the table itself did not reach the disk. The log is truncated
+    /// whenever the table is flushed and the table is flushed automatically when the log grows larger than 64 MiB.
+    ///
+    /// Changes made via [`Table::get_mut`] or [`Table::each_mut`] are not logged.
+    #[inline]
+    pub fn wal(mut self, wal: bool) -> Self {
+        self.wal = wal;
+        self
+    }
+
+    /// Stores a CRC32 checksum of the key and value with every entry that is written.
+    ///
+    /// The checksums can be verified via [`Table::get_checked`] and [`Table::verify_checksums`] to detect silent
+    /// corruption of the table file. Entries written without this option have no checksum and always pass.
+    /// Values modified in place via [`Table::get_mut`] or [`Table::each_mut`] will fail the verification.
+    #[inline]
+    pub fn checksums(mut self, checksums: bool) -> Self {
+        self.checksums = checksums;
+        self
+    }
+
+    /// Overwrites the data of deleted and replaced values with zeros, so that they do not linger in the table file.
+    ///
+    /// As the old value is still returned by [`Table::delete`] and [`Table::set`], its data block is overwritten
+    /// when the next data block is allocated or freed, or at the latest when the table is closed. Regions vacated by
+    /// the defragmentation are overwritten as well before the file is truncated. By default, old values stay in the
+    /// file until their space is reused.
+    #[inline]
+    pub fn shred(mut self, shred: bool) -> Self {
+        self.shred = shred;
+        self
+    }
+
+    /// Places guard bytes behind every data block that is allocated and checks them when the block is freed.
+    ///
+    /// Writes past the end of a value, e.g. by bugs in unsafe code writing through [`Table::get_mut`], then hit the
+    /// guard bytes instead of going unnoticed in the neighboring entry. Overwritten guard bytes cause a panic naming
+    /// their position when the block is freed or the table is defragmented, with the `no-panic` feature the table
+    /// reports [`Error::Corrupt`](crate::Error::Corrupt) from then on. [`Table::verify`] reports them as
+    /// [`Finding::GuardOverwritten`](crate::Finding::GuardOverwritten).
+    ///
+    /// The guards take 16 bytes per entry and are meant for debug and validation builds, e.g. via
+    /// `guard_bytes(cfg!(debug_assertions))`. They are not persisted and are dropped by defragmentations, so only
+    /// blocks written since then are guarded.
+    #[inline]
+    pub fn guard_bytes(mut self, guard_bytes: bool) -> Self {
+        self.guard_bytes = guard_bytes;
+        self
+    }
+
+    /// Limits the size of the memory map and thereby the virtual memory used by the table.
+    ///
+    /// The whole table file is mapped, as entries are returned as slices into the map. With this limit, opening a
+    /// larger table and any operation that would grow the table file beyond it fail with [`Error::MapLimit`], so
+    /// the table never uses more virtual memory than the given number of bytes. Larger tables can still be read
+    /// via [`TableOptions::open_windowed`], which only maps a window of the data section. By default, there is no
+    /// limit.
+    #[inline]
+    pub fn max_map_size(mut self, size: Option<u64>) -> Self {
+        self.max_map_size = size;
+        self
+    }
+
+    /// Records maintenance operations that take at least the given duration, see [`Table::slow_ops`].
+    ///
+    /// By default, no operations are recorded.
+    #[inline]
+    pub fn slow_op_threshold(mut self, threshold: Option<Duration>) -> Self {
+        self.slow_op_threshold = threshold;
+        self
+    }
+
+    /// Automatically records a snapshot of the table statistics in the table when the given interval has passed
+    /// since the last snapshot, see [`Table::record_stats`].
+    ///
+    /// The interval is checked whenever an entry is stored via [`Table::set_entry`] or deleted via
+    /// [`Table::delete_entry`] (and the methods based on them), using the table clock with a resolution of seconds.
+    /// By default, snapshots are only recorded explicitly.
+    #[inline]
+    pub fn stats_interval(mut self, interval: Option<Duration>) -> Self {
+        self.stats_interval = interval;
+        self
+    }
+
+    /// Sets the clock used by all time-based features of the table, see [`Table::set_clock`].
+    ///
+    /// In contrast to [`Table::set_clock`], the clock is already used while the table is opened, e.g. for the
+    /// creation time in [`Table::info`]. By default, the [`SystemClock`](crate::SystemClock) is used.
+    #[inline]
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = OptionsClock(clock);
+        self
+    }
+
+    /// Limits the total size of all stored entries (keys, values and checksums) to the given number of bytes.
+    ///
+    /// When storing an entry would exceed the limit, entries that have not been used recently are evicted instead,
+    /// see [`Table::touch`]. Entries of namespaces are evicted as well, but pinned entries (see [`Table::pin_front`])
+    /// and internal entries are never evicted. Entries larger than the limit are rejected with [`Error::TooLarge`]. If
+    /// the limit cannot be met as all remaining entries are pinned or internal, storing fails with
+    /// [`Error::DataLimit`]. All modifications that store data evict entries, but [`Table::rename`], [`Table::swap`],
+    /// [`Table::rewrite_keys`] and [`Table::rewrite_values`] only evict after they completed, so they can exceed the
+    /// limit temporarily and report [`Error::DataLimit`] after the modification has been made.
+    ///
+    /// This is a limit on the stored data only, not on the size of the table file. The file also contains the header,
+    /// the index and free space in the data section, which grow as needed, so its size is not fixed by this option.
+    ///
+    /// By default, there is no limit.
+    #[inline]
+    pub fn max_data_size(mut self, size: Option<u64>) -> Self {
+        self.max_data_size = size;
+        self
+    }
+
+    /// Sets how the space is initialized when the table file is created or grows.
+    ///
+    /// The default is [`GrowFill::Sparse`].
+    #[inline]
+    pub fn grow_fill(mut self, fill: GrowFill) -> Self {
+        self.grow_fill = fill;
+        self
+    }
+
+    /// Sets the initial index capacity and data size to hold the given number of entries without resizing.
+    ///
+    /// The sizes are computed from the average key and value sizes and the current index usage and checksum
+    /// options, so this method should be called after [`TableOptions::index_usage`] and
+    /// [`TableOptions::checksums`].
+    ///
+    /// ```
+    /// use rust_persist::Table;
+    ///
+    /// let options = Table::options().checksums(true).plan_for(100_000, 16, 200);
+    /// println!("Provisioning {} bytes", options.estimate_file_size(100_000, 16, 200));
+    /// let table = options.create("example_plan.tbl").unwrap();
+    /// ```
+    #[inline]
+    pub fn plan_for(mut self, entries: usize, avg_key: usize, avg_value: usize) -> Self {
+        let (index_capacity, data_size) = self.planned_sizes(entries, avg_key, avg_value);
+        self.index_capacity = index_capacity;
+        self.data_size = data_size;
+        self
+    }
+
+    /// Estimates the size of a table file with these options holding the given number of entries.
+    ///
+    /// The estimate assumes that the table has been defragmented, i.e. the data section contains no free space.
+    /// The index capacity and data size configured in these options are treated as lower bounds.
+    pub fn estimate_file_size(&self, entries: usize, avg_key: usize, avg_value: usize) -> u64 {
+        let (index_capacity, data_size) = self.planned_sizes(entries, avg_key, avg_value);
+        total_size(index_capacity, cmp::max(data_size, self.data_size)).unwrap_or(u64::MAX)
+    }
+
+    /// Returns the index capacity and the data size needed for the given number of entries
+    fn planned_sizes(&self, entries: usize, avg_key: usize, avg_value: usize) -> (usize, u64) {
+        // the index is limited to u32::MAX entries anyway, this just prevents an overflow
+        let index_capacity =
+            index_capacity_for(self.index_capacity, cmp::min(entries, u32::MAX as usize), self.max_usage);
+        let checksum_size = if self.checksums { CHECKSUM_SIZE as u64 } else { 0 };
+        let block_size = cmp::max((avg_key as u64).saturating_add(avg_value as u64).saturating_add(checksum_size), 1);
+        (index_capacity, block_size.saturating_mul(entries as u64))
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), Error> {
+        if !(self.min_usage >= 0.0 && self.max_usage < 1.0 && self.min_usage * 2.0 < self.max_usage) {
+            return Err(Error::InvalidOptions("index usage must satisfy 0 <= 2 * min < max < 1"));
+        }
+        if self.target_displacement.is_some_and(|target| !(target > 0.0 && target.is_finite())) {
+            return Err(Error::InvalidOptions("target displacement must be positive"));
+        }
+        if self.read_only && self.lock == LockMode::Shared {
+            return Err(Error::InvalidOptions("shared lock mode requires a writable table"));
+        }
+        Ok(())
+    }
+
+    /// Opens an existing table from the given path using these options.
+    #[inline]
+    pub fn open<P: AsRef<Path>>(self, path: P) -> Result<Table, Error> {
+        Table::new_index(path.as_ref(), false, self)
+    }
+
+    /// Opens an existing table read-only, mapping at most [`TableOptions::max_map_size`] bytes of it at a time.
+    ///
+    /// The header and the index are mapped completely, the data section is read through a window of the remaining
+    /// size, see [`WindowedTable`]. Like with [`Table::open_read_only`], only a shared lock is taken and neither a
+    /// journal nor a write-ahead log is replayed. Fails with [`Error::InvalidOptions`] if no maximum map size is set
+    /// and with [`Error::MapLimit`] if the index does not fit into the limit with room for a window.
+    ///
+    /// ```
+    /// use rust_persist::Table;
+    ///
+    /// let mut table = Table::create("example_windowed.tbl").unwrap();
+    /// for i in 0u32..1000 {
+    ///     table.set(&i.to_le_bytes(), &[0; 1000]).unwrap();
+    /// }
+    /// drop(table);
+    /// let table = Table::options().max_map_size(Some(256 * 1024)).open_windowed("example_windowed.tbl").unwrap();
+    /// assert_eq!(table.get(&5u32.to_le_bytes()).unwrap(), Some(vec![0; 1000]));
+    /// ```
+    #[inline]
+    pub fn open_windowed<P: AsRef<Path>>(self, path: P) -> Result<WindowedTable, Error> {
+        WindowedTable::open(path.as_ref(), self)
+    }
+
+    /// Opens a reader of a table that is written by another process in [`LockMode::Shared`], see [`SharedReader`].
+    ///
+    /// The table is only opened on the first read, the lock mode and the read-only setting of the options are
+    /// ignored.
+    #[inline]
+    pub fn open_reader<P: AsRef<Path>>(self, path: P) -> Result<SharedReader, Error> {
+        SharedReader::open(path.as_ref(), self)
+    }
+
+    /// Creates a new empty table using these options. If the file exists, it will be overwritten.
+    #[inline]
+    pub fn create<P: AsRef<Path>>(self, path: P) -> Result<Table, Error> {
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
+        Table::new_index(path.as_ref(), true, self)
+    }
+
+    /// Opens an existing or creates a new table at the given path using these options.
+    #[inline]
+    pub fn open_or_create<P: AsRef<Path>>(self, path: P) -> Result<Table, Error> {
+        let path = path.as_ref();
+        if path.exists() {
+            self.open(path)
+        } else {
+            self.create(path)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan_for() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let options = Table::options().plan_for(1000, 8, 100);
+        let expected = options.estimate_file_size(1000, 8, 100);
+        assert_eq!(expected, Table::estimate_file_size(1000, 8, 100));
+        let mut tbl = options.create(file.path()).unwrap();
+        assert_eq!(tbl.index.capacity(), 2048);
+        for i in 0u64..1000 {
+            tbl.set(&i.to_ne_bytes(), &[0; 100]).unwrap();
+        }
+        assert_eq!(file.as_file().metadata().unwrap().len(), expected);
+        assert_eq!(tbl.stats().data_free, 0);
+        assert!(Table::estimate_file_size(2000, 8, 100) > expected);
+        assert!(Table::options().checksums(true).estimate_file_size(1000, 8, 100) > expected);
+        assert_eq!(Table::estimate_file_size(usize::MAX, usize::MAX, 1), u64::MAX);
+    }
+
+    #[test]
+    fn test_options() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::options().index_capacity(1000).data_size(4096).create(file.path()).unwrap();
+        assert_eq!(tbl.index.capacity(), 1024);
+        assert_eq!(tbl.data.len(), 4096);
+        for i in 0u16..500 {
+            tbl.set(&i.to_ne_bytes(), &[]).unwrap();
+        }
+        assert_eq!(tbl.index.capacity(), 1024);
+        for i in 0u16..500 {
+            tbl.delete(&i.to_ne_bytes()).unwrap();
+        }
+        assert_eq!(tbl.index.capacity(), 1024);
+        assert!(tbl.is_valid());
+        tbl.close();
+        let tbl = Table::options().index_usage(0.1, 0.5).flush(FlushMode::EveryWrite).open(file.path()).unwrap();
+        assert!(tbl.is_valid());
+        assert!(Table::options().lock(LockMode::None).open(file.path()).is_ok());
+        assert!(matches!(Table::open(file.path()), Err(Error::TableLocked)));
+        assert!(matches!(Table::options().index_usage(0.5, 0.9).open(file.path()), Err(Error::InvalidOptions(_))));
+    }
+
+    #[test]
+    fn test_grow_fill() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        // Simulate stale data left behind by a previous file at the same path
+        std::fs::write(file.path(), vec![0x55; 1 << 16]).unwrap();
+        let tbl = Table::options().grow_fill(GrowFill::Zero).data_size(1024).create(file.path()).unwrap();
+        assert!(tbl.data.iter().all(|&b| b == 0));
+        tbl.close();
+        let mut tbl = Table::options().grow_fill(GrowFill::Pattern(0xaa)).data_size(1024).create(file.path()).unwrap();
+        assert!(tbl.data.iter().all(|&b| b == 0xaa));
+        tbl.set("key".as_bytes(), &[1; 4000]).unwrap();
+        let end = tbl.data.len() - tbl.mem.free_tail() as usize;
+        assert!(tbl.data[end..].iter().all(|&b| b == 0xaa));
+        tbl.delete("key".as_bytes()).unwrap();
+        // Reused blocks are filled as well, so no old value is visible in an allocated block
+        let pos = tbl.allocate_data(1, 100).unwrap();
+        assert!(tbl.get_data(pos, 100).iter().all(|&b| b == 0xaa));
+        tbl.free_data(pos);
+        tbl.set("key".as_bytes(), "value".as_bytes()).unwrap();
+        assert_eq!(tbl.get("key".as_bytes()), Some("value".as_bytes()));
+        assert!(tbl.is_valid());
+    }
+}
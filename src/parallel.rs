@@ -0,0 +1,56 @@
+use rayon::prelude::*;
+
+use crate::{index::is_namespaced, Entry, Table};
+
+/// Minimal number of index slots scanned by a single task, so that splitting does not cost more than it saves
+const MIN_SLOTS_PER_TASK: usize = 4096;
+
+impl Table {
+    /// Returns a parallel iterator over all entries in the table.
+    ///
+    /// The index is split into chunks that are scanned on the rayon thread pool, so the entries are yielded in no
+    /// particular order. This is useful for scanning large tables, e.g. to aggregate over all values.
+    ///
+    /// ```
+    /// use rayon::prelude::*;
+    /// use rust_persist::Table;
+    ///
+    /// let mut table = Table::create("example_par_iter.tbl").unwrap();
+    /// for i in 0u32..1000 {
+    ///     table.set(&i.to_le_bytes(), &[1; 10]).unwrap();
+    /// }
+    /// let total: usize = table.par_iter().map(|entry| entry.value.len()).sum();
+    /// assert_eq!(total, 10_000);
+    /// ```
+    pub fn par_iter(&self) -> impl ParallelIterator<Item = Entry<'_>> {
+        self.index
+            .get_entries()
+            .par_iter()
+            .with_min_len(MIN_SLOTS_PER_TASK)
+            .filter(|entry| entry.is_used() && !is_namespaced(&entry.data))
+            .map(move |entry| self.entry_from_index_data(entry.data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_par_iter() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::options().checksums(true).create(file.path()).unwrap();
+        assert_eq!(tbl.par_iter().count(), 0);
+        tbl.set_many((0u32..10_000).map(|i| (i.to_le_bytes(), i.to_be_bytes()))).unwrap();
+        let keys: HashSet<_> = tbl
+            .par_iter()
+            .map(|entry| {
+                assert_eq!(entry.key.iter().rev().collect::<Vec<_>>(), entry.value.iter().collect::<Vec<_>>());
+                entry.key.to_vec()
+            })
+            .collect();
+        assert_eq!(keys, tbl.iter().map(|entry| entry.key.to_vec()).collect());
+        assert_eq!(keys.len(), 10_000);
+    }
+}
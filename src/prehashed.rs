@@ -0,0 +1,106 @@
+use crate::{table::hash_key, Entry, EntryMut, Error, Table};
+
+impl Table {
+    /// Returns the hash of the given key as used by the index of the table.
+    ///
+    /// The hash does not depend on the table or the process, so it can be computed once, cached alongside the key
+    /// (e.g. for interned keys) and passed to [`Table::get_with_hash`] and [`Table::delete_with_hash`] to avoid
+    /// hashing the key again on every operation. [`Table::set_with_hash`] accepts it as well but checks it.
+    ///
+    /// ```
+    /// use rust_persist::Table;
+    ///
+    /// let mut table = Table::create("example_hash_of.tbl").unwrap();
+    /// let key = "hello".as_bytes();
+    /// let hash = Table::hash_of(key);
+    /// table.set(key, "world".as_bytes()).unwrap();
+    /// assert_eq!(table.get_with_hash(hash, key), Some("world".as_bytes()));
+    /// ```
+    #[inline]
+    pub fn hash_of(key: &[u8]) -> u64 {
+        hash_key(key)
+    }
+
+    /// Retrieves the value associated with the given key like [`Table::get`], using a hash precomputed via
+    /// [`Table::hash_of`].
+    ///
+    /// The hash must belong to the key, otherwise the entry will not be found. This is only checked in debug builds.
+    #[inline]
+    pub fn get_with_hash(&self, hash: u64, key: &[u8]) -> Option<&[u8]> {
+        debug_assert_eq!(hash, hash_key(key), "hash does not belong to key");
+        self.get_plain_entry_hashed(hash, key).map(|e| e.value)
+    }
+
+    /// Stores the given entry like [`Table::set_entry`], using a hash of its key precomputed via
+    /// [`Table::hash_of`].
+    ///
+    /// The hash must belong to the key, as the entry would otherwise be stored where lookups cannot find it. This is
+    /// checked in all builds and [`Error::InvalidOptions`] is returned for a wrong hash, so in contrast to
+    /// [`Table::get_with_hash`], the key is hashed anyway. Tables created via [`HashKeyTable`](crate::HashKeyTable)
+    /// are not supported.
+    #[inline]
+    pub fn set_with_hash<'a>(&mut self, hash: u64, entry: Entry<'a>) -> Result<Option<EntryMut<'_>>, Error> {
+        if hash != hash_key(entry.key) {
+            return Err(Error::InvalidOptions("hash does not belong to key"));
+        }
+        self.check_writable()?;
+        self.check_byte_keys()?;
+        self.check_plain_key(hash, entry.key)?;
+        let entry = entry.without_reserved();
+        self.log_set(entry.key, entry.value, entry.flags)?;
+        Ok(self.set_entry_hashed(hash, entry)?.map(EntryMut::without_reserved))
+    }
+
+    /// Deletes the entry with the given key like [`Table::delete_entry`], using a hash precomputed via
+    /// [`Table::hash_of`].
+    ///
+    /// The hash must belong to the key, otherwise the entry will not be found. This is only checked in debug builds.
+    /// Tables created via [`HashKeyTable`](crate::HashKeyTable) are not supported.
+    #[inline]
+    pub fn delete_with_hash(&mut self, hash: u64, key: &[u8]) -> Result<Option<EntryMut<'_>>, Error> {
+        debug_assert_eq!(hash, hash_key(key), "hash does not belong to key");
+        self.check_writable()?;
+        self.check_byte_keys()?;
+        self.check_plain_key(hash, key)?;
+        self.log_delete(key)?;
+        Ok(self.delete_entry_hashed(hash, key)?.map(EntryMut::without_reserved))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wal::wal_path;
+    use std::fs;
+
+    #[test]
+    fn test_with_hash() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::options().wal(true).create(file.path()).unwrap();
+        let hashes: Vec<_> = (0u32..100).map(|i| (i, Table::hash_of(&i.to_le_bytes()))).collect();
+        for &(i, hash) in &hashes {
+            let key = i.to_le_bytes();
+            assert!(tbl.set_with_hash(hash, Entry { key: &key, value: &key, flags: 0 }).unwrap().is_none());
+        }
+        for &(i, hash) in &hashes {
+            assert_eq!(tbl.get_with_hash(hash, &i.to_le_bytes()), Some(&i.to_le_bytes()[..]));
+            assert_eq!(tbl.get(&i.to_le_bytes()), Some(&i.to_le_bytes()[..]));
+        }
+        for &(i, hash) in &hashes[..50] {
+            assert_eq!(tbl.delete_with_hash(hash, &i.to_le_bytes()).unwrap().unwrap().value, &i.to_le_bytes());
+        }
+        assert!(tbl.get_with_hash(hashes[0].1, &0u32.to_le_bytes()).is_none());
+        let entry = Entry { key: &0u32.to_le_bytes(), value: &[], flags: 0 };
+        assert!(matches!(tbl.set_with_hash(hashes[1].1, entry), Err(Error::InvalidOptions(_))));
+        assert_eq!(tbl.len(), 50);
+        assert!(tbl.is_valid());
+        let wal = fs::read(wal_path(file.path())).unwrap();
+        tbl.close();
+        // The changes are logged, so they survive a crash like regular ones
+        Table::create(file.path()).unwrap().close();
+        fs::write(wal_path(file.path()), &wal).unwrap();
+        let tbl = Table::options().wal(true).open(file.path()).unwrap();
+        assert_eq!(tbl.len(), 50);
+        assert_eq!(tbl.get(&99u32.to_le_bytes()), Some(&99u32.to_le_bytes()[..]));
+    }
+}
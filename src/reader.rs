@@ -0,0 +1,303 @@
+use std::{
+    fs::{File, OpenOptions},
+    io, mem,
+    ops::Deref,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use fs2::FileExt;
+use memmap::{Mmap, MmapOptions};
+
+use crate::{batch::sibling_path, table::Header, Error, LockMode, Table, TableOptions, INDEX_HEADER};
+
+/// Returns the path of the file that coordinates a writer in [`LockMode::Shared`] with its readers
+#[inline]
+pub(crate) fn readers_path(path: &Path) -> PathBuf {
+    sibling_path(path, ".readers")
+}
+
+fn open_readers(path: &Path) -> Result<File, Error> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(readers_path(path))
+        .map_err(Error::Io)
+}
+
+/// Lock of a writer in [`LockMode::Shared`] that keeps readers out while the table is modified
+///
+/// The lock is taken exclusively on the file `<path>.readers`, readers take it shared while they read.
+pub(crate) struct ReaderLock {
+    fd: File,
+    locked: AtomicBool,
+}
+
+impl ReaderLock {
+    /// Waits until no reader is active and locks out new ones.
+    pub(crate) fn acquire(path: &Path) -> Result<Self, Error> {
+        let fd = open_readers(path)?;
+        FileExt::lock_exclusive(&fd).map_err(Error::Io)?;
+        Ok(Self { fd, locked: AtomicBool::new(true) })
+    }
+}
+
+impl Table {
+    /// Locks out shared readers before the table is modified, see [`LockMode::Shared`].
+    ///
+    /// Each time the lock is taken, the generation in the header is incremented, so that readers reload the table.
+    pub(crate) fn lock_readers(&mut self) -> Result<(), Error> {
+        if let Some(readers) = &self.readers {
+            if !readers.locked.load(Ordering::Acquire) {
+                FileExt::lock_exclusive(&readers.fd).map_err(Error::Io)?;
+                readers.locked.store(true, Ordering::Release);
+                self.header.set_generation(self.header.generation().wrapping_add(1));
+            }
+        }
+        Ok(())
+    }
+
+    /// Lets shared readers in again once the changes are on disk
+    pub(crate) fn release_readers(&self) -> Result<(), Error> {
+        if let Some(readers) = &self.readers {
+            if readers.locked.swap(false, Ordering::AcqRel) {
+                FileExt::unlock(&readers.fd).map_err(Error::Io)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The table file of a [`SharedReader`] with a shared mapping of its header
+struct TableFile {
+    fd: File,
+    map: Mmap,
+}
+
+impl TableFile {
+    fn open(path: &Path) -> Result<Self, Error> {
+        let fd = OpenOptions::new().read(true).open(path).map_err(Error::Io)?;
+        if fd.metadata().map_err(Error::Io)?.len() < mem::size_of::<Header>() as u64 {
+            return Err(Error::WrongHeader);
+        }
+        let map = unsafe { MmapOptions::new().len(mem::size_of::<Header>()).map(&fd).map_err(Error::Io)? };
+        let file = Self { fd, map };
+        if file.header().header != INDEX_HEADER {
+            return Err(Error::WrongHeader);
+        }
+        Ok(file)
+    }
+
+    #[inline]
+    fn header(&self) -> &Header {
+        unsafe { &*(self.map.as_ptr() as *const Header) }
+    }
+
+    /// Locks the table file shared unless a writer in [`LockMode::Shared`] has it open and returns whether it has
+    /// been locked.
+    ///
+    /// This must only be called while holding the lock of the readers, so that the header is not modified meanwhile.
+    fn lock(&self) -> Result<bool, Error> {
+        match FileExt::try_lock_shared(&self.fd) {
+            Ok(()) => Ok(true),
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock && self.header().is_shared_writer() => Ok(false),
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => Err(Error::TableLocked),
+            Err(err) => Err(Error::Io(err)),
+        }
+    }
+}
+
+/// A reader of a table that another process writes in [`LockMode::Shared`]
+///
+/// Every access happens via [`SharedReader::read`], which waits until the writer has published its changes via
+/// [`Table::flush`] and keeps the writer from modifying the table while the returned guard is alive. The writer
+/// increments a generation counter in the header of the table whenever it starts to modify it. If the generation
+/// has changed since the last read (e.g. because the table has been resized), the table is mapped and loaded again,
+/// otherwise the loaded table is reused.
+///
+/// If no writer has the table open, the reader takes a shared lock while reading, like [`Table::open_read_only`].
+/// If the table is open in another lock mode, [`Error::TableLocked`] is returned. Keys and values can not be kept
+/// beyond the guard, as the writer may change them afterwards.
+///
+/// ```
+/// use rust_persist::{LockMode, Table};
+///
+/// let mut writer = Table::options().lock(LockMode::Shared).create("example_reader.tbl").unwrap();
+/// writer.set("key".as_bytes(), "value1".as_bytes()).unwrap();
+/// writer.flush().unwrap();
+/// let mut reader = Table::options().open_reader("example_reader.tbl").unwrap();
+/// assert_eq!(reader.read().unwrap().get("key".as_bytes()), Some("value1".as_bytes()));
+/// writer.set("key".as_bytes(), "value2".as_bytes()).unwrap();
+/// writer.flush().unwrap();
+/// assert_eq!(reader.read().unwrap().get("key".as_bytes()), Some("value2".as_bytes()));
+/// ```
+pub struct SharedReader {
+    path: PathBuf,
+    options: TableOptions,
+    readers: File,
+    file: Option<TableFile>,
+    table: Option<(u64, Table)>,
+}
+
+impl SharedReader {
+    pub(crate) fn open(path: &Path, options: TableOptions) -> Result<Self, Error> {
+        let options = options.read_only(true).lock(LockMode::None);
+        options.validate()?;
+        let readers = open_readers(path)?;
+        Ok(Self { path: path.to_path_buf(), options, readers, file: None, table: None })
+    }
+
+    /// Returns the path of the table file
+    #[inline]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Waits until the writer has published its changes and returns a guard that gives access to the table.
+    ///
+    /// The writer cannot modify the table until the guard is dropped, so guards should be short-lived.
+    pub fn read(&mut self) -> Result<ReadGuard<'_>, Error> {
+        FileExt::lock_shared(&self.readers).map_err(Error::Io)?;
+        let locked = match self.load() {
+            Ok(locked) => locked,
+            Err(err) => {
+                FileExt::unlock(&self.readers).ok();
+                return Err(err);
+            }
+        };
+        match (&self.file, &self.table) {
+            (Some(file), Some((_, table))) => {
+                Ok(ReadGuard { table, fd: if locked { Some(&file.fd) } else { None }, readers: &self.readers })
+            }
+            _ => invariant_violated!("Shared reader has no table"),
+        }
+    }
+
+    /// Locks the table file if needed, reloads the table if its generation changed and returns whether the file has
+    /// been locked
+    fn load(&mut self) -> Result<bool, Error> {
+        if let Some(file) = &self.file {
+            let locked = file.lock()?;
+            if matches!(&self.table, Some((generation, _)) if *generation == file.header().generation()) {
+                return Ok(locked);
+            }
+            if locked {
+                FileExt::unlock(&file.fd).map_err(Error::Io)?;
+            }
+        }
+        // The file is opened again, as the writer might have replaced it (e.g. via `Table::rebuild`)
+        self.table = None;
+        self.file = None;
+        let file = TableFile::open(&self.path)?;
+        let locked = file.lock()?;
+        match self.options.clone().open(&self.path) {
+            Ok(table) => self.table = Some((file.header().generation(), table)),
+            Err(err) => {
+                if locked {
+                    FileExt::unlock(&file.fd).ok();
+                }
+                return Err(err);
+            }
+        }
+        self.file = Some(file);
+        Ok(locked)
+    }
+}
+
+/// Access to the table of a [`SharedReader`], see [`SharedReader::read`]
+///
+/// The guard gives shared access to the [`Table`] via [`Deref`] and lets the writer continue when it is dropped.
+pub struct ReadGuard<'a> {
+    table: &'a Table,
+    fd: Option<&'a File>,
+    readers: &'a File,
+}
+
+impl Deref for ReadGuard<'_> {
+    type Target = Table;
+
+    #[inline]
+    fn deref(&self) -> &Table {
+        self.table
+    }
+}
+
+impl Drop for ReadGuard<'_> {
+    fn drop(&mut self) {
+        if let Some(fd) = self.fd {
+            FileExt::unlock(fd).ok();
+        }
+        FileExt::unlock(self.readers).ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::INITIAL_INDEX_CAPACITY;
+
+    #[test]
+    fn test_shared_reader() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut reader = Table::options().open_reader(file.path()).unwrap();
+        assert!(matches!(reader.read(), Err(Error::WrongHeader)));
+        let mut writer = Table::options().lock(LockMode::Shared).create(file.path()).unwrap();
+        // Readers are only locked out by modifications, not by opening the table
+        assert!(can_lock(file.path(), true));
+        assert!(reader.read().unwrap().is_empty());
+        // A second writer fails instead of waiting for the readers
+        assert!(matches!(Table::options().lock(LockMode::Shared).open(file.path()), Err(Error::TableLocked)));
+        writer.set(&[0], &[0]).unwrap();
+        writer.flush().unwrap();
+        // Modifying values in place locks the readers out as well
+        writer.try_get_mut(&[0]).unwrap().unwrap()[0] = 2;
+        assert!(!can_lock(file.path(), true));
+        assert_eq!(writer.try_iter_mut().unwrap().count(), 1);
+        writer.flush().unwrap();
+        assert_eq!(reader.read().unwrap().get(&[0]), Some(&[2][..]));
+        let generation = writer.header.generation();
+        assert!(reader.read().unwrap().contains(&[0]));
+        // Unpublished changes are not visible, as the reader is locked out until the writer flushes
+        writer.set(&[1], &[1]).unwrap();
+        assert_eq!(writer.header.generation(), generation + 1);
+        assert!(!can_lock(file.path(), true));
+        writer.flush().unwrap();
+        assert!(can_lock(file.path(), true));
+        {
+            let guard = reader.read().unwrap();
+            assert_eq!(guard.get(&[1]), Some(&[1][..]));
+            // The writer cannot modify the table while a reader is active
+            assert!(!can_lock(file.path(), false));
+        }
+        // Resizing the table changes the generation, so the reader loads it again
+        for i in 0u32..INITIAL_INDEX_CAPACITY as u32 * 2 {
+            writer.set(&i.to_le_bytes(), &[0; 100]).unwrap();
+        }
+        writer.flush().unwrap();
+        let guard = reader.read().unwrap();
+        assert_eq!(guard.len(), writer.len());
+        assert_eq!(guard.size(), writer.size());
+        assert!(guard.is_valid());
+        drop(guard);
+        // Other writers are locked out as usual and keep the readers out
+        assert!(matches!(Table::open(file.path()), Err(Error::TableLocked)));
+        writer.close();
+        let other = Table::open(file.path()).unwrap();
+        assert!(matches!(reader.read(), Err(Error::TableLocked)));
+        other.close();
+        assert_eq!(reader.read().unwrap().len(), INITIAL_INDEX_CAPACITY * 2 + 2);
+        assert!(matches!(
+            Table::options().read_only(true).lock(LockMode::Shared).open(file.path()),
+            Err(Error::InvalidOptions(_))
+        ));
+    }
+
+    /// Returns whether the lock of the readers of the table could be taken shared or exclusively right now
+    fn can_lock(path: &Path, shared: bool) -> bool {
+        let fd = open_readers(path).unwrap();
+        let locked = if shared { FileExt::try_lock_shared(&fd) } else { FileExt::try_lock_exclusive(&fd) };
+        locked.is_ok()
+    }
+}
@@ -0,0 +1,50 @@
+use std::{ops::Deref, path::Path};
+
+use crate::{Error, Table, TableOptions};
+
+/// A table that has been opened read-only
+///
+/// This handle only gives shared access to the underlying [`Table`] via [`Deref`], so all methods that take `&self`
+/// (e.g. [`Table::get`] or [`Table::iter`]) are available, while methods that modify the table, including
+/// [`Table::get_mut`] and [`Table::each_mut`], are rejected at compile time.
+///
+/// ```
+/// use rust_persist::Table;
+///
+/// Table::create("example_ro.tbl").unwrap().set("key".as_bytes(), "value".as_bytes()).unwrap();
+/// let table = Table::open_read_only("example_ro.tbl").unwrap();
+/// assert_eq!(table.get("key".as_bytes()), Some("value".as_bytes()));
+/// ```
+///
+/// ```compile_fail
+/// use rust_persist::Table;
+///
+/// let mut table = Table::open_read_only("example_ro.tbl").unwrap();
+/// table.set("key".as_bytes(), "value".as_bytes());
+/// ```
+pub struct ReadOnlyTable {
+    inner: Table,
+}
+
+impl ReadOnlyTable {
+    /// Opens an existing table from the given path in read-only mode, see [`Table::open_read_only`].
+    #[inline]
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        TableOptions::default().read_only(true).open(path).map(|inner| Self { inner })
+    }
+
+    /// Explicitly closes the table.
+    #[inline]
+    pub fn close(self) {
+        // nothing to do, just drop self
+    }
+}
+
+impl Deref for ReadOnlyTable {
+    type Target = Table;
+
+    #[inline]
+    fn deref(&self) -> &Table {
+        &self.inner
+    }
+}
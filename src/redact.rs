@@ -0,0 +1,27 @@
+use std::borrow::Cow;
+
+use crate::Entry;
+
+/// Decides how an entry is written by the redacting export methods, e.g. [`Table::export_redacted_to`]
+///
+/// [`Table::export_redacted_to`]: crate::Table::export_redacted_to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Redaction {
+    /// Write the entry as it is
+    Keep,
+    /// Leave the entry out
+    Drop,
+    /// Write the entry with the given value instead of its own
+    Replace(Vec<u8>),
+}
+
+/// Applies the filter to the entry and returns the value to write, or `None` if the entry is left out
+pub(crate) fn redact<'a, F: FnMut(&Entry<'_>) -> Redaction>(
+    filter: &mut F, entry: &Entry<'a>,
+) -> Option<Cow<'a, [u8]>> {
+    match filter(entry) {
+        Redaction::Keep => Some(Cow::Borrowed(entry.value)),
+        Redaction::Drop => None,
+        Redaction::Replace(value) => Some(Cow::Owned(value)),
+    }
+}
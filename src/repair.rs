@@ -0,0 +1,258 @@
+use std::{cmp, collections::HashSet, ops::Deref, path::Path};
+
+use crate::{
+    checksum::CHECKSUM_SIZE,
+    mmap::{self, OpenFdResult},
+    table::check_key_hash,
+    Error, Table, TableOptions, FLAG_CHECKSUM,
+};
+
+/// Reason why an entry has been discarded by [`Table::repair`] or skipped by [`Table::open_degraded`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiscardReason {
+    /// The data of the entry is not within the data section or smaller than its key
+    OutOfBounds,
+    /// The key of the entry does not hash to the hash stored in the index
+    HashMismatch,
+    /// The data of the entry overlaps with the data of another entry that has been kept
+    Overlapping,
+    /// Another entry with the same key has been kept
+    Duplicate,
+}
+
+/// An index entry that has been discarded by [`Table::repair`] or skipped by [`Table::open_degraded`]
+#[derive(Debug, Clone)]
+pub struct DiscardedEntry {
+    /// Position of the data relative to the start of the data section
+    pub position: u64,
+    /// Size of the data
+    pub size: u64,
+    /// Why the entry has been discarded
+    pub reason: DiscardReason,
+}
+
+/// Result of [`Table::repair`], also attached to tables opened via [`Table::open_degraded`]
+#[derive(Debug, Clone, Default)]
+pub struct RepairReport {
+    /// Number of entries that have been kept
+    pub kept: usize,
+    /// All entries that have been discarded
+    pub discarded: Vec<DiscardedEntry>,
+}
+
+impl Table {
+    /// Opens a damaged table and salvages all entries that are still intact.
+    ///
+    /// Every index entry whose data lies within the data section and whose key hashes to the stored hash is kept.
+    /// Of overlapping entries and entries with duplicate keys, only the one at the lowest position is kept. All other
+    /// entries are removed, the index is rebuilt and the table is opened. The returned report lists the discarded
+    /// entries.
+    ///
+    /// The header of the table must be intact, otherwise [`Error::WrongHeader`] or [`Error::Corrupt`] is returned.
+    pub fn repair<P: AsRef<Path>>(path: P) -> Result<(Self, RepairReport), Error> {
+        let path = path.as_ref();
+        let options = TableOptions::default();
+        let mut opened_fd = mmap::open_fd(path, false, &options)?;
+        let report = salvage(&mut opened_fd);
+        let mut tbl = Self::from_opened(opened_fd, false, options)?;
+        tbl.attach_path(path, false)?;
+        Ok((tbl, report))
+    }
+
+    /// Opens a damaged table read-only, serving only the entries that are still intact.
+    ///
+    /// The entries are selected like in [`Table::repair`], but the table file is not modified: the index is only
+    /// rebuilt in the private memory map, so services can keep serving the intact entries while the table is
+    /// repaired or restored elsewhere. Like [`ReadOnlyTable`](crate::ReadOnlyTable), the returned handle rejects all
+    /// modifications at compile time. The report attached to it lists the entries that are not available.
+    ///
+    /// Like with [`Table::open_read_only`], only a shared lock is taken and neither a journal nor a write-ahead log
+    /// is replayed. The header of the table must be intact, otherwise [`Error::WrongHeader`] or [`Error::Corrupt`]
+    /// is returned.
+    ///
+    /// ```
+    /// use rust_persist::{Error, Table};
+    ///
+    /// # Table::create("example_degraded.tbl").unwrap().set("key".as_bytes(), "value".as_bytes()).unwrap();
+    /// let table = match Table::open("example_degraded.tbl") {
+    ///     Ok(table) => table,
+    ///     Err(Error::Corrupt(_)) => {
+    ///         let table = Table::open_degraded("example_degraded.tbl").unwrap();
+    ///         eprintln!("{} damaged entries are not available", table.report().discarded.len());
+    ///         return;
+    ///     }
+    ///     Err(err) => panic!("{}", err),
+    /// };
+    /// assert_eq!(table.get("key".as_bytes()), Some("value".as_bytes()));
+    /// ```
+    pub fn open_degraded<P: AsRef<Path>>(path: P) -> Result<DegradedTable, Error> {
+        let path = path.as_ref();
+        let options = TableOptions::default().read_only(true);
+        let mut opened_fd = mmap::open_fd(path, false, &options)?;
+        let report = salvage(&mut opened_fd);
+        let mut inner = Self::from_opened(opened_fd, false, options)?;
+        inner.attach_path(path, false)?;
+        Ok(DegradedTable { inner, report })
+    }
+}
+
+/// Removes all damaged entries from the opened index and marks the index to be rebuilt
+fn salvage(opened_fd: &mut OpenFdResult) -> RepairReport {
+    opened_fd.fix_endianness();
+    let data_start = opened_fd.data_start as u64;
+    let data = &*opened_fd.data;
+    let mut report = RepairReport::default();
+    let mut candidates = Vec::new();
+    for (pos, entry) in opened_fd.index_entries.iter().enumerate() {
+        if !entry.is_used() {
+            continue;
+        }
+        let block = &entry.data;
+        let discard =
+            |reason| DiscardedEntry { position: block.position().wrapping_sub(data_start), size: block.size(), reason };
+        let checksum_size = if block.flags & FLAG_CHECKSUM > 0 { CHECKSUM_SIZE } else { 0 };
+        let in_bounds = block.position() >= data_start
+            && block.key_size as u64 + checksum_size as u64 <= block.size()
+            && matches!(block.position().checked_add(cmp::max(block.size(), 1) as u64), Some(end) if end <= data_start + data.len() as u64);
+        if !in_bounds {
+            report.discarded.push(discard(DiscardReason::OutOfBounds));
+            continue;
+        }
+        let key_start = (block.position() - data_start) as usize;
+        let key = &data[key_start..key_start + block.key_size as usize];
+        if !check_key_hash(opened_fd.header, key, entry.hash) {
+            report.discarded.push(discard(DiscardReason::HashMismatch));
+            continue;
+        }
+        candidates.push((block.position(), pos));
+    }
+    candidates.sort_unstable();
+    let mut keep = vec![false; opened_fd.index_entries.len()];
+    let mut keys = HashSet::new();
+    let mut last_end = data_start;
+    for (position, pos) in candidates {
+        let block = &opened_fd.index_entries[pos].data;
+        let reason = if position < last_end {
+            DiscardReason::Overlapping
+        } else if !keys.insert(&data[(position - data_start) as usize..][..block.key_size as usize]) {
+            DiscardReason::Duplicate
+        } else {
+            keep[pos] = true;
+            last_end = position + cmp::max(block.size(), 1) as u64;
+            report.kept += 1;
+            continue;
+        };
+        report.discarded.push(DiscardedEntry { position: position - data_start, size: block.size(), reason });
+    }
+    for (entry, keep) in opened_fd.index_entries.iter_mut().zip(keep) {
+        if !keep && entry.is_used() {
+            entry.clear();
+        }
+    }
+    // Make sure that the index is rebuilt from the remaining entries
+    opened_fd.header.set_dirty(true);
+    report
+}
+
+/// A damaged table that has been opened read-only via [`Table::open_degraded`]
+///
+/// Like [`ReadOnlyTable`](crate::ReadOnlyTable), this handle only gives shared access to the underlying [`Table`]
+/// via [`Deref`]. It contains only the intact entries, the damaged ones are listed in [`DegradedTable::report`].
+pub struct DegradedTable {
+    inner: Table,
+    report: RepairReport,
+}
+
+impl DegradedTable {
+    /// Returns the report listing the entries that have been skipped because they are damaged
+    #[inline]
+    pub fn report(&self) -> &RepairReport {
+        &self.report
+    }
+
+    /// Explicitly closes the table.
+    #[inline]
+    pub fn close(self) {
+        // nothing to do, just drop self
+    }
+}
+
+impl Deref for DegradedTable {
+    type Target = Table;
+
+    #[inline]
+    fn deref(&self) -> &Table {
+        &self.inner
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, mem};
+
+    use super::*;
+    use crate::{index::IndexEntry, table::Header};
+
+    #[test]
+    fn test_repair() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::create(file.path()).unwrap();
+        for i in 0u16..10 {
+            tbl.set(&i.to_ne_bytes(), &i.to_be_bytes()).unwrap();
+        }
+        let entries: Vec<_> = (0..tbl.index.capacity()).filter(|&i| tbl.index.get_entries()[i].is_used()).collect();
+        tbl.close();
+        let mut bytes = fs::read(file.path()).unwrap();
+        let offset = |i: usize| mem::size_of::<Header>() + entries[i] * mem::size_of::<IndexEntry>();
+        // Entry pointing behind the end of the data section
+        bytes[offset(0) + 8..offset(0) + 16].copy_from_slice(&u64::MAX.to_ne_bytes());
+        // Entry with a different hash
+        bytes[offset(1)] ^= 1;
+        fs::write(file.path(), &bytes).unwrap();
+        assert!(matches!(Table::open(file.path()), Err(Error::Corrupt(_))));
+        let (tbl, report) = Table::repair(file.path()).unwrap();
+        assert_eq!(report.kept, 8);
+        let reasons: Vec<_> = report.discarded.iter().map(|d| d.reason).collect();
+        assert_eq!(reasons, vec![DiscardReason::OutOfBounds, DiscardReason::HashMismatch]);
+        assert_eq!(tbl.len(), 8);
+        assert!(tbl.is_valid());
+        tbl.close();
+        let tbl = Table::open(file.path()).unwrap();
+        assert_eq!(tbl.len(), 8);
+        for entry in tbl.iter() {
+            assert_eq!(entry.value, u16::from_ne_bytes([entry.key[0], entry.key[1]]).to_be_bytes());
+        }
+    }
+
+    #[test]
+    fn test_open_degraded() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::create(file.path()).unwrap();
+        for i in 0u16..10 {
+            tbl.set(&i.to_ne_bytes(), &i.to_be_bytes()).unwrap();
+        }
+        let entries: Vec<_> = (0..tbl.index.capacity()).filter(|&i| tbl.index.get_entries()[i].is_used()).collect();
+        tbl.close();
+        let mut bytes = fs::read(file.path()).unwrap();
+        let offset = mem::size_of::<Header>() + entries[0] * mem::size_of::<IndexEntry>();
+        bytes[offset] ^= 1;
+        fs::write(file.path(), &bytes).unwrap();
+        let tbl = Table::open_degraded(file.path()).unwrap();
+        assert_eq!(tbl.report().kept, 9);
+        assert_eq!(tbl.report().discarded[0].reason, DiscardReason::HashMismatch);
+        assert_eq!(tbl.len(), 9);
+        assert!(tbl.is_read_only());
+        for entry in tbl.iter() {
+            assert_eq!(entry.value, u16::from_ne_bytes([entry.key[0], entry.key[1]]).to_be_bytes());
+        }
+        // Other readers can use the table at the same time, but it cannot be opened for writing
+        assert_eq!(Table::open_degraded(file.path()).unwrap().len(), 9);
+        assert!(matches!(Table::open(file.path()), Err(Error::TableLocked)));
+        tbl.close();
+        // The table file is left untouched
+        assert_eq!(fs::read(file.path()).unwrap(), bytes);
+        assert!(matches!(Table::open(file.path()), Err(Error::Corrupt(_))));
+        let tbl = Table::open_degraded(file.path()).unwrap();
+        assert_eq!(tbl.report().discarded.len(), 1);
+    }
+}
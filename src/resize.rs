@@ -1,81 +1,440 @@
-use std::mem;
+use std::{cmp, mem, ops::RangeBounds, time::Instant};
 
 use crate::{
-    index::Index,
-    memmngr::MemoryManagment,
+    index::{is_namespaced, Hash, LocateResult, MAX_POSITION},
+    memmngr::{MemoryManagment, Size},
     mmap::{self, mmap_as_ref},
-    table::total_size,
-    Error, Table, INITIAL_INDEX_CAPACITY, MAX_USAGE, MIN_USAGE,
+    table::{hash_key, match_key, total_size},
+    Error, SlowOpKind, Table, FLAG_PINNED,
 };
 
+/// Returns the smallest capacity that is a power-of-two multiple of `capacity` and can hold `entries` entries
+pub(crate) fn index_capacity_for(mut capacity: usize, entries: usize, max_usage: f64) -> usize {
+    while entries > (capacity as f64 * max_usage) as usize {
+        capacity *= 2;
+    }
+    capacity
+}
+
 impl Table {
+    /// Sets the end of the memory management to the end of the mapped data section, which must not evict blocks
+    #[inline]
+    fn sync_mem_end(&mut self) -> Result<(), Error> {
+        let evicted = self.mem.set_end(self.data_start + self.data.len() as u64);
+        invariant!(evicted.is_empty(), "No blocks after the data end");
+        Ok(())
+    }
+
     pub(crate) fn resize_fd(&mut self, index_capacity: usize, data_size: u64) -> Result<(), Error> {
-        self.flush()?;
-        self.fd.set_len(total_size(index_capacity, data_size)).map_err(Error::Io)?;
-        self.mmap = mmap::map_fd(&self.fd)?;
-        let (header, entries, data_start, data) = unsafe { mmap_as_ref(&mut self.mmap, index_capacity) };
+        let fd = self.fd.as_ref().ok_or(Error::ReadOnly)?;
+        let size = total_size(index_capacity, data_size)?;
+        // Not using flush() here as the write-ahead log must be kept for the current change
+        mmap::check_map_size(&self.options, size)?;
+        self.mmap.flush().map_err(Error::Io)?;
+        mmap::set_len(fd, size)?;
+        let old_len = self.mmap.len();
+        self.mmap = mmap::map_fd(fd)?;
+        if self.mmap.len() > old_len {
+            mmap::fill_grown(&mut self.mmap[old_len..], self.options.grow_fill);
+        }
+        let (header, entries, data_start, data) = unsafe { mmap_as_ref(&mut self.mmap, index_capacity)? };
         self.header = header;
         self.data = data;
         self.data_start = data_start as u64;
-        self.index = Index::new(entries, self.index.len());
-        self.min_entries = (index_capacity as f64 * MIN_USAGE) as usize;
-        self.max_entries = (index_capacity as f64 * MAX_USAGE) as usize;
+        self.index.remap(entries);
+        self.min_entries = (index_capacity as f64 * self.options.min_usage) as usize;
+        self.max_entries = (index_capacity as f64 * self.options.max_usage) as usize;
         Ok(())
     }
 
-    pub(crate) fn extend_data(&mut self, size: u32) -> Result<(), Error> {
+    pub(crate) fn extend_data(&mut self, mut size: Size) -> Result<(), Error> {
         debug_assert!(self.is_valid(), "Invalid before extend data");
-        self.resize_fd(self.index.capacity(), (self.data.len() + size as usize) as u64)?;
-        assert!(self.mem.set_end(self.data_start + self.data.len() as u64).is_empty());
+        let started = Instant::now();
+        if self.bulk {
+            // The data section is doubled, so that a bulk import resizes the file only a few times
+            size = cmp::max(size, self.data.len() as u64);
+        }
+        let data_size = (self.data.len() as u64).saturating_add(size);
+        if self.data_start.saturating_add(data_size) > MAX_POSITION {
+            // block positions are limited to 48 bits
+            return Err(Error::TooLarge);
+        }
+        self.resize_fd(self.index.capacity(), data_size)?;
+        self.sync_mem_end()?;
         debug_assert!(self.is_valid(), "Invalid after extend data");
+        self.record_slow_op(SlowOpKind::GrowData, started);
         Ok(())
     }
 
+    /// Makes sure that `size` bytes can be allocated without extending the data section.
+    pub(crate) fn reserve_data(&mut self, size: u64) -> Result<(), Error> {
+        let free_tail = self.mem.free_tail();
+        if free_tail >= size {
+            return Ok(());
+        }
+        self.extend_data(size - free_tail)
+    }
+
     /// Forces the defragmentation of the data section.
     ///
     /// This method will move all data chunks to the front and remove all gaps between them.
+    /// Entries that have been pinned via [`Table::pin_front`] are placed before all other entries.
     /// After this, the free space at the end will be truncated to save space.
     ///
     /// This method is automatically called when the used space of the data section is less than 50%
     pub fn defragment(&mut self) -> Result<(), Error> {
+        self.check_writable()?;
         debug_assert!(self.is_valid(), "Invalid before shrink data");
+        let started = Instant::now();
+        self.shred_freed();
+        if self.options.guard_bytes {
+            self.remove_guards()?;
+        }
         let mut old_mem = MemoryManagment::new(self.mem.start(), self.mem.end());
         mem::swap(&mut self.mem, &mut old_mem);
+        let mut pinned = vec![];
+        let mut blocks = vec![];
         for old_entry in old_mem.take_used() {
-            let new_pos =
-                self.mem.allocate(old_entry.size, old_entry.hash).expect("Defragmented bigger than fragmented");
+            match self.index.get_block(old_entry.hash, old_entry.start) {
+                Some(entry) if entry.flags & FLAG_PINNED != 0 => {
+                    pinned.push((self.get_data(old_entry.start, old_entry.size).to_vec(), old_entry))
+                }
+                _ => blocks.push(old_entry),
+            }
+        }
+        let mut pinned_pos = Vec::with_capacity(pinned.len());
+        for (_, block) in &pinned {
+            match self.mem.allocate(block.size, block.hash) {
+                Some(pos) => pinned_pos.push(pos),
+                None => invariant_violated!("Defragmented bigger than fragmented"),
+            }
+        }
+        let mut new_pos = Vec::with_capacity(blocks.len());
+        for block in &blocks {
+            match self.mem.allocate(block.size, block.hash) {
+                Some(pos) => new_pos.push(pos),
+                None => invariant_violated!("Defragmented bigger than fragmented"),
+            }
+        }
+        // Blocks moving to the left have to be moved first and in ascending order, blocks moving to the right
+        // (to make room for pinned blocks) afterwards in descending order to avoid overwriting unmoved data.
+        let split = blocks.iter().zip(&new_pos).position(|(b, &p)| p <= b.start).unwrap_or(blocks.len());
+        for i in (split..blocks.len()).chain((0..split).rev()) {
+            let old_entry = &blocks[i];
             safemem::copy_over(
                 self.data,
                 (old_entry.start - self.data_start) as usize,
-                (new_pos - self.data_start) as usize,
+                (new_pos[i] - self.data_start) as usize,
                 old_entry.size as usize,
             );
-            self.index.update_block_position(old_entry.hash, old_entry.start, new_pos);
+            self.index.update_block_position(old_entry.hash, old_entry.start, new_pos[i]);
+        }
+        for ((data, old_entry), pos) in pinned.into_iter().zip(pinned_pos) {
+            self.get_data_mut(pos, old_entry.size).copy_from_slice(&data);
+            self.index.update_block_position(old_entry.hash, old_entry.start, pos);
+        }
+        if self.options.shred {
+            // The vacated space is written to disk as zeros before the file is truncated
+            self.shred_range(self.mem.start(), self.mem.end());
         }
         self.resize_fd(self.index.capacity(), self.mem.used_size())?;
-        assert!(self.mem.set_end(self.data_start + self.data.len() as u64).is_empty());
+        self.sync_mem_end()?;
         debug_assert!(self.is_valid(), "Invalid after shrink data");
+        self.record_slow_op(SlowOpKind::Defragment, started);
         Ok(())
     }
 
+    /// Starts a bulk phase, e.g. an import of many entries.
+    ///
+    /// Until [`Table::end_bulk`] is called, the index and the data section are never shrunk and the data section is
+    /// never defragmented automatically, and the data section is at least doubled whenever it has to grow. This
+    /// avoids repeated resizes and defragmentations while the table changes heavily.
+    ///
+    /// ```
+    /// use rust_persist::Table;
+    ///
+    /// let mut table = Table::create("example_bulk.tbl").unwrap();
+    /// table.begin_bulk();
+    /// for i in 0u32..1000 {
+    ///     table.set(&i.to_be_bytes(), &[0; 100]).unwrap();
+    /// }
+    /// table.end_bulk().unwrap();
+    /// ```
+    #[inline]
+    pub fn begin_bulk(&mut self) {
+        self.bulk = true;
+    }
+
+    /// Ends a bulk phase started via [`Table::begin_bulk`] and compacts the table once.
+    ///
+    /// The index is shrunk as far as the number of entries allows and the data section is defragmented if it
+    /// contains any free space, which also truncates the space reserved during the bulk phase.
+    pub fn end_bulk(&mut self) -> Result<(), Error> {
+        self.check_writable()?;
+        self.bulk = false;
+        while self.maybe_shrink_index()? {}
+        if self.mem.used_size() < self.data.len() as u64 {
+            self.defragment()?;
+        }
+        Ok(())
+    }
+
+    /// Performs a bounded part of the defragmentation and returns whether the data section is fully defragmented.
+    ///
+    /// Entries are moved to the front in the order of their position, one at a time, until at least `max_bytes` have
+    /// been moved. Once no gaps are left, the free space at the end is truncated and `true` is returned. In contrast
+    /// to [`Table::defragment`], pinned entries are not moved in front of the others.
+    ///
+    /// Calling this method repeatedly until it returns `true` spreads the defragmentation over several short steps,
+    /// e.g. to release a lock around a shared table in between, so that readers are not blocked for the whole
+    /// defragmentation. Every step leaves the table consistent and other operations can be performed between steps.
+    ///
+    /// ```
+    /// use rust_persist::Table;
+    ///
+    /// let mut table = Table::create("example_defragment_step.tbl").unwrap();
+    /// table.set("key1".as_bytes(), &[0; 1000]).unwrap();
+    /// table.set("key2".as_bytes(), &[0; 1000]).unwrap();
+    /// table.delete("key1".as_bytes()).unwrap();
+    /// while !table.defragment_step(64 * 1024).unwrap() {
+    ///     assert!(table.contains("key2".as_bytes()));
+    /// }
+    /// ```
+    pub fn defragment_step(&mut self, max_bytes: u64) -> Result<bool, Error> {
+        self.check_writable()?;
+        if self.options.guard_bytes {
+            self.remove_guards()?;
+        }
+        let started = Instant::now();
+        let mut end = self.mem.start();
+        let mut moved = 0;
+        let blocks: Vec<_> = self.mem.get_used().iter().cloned().collect();
+        for block in blocks {
+            if block.start != end {
+                if moved >= max_bytes {
+                    self.record_slow_op(SlowOpKind::Defragment, started);
+                    return Ok(false);
+                }
+                if let Some(entry) = self.index.get_block(block.hash, block.start) {
+                    // The freed block is directly followed by the gap in front of it, so it is moved to the gap
+                    self.relocate_block(block.hash, entry.position(), entry.size());
+                }
+                moved += block.size;
+            }
+            end += block.size;
+        }
+        if self.data_start + self.data.len() as u64 > end {
+            if self.options.shred {
+                self.shred_range(end, self.mem.end());
+            }
+            self.resize_fd(self.index.capacity(), self.mem.used_size())?;
+            self.sync_mem_end()?;
+        }
+        debug_assert!(self.is_valid(), "Invalid after defragment step");
+        self.record_slow_op(SlowOpKind::Defragment, started);
+        Ok(true)
+    }
+
+    /// Marks the entry with the given key to be placed at the front of the data section.
+    ///
+    /// Pinned entries are moved in front of all other entries on the next defragmentation, so that frequently used
+    /// entries are clustered in few pages. The mark is stored as [`FLAG_PINNED`] in the entry flags and is lost when
+    /// the value is overwritten.
+    ///
+    /// Returns whether an entry with the given key exists in the table. On read-only tables, [`Error::ReadOnly`] is
+    /// returned.
+    pub fn pin_front(&mut self, key: &[u8]) -> Result<bool, Error> {
+        self.set_pinned(key, true)
+    }
+
+    /// Removes the mark set by [`Table::pin_front`] from the entry with the given key.
+    ///
+    /// Returns whether an entry with the given key exists in the table. On read-only tables, [`Error::ReadOnly`] is
+    /// returned.
+    pub fn unpin(&mut self, key: &[u8]) -> Result<bool, Error> {
+        self.set_pinned(key, false)
+    }
+
+    fn set_pinned(&mut self, key: &[u8], pinned: bool) -> Result<bool, Error> {
+        self.check_writable()?;
+        let hash = hash_key(key);
+        let slot = match self.index.locate(hash, |e| match_key(e, self.data, self.data_start, key)) {
+            LocateResult::Found(slot) => slot,
+            _ => return Ok(false),
+        };
+        let data = self.index.get_entries()[slot].data;
+        if is_namespaced(&data) {
+            return Ok(false);
+        }
+        // The flags are changed via the index, so that it keeps track of the number of pinned entries
+        self.index.set_slot_flags(slot, if pinned { data.flags | FLAG_PINNED } else { data.flags & !FLAG_PINNED });
+        Ok(true)
+    }
+
+    /// Moves the entry with the given key to the first gap in the data section where it fits.
+    ///
+    /// Returns whether an entry with the given key exists in the table. If no suitable gap exists in front of the
+    /// entry, it stays at its current position. On read-only tables, [`Error::ReadOnly`] is returned.
+    ///
+    /// This method can be used to implement custom placement policies, e.g. to keep frequently accessed entries
+    /// close together at the front of the data section.
+    pub fn relocate(&mut self, key: &[u8]) -> Result<bool, Error> {
+        self.check_writable()?;
+        let hash = hash_key(key);
+        let entry = match self.index.index_get(hash, |e| match_key(e, self.data, self.data_start, key)) {
+            Some(entry) if !is_namespaced(&entry) => entry,
+            _ => return Ok(false),
+        };
+        self.relocate_block(hash, entry.position(), entry.size());
+        Ok(true)
+    }
+
+    /// Moves all entries stored within the given range of the data section to the first gaps where they fit.
+    ///
+    /// The range is given as byte offsets relative to the start of the data section.
+    /// Entries are processed in the order of their position. Returns the number of entries that have been moved.
+    /// On read-only tables, [`Error::ReadOnly`] is returned.
+    ///
+    /// See [`Table::relocate`] for more info.
+    pub fn relocate_range<R: RangeBounds<u64>>(&mut self, range: R) -> Result<usize, Error> {
+        self.check_writable()?;
+        let data_start = self.data_start;
+        let blocks: Vec<_> =
+            self.mem.get_used().iter().filter(|b| range.contains(&(b.start - data_start))).cloned().collect();
+        let mut moved = 0;
+        for block in blocks {
+            if let Some(entry) = self.index.get_block(block.hash, block.start) {
+                if self.relocate_block(block.hash, entry.position(), entry.size()) {
+                    moved += 1;
+                }
+            }
+        }
+        debug_assert!(self.is_valid(), "Invalid after relocate");
+        Ok(moved)
+    }
+
+    fn relocate_block(&mut self, hash: Hash, old_pos: u64, size: Size) -> bool {
+        self.shred_freed();
+        if self.options.guard_bytes {
+            self.remove_guard(old_pos);
+        }
+        self.mem.free(old_pos);
+        let new_pos = match self.mem.allocate_lowest(size, hash) {
+            Some(pos) => pos,
+            None => {
+                self.mem.check_invariant(false, "Freed block must fit again");
+                return false;
+            }
+        };
+        if new_pos == old_pos {
+            return false;
+        }
+        safemem::copy_over(
+            self.data,
+            (old_pos - self.data_start) as usize,
+            (new_pos - self.data_start) as usize,
+            size as usize,
+        );
+        self.index.update_block_position(hash, old_pos, new_pos);
+        if self.options.shred {
+            self.shred_range(old_pos, old_pos + size);
+        }
+        true
+    }
+
     #[inline]
     pub(crate) fn maybe_shrink_data(&mut self) -> Result<(), Error> {
-        if self.mem.used_size() > self.data.len() as u64 / 2 || self.data.len() <= 4 * 1024 {
+        let min_size = cmp::max(self.options.data_size, 4 * 1024);
+        if self.bulk || self.mem.used_size() > self.data.len() as u64 / 2 || self.data.len() as u64 <= min_size {
             return Ok(());
         }
         self.defragment()
     }
 
     pub(crate) fn maybe_extend_index(&mut self) -> Result<(), Error> {
-        if self.index.len() <= self.max_entries {
+        if self.index.len() <= self.max_entries && !self.displacement_too_high() {
+            return Ok(());
+        }
+        self.extend_index(self.index.capacity() * 2)
+    }
+
+    /// Returns whether the index should grow because its entries are displaced more than the target on average,
+    /// see [`TableOptions::target_displacement`]
+    #[inline]
+    fn displacement_too_high(&self) -> bool {
+        // The grown index must not be used less than the minimum usage, otherwise it would be shrunk again
+        match self.options.target_displacement {
+            Some(target) => self.index.avg_displacement() > target && self.index.len() >= 2 * self.min_entries,
+            None => false,
+        }
+    }
+
+    /// Returns whether the index should shrink because its entries would still be displaced far less than the target
+    /// on average after shrinking, see [`TableOptions::target_displacement`]
+    #[inline]
+    fn displacement_too_low(&self) -> bool {
+        let target = match self.options.target_displacement {
+            Some(target) => target,
+            None => return false,
+        };
+        // The shrunk index must not be used more than the maximum usage, otherwise it would be grown again
+        if 2 * self.index.len() > self.max_entries {
+            return false;
+        }
+        // With evenly distributed hashes, the average displacement is `u / 2(1-u)` at usage `u`. Scaling the current
+        // displacement by the factor of doubling the usage keeps any skew of the hashes in the estimate.
+        let usage = self.index.len() as f64 / self.index.capacity() as f64;
+        let expected = self.index.avg_displacement() * 2.0 * (1.0 - usage) / (1.0 - 2.0 * usage);
+        expected < target / 2.0
+    }
+
+    /// Makes sure that `additional` more entries can be inserted without extending the index.
+    pub(crate) fn reserve_index(&mut self, additional: usize) -> Result<(), Error> {
+        let index_capacity_new =
+            index_capacity_for(self.index.capacity(), self.index.len() + additional, self.options.max_usage);
+        if index_capacity_new == self.index.capacity() {
             return Ok(());
         }
+        self.extend_index(index_capacity_new)
+    }
+
+    /// Doubles the index capacity (or more) and moves the data blocks that are in the way.
+    ///
+    /// The resize is crash-safe: the original capacity is stored in the header while resizing, so that an interrupted
+    /// resize can be rolled back or completed when opening the table.
+    fn extend_index(&mut self, index_capacity_new: usize) -> Result<(), Error> {
+        debug_assert!(index_capacity_new > self.index.capacity() && index_capacity_new.count_ones() == 1);
+        if index_capacity_new > u32::MAX as usize {
+            return Err(Error::TooLarge);
+        }
         debug_assert!(self.is_valid(), "Invalid before extend index");
+        let started = Instant::now();
+        let index_capacity_old = self.index.capacity();
+        self.prepare_index_growth(index_capacity_new)?;
+        self.switch_index_capacity(index_capacity_new)?;
+        self.index.grow_from(index_capacity_old);
+        self.header.set_dirty(false);
+        self.header.set_resize_origin(None);
+        debug_assert!(self.is_valid(), "Invalid after extend index");
+        self.record_slow_op(SlowOpKind::GrowIndex, started);
+        Ok(())
+    }
+
+    /// Moves all data blocks out of the space needed by the grown index and clears that space.
+    ///
+    /// Until the new capacity is stored, the table stays consistent with the old capacity.
+    fn prepare_index_growth(&mut self, index_capacity_new: usize) -> Result<(), Error> {
+        let data_start_new = total_size(index_capacity_new, 0)?;
+        // The evicted blocks might need to be moved to the end, so make sure the file can grow before modifying it
+        let size_max = data_start_new + self.data.len() as u64 + (data_start_new - self.data_start);
+        mmap::check_map_size(&self.options, size_max)?;
+        if let Some(fd) = &self.fd {
+            mmap::check_space(fd, size_max)?;
+        }
+        self.header.set_resize_origin(Some(self.index.capacity() as u32));
         self.header.set_dirty(true);
-        let index_capacity_new = self.index.capacity() * 2;
-        let data_start_new = total_size(index_capacity_new, 0);
         if data_start_new > self.mem.end() {
-            self.extend_data((data_start_new - self.mem.end()) as u32)?;
+            self.extend_data(data_start_new - self.mem.end())?;
         }
         let evicted = self.mem.set_start(data_start_new);
         // important: begin with last evicted block to avoid overwriting its second half with the first entry
@@ -84,8 +443,11 @@ impl Table {
                 Some(pos) => pos,
                 None => {
                     self.resize_fd(self.index.capacity(), (self.data.len() + old_entry.size as usize) as u64)?;
-                    assert!(self.mem.set_end(self.data_start + self.data.len() as u64).is_empty());
-                    self.mem.allocate(old_entry.size, old_entry.hash).expect("Not big enough after extending")
+                    self.sync_mem_end()?;
+                    match self.mem.allocate(old_entry.size, old_entry.hash) {
+                        Some(pos) => pos,
+                        None => invariant_violated!("Not big enough after extending"),
+                    }
                 }
             };
             safemem::copy_over(
@@ -97,33 +459,46 @@ impl Table {
             self.index.update_block_position(old_entry.hash, old_entry.start, new_pos);
         }
         debug_assert!(self.is_valid(), "Invalid middle extend index");
+        // The new index entries must be empty before the new capacity is stored
+        let cleared = (data_start_new - self.data_start) as usize;
+        self.data[..cleared].fill(0);
+        Ok(())
+    }
+
+    /// Stores the new index capacity and maps the file accordingly.
+    ///
+    /// From here on, the table is consistent with the new capacity, the entries just need to be rehashed.
+    fn switch_index_capacity(&mut self, index_capacity_new: usize) -> Result<(), Error> {
         self.header.index_capacity = index_capacity_new as u32;
         let data_size_new = self.mem.end() - self.mem.start();
         self.resize_fd(index_capacity_new, data_size_new)?;
-        assert!(self.mem.set_end(self.data_start + self.data.len() as u64).is_empty());
-        self.index.grow_from_half();
-        self.header.set_dirty(false);
-        debug_assert!(self.is_valid(), "Invalid after extend index");
+        self.sync_mem_end()?;
         Ok(())
     }
 
     pub(crate) fn maybe_shrink_index(&mut self) -> Result<bool, Error> {
-        if self.index.len() >= self.min_entries || self.index.capacity() <= INITIAL_INDEX_CAPACITY {
+        if self.bulk
+            || (self.index.len() >= self.min_entries && !self.displacement_too_low())
+            || self.index.capacity() <= self.options.index_capacity
+        {
             return Ok(false);
         }
         debug_assert!(self.is_valid(), "Invalid before shrink index");
+        let started = Instant::now();
         self.header.set_dirty(true);
         let index_capacity_new = self.index.capacity() / 2;
-        let data_start_new = total_size(index_capacity_new, 0);
-        self.index.shrink_to_half();
+        let data_start_new = total_size(index_capacity_new, 0)?;
+        invariant!(self.index.len() <= index_capacity_new, "Index must fit into half of its capacity");
+        self.index.shrink_to_half()?;
         debug_assert!(self.is_valid(), "Invalid middle shrink index");
         self.header.index_capacity = index_capacity_new as u32;
-        assert!(self.mem.set_start(data_start_new).is_empty());
+        invariant!(self.mem.set_start(data_start_new).is_empty(), "No blocks before the data start");
         let data_size_new = self.mem.end() - self.mem.start();
         self.resize_fd(index_capacity_new, data_size_new)?;
-        assert_eq!(self.data_start, data_start_new);
+        invariant!(self.data_start == data_start_new, "Data must start after the shrunk index");
         self.header.set_dirty(false);
         debug_assert!(self.is_valid(), "Invalid after shrink index");
+        self.record_slow_op(SlowOpKind::ShrinkIndex, started);
         Ok(true)
     }
 }
@@ -131,6 +506,7 @@ impl Table {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{LockMode, INITIAL_INDEX_CAPACITY};
 
     #[test]
     fn extend_data() {
@@ -193,6 +569,127 @@ mod tests {
         assert!(tbl.is_valid());
     }
 
+    #[test]
+    fn relocate() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::create(file.path()).unwrap();
+        let data = [0; 100];
+        for i in 0u16..10 {
+            tbl.set(&i.to_ne_bytes(), &data).unwrap();
+        }
+        tbl.delete(&0u16.to_ne_bytes()).unwrap();
+        tbl.delete(&1u16.to_ne_bytes()).unwrap();
+        let before = tbl.get(&9u16.to_ne_bytes()).unwrap().as_ptr();
+        assert!(tbl.relocate(&9u16.to_ne_bytes()).unwrap());
+        assert!(tbl.get(&9u16.to_ne_bytes()).unwrap().as_ptr() < before);
+        assert!(!tbl.relocate(&100u16.to_ne_bytes()).unwrap());
+        assert!(tbl.is_valid());
+        assert_eq!(tbl.relocate_range(..).unwrap(), 7);
+        assert!(tbl.is_valid());
+        assert_eq!(tbl.relocate_range(..).unwrap(), 0);
+        for i in 2u16..10 {
+            assert_eq!(tbl.get(&i.to_ne_bytes()), Some(&data as &[u8]));
+        }
+        tbl.close();
+        let mut tbl = Table::options().read_only(true).open(file.path()).unwrap();
+        assert!(matches!(tbl.relocate(&9u16.to_ne_bytes()), Err(Error::ReadOnly)));
+        assert!(matches!(tbl.relocate_range(..), Err(Error::ReadOnly)));
+    }
+
+    #[test]
+    fn defragment_step() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::create(file.path()).unwrap();
+        for i in 0u16..1000 {
+            tbl.set(&i.to_ne_bytes(), &[i as u8; 100]).unwrap();
+        }
+        for i in (0u16..1000).step_by(3) {
+            tbl.delete_entry_no_shrink(&i.to_ne_bytes());
+        }
+        let mut steps = 0;
+        while !tbl.defragment_step(10_000).unwrap() {
+            steps += 1;
+            assert!(tbl.is_valid());
+            assert_eq!(tbl.get(&1u16.to_ne_bytes()), Some(&[1; 100][..]));
+            tbl.set(&1u16.to_ne_bytes(), &[1; 100]).unwrap();
+        }
+        assert!(steps > 5);
+        assert_eq!(tbl.stats().data_free, 0);
+        assert_eq!(tbl.len(), 666);
+        for i in (1u16..1000).filter(|i| i % 3 != 0) {
+            assert_eq!(tbl.get(&i.to_ne_bytes()), Some(&[i as u8; 100][..]));
+        }
+        assert!(tbl.defragment_step(0).unwrap());
+    }
+
+    #[test]
+    fn pin_front() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::create(file.path()).unwrap();
+        for i in 0u16..100 {
+            tbl.set(&i.to_ne_bytes(), &[i as u8; 100]).unwrap();
+        }
+        assert!(tbl.pin_front(&90u16.to_ne_bytes()).unwrap());
+        assert!(tbl.pin_front(&95u16.to_ne_bytes()).unwrap());
+        assert!(!tbl.pin_front(&100u16.to_ne_bytes()).unwrap());
+        assert_eq!(tbl.stats().pinned, 2);
+        tbl.delete(&10u16.to_ne_bytes()).unwrap();
+        tbl.defragment().unwrap();
+        assert!(tbl.is_valid());
+        let first = tbl.iter_by_position().take(2).map(|e| e.key.to_vec()).collect::<Vec<_>>();
+        assert_eq!(first, vec![90u16.to_ne_bytes().to_vec(), 95u16.to_ne_bytes().to_vec()]);
+        assert_eq!(tbl.get_entry(&90u16.to_ne_bytes()).unwrap().flags, FLAG_PINNED);
+        for i in (0u16..100).filter(|&i| i != 10) {
+            assert_eq!(tbl.get(&i.to_ne_bytes()), Some(&[i as u8; 100] as &[u8]));
+        }
+        assert!(tbl.unpin(&90u16.to_ne_bytes()).unwrap());
+        assert_eq!(tbl.stats().pinned, 1);
+        assert_eq!(tbl.get_entry(&90u16.to_ne_bytes()).unwrap().flags, 0);
+        // The number of pinned entries is tracked by all modifications and recounted when the table is opened
+        assert!(tbl.pin_front(&95u16.to_ne_bytes()).unwrap());
+        tbl.set_entry(crate::Entry { key: &[1], value: &[], flags: FLAG_PINNED }).unwrap();
+        tbl.set_entry(crate::Entry { key: &0u16.to_ne_bytes(), value: &[], flags: FLAG_PINNED }).unwrap();
+        tbl.set(&0u16.to_ne_bytes(), &[]).unwrap();
+        assert_eq!(tbl.stats().pinned, 2);
+        tbl.close();
+        let mut tbl = Table::open(file.path()).unwrap();
+        assert_eq!(tbl.stats().pinned, 2);
+        tbl.delete(&95u16.to_ne_bytes()).unwrap();
+        assert_eq!(tbl.stats().pinned, 1);
+        tbl.close();
+        let mut tbl = Table::options().read_only(true).open(file.path()).unwrap();
+        assert!(matches!(tbl.pin_front(&1u16.to_ne_bytes()), Err(Error::ReadOnly)));
+    }
+
+    #[test]
+    fn bulk() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::create(file.path()).unwrap();
+        tbl.begin_bulk();
+        let mut resizes = 0;
+        for i in 0u32..10_000 {
+            let size = tbl.data.len();
+            tbl.set(&i.to_ne_bytes(), &[0; 100]).unwrap();
+            if tbl.data.len() != size {
+                resizes += 1;
+            }
+        }
+        assert!(resizes < 20);
+        for i in 0u32..9_000 {
+            tbl.delete(&i.to_ne_bytes()).unwrap();
+        }
+        // Neither the index nor the data section have been shrunk
+        let capacity = tbl.index.capacity();
+        assert!(tbl.index.len() < tbl.min_entries);
+        assert!(tbl.mem.used_size() < tbl.data.len() as u64 / 2);
+        tbl.end_bulk().unwrap();
+        assert!(tbl.index.capacity() < capacity);
+        assert!(tbl.index.len() >= tbl.min_entries);
+        assert_eq!(tbl.mem.used_size(), tbl.data.len() as u64);
+        assert_eq!(tbl.len(), 1000);
+        assert!(tbl.is_valid());
+    }
+
     #[test]
     fn shrink_index() {
         let file = tempfile::NamedTempFile::new().unwrap();
@@ -211,4 +708,114 @@ mod tests {
         let tbl = Table::open(file.path()).unwrap();
         assert!(tbl.is_valid());
     }
+
+    #[test]
+    fn target_displacement() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let options = Table::options().index_usage(0.05, 0.9).target_displacement(Some(0.2));
+        let mut tbl = options.create(file.path()).unwrap();
+        let file2 = tempfile::NamedTempFile::new().unwrap();
+        let mut fixed = Table::create(file2.path()).unwrap();
+        for i in 0u32..1000 {
+            tbl.set(&i.to_ne_bytes(), &[0; 10]).unwrap();
+            fixed.set(&i.to_ne_bytes(), &[0; 10]).unwrap();
+            // The index is resized before an insertion, so the last insertion might exceed the target slightly
+            assert!(tbl.quick_stats().avg_displacement < 0.25);
+        }
+        assert!(tbl.index.capacity() > fixed.index.capacity());
+        assert!(tbl.quick_stats().avg_displacement < fixed.quick_stats().avg_displacement);
+        assert!(tbl.index.len() >= tbl.min_entries);
+        let capacity = tbl.index.capacity();
+        for i in 0u32..950 {
+            tbl.delete(&i.to_ne_bytes()).unwrap();
+        }
+        assert!(tbl.index.capacity() < capacity);
+        assert!(tbl.quick_stats().avg_displacement < 0.25);
+        assert!(tbl.is_valid());
+        assert!(matches!(
+            Table::options().target_displacement(Some(0.0)).create(file.path()),
+            Err(Error::InvalidOptions(_))
+        ));
+    }
+
+    /// Simulates a crash while growing the index, `stored` is the capacity that has reached the header
+    fn crash_during_extend_index(switch: bool, stored: Option<usize>) {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let options = Table::options().lock(LockMode::None);
+        let mut tbl = options.clone().create(file.path()).unwrap();
+        let data = [7; 100];
+        for i in 0u16..100 {
+            tbl.set(&i.to_ne_bytes(), &data).unwrap();
+        }
+        let capacity = tbl.index.capacity();
+        tbl.prepare_index_growth(capacity * 2).unwrap();
+        if switch {
+            tbl.switch_index_capacity(capacity * 2).unwrap();
+        }
+        if let Some(stored) = stored {
+            tbl.header.index_capacity = stored as u32;
+        }
+        // Simulate a crash by not closing the table
+        mem::forget(tbl);
+        let tbl = options.open(file.path()).unwrap();
+        assert!(tbl.info().interrupted_resize);
+        assert_eq!(tbl.index.capacity(), if switch { capacity * 2 } else { capacity });
+        assert!(tbl.is_valid());
+        assert_eq!(tbl.len(), 100);
+        for i in 0u16..100 {
+            assert_eq!(tbl.get(&i.to_ne_bytes()), Some(&data[..]));
+        }
+        tbl.close();
+        assert!(!Table::open(file.path()).unwrap().info().interrupted_resize);
+    }
+
+    #[test]
+    fn extend_index_rollback() {
+        crash_during_extend_index(false, None);
+        // The file is too small for the stored capacity
+        crash_during_extend_index(false, Some(1 << 20));
+    }
+
+    #[test]
+    fn extend_index_completion() {
+        crash_during_extend_index(true, None);
+    }
+
+    #[test]
+    fn out_of_space() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let result = Table::options().data_size(1 << 46).create(file.path());
+        assert!(matches!(result, Err(Error::OutOfSpace { needed, .. }) if needed >= 1 << 46));
+        let mut tbl = Table::create(file.path()).unwrap();
+        tbl.set("key".as_bytes(), "value".as_bytes()).unwrap();
+        let size = file.as_file().metadata().unwrap().len();
+        assert!(matches!(tbl.resize_fd(tbl.index.capacity(), 1 << 46), Err(Error::OutOfSpace { .. })));
+        assert_eq!(file.as_file().metadata().unwrap().len(), size);
+        assert!(tbl.is_valid());
+        assert_eq!(tbl.get("key".as_bytes()), Some("value".as_bytes()));
+    }
+
+    #[test]
+    fn map_limit() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let limit = 1 << 20;
+        let options = Table::options().max_map_size(Some(limit));
+        let result = options.clone().data_size(limit).create(file.path());
+        assert!(matches!(result, Err(Error::MapLimit { limit: 1048576, .. })));
+        let mut tbl = options.clone().create(file.path()).unwrap();
+        let mut stored = 0u32;
+        let err = loop {
+            match tbl.set(&stored.to_ne_bytes(), &[0; 1000]) {
+                Ok(_) => stored += 1,
+                Err(err) => break err,
+            }
+        };
+        assert!(matches!(err, Error::MapLimit { needed, .. } if needed > limit));
+        assert!(tbl.size() <= limit);
+        assert_eq!(tbl.len(), stored as usize);
+        assert!(tbl.is_valid());
+        tbl.close();
+        let tbl = options.max_map_size(Some(4096)).open(file.path());
+        assert!(matches!(tbl, Err(Error::MapLimit { .. })));
+    }
 }
@@ -0,0 +1,648 @@
+use std::{cmp, collections::HashSet};
+
+use crate::{
+    batch::check_record_size,
+    checksum::{self, CHECKSUM_SIZE},
+    index::{is_namespaced, Hash, IndexEntryData, MAX_BLOCK_SIZE_V1},
+    memmngr::Size,
+    table::{hash_key, match_key},
+    Entry, Error, Table, FLAG_CHECKSUM,
+};
+
+/// Internal entry flag that marks entries that have been processed by a pending [`Table::rewrite_values`]
+const FLAG_REWRITTEN: u16 = 1 << 11;
+
+/// Number of entries that are processed by [`Table::rewrite_values`] before the table is flushed
+const REWRITE_CHUNK: usize = 4096;
+
+impl Table {
+    /// Renames or drops the keys of all entries in a single pass and returns the number of changed entries.
+    ///
+    /// The `rewrite` closure is called once with the key of every entry and returns the new key of the entry or
+    /// `None` to delete the entry. Entries whose key does not change are left untouched. If the new key has the same
+    /// length as the old one, the data block of the entry is reused, otherwise the value is copied to a new block.
+    /// All other entry flags are kept.
+    ///
+    /// The new keys must be unique, i.e. no two entries may be renamed to the same key and no entry may be renamed to
+    /// the key of an entry that is kept. Otherwise, [`Error::InvalidOptions`] is returned. All keys are checked before
+    /// the table is modified, so the table is left unchanged on errors.
+    ///
+    /// If the table uses a write-ahead log, all changes are logged as a single record. Tables created via
+    /// [`HashKeyTable`](crate::HashKeyTable) are not supported.
+    ///
+    /// ```
+    /// use rust_persist::Table;
+    ///
+    /// let mut table = Table::create("example_rewrite.tbl").unwrap();
+    /// table.set("v1/key1".as_bytes(), "value1".as_bytes()).unwrap();
+    /// table.set("tmp/key2".as_bytes(), "value2".as_bytes()).unwrap();
+    /// let changed = table
+    ///     .rewrite_keys(|key| match key.strip_prefix("v1/".as_bytes()) {
+    ///         Some(rest) => Some([&b"v2/"[..], rest].concat()),
+    ///         None => None,
+    ///     })
+    ///     .unwrap();
+    /// assert_eq!(changed, 2);
+    /// assert_eq!(table.get("v2/key1".as_bytes()), Some("value1".as_bytes()));
+    /// assert_eq!(table.len(), 1);
+    /// ```
+    pub fn rewrite_keys<F: FnMut(&[u8]) -> Option<Vec<u8>>>(&mut self, mut rewrite: F) -> Result<usize, Error> {
+        self.check_writable()?;
+        self.check_byte_keys()?;
+        let mut dropped = Vec::new();
+        let mut renamed = Vec::new();
+        let mut data_size = 0u64;
+        let entries = self.index.get_entries().iter().filter(|entry| entry.is_used() && !is_namespaced(&entry.data));
+        for index_entry in entries {
+            let entry = self.entry_from_index_data(index_entry.data);
+            match rewrite(entry.key) {
+                Some(key) if key == entry.key => (),
+                Some(key) => {
+                    let size = self.block_size(&key, entry.value)?;
+                    if key.len() != entry.key.len() {
+                        data_size += cmp::max(size, 1);
+                    }
+                    renamed.push((index_entry.hash, index_entry.data, key));
+                }
+                None => dropped.push((index_entry.hash, index_entry.data)),
+            }
+        }
+        if dropped.is_empty() && renamed.is_empty() {
+            return Ok(0);
+        }
+        let moved: HashSet<u64> = dropped
+            .iter()
+            .map(|(_, entry)| entry.position())
+            .chain(renamed.iter().map(|(_, entry, _)| entry.position()))
+            .collect();
+        let mut new_keys = HashSet::with_capacity(renamed.len());
+        for (_, _, key) in &renamed {
+            let taken = self
+                .index
+                .index_get(hash_key(key), |e| match_key(e, self.data, self.data_start, key))
+                .is_some_and(|existing| !moved.contains(&existing.position()));
+            if taken || !new_keys.insert(&key[..]) {
+                return Err(Error::InvalidOptions("rewritten keys are not unique"));
+            }
+        }
+        self.log_rewrite(&dropped, &renamed)?;
+        self.reserve_data(data_size)?;
+        for (hash, entry) in &dropped {
+            self.index.index_delete(*hash, |e| e.position() == entry.position());
+            self.free_data(entry.position());
+        }
+        self.rename_entries(&renamed)?;
+        debug_assert!(self.is_valid(), "Invalid after rewrite keys");
+        self.maybe_evict(0, None)?;
+        self.maybe_shrink_index()?;
+        self.maybe_shrink_data()?;
+        self.maybe_flush()?;
+        Ok(dropped.len() + renamed.len())
+    }
+
+    /// Renames the entry with the key `old_key` to `new_key` and returns whether an entry with the old key exists.
+    ///
+    /// If the new key has the same length as the old one, only the key is rewritten in the data block of the entry,
+    /// otherwise the value is copied to a new block. An existing entry with the new key is replaced. The entry flags
+    /// are kept. If one of the keys is used by an entry of a namespace, [`Error::InvalidOptions`] is returned.
+    ///
+    /// If the table uses a write-ahead log, the rename is logged as a single record. Tables created via
+    /// [`HashKeyTable`](crate::HashKeyTable) are not supported.
+    ///
+    /// ```
+    /// use rust_persist::Table;
+    ///
+    /// let mut table = Table::create("example_rename.tbl").unwrap();
+    /// table.set("draft".as_bytes(), &[0; 10_000]).unwrap();
+    /// assert!(table.rename("draft".as_bytes(), "final".as_bytes()).unwrap());
+    /// assert_eq!(table.get("final".as_bytes()), Some(&[0; 10_000][..]));
+    /// assert!(!table.contains("draft".as_bytes()));
+    /// ```
+    pub fn rename(&mut self, old_key: &[u8], new_key: &[u8]) -> Result<bool, Error> {
+        self.check_writable()?;
+        self.check_byte_keys()?;
+        let old_hash = hash_key(old_key);
+        self.check_plain_key(old_hash, old_key)?;
+        self.check_plain_key(hash_key(new_key), new_key)?;
+        let entry = match self.index.index_get(old_hash, |e| match_key(e, self.data, self.data_start, old_key)) {
+            Some(entry) => entry,
+            None => return Ok(false),
+        };
+        if old_key == new_key {
+            return Ok(true);
+        }
+        let size = self.block_size(new_key, self.entry_from_index_data(entry).value)?;
+        let hash = hash_key(new_key);
+        let replaced: Vec<_> = self
+            .index
+            .index_get(hash, |e| match_key(e, self.data, self.data_start, new_key))
+            .map(|existing| (hash, existing))
+            .into_iter()
+            .collect();
+        let renamed = [(old_hash, entry, new_key.to_vec())];
+        self.log_rewrite(&replaced, &renamed)?;
+        if new_key.len() != old_key.len() {
+            self.reserve_data(cmp::max(size, 1))?;
+        }
+        for (hash, existing) in &replaced {
+            self.index.index_delete(*hash, |e| e.position() == existing.position());
+            self.free_data(existing.position());
+        }
+        self.rename_entries(&renamed)?;
+        debug_assert!(self.is_valid(), "Invalid after rename");
+        self.maybe_evict(0, Some(new_key))?;
+        self.maybe_shrink_index()?;
+        self.maybe_shrink_data()?;
+        self.maybe_flush()?;
+        Ok(true)
+    }
+
+    /// Exchanges the values of the entries with the given keys and returns whether both entries exist.
+    ///
+    /// The data blocks of both entries are kept and only their keys are exchanged, so the values are not copied if
+    /// both keys have the same length. The entry flags are exchanged along with the values. If one of the entries
+    /// does not exist, the table is not changed. Keys of entries of namespaces are rejected like in [`Table::rename`].
+    ///
+    /// If the table uses a write-ahead log, the swap is logged as a single record. Tables created via
+    /// [`HashKeyTable`](crate::HashKeyTable) are not supported.
+    ///
+    /// ```
+    /// use rust_persist::Table;
+    ///
+    /// let mut table = Table::create("example_swap.tbl").unwrap();
+    /// table.set("current".as_bytes(), "v2".as_bytes()).unwrap();
+    /// table.set("previous".as_bytes(), "v1".as_bytes()).unwrap();
+    /// assert!(table.swap("current".as_bytes(), "previous".as_bytes()).unwrap());
+    /// assert_eq!(table.get("current".as_bytes()), Some("v1".as_bytes()));
+    /// assert_eq!(table.get("previous".as_bytes()), Some("v2".as_bytes()));
+    /// ```
+    pub fn swap(&mut self, key_a: &[u8], key_b: &[u8]) -> Result<bool, Error> {
+        self.check_writable()?;
+        self.check_byte_keys()?;
+        let (hash_a, hash_b) = (hash_key(key_a), hash_key(key_b));
+        self.check_plain_key(hash_a, key_a)?;
+        self.check_plain_key(hash_b, key_b)?;
+        let entry_a = self.index.index_get(hash_a, |e| match_key(e, self.data, self.data_start, key_a));
+        let entry_b = self.index.index_get(hash_b, |e| match_key(e, self.data, self.data_start, key_b));
+        let (entry_a, entry_b) = match (entry_a, entry_b) {
+            (Some(entry_a), Some(entry_b)) => (entry_a, entry_b),
+            _ => return Ok(false),
+        };
+        if key_a == key_b {
+            return Ok(true);
+        }
+        let mut data_size = 0;
+        if key_a.len() != key_b.len() {
+            data_size += cmp::max(self.block_size(key_b, self.entry_from_index_data(entry_a).value)?, 1);
+            data_size += cmp::max(self.block_size(key_a, self.entry_from_index_data(entry_b).value)?, 1);
+        }
+        let renamed = [(hash_a, entry_a, key_b.to_vec()), (hash_b, entry_b, key_a.to_vec())];
+        self.log_rewrite(&[], &renamed)?;
+        self.reserve_data(data_size)?;
+        self.rename_entries(&renamed)?;
+        debug_assert!(self.is_valid(), "Invalid after swap");
+        self.maybe_evict(0, None)?;
+        self.maybe_flush()?;
+        Ok(true)
+    }
+
+    /// Removes the renamed entries from the index and inserts them under their new keys.
+    ///
+    /// No entries with the new keys may be stored in the table, except for other renamed entries.
+    fn rename_entries(&mut self, renamed: &[(Hash, IndexEntryData, Vec<u8>)]) -> Result<(), Error> {
+        for (hash, entry, _) in renamed {
+            self.index.index_delete(*hash, |e| e.position() == entry.position());
+        }
+        // The blocks of renamed entries are unindexed until they are inserted under their new keys
+        self.unindexed += renamed.len();
+        for (_, entry, key) in renamed {
+            let hash = hash_key(key);
+            let index_entry = self.rename_block(hash, entry, key)?;
+            let old = {
+                let (data, data_start) = (&self.data, self.data_start);
+                self.index.index_set(hash, |e| match_key(e, data, data_start, key), index_entry)
+            };
+            debug_assert!(old.is_none());
+            self.unindexed -= 1;
+        }
+        Ok(())
+    }
+
+    /// Writes the deletion of all dropped and renamed keys and the entries under their new keys to the write-ahead log
+    fn log_rewrite(
+        &self, dropped: &[(Hash, IndexEntryData)], renamed: &[(Hash, IndexEntryData, Vec<u8>)],
+    ) -> Result<(), Error> {
+        if self.wal.is_none() {
+            return Ok(());
+        }
+        let mut ops = Vec::with_capacity(dropped.len() + 2 * renamed.len());
+        for (_, entry) in dropped {
+            ops.push((self.entry_from_index_data(*entry).key, None));
+        }
+        for (_, entry, _) in renamed {
+            ops.push((self.entry_from_index_data(*entry).key, None));
+        }
+        for (_, entry, key) in renamed {
+            let value = self.entry_from_index_data(*entry).value;
+            check_record_size(key, value)?;
+            ops.push((&key[..], Some((value, entry.flags))));
+        }
+        self.log_ops(ops.into_iter())
+    }
+
+    /// Writes the new key to the data block of the entry and returns the new index entry.
+    ///
+    /// The block is reused if the key size does not change, otherwise the value is moved to a new block.
+    fn rename_block(&mut self, hash: Hash, entry: &IndexEntryData, key: &[u8]) -> Result<IndexEntryData, Error> {
+        let (position, size, flags) = if key.len() == entry.key_size as usize {
+            invariant!(self.mem.rehash(entry.position(), hash), "Renamed block must be tracked");
+            (entry.position(), entry.size(), entry.flags)
+        } else {
+            let old_checksum_size = if entry.flags & FLAG_CHECKSUM > 0 { CHECKSUM_SIZE as Size } else { 0 };
+            let value_len = entry.size() - entry.key_size as Size - old_checksum_size;
+            let mut flags = entry.flags & !FLAG_CHECKSUM;
+            let mut size = key.len() as Size + value_len;
+            if self.options.checksums {
+                size += CHECKSUM_SIZE as Size;
+                flags |= FLAG_CHECKSUM;
+            }
+            let position = self.allocate_data(hash, size)?;
+            let data_start = self.data_start;
+            safemem::copy_over(
+                self.data,
+                (entry.position() + entry.key_size as u64 - data_start) as usize,
+                (position + key.len() as u64 - data_start) as usize,
+                value_len as usize,
+            );
+            self.free_data(entry.position());
+            (position, size, flags)
+        };
+        self.get_data_mut(position, key.len() as Size).copy_from_slice(key);
+        if flags & FLAG_CHECKSUM > 0 {
+            let content_size = size - CHECKSUM_SIZE as Size;
+            let checksum = checksum::checksum(self.get_data(position, content_size));
+            self.get_data_mut(position + content_size, CHECKSUM_SIZE as Size).copy_from_slice(&checksum.to_le_bytes());
+        }
+        if size > MAX_BLOCK_SIZE_V1 {
+            self.header.set_wide_sizes(true);
+        }
+        Ok(IndexEntryData::new(position, size, key.len() as u16, flags))
+    }
+
+    /// Transforms the values of all entries in place and returns the number of changed values.
+    ///
+    /// The `rewrite` closure is called with the key and the value of every entry and returns the new value or `None`
+    /// to keep the value. This can be used to recompress, re-encrypt or migrate all values of a table. The keys and
+    /// the entry flags are not changed.
+    ///
+    /// The entries are processed in chunks. After every chunk, the table is flushed and `progress` is called with the
+    /// number of processed entries and the total number of entries. If `progress` returns `false`, the rewrite is
+    /// paused and this method returns.
+    ///
+    /// The rewrite is resumable: until all entries have been processed, the table remembers which entries are done
+    /// (see [`Table::has_pending_rewrite`]) and the next call only processes the remaining entries, so it must be
+    /// called with the same closure. This also holds if the process crashed during the rewrite, as new values are
+    /// written to new data blocks and only become visible when the chunk is flushed. The changes are therefore not
+    /// written to the write-ahead log. The table should not be modified otherwise while a rewrite is pending.
+    ///
+    /// If a new value is too large, [`Error::TooLarge`] is returned and the current chunk is discarded. Tables created
+    /// via [`HashKeyTable`](crate::HashKeyTable) are not supported.
+    ///
+    /// ```
+    /// use rust_persist::Table;
+    ///
+    /// let mut table = Table::create("example_rewrite_values.tbl").unwrap();
+    /// table.set("key1".as_bytes(), "value1".as_bytes()).unwrap();
+    /// table.set("key2".as_bytes(), "VALUE2".as_bytes()).unwrap();
+    /// let changed = table
+    ///     .rewrite_values(
+    ///         |_key, value| Some(value.to_ascii_uppercase()),
+    ///         |processed, total| {
+    ///             println!("{}/{}", processed, total);
+    ///             true
+    ///         },
+    ///     )
+    ///     .unwrap();
+    /// assert_eq!(changed, 1);
+    /// assert_eq!(table.get("key1".as_bytes()), Some("VALUE1".as_bytes()));
+    /// assert!(!table.has_pending_rewrite());
+    /// ```
+    pub fn rewrite_values<F, P>(&mut self, mut rewrite: F, mut progress: P) -> Result<usize, Error>
+    where
+        F: FnMut(&[u8], &[u8]) -> Option<Vec<u8>>,
+        P: FnMut(usize, usize) -> bool,
+    {
+        self.check_writable()?;
+        self.check_byte_keys()?;
+        if !self.header.has_pending_rewrite() {
+            // Markers are left over if a previous rewrite was interrupted while removing them
+            self.clear_rewrite_markers();
+            self.header.set_pending_rewrite(true);
+            self.sync()?;
+        }
+        let total = self.len();
+        let mut processed = self.rewrite_markers();
+        let mut changed_count = 0;
+        let mut slot = 0;
+        while slot < self.index.capacity() {
+            let mut marked = Vec::new();
+            let mut values = Vec::new();
+            self.collect_rewrite_chunk(&mut slot, &mut rewrite, &mut marked, &mut values);
+            let mut data_size = 0u64;
+            for (_, _, old, value) in &values {
+                let key = self.entry_from_index_data(*old).key;
+                data_size += cmp::max(self.block_size(key, value)?, 1);
+            }
+            // The new blocks are allocated at once, so the data section is grown at most once per chunk
+            self.reserve_data(data_size)?;
+            let mut changed = Vec::with_capacity(values.len());
+            for (chunk_slot, hash, old, value) in values {
+                let key = self.entry_from_index_data(old).key.to_vec();
+                match self.write_block_hashed(hash, &Entry { key: &key, value: &value, flags: old.flags }) {
+                    Ok(new) => changed.push((chunk_slot, new, old.position())),
+                    Err(err) => {
+                        for (_, new, _) in changed {
+                            self.free_data(new.position());
+                            self.unindexed -= 1;
+                        }
+                        return Err(err);
+                    }
+                }
+                self.unindexed += 1;
+            }
+            if marked.is_empty() && changed.is_empty() {
+                continue;
+            }
+            if !changed.is_empty() {
+                // The new blocks must be on disk before the index refers to them
+                self.mmap.flush().map_err(Error::Io)?;
+            }
+            for &chunk_slot in &marked {
+                self.index.slot_data_mut(chunk_slot).flags |= FLAG_REWRITTEN;
+            }
+            for &(chunk_slot, mut new, old_position) in &changed {
+                new.flags |= FLAG_REWRITTEN;
+                *self.index.slot_data_mut(chunk_slot) = new;
+                self.free_data(old_position);
+                self.unindexed -= 1;
+            }
+            // The old blocks are not reused before the index is on disk
+            self.sync()?;
+            processed += marked.len() + changed.len();
+            changed_count += changed.len();
+            if !progress(processed, total) {
+                return Ok(changed_count);
+            }
+        }
+        debug_assert!(self.is_valid(), "Invalid after rewrite values");
+        self.header.set_pending_rewrite(false);
+        self.sync()?;
+        self.clear_rewrite_markers();
+        self.sync()?;
+        self.maybe_evict(0, None)?;
+        self.maybe_shrink_data()?;
+        Ok(changed_count)
+    }
+
+    /// Calls the closure for the next chunk of entries starting at the given index slot.
+    ///
+    /// The slots of unchanged entries are added to `marked`, changed values are added to `values` together with the
+    /// slot, the hash and the old index entry.
+    fn collect_rewrite_chunk<F: FnMut(&[u8], &[u8]) -> Option<Vec<u8>>>(
+        &self, slot: &mut usize, rewrite: &mut F, marked: &mut Vec<usize>,
+        values: &mut Vec<(usize, Hash, IndexEntryData, Vec<u8>)>,
+    ) {
+        while *slot < self.index.capacity() && marked.len() + values.len() < REWRITE_CHUNK {
+            let current = *slot;
+            *slot += 1;
+            let index_entry = &self.index.get_entries()[current];
+            let data = &index_entry.data;
+            if !index_entry.is_used() || is_namespaced(data) || data.flags & FLAG_REWRITTEN > 0 {
+                continue;
+            }
+            let entry = self.entry_from_index_data(index_entry.data);
+            match rewrite(entry.key, entry.value) {
+                Some(value) if value != entry.value => {
+                    values.push((current, index_entry.hash, index_entry.data, value))
+                }
+                _ => marked.push(current),
+            }
+        }
+    }
+
+    /// Returns the number of entries marked as processed by a pending rewrite
+    fn rewrite_markers(&self) -> usize {
+        self.index.get_entries().iter().filter(|e| e.is_used() && e.data.flags & FLAG_REWRITTEN > 0).count()
+    }
+
+    fn clear_rewrite_markers(&mut self) {
+        for slot in 0..self.index.capacity() {
+            self.index.slot_data_mut(slot).flags &= !FLAG_REWRITTEN;
+        }
+    }
+
+    /// Returns whether a value rewrite has been started but not completed, see [`Table::rewrite_values`]
+    #[inline]
+    pub fn has_pending_rewrite(&self) -> bool {
+        self.header.has_pending_rewrite()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{wal::wal_path, FLAG_PINNED};
+    use std::fs;
+
+    #[test]
+    fn test_rewrite_keys() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::options().checksums(true).create(file.path()).unwrap();
+        for i in 0u32..1000 {
+            tbl.set(&i.to_be_bytes(), &i.to_le_bytes()).unwrap();
+        }
+        tbl.pin_front(&5u32.to_be_bytes()).unwrap();
+        // Keys are shifted by one, so every renamed entry takes the key of another renamed entry
+        let changed = tbl
+            .rewrite_keys(|key| {
+                let i = u32::from_be_bytes([key[0], key[1], key[2], key[3]]);
+                match i {
+                    0..=99 => None,
+                    100..=499 => Some((i + 1).to_be_bytes().to_vec()),
+                    500..=599 => Some([&b"long/"[..], key].concat()),
+                    _ => Some(key.to_vec()),
+                }
+            })
+            .unwrap();
+        assert_eq!(changed, 600);
+        assert_eq!(tbl.len(), 900);
+        assert!(tbl.is_valid());
+        assert!(tbl.verify_checksums().is_empty());
+        assert_eq!(tbl.get(&50u32.to_be_bytes()), None);
+        assert_eq!(tbl.get(&100u32.to_be_bytes()), None);
+        assert_eq!(tbl.get(&101u32.to_be_bytes()), Some(&100u32.to_le_bytes()[..]));
+        assert_eq!(tbl.get(&500u32.to_be_bytes()), Some(&499u32.to_le_bytes()[..]));
+        assert_eq!(tbl.get(&[&b"long/"[..], &550u32.to_be_bytes()].concat()), Some(&550u32.to_le_bytes()[..]));
+        assert_eq!(tbl.get(&700u32.to_be_bytes()), Some(&700u32.to_le_bytes()[..]));
+        tbl.close();
+        let tbl = Table::open(file.path()).unwrap();
+        assert!(tbl.is_valid());
+        assert_eq!(tbl.len(), 900);
+    }
+
+    #[test]
+    fn test_rewrite_keys_conflict() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::create(file.path()).unwrap();
+        tbl.set("key1".as_bytes(), "value1".as_bytes()).unwrap();
+        tbl.set("key2".as_bytes(), "value2".as_bytes()).unwrap();
+        tbl.pin_front("key1".as_bytes()).unwrap();
+        let result = tbl.rewrite_keys(|key| if key == b"key1" { Some(b"key2".to_vec()) } else { Some(key.to_vec()) });
+        assert!(matches!(result, Err(Error::InvalidOptions(_))));
+        assert!(matches!(tbl.rewrite_keys(|_| Some(b"same".to_vec())), Err(Error::InvalidOptions(_))));
+        assert_eq!(tbl.get("key1".as_bytes()), Some("value1".as_bytes()));
+        // Swapping keys is allowed as both entries are renamed
+        assert_eq!(tbl.rewrite_keys(|key| Some(if key == b"key1" { b"key2" } else { b"key1" }.to_vec())).unwrap(), 2);
+        assert_eq!(tbl.get("key2".as_bytes()), Some("value1".as_bytes()));
+        assert_eq!(tbl.get_entry("key2".as_bytes()).unwrap().flags, FLAG_PINNED);
+        assert!(tbl.is_valid());
+    }
+
+    #[test]
+    fn test_rewrite_keys_wal() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::options().wal(true).create(file.path()).unwrap();
+        tbl.set("key1".as_bytes(), "value1".as_bytes()).unwrap();
+        tbl.set("key2".as_bytes(), "value2".as_bytes()).unwrap();
+        tbl.flush().unwrap();
+        tbl.rewrite_keys(|key| if key == b"key1" { Some(b"renamed".to_vec()) } else { None }).unwrap();
+        let wal = fs::read(wal_path(file.path())).unwrap();
+        tbl.close();
+        // Simulate a crash where none of the changes reached the table file
+        let mut tbl = Table::create(file.path()).unwrap();
+        tbl.set("key1".as_bytes(), "value1".as_bytes()).unwrap();
+        tbl.set("key2".as_bytes(), "value2".as_bytes()).unwrap();
+        tbl.close();
+        fs::write(wal_path(file.path()), &wal).unwrap();
+        let tbl = Table::options().wal(true).open(file.path()).unwrap();
+        assert_eq!(tbl.len(), 1);
+        assert_eq!(tbl.get("renamed".as_bytes()), Some("value1".as_bytes()));
+    }
+
+    #[test]
+    fn test_rename() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::options().checksums(true).create(file.path()).unwrap();
+        for i in 0u32..100 {
+            tbl.set(&i.to_be_bytes(), &[i as u8; 1000]).unwrap();
+        }
+        tbl.pin_front(&1u32.to_be_bytes()).unwrap();
+        let position = tbl.index.index_get(hash_key(&1u32.to_be_bytes()), |_| true).unwrap().position();
+        // Keys of the same length reuse the data block
+        assert!(tbl.rename(&1u32.to_be_bytes(), &1000u32.to_be_bytes()).unwrap());
+        let entry = tbl.index.index_get(hash_key(&1000u32.to_be_bytes()), |_| true).unwrap();
+        assert_eq!(entry.position(), position);
+        assert_eq!(entry.flags & FLAG_PINNED, FLAG_PINNED);
+        assert!(tbl.rename(&2u32.to_be_bytes(), "longer key".as_bytes()).unwrap());
+        assert_eq!(tbl.get("longer key".as_bytes()), Some(&[2; 1000][..]));
+        // The entry with the new key is replaced
+        assert!(tbl.rename(&3u32.to_be_bytes(), &4u32.to_be_bytes()).unwrap());
+        assert_eq!(tbl.get(&4u32.to_be_bytes()), Some(&[3; 1000][..]));
+        assert!(tbl.rename(&5u32.to_be_bytes(), &5u32.to_be_bytes()).unwrap());
+        assert!(!tbl.rename(&1u32.to_be_bytes(), &6u32.to_be_bytes()).unwrap());
+        assert!(matches!(tbl.rename(&7u32.to_be_bytes(), &[0; 70_000]), Err(Error::TooLarge)));
+        assert_eq!(tbl.len(), 99);
+        for key in [&1u32.to_be_bytes()[..], &2u32.to_be_bytes(), &3u32.to_be_bytes()].iter() {
+            assert!(!tbl.contains(key));
+        }
+        assert!(tbl.is_valid());
+        assert!(tbl.verify_checksums().is_empty());
+    }
+
+    #[test]
+    fn test_rename_wal() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::options().wal(true).create(file.path()).unwrap();
+        tbl.set("key1".as_bytes(), "value1".as_bytes()).unwrap();
+        tbl.set("key2".as_bytes(), "value2".as_bytes()).unwrap();
+        tbl.flush().unwrap();
+        tbl.rename("key1".as_bytes(), "key2".as_bytes()).unwrap();
+        let wal = fs::read(wal_path(file.path())).unwrap();
+        tbl.close();
+        // Simulate a crash where none of the changes reached the table file
+        let mut tbl = Table::create(file.path()).unwrap();
+        tbl.set("key1".as_bytes(), "value1".as_bytes()).unwrap();
+        tbl.set("key2".as_bytes(), "value2".as_bytes()).unwrap();
+        tbl.close();
+        fs::write(wal_path(file.path()), &wal).unwrap();
+        let tbl = Table::options().wal(true).open(file.path()).unwrap();
+        assert_eq!(tbl.len(), 1);
+        assert_eq!(tbl.get("key2".as_bytes()), Some("value1".as_bytes()));
+    }
+
+    #[test]
+    fn test_swap() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::options().checksums(true).create(file.path()).unwrap();
+        tbl.set("current".as_bytes(), "v2".as_bytes()).unwrap();
+        tbl.set("backup1".as_bytes(), "v1".as_bytes()).unwrap();
+        tbl.set("old".as_bytes(), &[0; 1000]).unwrap();
+        tbl.pin_front("current".as_bytes()).unwrap();
+        assert!(tbl.swap("current".as_bytes(), "backup1".as_bytes()).unwrap());
+        assert_eq!(tbl.get("current".as_bytes()), Some("v1".as_bytes()));
+        assert_eq!(tbl.get("backup1".as_bytes()), Some("v2".as_bytes()));
+        assert_eq!(tbl.get_entry("backup1".as_bytes()).unwrap().flags & FLAG_PINNED, FLAG_PINNED);
+        // Keys of different lengths
+        assert!(tbl.swap("old".as_bytes(), "current".as_bytes()).unwrap());
+        assert_eq!(tbl.get("old".as_bytes()), Some("v1".as_bytes()));
+        assert_eq!(tbl.get("current".as_bytes()), Some(&[0; 1000][..]));
+        assert!(tbl.swap("old".as_bytes(), "old".as_bytes()).unwrap());
+        assert!(!tbl.swap("old".as_bytes(), "missing".as_bytes()).unwrap());
+        assert_eq!(tbl.get("old".as_bytes()), Some("v1".as_bytes()));
+        assert_eq!(tbl.len(), 3);
+        assert!(tbl.is_valid());
+        assert!(tbl.verify_checksums().is_empty());
+    }
+
+    #[test]
+    fn test_rewrite_values() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::options().checksums(true).create(file.path()).unwrap();
+        tbl.set_many((0u32..10_000).map(|i| (i.to_ne_bytes(), vec![1; (i % 100) as usize]))).unwrap();
+        tbl.pin_front(&5u32.to_ne_bytes()).unwrap();
+        let double = |_key: &[u8], value: &[u8]| if value.len() < 50 { Some([value, value].concat()) } else { None };
+        let mut calls = 0;
+        let changed = tbl.rewrite_values(double, |processed, total| {
+            calls += 1;
+            assert_eq!(total, 10_000);
+            processed < 5000
+        });
+        assert_eq!(calls, 2);
+        assert!(changed.unwrap() > 0);
+        assert!(tbl.has_pending_rewrite());
+        assert!(tbl.is_valid());
+        tbl.close();
+        let mut tbl = Table::open(file.path()).unwrap();
+        assert!(tbl.has_pending_rewrite());
+        let mut processed_last = 0;
+        tbl.rewrite_values(double, |processed, _| {
+            processed_last = processed;
+            true
+        })
+        .unwrap();
+        assert_eq!(processed_last, 10_000);
+        assert!(!tbl.has_pending_rewrite());
+        assert!(tbl.is_valid());
+        assert!(tbl.verify_checksums().is_empty());
+        for i in 0u32..10_000 {
+            let len = (i % 100) as usize;
+            let expected = if len < 50 { 2 * len } else { len };
+            let entry = tbl.get_raw_entry(&i.to_ne_bytes()).unwrap();
+            assert_eq!(entry.value, &vec![1; expected][..]);
+            assert_eq!(entry.flags & FLAG_REWRITTEN, 0);
+        }
+        assert_eq!(tbl.get_entry(&5u32.to_ne_bytes()).unwrap().flags & FLAG_PINNED, FLAG_PINNED);
+        assert_eq!(tbl.rewrite_values(|_, _| Some(vec![0; 10]), |_, _| false).unwrap(), REWRITE_CHUNK);
+        assert!(tbl.has_pending_rewrite());
+    }
+}
@@ -0,0 +1,297 @@
+use std::{
+    borrow::Cow,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    batch::{checksum, read_bytes, read_u32, read_u64},
+    table::hash_key,
+    BorrowedEntries, Entries, Entry, Error, Table, TableOptions, TableRead, TableWrite,
+};
+
+const SHARDS_HEADER: [u8; 16] = *b"rust-persist-s1\n";
+
+/// Name of the file in the directory of a [`ShardedTable`] that stores the number of shards
+const SHARDS_FILE: &str = "shards";
+
+#[inline]
+fn shard_path(dir: &Path, shard: usize) -> PathBuf {
+    dir.join(format!("shard-{:04}.tbl", shard))
+}
+
+/// Returns the index of the shard that stores keys with the given hash among `count` shards
+#[inline]
+pub(crate) fn shard_for(hash: u64, count: usize) -> usize {
+    // The upper bits of the hash are used, as the lower bits determine the position in the index of the shard
+    (((hash >> 32) * count as u64) >> 32) as usize
+}
+
+fn encode_shard_count(count: usize) -> Vec<u8> {
+    let mut buf = Vec::new();
+    buf.extend_from_slice(&SHARDS_HEADER);
+    buf.extend_from_slice(&(count as u32).to_le_bytes());
+    let checksum = checksum(&buf);
+    buf.extend_from_slice(&checksum.to_le_bytes());
+    buf
+}
+
+fn decode_shard_count(content: &[u8]) -> Option<usize> {
+    let data = &mut &content[..];
+    if read_bytes(data, SHARDS_HEADER.len())? != SHARDS_HEADER {
+        return None;
+    }
+    let count = read_u32(data)? as usize;
+    let content = &content[..content.len() - data.len()];
+    if read_u64(data)? != checksum(content) || !data.is_empty() || count == 0 {
+        return None;
+    }
+    Some(count)
+}
+
+/// A table that is partitioned by the hashes of its keys into multiple table files in a directory
+///
+/// Each shard is a normal [`Table`] (stored as `shard-NNNN.tbl`) that is resized independently, so a large dataset
+/// never needs to resize a single huge file at once. The number of shards is fixed when the table is created and
+/// stored in the file `shards` in the directory.
+///
+/// ```
+/// use rust_persist::ShardedTable;
+///
+/// let mut table = ShardedTable::create("example_sharded", 4).unwrap();
+/// table.set("key1".as_bytes(), "value1".as_bytes()).unwrap();
+/// assert_eq!(table.get("key1".as_bytes()), Some("value1".as_bytes()));
+/// assert_eq!(table.shards().len(), 4);
+/// ```
+pub struct ShardedTable {
+    shards: Vec<Table>,
+}
+
+impl ShardedTable {
+    /// Opens an existing sharded table from the given directory.
+    #[inline]
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self, Error> {
+        Self::open_with(dir, TableOptions::default())
+    }
+
+    /// Opens an existing sharded table from the given directory, opening all shards with the given options.
+    pub fn open_with<P: AsRef<Path>>(dir: P, options: TableOptions) -> Result<Self, Error> {
+        let dir = dir.as_ref();
+        let content = fs::read(dir.join(SHARDS_FILE)).map_err(Error::Io)?;
+        let count = decode_shard_count(&content).ok_or(Error::Corrupt("invalid shards file"))?;
+        let shards = (0..count).map(|shard| options.clone().open(shard_path(dir, shard))).collect::<Result<_, _>>()?;
+        Ok(Self { shards })
+    }
+
+    /// Creates a new sharded table with the given number of shards in the given directory.
+    ///
+    /// The directory is created if needed. Existing shards in the directory will be overwritten.
+    #[inline]
+    pub fn create<P: AsRef<Path>>(dir: P, shards: usize) -> Result<Self, Error> {
+        Self::create_with(dir, shards, TableOptions::default())
+    }
+
+    /// Creates a new sharded table, creating all shards with the given options, see [`ShardedTable::create`].
+    pub fn create_with<P: AsRef<Path>>(dir: P, shards: usize, options: TableOptions) -> Result<Self, Error> {
+        if shards == 0 || shards > u32::MAX as usize {
+            return Err(Error::InvalidOptions("number of shards must be between 1 and 2^32-1"));
+        }
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir).map_err(Error::Io)?;
+        let shards =
+            (0..shards).map(|shard| options.clone().create(shard_path(dir, shard))).collect::<Result<Vec<_>, _>>()?;
+        fs::write(dir.join(SHARDS_FILE), encode_shard_count(shards.len())).map_err(Error::Io)?;
+        Ok(Self { shards })
+    }
+
+    /// Returns the shards of the table
+    #[inline]
+    pub fn shards(&self) -> &[Table] {
+        &self.shards
+    }
+
+    #[inline]
+    pub(crate) fn from_shards(shards: Vec<Table>) -> Self {
+        Self { shards }
+    }
+
+    #[inline]
+    pub(crate) fn into_shards(self) -> Vec<Table> {
+        self.shards
+    }
+
+    /// Returns the index of the shard that stores the given key
+    #[inline]
+    pub fn shard_of(&self, key: &[u8]) -> usize {
+        shard_for(hash_key(key), self.shards.len())
+    }
+
+    /// Returns the value of the given key
+    #[inline]
+    pub fn get(&self, key: &[u8]) -> Option<&[u8]> {
+        self.shards[self.shard_of(key)].get(key)
+    }
+
+    /// Returns whether the given key exists in the table
+    #[inline]
+    pub fn contains(&self, key: &[u8]) -> bool {
+        self.shards[self.shard_of(key)].contains(key)
+    }
+
+    /// Stores the value for the given key in its shard and returns the previous value (if any), see [`Table::set`].
+    #[inline]
+    pub fn set(&mut self, key: &[u8], value: &[u8]) -> Result<Option<&mut [u8]>, Error> {
+        let shard = self.shard_of(key);
+        self.shards[shard].set(key, value)
+    }
+
+    /// Deletes the given key from its shard and returns the deleted value (if any), see [`Table::delete`].
+    #[inline]
+    pub fn delete(&mut self, key: &[u8]) -> Result<Option<&mut [u8]>, Error> {
+        let shard = self.shard_of(key);
+        self.shards[shard].delete(key)
+    }
+
+    /// Returns an iterator over all entries of all shards, shard by shard
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = Entry<'_>> {
+        self.shards.iter().flat_map(Table::iter)
+    }
+
+    /// Returns the number of entries in all shards
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(Table::len).sum()
+    }
+
+    /// Returns whether all shards are empty
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.shards.iter().all(Table::is_empty)
+    }
+
+    /// Returns the total size of all shard files
+    #[inline]
+    pub fn size(&self) -> u64 {
+        self.shards.iter().map(Table::size).sum()
+    }
+
+    /// Deletes all entries in all shards
+    pub fn clear(&mut self) -> Result<(), Error> {
+        self.shards.iter_mut().try_for_each(Table::clear)
+    }
+
+    /// Flushes all shards
+    pub fn flush(&self) -> Result<(), Error> {
+        self.shards.iter().try_for_each(Table::flush)
+    }
+
+    /// Explicitly closes all shards.
+    ///
+    /// Normally this method does not need to be called.
+    #[inline]
+    pub fn close(self) {
+        // nothing to do, just drop self
+    }
+}
+
+impl TableRead for ShardedTable {
+    type Key = [u8];
+    type OwnedKey = Vec<u8>;
+    type Value = [u8];
+    type OwnedValue = Vec<u8>;
+
+    #[inline]
+    fn contains(&self, key: &[u8]) -> Result<bool, Error> {
+        Ok(ShardedTable::contains(self, key))
+    }
+
+    #[inline]
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        Ok(ShardedTable::get(self, key).map(|value| value.to_vec()))
+    }
+
+    #[inline]
+    fn entries(&self) -> Entries<'_, Vec<u8>, Vec<u8>> {
+        Box::new(self.iter().map(|entry| Ok((entry.key.to_vec(), entry.value.to_vec()))))
+    }
+
+    #[inline]
+    fn entries_borrowed(&self) -> BorrowedEntries<'_, [u8], [u8]> {
+        Box::new(self.iter().map(|entry| Ok((Cow::Borrowed(entry.key), Cow::Borrowed(entry.value)))))
+    }
+
+    #[inline]
+    fn len(&self) -> usize {
+        ShardedTable::len(self)
+    }
+}
+
+impl TableWrite for ShardedTable {
+    #[inline]
+    fn set(&mut self, key: &[u8], value: &[u8]) -> Result<bool, Error> {
+        ShardedTable::set(self, key, value).map(|old| old.is_some())
+    }
+
+    #[inline]
+    fn delete(&mut self, key: &[u8]) -> Result<bool, Error> {
+        ShardedTable::delete(self, key).map(|old| old.is_some())
+    }
+
+    #[inline]
+    fn take(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        let shard = self.shard_of(key);
+        self.shards[shard].take(key)
+    }
+
+    #[inline]
+    fn clear(&mut self) -> Result<(), Error> {
+        ShardedTable::clear(self)
+    }
+
+    #[inline]
+    fn flush(&mut self) -> Result<(), Error> {
+        ShardedTable::flush(self)
+    }
+
+    #[inline]
+    fn defragment(&mut self) -> Result<(), Error> {
+        self.shards.iter_mut().try_for_each(Table::defragment)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sharded() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(matches!(ShardedTable::create(dir.path(), 0), Err(Error::InvalidOptions(_))));
+        let mut tbl = ShardedTable::create(dir.path(), 8).unwrap();
+        for i in 0u32..2000 {
+            tbl.set(&i.to_ne_bytes(), &i.to_be_bytes()).unwrap();
+        }
+        for i in (0u32..2000).step_by(2) {
+            assert_eq!(tbl.delete(&i.to_ne_bytes()).unwrap(), Some(&mut i.to_be_bytes()[..]));
+        }
+        assert_eq!(tbl.len(), 1000);
+        assert_eq!(tbl.iter().count(), 1000);
+        for shard in tbl.shards() {
+            // The keys are distributed evenly and every shard has resized its index on its own
+            assert!(shard.len() > 90 && shard.len() < 160);
+            assert!(shard.is_valid());
+        }
+        tbl.close();
+        let tbl = ShardedTable::open(dir.path()).unwrap();
+        assert_eq!(tbl.shards().len(), 8);
+        for i in 0u32..2000 {
+            let expected = i.to_be_bytes();
+            assert_eq!(tbl.get(&i.to_ne_bytes()), Some(&expected[..]).filter(|_| i % 2 == 1));
+            assert_eq!(tbl.shards()[tbl.shard_of(&i.to_ne_bytes())].contains(&i.to_ne_bytes()), i % 2 == 1);
+        }
+        tbl.close();
+        fs::write(dir.path().join(SHARDS_FILE), b"invalid").unwrap();
+        assert!(matches!(ShardedTable::open(dir.path()), Err(Error::Corrupt(_))));
+    }
+}
@@ -0,0 +1,57 @@
+use crate::{memmngr::Size, Table};
+
+impl Table {
+    /// Overwrites the data block freed last with zeros, as far as it has not been reused.
+    pub(crate) fn shred_freed(&mut self) {
+        if let Some((start, size)) = self.unshredded.take() {
+            self.shred_range(start, start + size);
+        }
+    }
+
+    /// Overwrites all parts of the given range of the data section that are not used by any block with zeros.
+    pub(crate) fn shred_range(&mut self, start: u64, end: u64) {
+        for (start, end) in self.mem.unused_parts(start, end) {
+            self.get_data_mut(start, (end - start) as Size).fill(0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Table;
+    use std::fs;
+
+    fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+        haystack.windows(needle.len()).any(|w| w == needle)
+    }
+
+    #[test]
+    fn test_shred() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::options().shred(true).create(file.path()).unwrap();
+        for i in 0u16..100 {
+            tbl.set(&i.to_ne_bytes(), &[0x11; 100]).unwrap();
+        }
+        tbl.set("secret".as_bytes(), "password123".as_bytes()).unwrap();
+        tbl.set("replaced".as_bytes(), "oldsecret456".as_bytes()).unwrap();
+        // The old value is still returned, it is only overwritten with the next change
+        assert_eq!(tbl.set("replaced".as_bytes(), "new".as_bytes()).unwrap(), Some(&mut b"oldsecret456".to_vec()[..]));
+        assert_eq!(tbl.delete("secret".as_bytes()).unwrap(), Some(&mut b"password123".to_vec()[..]));
+        tbl.set("other".as_bytes(), "value".as_bytes()).unwrap();
+        assert!(tbl.is_valid());
+        tbl.close();
+        let content = fs::read(file.path()).unwrap();
+        assert!(!contains(&content, b"password123"));
+        assert!(!contains(&content, b"oldsecret456"));
+        let mut tbl = Table::options().shred(true).create(file.path()).unwrap();
+        tbl.set("gap".as_bytes(), &[0x22; 100]).unwrap();
+        tbl.set("keep".as_bytes(), "topsecret789".as_bytes()).unwrap();
+        tbl.set("after".as_bytes(), &[0x33; 100]).unwrap();
+        tbl.delete("gap".as_bytes()).unwrap();
+        // The moved value must not stay behind at its old position
+        assert!(!tbl.defragment_step(1).unwrap());
+        assert_eq!(tbl.data.windows(12).filter(|w| w == b"topsecret789").count(), 1);
+        assert_eq!(tbl.get("keep".as_bytes()), Some("topsecret789".as_bytes()));
+        assert!(tbl.is_valid());
+    }
+}
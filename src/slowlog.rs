@@ -0,0 +1,109 @@
+use std::time::{Duration, Instant};
+
+use crate::Table;
+
+/// Maximum number of slow operations that are kept, older operations are dropped
+const SLOW_OPS_CAPACITY: usize = 128;
+
+/// Kind of a maintenance operation recorded in the slow operation log, see [`Table::slow_ops`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SlowOpKind {
+    /// The index has been grown
+    GrowIndex,
+    /// The index has been shrunk
+    ShrinkIndex,
+    /// The data section has been grown
+    GrowData,
+    /// The data section has been defragmented (completely or in a step)
+    Defragment,
+    /// The table has been flushed to disk
+    Flush,
+}
+
+/// A maintenance operation that took longer than the configured threshold
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlowOp {
+    /// The kind of operation
+    pub kind: SlowOpKind,
+    /// Time when the operation finished according to the table clock in seconds since the UNIX epoch
+    pub time: u64,
+    /// How long the operation took
+    pub duration: Duration,
+}
+
+impl Table {
+    /// Records the operation that has been started at the given instant if it exceeded the threshold
+    pub(crate) fn record_slow_op(&self, kind: SlowOpKind, started: Instant) {
+        let duration = started.elapsed();
+        match self.options.slow_op_threshold {
+            Some(threshold) if duration >= threshold => (),
+            _ => return,
+        }
+        let mut slow_ops = self.slow_ops.lock().unwrap_or_else(|err| err.into_inner());
+        if slow_ops.len() >= SLOW_OPS_CAPACITY {
+            slow_ops.pop_front();
+        }
+        slow_ops.push_back(SlowOp { kind, time: self.now(), duration });
+    }
+
+    /// Returns the maintenance operations that exceeded the threshold configured via
+    /// [`TableOptions::slow_op_threshold`](crate::TableOptions::slow_op_threshold), oldest first.
+    ///
+    /// Growing and shrinking the index, growing the data section, defragmentations and flushes are recorded. Only the
+    /// last 128 operations since the table has been opened are kept.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use rust_persist::Table;
+    ///
+    /// let options = Table::options().slow_op_threshold(Some(Duration::from_millis(100)));
+    /// let table = options.create("example_slow.tbl").unwrap();
+    /// for op in table.slow_ops() {
+    ///     println!("{:?} took {:?}", op.kind, op.duration);
+    /// }
+    /// ```
+    pub fn slow_ops(&self) -> Vec<SlowOp> {
+        let slow_ops = self.slow_ops.lock().unwrap_or_else(|err| err.into_inner());
+        slow_ops.iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ManualClock, TableOptions};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_slow_ops() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::create(file.path()).unwrap();
+        for i in 0u16..1000 {
+            tbl.set(&i.to_ne_bytes(), &[0; 100]).unwrap();
+        }
+        tbl.flush().unwrap();
+        assert!(tbl.slow_ops().is_empty());
+        tbl.close();
+        let options = TableOptions::default().slow_op_threshold(Some(Duration::from_secs(0)));
+        let mut tbl = options.open(file.path()).unwrap();
+        tbl.set_clock(Arc::new(ManualClock::new(1000)));
+        tbl.flush().unwrap();
+        tbl.defragment().unwrap();
+        let ops = tbl.slow_ops();
+        assert_eq!(ops.iter().map(|op| op.kind).collect::<Vec<_>>(), [SlowOpKind::Flush, SlowOpKind::Defragment]);
+        assert_eq!(ops[0].time, 1000);
+        let mut kinds = Vec::new();
+        for i in 1000u16..2000 {
+            tbl.set(&i.to_ne_bytes(), &[0; 100]).unwrap();
+            kinds.extend(tbl.slow_ops().iter().map(|op| op.kind));
+        }
+        assert!(kinds.contains(&SlowOpKind::GrowIndex));
+        assert!(kinds.contains(&SlowOpKind::GrowData));
+        for i in 0u16..2000 {
+            tbl.delete(&i.to_ne_bytes()).unwrap();
+        }
+        let ops = tbl.slow_ops();
+        assert_eq!(ops.len(), SLOW_OPS_CAPACITY);
+        assert!(ops.iter().any(|op| op.kind == SlowOpKind::ShrinkIndex));
+    }
+}
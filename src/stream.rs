@@ -0,0 +1,228 @@
+use std::{
+    cmp,
+    io::{self, Cursor, Write},
+};
+
+use crate::{
+    checksum::{self, CHECKSUM_SIZE},
+    index::{Hash, IndexEntryData, MAX_BLOCK_SIZE, MAX_BLOCK_SIZE_V1},
+    memmngr::Size,
+    table::{entry_size, hash_key, match_key},
+    Error, Table, FLAG_CHECKSUM,
+};
+
+/// Space for the value that is allocated when the writer is created, the block is doubled whenever it is full
+const INITIAL_CAPACITY: Size = 4096;
+
+/// Handle for writing a value incrementally as returned by [`Table::writer`]
+///
+/// The value is written directly into a data block of the table that grows as needed, so the value never has to be
+/// held in memory by the caller. It only becomes visible when [`ValueWriter::finish`] is called. Dropping the writer
+/// without finishing it discards the written data and leaves the table unchanged.
+pub struct ValueWriter<'a> {
+    tbl: &'a mut Table,
+    key: Vec<u8>,
+    hash: Hash,
+    position: u64,
+    capacity: Size,
+    len: u64,
+    finished: bool,
+}
+
+impl<'a> ValueWriter<'a> {
+    /// Returns the number of value bytes written so far
+    #[inline]
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns whether no value bytes have been written so far
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the size of the block holding the key, the value written so far and the given additional bytes
+    fn needed(&self, additional: usize) -> Result<Size, Error> {
+        let checksum_size = if self.tbl.options.checksums { CHECKSUM_SIZE as Size } else { 0 };
+        (self.key.len() as Size + checksum_size)
+            .checked_add(self.len)
+            .and_then(|size| size.checked_add(additional as Size))
+            .filter(|&size| size <= MAX_BLOCK_SIZE)
+            .ok_or(Error::TooLarge)
+    }
+
+    /// Moves the written data into a block that can hold at least the given size
+    fn grow(&mut self, needed: Size) -> Result<(), Error> {
+        let capacity = cmp::min(cmp::max(needed, self.capacity * 2), MAX_BLOCK_SIZE);
+        let position = self.tbl.allocate_data(self.hash, capacity)?;
+        let data_start = self.tbl.data_start;
+        safemem::copy_over(
+            self.tbl.data,
+            (self.position - data_start) as usize,
+            (position - data_start) as usize,
+            self.key.len() + self.len as usize,
+        );
+        self.tbl.free_data(self.position);
+        self.position = position;
+        self.capacity = capacity;
+        Ok(())
+    }
+
+    /// Stores the written value for the key and returns whether a previous value has been replaced.
+    ///
+    /// If the table uses a write-ahead log, the value is logged now, see [`Table::set`].
+    pub fn finish(mut self) -> Result<bool, Error> {
+        let key_size = self.key.len() as Size;
+        let mut size = key_size + self.len;
+        let mut flags = 0;
+        if self.tbl.options.checksums {
+            let checksum = checksum::checksum(self.tbl.get_data(self.position, size));
+            self.tbl.get_data_mut(self.position + size, CHECKSUM_SIZE as Size).copy_from_slice(&checksum.to_le_bytes());
+            size += CHECKSUM_SIZE as Size;
+            flags |= FLAG_CHECKSUM;
+        }
+        invariant!(self.tbl.mem.shrink(self.position, size), "Written block must shrink");
+        // The written block is not indexed yet, so it is never evicted
+        self.tbl.maybe_evict(0, Some(&self.key))?;
+        self.tbl.log_set(&self.key, self.tbl.get_data(self.position + key_size, self.len), flags)?;
+        if size > MAX_BLOCK_SIZE_V1 {
+            self.tbl.header.set_wide_sizes(true);
+        }
+        let index_entry = IndexEntryData::new(self.position, size, key_size as u16, flags);
+        let old = {
+            let (data, data_start, key) = (&self.tbl.data, self.tbl.data_start, &self.key);
+            self.tbl.index.index_set(self.hash, |e| match_key(e, data, data_start, key), index_entry)
+        };
+        self.tbl.unindexed -= 1;
+        self.finished = true;
+        if let Some(old) = old {
+            self.tbl.free_data(old.position());
+        }
+        let key = &self.key;
+        self.tbl.run_hooks(|hooks, tbl| {
+            let value = tbl.entry_from_index_data(index_entry).value;
+            hooks.set(key, old.map(|old| tbl.entry_from_index_data(old).value), value)
+        });
+        debug_assert!(self.tbl.is_valid(), "Invalid after value writer");
+        self.tbl.maybe_flush()?;
+        Ok(old.is_some())
+    }
+}
+
+impl<'a> Write for ValueWriter<'a> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let needed = self.needed(buf.len()).map_err(io_error)?;
+        if needed > self.capacity {
+            self.grow(needed).map_err(io_error)?;
+        }
+        let start = self.position + self.key.len() as u64 + self.len;
+        self.tbl.get_data_mut(start, buf.len() as Size).copy_from_slice(buf);
+        self.len += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    #[inline]
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> Drop for ValueWriter<'a> {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.tbl.free_data(self.position);
+            self.tbl.unindexed -= 1;
+        }
+    }
+}
+
+fn io_error(err: Error) -> io::Error {
+    match err {
+        Error::Io(err) => err,
+        err => io::Error::other(err.to_string()),
+    }
+}
+
+impl Table {
+    /// Returns a writer that stores a value for the given key incrementally.
+    ///
+    /// This allows storing large values without having them in memory as a whole. The value is stored when
+    /// [`ValueWriter::finish`] is called, see [`ValueWriter`] for details.
+    ///
+    /// ```
+    /// use std::io::{Read, Write};
+    /// use rust_persist::Table;
+    ///
+    /// let mut table = Table::create("example_stream_value.tbl").unwrap();
+    /// let mut writer = table.writer("key1".as_bytes()).unwrap();
+    /// for _ in 0..100 {
+    ///     writer.write_all(&[1; 1000]).unwrap();
+    /// }
+    /// writer.finish().unwrap();
+    /// let mut value = Vec::new();
+    /// table.reader("key1".as_bytes()).unwrap().read_to_end(&mut value).unwrap();
+    /// assert_eq!(value.len(), 100_000);
+    /// ```
+    pub fn writer(&mut self, key: &[u8]) -> Result<ValueWriter<'_>, Error> {
+        self.check_writable()?;
+        entry_size(key, &[])?;
+        self.check_plain_key(hash_key(key), key)?;
+        // Growing the index moves unindexed blocks, so the index must not be grown while writing
+        self.reserve_index(1)?;
+        let hash = hash_key(key);
+        let capacity = key.len() as Size + INITIAL_CAPACITY;
+        let position = self.allocate_data(hash, capacity)?;
+        self.get_data_mut(position, key.len() as Size).copy_from_slice(key);
+        self.unindexed += 1;
+        Ok(ValueWriter { tbl: self, key: key.to_vec(), hash, position, capacity, len: 0, finished: false })
+    }
+
+    /// Returns a reader for the value of the given key.
+    ///
+    /// The value is read directly from the table without being copied first.
+    #[inline]
+    pub fn reader(&self, key: &[u8]) -> Option<Cursor<&[u8]>> {
+        self.get(key).map(Cursor::new)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_value_writer() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::options().checksums(true).create(file.path()).unwrap();
+        for i in 0u16..100 {
+            tbl.set(&i.to_ne_bytes(), &[0; 100]).unwrap();
+        }
+        let expected: Vec<u8> = (0..100_000u32).map(|i| i as u8).collect();
+        let mut writer = tbl.writer(&5u16.to_ne_bytes()).unwrap();
+        for chunk in expected.chunks(999) {
+            writer.write_all(chunk).unwrap();
+        }
+        assert_eq!(writer.len(), 100_000);
+        assert!(writer.finish().unwrap());
+        assert!(tbl.is_valid());
+        assert!(tbl.verify_checksums().is_empty());
+        let mut value = Vec::new();
+        tbl.reader(&5u16.to_ne_bytes()).unwrap().read_to_end(&mut value).unwrap();
+        assert_eq!(value, expected);
+        let mut writer = tbl.writer("new".as_bytes()).unwrap();
+        writer.write_all(&[1; 10_000]).unwrap();
+        drop(writer);
+        assert!(tbl.is_valid());
+        assert!(tbl.get("new".as_bytes()).is_none());
+        assert!(!tbl.writer(&[]).unwrap().finish().unwrap());
+        assert_eq!(tbl.get(&[]), Some(&[][..]));
+        assert!(tbl.reader("missing".as_bytes()).is_none());
+        assert_eq!(tbl.len(), 101);
+        tbl.close();
+        let tbl = Table::open(file.path()).unwrap();
+        assert_eq!(tbl.get(&5u16.to_ne_bytes()), Some(&expected[..]));
+        assert!(tbl.is_valid());
+    }
+}
@@ -1,13 +1,37 @@
-use std::{cmp, fs::File, hash::Hasher, mem, path::Path};
+use std::{
+    cmp,
+    collections::VecDeque,
+    fs::{self, File},
+    hash::Hasher,
+    io, mem,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Instant,
+};
 
 use serde_derive::Serialize;
 use siphasher::sip::SipHasher13;
 
-use crate::memmngr::{MemoryManagment, Used};
+use crate::memmngr::{MemoryManagment, Size};
 use crate::{
-    index::{Hash, Index, IndexEntry, IndexEntryData},
-    mmap::{self, MMap},
-    Error, INITIAL_DATA_SIZE, INITIAL_INDEX_CAPACITY, MAX_USAGE, MIN_USAGE,
+    batch,
+    hashkey,
+    checksum::{self, CHECKSUM_SIZE},
+    clock::{Clock, OptionsClock},
+    evict::FLAG_REFERENCED,
+    guard::GUARD_SIZE,
+    hooks::Hooks,
+    index::{
+        is_namespaced, Hash, Index, IndexEntry, IndexEntryData, LocateResult, MAX_BLOCK_SIZE, MAX_BLOCK_SIZE_V1,
+        MAX_POSITION,
+    },
+    mmap::{self, MMap, OpenFdResult},
+    namespace::FLAG_NAMESPACE,
+    reader::ReaderLock,
+    resize,
+    slowlog::{SlowOp, SlowOpKind},
+    wal, CheckLevel, Error, FlushMode, GrowFill, LockMode, ReadOnlyTable, TableOptions, FLAG_CHECKSUM, RESERVED_FLAGS,
 };
 
 #[inline(always)]
@@ -20,6 +44,10 @@ pub(crate) struct Header {
     pub(crate) header: [u8; 16],
     pub(crate) flags: [u8; 16],
     pub(crate) index_capacity: u32,
+    pub(crate) page_size: u32,
+    pub(crate) created: u64,
+    pub(crate) last_close: u64,
+    pub(crate) version: [u8; 16],
 }
 
 impl Header {
@@ -45,9 +73,98 @@ impl Header {
         self.set_flag(0, 0, dirty)
     }
 
+    /// Returns whether the table stores caller-supplied hashes as keys, see [`HashKeyTable`](crate::HashKeyTable)
+    #[inline]
+    pub fn has_hash_keys(&self) -> bool {
+        self.get_flag(0, 3)
+    }
+
+    /// Returns whether the index entries may contain sizes larger than 32 bits (format v2), see [`IndexEntryData`]
+    #[inline]
+    pub fn has_wide_sizes(&self) -> bool {
+        self.get_flag(0, 4)
+    }
+
+    #[inline]
+    pub fn set_wide_sizes(&mut self, wide_sizes: bool) {
+        self.set_flag(0, 4, wide_sizes)
+    }
+
+    #[inline]
+    pub fn set_hash_keys(&mut self, hash_keys: bool) {
+        self.set_flag(0, 3, hash_keys)
+    }
+
+    /// Returns whether a value rewrite has been started but not completed, see [`Table::rewrite_values`]
+    #[inline]
+    pub fn has_pending_rewrite(&self) -> bool {
+        self.get_flag(0, 5)
+    }
+
+    #[inline]
+    pub fn set_pending_rewrite(&mut self, pending: bool) {
+        self.set_flag(0, 5, pending)
+    }
+
+    /// Returns the index capacity before the index resize that is in progress (if any)
+    ///
+    /// The capacity is stored as exponent of two in a single byte, so it does not depend on the byte order.
+    #[inline]
+    pub fn resize_origin(&self) -> Option<u32> {
+        match self.flags[1] {
+            0 => None,
+            exp => 1u32.checked_shl(exp as u32),
+        }
+    }
+
+    #[inline]
+    pub fn set_resize_origin(&mut self, capacity: Option<u32>) {
+        self.flags[1] = capacity.map(|c| c.trailing_zeros() as u8).unwrap_or(0)
+    }
+
+    #[inline]
+    pub fn is_open(&self) -> bool {
+        self.get_flag(0, 2)
+    }
+
+    #[inline]
+    pub fn set_open(&mut self, open: bool) {
+        self.set_flag(0, 2, open)
+    }
+
+    /// Returns whether the table is open for writing in [`LockMode::Shared`](crate::LockMode::Shared)
+    #[inline]
+    pub fn is_shared_writer(&self) -> bool {
+        self.get_flag(0, 6)
+    }
+
+    #[inline]
+    pub fn set_shared_writer(&mut self, shared: bool) {
+        self.set_flag(0, 6, shared)
+    }
+
+    /// Returns a counter that is incremented whenever the table is opened for writing and whenever a writer in
+    /// [`LockMode::Shared`](crate::LockMode::Shared) starts to modify it, see [`SharedReader`](crate::SharedReader)
+    ///
+    /// The counter is stored little-endian in the last 8 bytes of the flags, so it does not depend on the byte order.
+    #[inline]
+    pub fn generation(&self) -> u64 {
+        let mut generation = [0; 8];
+        generation.copy_from_slice(&self.flags[8..]);
+        u64::from_le_bytes(generation)
+    }
+
+    #[inline]
+    pub fn set_generation(&mut self, generation: u64) {
+        self.flags[8..].copy_from_slice(&generation.to_le_bytes())
+    }
+
     #[inline]
     pub fn fix_endianness(&mut self) {
         self.index_capacity = self.index_capacity.to_be().to_le();
+        self.page_size = self.page_size.to_be().to_le();
+        self.created = self.created.to_be().to_le();
+        self.last_close = self.last_close.to_be().to_le();
     }
 
     #[inline]
@@ -62,8 +179,25 @@ impl Header {
 }
 
 #[inline]
-pub(crate) fn total_size(index_capacity: usize, data_size: u64) -> u64 {
-    mem::size_of::<Header>() as u64 + index_capacity as u64 * mem::size_of::<IndexEntry>() as u64 + data_size
+pub(crate) fn total_size(index_capacity: usize, data_size: u64) -> Result<u64, Error> {
+    (index_capacity as u64)
+        .checked_mul(mem::size_of::<IndexEntry>() as u64)
+        .and_then(|index_size| index_size.checked_add(mem::size_of::<Header>() as u64))
+        .and_then(|size| size.checked_add(data_size))
+        .filter(|&size| size <= MAX_POSITION)
+        .ok_or(Error::TooLarge)
+}
+
+/// Returns the size of the data block for the given entry or `Error::TooLarge` if it cannot be stored
+#[inline]
+pub(crate) fn entry_size(key: &[u8], value: &[u8]) -> Result<Size, Error> {
+    if key.len() > u16::MAX as usize {
+        return Err(Error::TooLarge);
+    }
+    (key.len() as u64)
+        .checked_add(value.len() as u64)
+        .filter(|&len| len <= MAX_BLOCK_SIZE)
+        .ok_or(Error::TooLarge)
 }
 
 #[inline]
@@ -73,12 +207,22 @@ pub(crate) fn hash_key(key: &[u8]) -> Hash {
     hasher.finish()
 }
 
+/// Returns whether the key of an entry is consistent with the hash stored in the index
+#[inline]
+pub(crate) fn check_key_hash(header: &Header, key: &[u8], hash: Hash) -> bool {
+    if header.has_hash_keys() {
+        hashkey::entry_key(hash, key).is_some()
+    } else {
+        hash_key(key) == hash
+    }
+}
+
 #[inline]
-fn match_key(entry: &IndexEntryData, data: &[u8], data_start: u64, key: &[u8]) -> bool {
+pub(crate) fn match_key(entry: &IndexEntryData, data: &[u8], data_start: u64, key: &[u8]) -> bool {
     if key.is_empty() && entry.key_size == 0 {
         return true;
     }
-    let start = (entry.position - data_start) as usize;
+    let start = (entry.position() - data_start) as usize;
     let end = start + entry.key_size as usize;
     &data[start..end] == key
 }
@@ -86,6 +230,10 @@ fn match_key(entry: &IndexEntryData, data: &[u8], data_start: u64, key: &[u8]) -
 /// An entry in the table
 pub struct Entry<'a> {
     /// Flags stored with the entry
+    ///
+    /// The highest two bits are reserved for [`FLAG_PINNED`](crate::FLAG_PINNED) and
+    /// [`FLAG_CHECKSUM`](crate::FLAG_CHECKSUM). The bits of [`RESERVED_FLAGS`](crate::RESERVED_FLAGS) are used
+    /// internally, they are ignored when storing entries and always cleared in returned entries.
     pub flags: u16,
 
     /// The key of the entry
@@ -111,6 +259,22 @@ pub struct EntryMut<'a> {
     pub value: &'a mut [u8],
 }
 
+impl<'a> Entry<'a> {
+    /// Returns the entry with the internal flags cleared
+    #[inline]
+    pub(crate) fn without_reserved(self) -> Self {
+        Entry { flags: self.flags & !RESERVED_FLAGS, ..self }
+    }
+}
+
+impl<'a> EntryMut<'a> {
+    /// Returns the entry with the internal flags cleared
+    #[inline]
+    pub(crate) fn without_reserved(self) -> Self {
+        EntryMut { flags: self.flags & !RESERVED_FLAGS, ..self }
+    }
+}
+
 /// A persistent hash table mapping key/value of type `&[u8]`.
 ///
 /// This is the main struct of the crate. It manages two data structures:
@@ -125,7 +289,7 @@ pub struct EntryMut<'a> {
 /// This data section is extended when needed and shrinked (by moving data blocks to the front and truncating the free data at the end)
 /// whenever less than 50% of the data section is used.
 pub struct Table {
-    pub(crate) fd: File,
+    pub(crate) fd: Option<File>,
     pub(crate) mmap: MMap,
     pub(crate) header: &'static mut Header,
     pub(crate) index: Index,
@@ -134,43 +298,143 @@ pub struct Table {
     pub(crate) data: &'static mut [u8],
     pub(crate) data_start: u64,
     pub(crate) mem: MemoryManagment,
+    pub(crate) unindexed: usize,
+    pub(crate) clock: Arc<dyn Clock>,
+    pub(crate) unclean_shutdown: bool,
+    pub(crate) interrupted_resize: bool,
+    pub(crate) page_size_changed: bool,
+    pub(crate) options: TableOptions,
+    pub(crate) path: Option<PathBuf>,
+    pub(crate) wal: Option<File>,
+    pub(crate) slow_ops: Mutex<VecDeque<SlowOp>>,
+    pub(crate) unshredded: Option<(u64, Size)>,
+    pub(crate) bulk: bool,
+    pub(crate) stats_recorded: Option<u64>,
+    pub(crate) hooks: Hooks,
+    pub(crate) evict_hand: usize,
+    pub(crate) evicted: u64,
+    pub(crate) readers: Option<ReaderLock>,
 }
 
 impl Table {
-    fn new_index(path: &Path, create: bool) -> Result<Self, Error> {
-        let opened_fd = mmap::open_fd(path, create)?;
+    pub(crate) fn new_index(path: &Path, create: bool, options: TableOptions) -> Result<Self, Error> {
+        options.validate()?;
+        let opened_fd = mmap::open_fd(path, create, &options)?;
+        // Shared readers are locked out only after the table lock has been taken, so that a second writer fails
+        // instead of waiting for the readers, and before the table is loaded, as loading it might modify it
+        let readers = if options.lock == LockMode::Shared { Some(ReaderLock::acquire(path)?) } else { None };
+        let mut tbl = Self::from_opened(opened_fd, create, options)?;
+        if let Some(readers) = readers {
+            tbl.header.set_shared_writer(true);
+            tbl.readers = Some(readers);
+        }
+        tbl.attach_path(path, create)?;
+        if tbl.readers.is_some() {
+            // The readers are let in again, so that a writer that only reads does not keep them out
+            tbl.flush()?;
+        }
+        Ok(tbl)
+    }
+
+    /// Associates the table with its path and handles the journal and the write-ahead log
+    pub(crate) fn attach_path(&mut self, path: &Path, create: bool) -> Result<(), Error> {
+        self.path = Some(path.to_path_buf());
+        if self.is_read_only() {
+            return Ok(());
+        }
+        if create {
+            for stale in &[batch::journal_path(path), wal::wal_path(path)] {
+                match fs::remove_file(stale) {
+                    Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(Error::Io(err)),
+                    _ => (),
+                }
+            }
+        } else {
+            self.replay_journal()?;
+        }
+        self.open_wal(path)
+    }
+
+    pub(crate) fn from_opened(
+        mut opened_fd: OpenFdResult, create: bool, options: TableOptions,
+    ) -> Result<Self, Error> {
+        let data_end = opened_fd.data_start as u64 + opened_fd.data.len() as u64;
         let mut mem = MemoryManagment::new(
             opened_fd.data_start as u64,
             opened_fd.data_start as u64 + opened_fd.data.len() as u64,
         );
-        if !opened_fd.header.has_correct_endianness() {
-            for entry in opened_fd.index_entries.iter_mut() {
-                entry.fix_endianness()
-            }
-            opened_fd.header.fix_endianness();
-            opened_fd.header.set_correct_endianness();
-        }
+        opened_fd.fix_endianness();
         let mut count = 0;
         for entry in opened_fd.index_entries.iter_mut() {
             if entry.is_used() {
                 if create {
                     entry.clear()
                 } else {
-                    mem.set_used(entry.data.position, entry.data.size, entry.hash);
+                    let data = &entry.data;
+                    let checksum_size = if data.flags & FLAG_CHECKSUM > 0 { CHECKSUM_SIZE } else { 0 };
+                    if data.key_size as u64 + checksum_size as u64 > data.size() {
+                        return Err(Error::Corrupt("entry is smaller than its key"));
+                    }
+                    if data.size() > MAX_BLOCK_SIZE_V1 && !opened_fd.header.has_wide_sizes() {
+                        return Err(Error::Corrupt("entry size requires format v2"));
+                    }
+                    if data.position() < opened_fd.data_start as u64
+                        || !matches!(data.position().checked_add(cmp::max(data.size(), 1) as u64), Some(end) if end <= data_end)
+                    {
+                        return Err(Error::Corrupt("entry outside of the data section"));
+                    }
+                    mem.set_used(entry.data.position(), entry.data.size(), entry.hash);
                     count += 1;
                 }
             }
         }
+        if count >= opened_fd.index_entries.len() {
+            return Err(Error::Corrupt("index is full"));
+        }
+        if mem.get_used().len() != count || mem.has_overlaps() {
+            return Err(Error::Corrupt("overlapping entries"));
+        }
         mem.fix_up();
         let mut index = Index::new(opened_fd.index_entries, count);
+        let interrupted_resize = opened_fd.header.is_dirty() && opened_fd.header.resize_origin().is_some();
         if opened_fd.header.is_dirty() {
+            // An interrupted resize is completed or rolled back depending on the capacity in the header (see
+            // `map_table`), in both cases all entries are in the index and just need to be placed correctly
             index.reinsert_all();
-            assert!(index.is_valid(), "Inconsistent after reinsert");
+            opened_fd.header.set_resize_origin(None);
             opened_fd.header.set_dirty(false);
         }
+        let mut findings = Vec::new();
+        index.check(&mut findings);
+        if !findings.is_empty() {
+            return Err(Error::Corrupt("index entries at wrong positions"));
+        }
+        let clock = options.clock.0.clone();
+        if create {
+            opened_fd.header.created = clock.now();
+            opened_fd.header.last_close = 0;
+            opened_fd.header.version = [0; 16];
+            let version = env!("CARGO_PKG_VERSION").as_bytes();
+            opened_fd.header.version[..version.len()].copy_from_slice(version);
+            opened_fd.header.set_open(false);
+            opened_fd.header.set_hash_keys(false);
+        }
+        let page_size = mmap::page_size() as u32;
+        if create {
+            opened_fd.header.page_size = page_size;
+        }
+        // Nothing in the layout depends on the page size, so the table can be used as is. The page size is updated
+        // so that alignment based features can rely on it in the future.
+        let page_size_changed = opened_fd.header.page_size != page_size;
+        opened_fd.header.page_size = page_size;
+        let unclean_shutdown = opened_fd.header.is_open();
+        opened_fd.header.set_open(true);
+        opened_fd.header.set_shared_writer(false);
+        // Shared readers load the table again after it has been opened for writing
+        opened_fd.header.set_generation(opened_fd.header.generation().wrapping_add(1));
         let tbl = Self {
-            max_entries: (opened_fd.header.index_capacity as f64 * MAX_USAGE) as usize,
-            min_entries: (opened_fd.header.index_capacity as f64 * MIN_USAGE) as usize,
+            max_entries: (opened_fd.header.index_capacity as f64 * options.max_usage) as usize,
+            min_entries: (opened_fd.header.index_capacity as f64 * options.min_usage) as usize,
             fd: opened_fd.fd,
             mmap: opened_fd.mmap,
             index,
@@ -178,21 +442,111 @@ impl Table {
             header: opened_fd.header,
             data: opened_fd.data,
             data_start: opened_fd.data_start as u64,
+            unindexed: 0,
+            clock,
+            unclean_shutdown,
+            interrupted_resize,
+            page_size_changed,
+            options,
+            path: None,
+            wal: None,
+            slow_ops: Mutex::new(VecDeque::new()),
+            unshredded: None,
+            bulk: false,
+            stats_recorded: None,
+            hooks: Hooks::default(),
+            evict_hand: 0,
+            evicted: 0,
+            readers: None,
         };
         debug_assert!(tbl.is_valid(), "Inconsistent after creation");
         Ok(tbl)
     }
 
+    /// Returns a builder to configure how a table is opened or created.
+    #[inline]
+    pub fn options() -> TableOptions {
+        TableOptions::default()
+    }
+
+    /// Estimates the size of a table file with default options holding the given number of entries.
+    ///
+    /// See [`TableOptions::estimate_file_size`] for details and [`TableOptions::plan_for`] to create a table with
+    /// these sizes.
+    #[inline]
+    pub fn estimate_file_size(entries: usize, avg_key: usize, avg_value: usize) -> u64 {
+        TableOptions::default().estimate_file_size(entries, avg_key, avg_value)
+    }
+
     /// Open an existing table from the given path.
     #[inline]
     pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
-        Self::new_index(path.as_ref(), false)
+        Self::new_index(path.as_ref(), false, TableOptions::default())
     }
 
     /// Creates a new empty table. If the file exists, it will be overwritten.
     #[inline]
     pub fn create<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
-        Self::new_index(path.as_ref(), true)
+        Self::new_index(path.as_ref(), true, TableOptions::default())
+    }
+
+    /// Opens an existing table from the given path in read-only mode.
+    ///
+    /// Only a shared lock is taken, so that multiple processes can read the table at the same time as long as no
+    /// process has opened it for writing. The returned handle only allows reading, see [`ReadOnlyTable`].
+    ///
+    /// A journal left by an interrupted [`Table::apply`] is not replayed.
+    #[inline]
+    pub fn open_read_only<P: AsRef<Path>>(path: P) -> Result<ReadOnlyTable, Error> {
+        ReadOnlyTable::open(path)
+    }
+
+    /// Opens an existing table from a file that might have been crafted by an untrusted party.
+    ///
+    /// All openings check that the index entries point to non-overlapping extents within the data section and that
+    /// every entry can be found in the index, and return [`Error::Corrupt`] otherwise. In addition, this method
+    /// validates the complete table and converts any panic while opening into [`Error::Corrupt`].
+    pub fn open_untrusted<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        Self::open_validated(|| Self::new_index(path, false, TableOptions::default()))
+    }
+
+    /// Runs the given function to open a table, validates the result and converts panics to errors
+    pub(crate) fn open_validated<F: FnOnce() -> Result<Self, Error>>(open: F) -> Result<Self, Error> {
+        panic::catch_unwind(AssertUnwindSafe(|| {
+            let tbl = open()?;
+            if !tbl.is_valid() {
+                return Err(Error::Corrupt("inconsistent table"));
+            }
+            Ok(tbl)
+        }))
+        .unwrap_or(Err(Error::Corrupt("panic while opening")))
+    }
+
+    /// Returns whether the table has been opened read-only.
+    #[inline]
+    pub fn is_read_only(&self) -> bool {
+        self.options.read_only
+    }
+
+    #[inline]
+    pub(crate) fn check_writable(&mut self) -> Result<(), Error> {
+        if self.options.read_only {
+            return Err(Error::ReadOnly);
+        }
+        if let Some(reason) = self.mem.violation() {
+            return Err(Error::Corrupt(reason));
+        }
+        self.lock_readers()
+    }
+
+    /// Fails for hash key tables, whose entries cannot be identified by their key bytes
+    #[inline]
+    pub(crate) fn check_byte_keys(&self) -> Result<(), Error> {
+        if self.header.has_hash_keys() {
+            return Err(Error::InvalidOptions("operation is not supported for hash key tables"));
+        }
+        Ok(())
     }
 
     /// Opens an existing or creates a new typed table at the given path.
@@ -206,46 +560,103 @@ impl Table {
         }
     }
 
-    pub(crate) fn allocate_data(&mut self, hash: Hash, mut size: u32) -> Result<u64, Error> {
+    pub(crate) fn allocate_data(&mut self, hash: Hash, mut size: Size) -> Result<u64, Error> {
         size = cmp::max(size, 1);
-        match self.mem.allocate(size, hash) {
-            Some(pos) => Ok(pos),
+        self.shred_freed();
+        let guarded = self.options.guard_bytes;
+        let alloc_size = if guarded { size + GUARD_SIZE } else { size };
+        let pos = match self.mem.allocate(alloc_size, hash) {
+            Some(pos) => pos,
             None => {
-                self.extend_data(size)?;
-                Ok(self.mem.allocate(size, hash).expect("Still not enough space after extend"))
+                self.extend_data(alloc_size)?;
+                match self.mem.allocate(alloc_size, hash) {
+                    Some(pos) => pos,
+                    None => invariant_violated!("Still not enough space after extend"),
+                }
             }
+        };
+        let fill = self.options.grow_fill;
+        if let GrowFill::Pattern(_) = fill {
+            // Reused blocks still contain old entries, so they are filled as well to expose reads of unwritten bytes
+            mmap::fill_grown(self.get_data_mut(pos, size), fill);
         }
+        if guarded {
+            self.add_guard(pos, size);
+        }
+        Ok(pos)
     }
 
     #[inline]
     pub(crate) fn free_data(&mut self, pos: u64) -> bool {
+        if self.options.guard_bytes {
+            self.remove_guard(pos);
+        }
+        if self.options.shred {
+            // The old value might still be returned to the caller, so the block is only overwritten later
+            self.shred_freed();
+            self.unshredded = self.mem.block_at(pos).map(|block| (block.start, block.size));
+        }
         self.mem.free(pos)
     }
 
     #[inline]
-    pub(crate) fn get_data(&self, pos: u64, len: u32) -> &[u8] {
+    pub(crate) fn get_data(&self, pos: u64, len: Size) -> &[u8] {
         if len == 0 {
             return &[];
         }
         debug_assert!(pos >= self.data_start);
-        debug_assert!(pos + len as u64 <= self.data_start + self.data.len() as u64);
-        &self.data[(pos - self.data_start) as usize..(pos + len as u64 - self.data_start) as usize]
+        debug_assert!(pos + len <= self.data_start + self.data.len() as u64);
+        &self.data[(pos - self.data_start) as usize..(pos + len - self.data_start) as usize]
     }
 
     #[inline]
-    pub(crate) fn get_data_mut(&mut self, pos: u64, len: u32) -> &mut [u8] {
+    pub(crate) fn get_data_mut(&mut self, pos: u64, len: Size) -> &mut [u8] {
         if len == 0 {
             return &mut [];
         }
         debug_assert!(pos >= self.data_start);
-        debug_assert!(pos + len as u64 <= self.data_start + self.data.len() as u64);
-        &mut self.data[(pos - self.data_start) as usize..(pos + len as u64 - self.data_start) as usize]
+        debug_assert!(pos + len <= self.data_start + self.data.len() as u64);
+        &mut self.data[(pos - self.data_start) as usize..(pos + len - self.data_start) as usize]
+    }
+
+    /// Sets the clock used by all time-based features of this table.
+    ///
+    /// By default, the clock given via [`TableOptions::clock`] is used, i.e. the
+    /// [`SystemClock`](crate::SystemClock) unless configured otherwise.
+    #[inline]
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.options.clock = OptionsClock(clock.clone());
+        self.clock = clock
+    }
+
+    /// Returns the current time of the table clock in seconds since the UNIX epoch.
+    #[inline]
+    pub fn now(&self) -> u64 {
+        self.clock.now()
+    }
+
+    /// Returns audit information about the table file.
+    pub fn info(&self) -> TableInfo {
+        let version = &self.header.version;
+        let len = version.iter().position(|&b| b == 0).unwrap_or(version.len());
+        TableInfo {
+            created: self.header.created,
+            version: String::from_utf8_lossy(&version[..len]).into_owned(),
+            last_close: if self.header.last_close == 0 { None } else { Some(self.header.last_close) },
+            unclean_shutdown: self.unclean_shutdown,
+            interrupted_resize: self.interrupted_resize,
+            page_size: self.header.page_size,
+            page_size_changed: self.page_size_changed,
+            format_version: if self.header.has_wide_sizes() { 2 } else { 1 },
+        }
     }
 
     /// Returns the number of key/value pairs stored in the table.
+    ///
+    /// Entries of namespaces are not counted, see [`Table::namespace`].
     #[inline]
     pub fn len(&self) -> usize {
-        self.index.len()
+        self.index.len() - self.index.namespaced()
     }
 
     /// Returns the raw size of the table in bytes.
@@ -257,25 +668,80 @@ impl Table {
     /// Returns whether the table is empty
     #[inline]
     pub fn is_empty(&self) -> bool {
-        self.index.len() == 0
+        self.len() == 0
     }
 
     /// Forces to write all pending changes to disk
+    ///
+    /// In write-ahead log mode, the log is truncated afterwards. In [`LockMode::Shared`], the changes become visible
+    /// to shared readers afterwards.
     #[inline]
     pub fn flush(&self) -> Result<(), Error> {
-        self.mmap.flush().map_err(Error::Io)
+        self.sync()?;
+        self.release_readers()
+    }
+
+    /// Writes all pending changes to disk like [`Table::flush`] without releasing shared readers, so it can be used
+    /// in the middle of an operation
+    pub(crate) fn sync(&self) -> Result<(), Error> {
+        let started = Instant::now();
+        self.mmap.flush().map_err(Error::Io)?;
+        self.truncate_wal()?;
+        self.record_slow_op(SlowOpKind::Flush, started);
+        Ok(())
+    }
+
+    #[inline]
+    pub(crate) fn maybe_flush(&self) -> Result<(), Error> {
+        if self.options.flush == FlushMode::EveryWrite {
+            self.sync()?;
+        }
+        self.maybe_checkpoint()
+    }
+
+    /// Configures the memory mapping for use as a secondary cache.
+    ///
+    /// This hints the operating system that the data section is accessed randomly and that its pages can be
+    /// reclaimed early in favor of other processes sharing the page cache. The index stays unaffected.
+    /// On platforms without support for memory advice, this method does nothing.
+    pub fn advise_shared(&self) -> Result<(), Error> {
+        mmap::advise_cold(&self.mmap, self.data_start as usize, self.data.len())
     }
 
+    /// Returns how many bytes of the table file are currently resident in memory.
+    ///
+    /// On platforms where this information is not available, the whole table size is returned.
+    pub fn resident_bytes(&self) -> Result<u64, Error> {
+        mmap::resident_bytes(&self.mmap)
+    }
+
+    /// Returns the entry of the given index data with the internal flags cleared
     #[inline]
     pub(crate) fn entry_from_index_data(&self, entry: IndexEntryData) -> Entry<'_> {
-        let data = self.get_data(entry.position, entry.size);
+        self.raw_entry_from_index_data(entry).without_reserved()
+    }
+
+    /// Returns the entry of the given index data with all flags as stored
+    #[inline]
+    pub(crate) fn raw_entry_from_index_data(&self, entry: IndexEntryData) -> Entry<'_> {
+        let data = self.get_data(entry.position(), entry.size());
+        let data = if entry.flags & FLAG_CHECKSUM > 0 { &data[..data.len() - CHECKSUM_SIZE as usize] } else { data };
         let (key, value) = data.split_at(entry.key_size as usize);
         Entry { key, value, flags: entry.flags }
     }
 
+    /// Returns the entry of the given index data for modification with the internal flags cleared
     #[inline]
     pub(crate) fn entry_mut_from_index_data(&mut self, entry: IndexEntryData) -> EntryMut<'_> {
-        let data = self.get_data_mut(entry.position, entry.size);
+        self.raw_entry_mut_from_index_data(entry).without_reserved()
+    }
+
+    /// Returns the entry of the given index data for modification with all flags as stored
+    #[inline]
+    pub(crate) fn raw_entry_mut_from_index_data(&mut self, entry: IndexEntryData) -> EntryMut<'_> {
+        let data = self.get_data_mut(entry.position(), entry.size());
+        let len = data.len();
+        let data = if entry.flags & FLAG_CHECKSUM > 0 { &mut data[..len - CHECKSUM_SIZE as usize] } else { data };
         let (key, value) = data.split_at_mut(entry.key_size as usize);
         EntryMut { key, value, flags: entry.flags }
     }
@@ -283,18 +749,48 @@ impl Table {
     /// Returns whether an entry is associated with the given key.
     #[inline]
     pub fn contains(&self, key: &[u8]) -> bool {
-        let hash = hash_key(key);
-        self.index.index_get(hash, |e| match_key(e, self.data, self.data_start, key)).is_some()
+        self.get_entry(key).is_some()
     }
 
     /// Retrieves and returns the entry associated with the given key.
     /// If no entry with the given key is stored in the table, `None` is returned.
     #[inline]
     pub fn get_entry(&self, key: &[u8]) -> Option<Entry<'_>> {
-        let hash = hash_key(key);
+        self.get_plain_entry_hashed(hash_key(key), key)
+    }
+
+    /// Retrieves the entry with the given hash and key with the internal flags cleared, unless it belongs to a
+    /// namespace
+    #[inline]
+    pub(crate) fn get_plain_entry_hashed(&self, hash: Hash, key: &[u8]) -> Option<Entry<'_>> {
+        self.get_entry_hashed(hash, key).filter(|e| e.flags & FLAG_NAMESPACE == 0).map(Entry::without_reserved)
+    }
+
+    /// Returns [`Error::InvalidOptions`] if the entry with the given hash and key belongs to a namespace, as plain
+    /// modifications must not replace or delete it
+    #[inline]
+    pub(crate) fn check_plain_key(&self, hash: Hash, key: &[u8]) -> Result<(), Error> {
+        if self.index.namespaced() == 0 {
+            return Ok(());
+        }
+        match self.index.index_get(hash, |e| match_key(e, self.data, self.data_start, key)) {
+            Some(entry) if is_namespaced(&entry) => Err(Error::InvalidOptions("key is used by a namespace")),
+            _ => Ok(()),
+        }
+    }
+
+    /// Retrieves the entry with the given key including the internal flags
+    #[inline]
+    pub(crate) fn get_raw_entry(&self, key: &[u8]) -> Option<Entry<'_>> {
+        self.get_entry_hashed(hash_key(key), key)
+    }
+
+    /// Retrieves the entry with the given hash and key including the internal flags
+    #[inline]
+    pub(crate) fn get_entry_hashed(&self, hash: Hash, key: &[u8]) -> Option<Entry<'_>> {
         self.index
             .index_get(hash, |e| match_key(e, self.data, self.data_start, key))
-            .map(|e| self.entry_from_index_data(e))
+            .map(|e| self.raw_entry_from_index_data(e))
     }
 
     /// Retrieves and returns the value associated with the given key.
@@ -304,25 +800,103 @@ impl Table {
         self.get_entry(key).map(|e| e.value)
     }
 
+    /// Retrieves the values associated with the given keys, in the same order as the keys.
+    ///
+    /// All keys are hashed first and then looked up in the order of their index positions, so that the index is
+    /// scanned front to back once instead of being accessed at random for every key.
+    ///
+    /// ```
+    /// use rust_persist::Table;
+    ///
+    /// let mut table = Table::create("example_get_many.tbl").unwrap();
+    /// table.set("key1".as_bytes(), "value1".as_bytes()).unwrap();
+    /// table.set("key2".as_bytes(), "value2".as_bytes()).unwrap();
+    /// let values = table.get_many(&["key2".as_bytes(), "key3".as_bytes(), "key1".as_bytes()]);
+    /// assert_eq!(values, vec![Some("value2".as_bytes()), None, Some("value1".as_bytes())]);
+    /// ```
+    pub fn get_many<'a>(&'a self, keys: &[&[u8]]) -> Vec<Option<&'a [u8]>> {
+        let mut hashes: Vec<(usize, Hash)> = keys.iter().map(|key| hash_key(key)).enumerate().collect();
+        let mask = self.index.capacity() as u64 - 1;
+        hashes.sort_unstable_by_key(|&(_, hash)| hash & mask);
+        let mut values = vec![None; keys.len()];
+        for (i, hash) in hashes {
+            values[i] = self.get_plain_entry_hashed(hash, keys[i]).map(|e| e.value);
+        }
+        values
+    }
+
+    /// Retrieves `len` bytes of the value associated with the given key, starting at `offset`.
+    ///
+    /// Only the requested part of the value is accessed, so this is cheap even for large values. If no entry with
+    /// the given key is stored in the table or the range exceeds the value, `None` is returned.
+    ///
+    /// ```
+    /// use rust_persist::Table;
+    ///
+    /// let mut table = Table::create("example_range.tbl").unwrap();
+    /// table.set("key1".as_bytes(), "header:body".as_bytes()).unwrap();
+    /// assert_eq!(table.get_range("key1".as_bytes(), 0, 6), Some("header".as_bytes()));
+    /// assert_eq!(table.get_range("key1".as_bytes(), 7, 10), None);
+    /// ```
+    pub fn get_range(&self, key: &[u8], offset: u64, len: u64) -> Option<&[u8]> {
+        let hash = hash_key(key);
+        let entry = self.index.index_get(hash, |e| match_key(e, self.data, self.data_start, key))?;
+        if is_namespaced(&entry) {
+            return None;
+        }
+        let checksum_size = if entry.flags & FLAG_CHECKSUM > 0 { CHECKSUM_SIZE as u64 } else { 0 };
+        let value_size = entry.size() - entry.key_size as u64 - checksum_size;
+        let end = offset.checked_add(len).filter(|&end| end <= value_size)?;
+        Some(self.get_data(entry.position() + entry.key_size as u64 + offset, end - offset))
+    }
+
     /// Retrieves and returns the entry associated with the given key.
     /// If no entry with the given key is stored in the table, `None` is returned.
     /// If the returned value is modified, it directly affects the stored value.
+    ///
+    /// In [`LockMode::Shared`], `None` is also returned if the readers cannot be locked out, use
+    /// [`Table::try_get_entry_mut`] to get the error instead.
     #[inline]
     pub fn get_entry_mut(&mut self, key: &[u8]) -> Option<EntryMut<'_>> {
-        let hash = hash_key(key);
+        self.try_get_entry_mut(key).ok().flatten()
+    }
+
+    /// Retrieves and returns the entry associated with the given key like [`Table::get_entry_mut`], but returns an
+    /// error if the readers cannot be locked out in [`LockMode::Shared`].
+    pub fn try_get_entry_mut(&mut self, key: &[u8]) -> Result<Option<EntryMut<'_>>, Error> {
+        self.lock_readers()?;
+        Ok(self
+            .get_entry_mut_hashed(hash_key(key), key)
+            .filter(|entry| entry.flags & FLAG_NAMESPACE == 0)
+            .map(EntryMut::without_reserved))
+    }
+
+    /// Retrieves the entry with the given hash and key for modification including the internal flags
+    #[inline]
+    pub(crate) fn get_entry_mut_hashed(&mut self, hash: Hash, key: &[u8]) -> Option<EntryMut<'_>> {
         self.index
             .index_get(hash, |e| match_key(e, self.data, self.data_start, key))
-            .map(move |entry| self.entry_mut_from_index_data(entry))
+            .map(move |entry| self.raw_entry_mut_from_index_data(entry))
     }
 
     /// Retrieves and returns the value associated with the given key.
     /// If no entry with the given key is stored in the table, `None` is returned.
     /// If the returned value is modified, it directly affects the stored value.
+    ///
+    /// In [`LockMode::Shared`], `None` is also returned if the readers cannot be locked out, use
+    /// [`Table::try_get_mut`] to get the error instead.
     #[inline]
     pub fn get_mut(&mut self, key: &[u8]) -> Option<&mut [u8]> {
         self.get_entry_mut(key).map(|e| e.value)
     }
 
+    /// Retrieves and returns the value associated with the given key like [`Table::get_mut`], but returns an error if
+    /// the readers cannot be locked out in [`LockMode::Shared`].
+    #[inline]
+    pub fn try_get_mut(&mut self, key: &[u8]) -> Result<Option<&mut [u8]>, Error> {
+        Ok(self.try_get_entry_mut(key)?.map(|e| e.value))
+    }
+
     /// Stores the given entry in the table.
     ///
     /// If another entry is already stored for the key, this old entry will be removed from the table and returned.
@@ -330,36 +904,200 @@ impl Table {
     /// If the key is new ot the table, `None` is returned.
     ///
     /// Internally, a copy-on-write method is used instead of overwriting existing values. Therefore old values might
-    /// be visible in the raw table file until a defragmentation happens.
+    /// be visible in the raw table file until a defragmentation happens, unless [`TableOptions::shred`] is enabled.
     ///
     /// This method might increase the size of the internal index or the data section as needed.
     /// If the table file cannot be extended (e.g. due to no space on device), the method will return an `Err` result.
+    ///
+    /// The bits of [`RESERVED_FLAGS`](crate::RESERVED_FLAGS) are cleared in the flags of the entry. If the key is
+    /// used by an entry of a namespace, [`Error::InvalidOptions`] is returned, see [`Table::namespace`].
     #[inline]
     pub fn set_entry<'a>(&mut self, entry: Entry<'a>) -> Result<Option<EntryMut<'_>>, Error> {
+        self.check_plain_key(hash_key(entry.key), entry.key)?;
+        Ok(self.set_raw_entry(entry.without_reserved())?.map(EntryMut::without_reserved))
+    }
+
+    /// Stores the given entry like [`Table::set_entry`] but keeps internal flags of the entry and returns the old
+    /// entry including its internal flags
+    pub(crate) fn set_raw_entry<'a>(&mut self, entry: Entry<'a>) -> Result<Option<EntryMut<'_>>, Error> {
+        self.check_writable()?;
+        self.maybe_record_stats()?;
+        self.log_set(entry.key, entry.value, entry.flags)?;
+        self.set_entry_hashed(hash_key(entry.key), entry)
+    }
+
+    /// Stores the given entry under the given hash, without writing it to the write-ahead log.
+    ///
+    /// The internal flags of the entry are kept and the old entry is returned including its internal flags.
+    pub(crate) fn set_entry_hashed<'a>(&mut self, hash: Hash, entry: Entry<'a>) -> Result<Option<EntryMut<'_>>, Error> {
+        self.check_writable()?;
         self.maybe_extend_index()?;
         self.maybe_shrink_data()?;
+        let (key, value) = (entry.key, entry.value);
+        self.maybe_evict(self.block_size(key, value)?, Some(key))?;
+        let result = self.insert_entry_hashed(hash, entry)?;
+        // The old block is freed before flushing, so that it does not leak if the flush fails
+        if let Some(old) = result {
+            self.free_data(old.position());
+        }
+        self.run_hooks(|hooks, tbl| hooks.set(key, result.map(|old| tbl.entry_from_index_data(old).value), value));
+        self.maybe_flush()?;
+        Ok(result.map(move |old| self.raw_entry_mut_from_index_data(old)))
+    }
+
+    /// Writes the entry to the data section and updates the index without resizing the index.
+    ///
+    /// The data of the replaced entry (if any) is not freed.
+    pub(crate) fn insert_entry(&mut self, entry: Entry<'_>) -> Result<Option<IndexEntryData>, Error> {
+        self.insert_entry_hashed(hash_key(entry.key), entry)
+    }
+
+    /// Writes the entry under the given hash, see [`Table::insert_entry`]
+    pub(crate) fn insert_entry_hashed(&mut self, hash: Hash, entry: Entry<'_>) -> Result<Option<IndexEntryData>, Error> {
+        let index_entry = self.write_block_hashed(hash, &entry)?;
+        let data = &self.data;
+        let data_start = self.data_start;
+        Ok(self.index.index_set(hash, |e| match_key(e, data, data_start, entry.key), index_entry))
+    }
+
+    /// Returns the size of the data block for the given key and value
+    #[inline]
+    pub(crate) fn block_size(&self, key: &[u8], value: &[u8]) -> Result<Size, Error> {
+        let len = entry_size(key, value)?;
+        if !self.options.checksums {
+            return Ok(len);
+        }
+        Some(len + CHECKSUM_SIZE as Size).filter(|&len| len <= MAX_BLOCK_SIZE).ok_or(Error::TooLarge)
+    }
+
+    /// Allocates a data block for the entry and writes the key, the value and the checksum (if enabled) to it.
+    pub(crate) fn write_block(&mut self, entry: &Entry<'_>) -> Result<(Hash, IndexEntryData), Error> {
         let hash = hash_key(entry.key);
-        let len = (entry.key.len() + entry.value.len()) as u32;
+        Ok((hash, self.write_block_hashed(hash, entry)?))
+    }
+
+    /// Allocates a data block for the entry under the given hash, see [`Table::write_block`]
+    pub(crate) fn write_block_hashed(&mut self, hash: Hash, entry: &Entry<'_>) -> Result<IndexEntryData, Error> {
+        let len = self.block_size(entry.key, entry.value)?;
+        let mut flags = entry.flags & !(FLAG_CHECKSUM | FLAG_REFERENCED);
+        if self.options.max_data_size.is_some() {
+            flags |= FLAG_REFERENCED;
+        }
+        if len > MAX_BLOCK_SIZE_V1 && !self.header.has_wide_sizes() {
+            // Entries of format v1 are valid in format v2, so the table is upgraded when the first large entry is stored
+            self.header.set_wide_sizes(true);
+        }
         let pos = self.allocate_data(hash, len)?;
         if len > 0 {
+            let with_checksum = self.options.checksums;
             let space = self.get_data_mut(pos, len);
+            let value_end = entry.key.len() + entry.value.len();
             space[..entry.key.len()].copy_from_slice(entry.key);
-            space[entry.key.len()..].copy_from_slice(entry.value);
+            space[entry.key.len()..value_end].copy_from_slice(entry.value);
+            if with_checksum {
+                let checksum = checksum::checksum(&space[..value_end]);
+                space[value_end..].copy_from_slice(&checksum.to_le_bytes());
+                flags |= FLAG_CHECKSUM;
+            }
         }
-        let index_entry =
-            IndexEntryData { position: pos, size: len, key_size: entry.key.len() as u16, flags: entry.flags };
-        let result = {
-            let data = &self.data;
-            let data_start = self.data_start;
-            self.index.index_set(hash, |e| match_key(e, data, data_start, entry.key), index_entry)
-        };
-        match result {
-            Some(old) => {
-                self.free_data(old.position);
-                Ok(Some(self.entry_mut_from_index_data(old)))
+        Ok(IndexEntryData::new(pos, len, entry.key.len() as u16, flags))
+    }
+
+    /// Creates a new table at the given path that contains all entries of the given map.
+    ///
+    /// The sizes of the index and the data section are computed from the map up front, so the table file is
+    /// created with its final size and never grown while inserting. If the file exists, it will be overwritten.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use rust_persist::Table;
+    ///
+    /// let mut map = HashMap::new();
+    /// map.insert("key1".as_bytes().to_vec(), "value1".as_bytes().to_vec());
+    /// let table = Table::from_map("example_map.tbl", &map).unwrap();
+    /// assert_eq!(table.get("key1".as_bytes()), Some("value1".as_bytes()));
+    /// ```
+    pub fn from_map<'a, P, K, V, M>(path: P, map: &'a M) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+        K: AsRef<[u8]> + 'a,
+        V: AsRef<[u8]> + 'a,
+        &'a M: IntoIterator<Item = (&'a K, &'a V)>, {
+        let mut count = 0;
+        let mut data_size = 0u64;
+        for (key, value) in map {
+            count += 1;
+            data_size += cmp::max(entry_size(key.as_ref(), value.as_ref())?, 1) as u64;
+        }
+        let defaults = TableOptions::default();
+        let index_capacity = resize::index_capacity_for(defaults.index_capacity, count, defaults.max_usage);
+        let mut tbl = defaults.clone().index_capacity(index_capacity).data_size(data_size).create(path)?;
+        tbl.options = defaults;
+        for (key, value) in map {
+            tbl.insert_entry(Entry { key: key.as_ref(), value: value.as_ref(), flags: 0 })?;
+        }
+        debug_assert!(tbl.is_valid(), "Invalid after from map");
+        Ok(tbl)
+    }
+
+    /// Creates a new table at the given path that contains all given key/value pairs.
+    ///
+    /// The pairs are stored via [`Table::set_many`], so the table is grown at most once. If the file exists, it will
+    /// be overwritten.
+    ///
+    /// ```
+    /// use rust_persist::Table;
+    ///
+    /// let pairs = (0u8..10).map(|i| ([i], [i; 8]));
+    /// let table = Table::create_from_iter("example_from_iter.tbl", pairs).unwrap();
+    /// assert_eq!(table.get(&[3]), Some(&[3; 8][..]));
+    /// ```
+    pub fn create_from_iter<P, K, V, I>(path: P, iter: I) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+        I: IntoIterator<Item = (K, V)>, {
+        let mut tbl = Self::create(path)?;
+        tbl.set_many(iter)?;
+        Ok(tbl)
+    }
+
+    /// Stores all given key/value pairs in the table.
+    ///
+    /// In contrast to calling [`Table::set`] for every pair, the required index capacity and data size are computed
+    /// up front, so that the index and the data section are grown at most once. If a key occurs multiple times, the
+    /// last value is stored.
+    ///
+    /// If any key or value is too large, [`Error::TooLarge`] is returned before the table is modified, and the same
+    /// applies to [`Error::InvalidOptions`] if a key is used by an entry of a namespace.
+    /// If the table file cannot be extended (e.g. due to no space on device), the method will return an `Err` result.
+    pub fn set_many<K: AsRef<[u8]>, V: AsRef<[u8]>, I: IntoIterator<Item = (K, V)>>(
+        &mut self, iter: I,
+    ) -> Result<(), Error> {
+        self.check_writable()?;
+        let entries: Vec<(K, V)> = iter.into_iter().collect();
+        let mut data_size = 0u64;
+        for (key, value) in &entries {
+            self.check_plain_key(hash_key(key.as_ref()), key.as_ref())?;
+            data_size += cmp::max(self.block_size(key.as_ref(), value.as_ref())?, 1) as u64;
+        }
+        // Entries are evicted before the new ones are logged, so replaying the log does not delete them again
+        self.maybe_evict(data_size, None)?;
+        self.log_entries(&entries)?;
+        self.reserve_index(entries.len())?;
+        self.reserve_data(data_size)?;
+        for (key, value) in &entries {
+            let (key, value) = (key.as_ref(), value.as_ref());
+            let result = self.insert_entry(Entry { key, value, flags: 0 })?;
+            if let Some(old) = result {
+                self.free_data(old.position());
             }
-            None => Ok(None),
+            self.run_hooks(|hooks, tbl| hooks.set(key, result.map(|old| tbl.entry_from_index_data(old).value), value));
         }
+        debug_assert!(self.is_valid(), "Invalid after set many");
+        self.maybe_flush()?;
+        self.maybe_shrink_data()
     }
 
     /// Stores the given key/value pair in the table.
@@ -369,7 +1107,7 @@ impl Table {
     /// If the key is new ot the table, `None` is returned.
     ///
     /// Internally, a copy-on-write method is used instead of overwriting existing values. Therefore old values might
-    /// be visible in the raw table file until a defragmentation happens.
+    /// be visible in the raw table file until a defragmentation happens, unless [`TableOptions::shred`] is enabled.
     ///
     /// This method might increase the size of the internal index or the data section as needed.
     /// If the table file cannot be extended (e.g. due to no space on device), the method will return an `Err` result.
@@ -378,22 +1116,104 @@ impl Table {
         self.set_entry(Entry { key, value, flags: 0 }).map(|r| r.map(|e| e.value))
     }
 
+    /// Replaces the value associated with the given key by the result of the given closure.
+    ///
+    /// The closure is called with the current value (if any) and returns the new value or `None` to delete the
+    /// entry. In contrast to calling [`Table::get`] and [`Table::set`], the key is only looked up once. Like
+    /// [`Table::set`], the new value is stored without any entry flags. Keys used by entries of namespaces are rejected
+    /// with [`Error::InvalidOptions`].
+    ///
+    /// This method might increase the size of the internal index or the data section as needed.
+    /// If the table file cannot be extended (e.g. due to no space on device), the method will return an `Err` result.
+    ///
+    /// ```
+    /// use rust_persist::Table;
+    ///
+    /// let mut table = Table::create("example_update.tbl").unwrap();
+    /// let increment = |old: Option<&[u8]>| Some(vec![old.map_or(0, |v| v[0]) + 1]);
+    /// table.update("counter".as_bytes(), increment).unwrap();
+    /// table.update("counter".as_bytes(), increment).unwrap();
+    /// assert_eq!(table.get("counter".as_bytes()), Some(&[2][..]));
+    /// table.update("counter".as_bytes(), |_| None).unwrap();
+    /// assert!(table.get("counter".as_bytes()).is_none());
+    /// ```
+    pub fn update<F: FnOnce(Option<&[u8]>) -> Option<Vec<u8>>>(&mut self, key: &[u8], update: F) -> Result<(), Error> {
+        self.check_writable()?;
+        // Resizing moves index entries, so it must happen before the key is located
+        self.maybe_extend_index()?;
+        self.maybe_shrink_data()?;
+        let hash = hash_key(key);
+        self.check_plain_key(hash, key)?;
+        let located = self.index.locate(hash, |e| match_key(e, self.data, self.data_start, key));
+        let old = match located {
+            LocateResult::Found(pos) => Some((pos, self.index.get_entries()[pos].data)),
+            _ => None,
+        };
+        match (update(old.map(|(_, old)| self.entry_from_index_data(old).value)), old) {
+            (None, None) => return Ok(()),
+            (None, Some((pos, old))) => {
+                self.log_delete(key)?;
+                self.index.delete_located(pos);
+                self.free_data(old.position());
+                self.run_hooks(|hooks, tbl| hooks.delete(key, tbl.entry_from_index_data(old).value));
+                self.maybe_shrink_index()?;
+            }
+            (Some(value), old) => {
+                self.maybe_evict(self.block_size(key, &value)?, Some(key))?;
+                // Evicting entries moves index entries, so the key is located again
+                let located = self.index.locate(hash, |e| match_key(e, self.data, self.data_start, key));
+                self.log_set(key, &value, 0)?;
+                let index_entry = self.write_block_hashed(hash, &Entry { key, value: &value, flags: 0 })?;
+                self.index.set_located(located, hash, index_entry);
+                if let Some((_, old)) = old {
+                    self.free_data(old.position());
+                }
+                self.run_hooks(|hooks, tbl| {
+                    hooks.set(key, old.map(|(_, old)| tbl.entry_from_index_data(old).value), &value)
+                });
+            }
+        }
+        debug_assert!(self.is_valid(), "Invalid after update");
+        self.maybe_flush()
+    }
+
     /// Deletes the entry with the given key
     ///
     /// If an entry with the given key exists in the table, the entry is removed and returned.
     /// The returned reference is valid until another modification is made to the table.
     /// If the key is not found in the table, `None` is returned.
+    /// If the key is used by an entry of a namespace, [`Error::InvalidOptions`] is returned.
     ///
     /// Internally, deleted values are just marked as unused. Therefore old values might be visible in the
-    /// raw table file until a defragmentation happens.
+    /// raw table file until a defragmentation happens, unless [`TableOptions::shred`] is enabled.
     ///
     /// This method might decrease the size of the internal index or the data section as needed.
     /// If the table file cannot be resized, the method will return an `Err` result.
     #[inline]
     pub fn delete_entry(&mut self, key: &[u8]) -> Result<Option<EntryMut<'_>>, Error> {
+        self.check_plain_key(hash_key(key), key)?;
+        Ok(self.delete_raw_entry(key)?.map(EntryMut::without_reserved))
+    }
+
+    /// Deletes the entry with the given key like [`Table::delete_entry`], also if it belongs to a namespace, and
+    /// returns it including its internal flags
+    pub(crate) fn delete_raw_entry(&mut self, key: &[u8]) -> Result<Option<EntryMut<'_>>, Error> {
+        self.check_writable()?;
+        self.maybe_record_stats()?;
+        self.log_delete(key)?;
+        self.delete_entry_hashed(hash_key(key), key)
+    }
+
+    /// Deletes the entry with the given hash and key, without writing it to the write-ahead log.
+    ///
+    /// The deleted entry is returned including its internal flags.
+    pub(crate) fn delete_entry_hashed(&mut self, hash: Hash, key: &[u8]) -> Result<Option<EntryMut<'_>>, Error> {
+        self.check_writable()?;
         self.maybe_shrink_index()?;
         self.maybe_shrink_data()?;
-        Ok(self.delete_entry_no_shrink(key))
+        let result = self.delete_index_entry(hash, key);
+        self.maybe_flush()?;
+        Ok(result.map(move |old| self.raw_entry_mut_from_index_data(old)))
     }
 
     /// Deletes the entry with the given key
@@ -403,7 +1223,7 @@ impl Table {
     /// If the key is not found in the table, `None` is returned.
     ///
     /// Internally, deleted values are just marked as unused. Therefore old values might be visible in the
-    /// raw table file until a defragmentation happens.
+    /// raw table file until a defragmentation happens, unless [`TableOptions::shred`] is enabled.
     ///
     /// This method might decrease the size of the internal index or the data section as needed.
     /// If the table file cannot be resized, the method will return an `Err` result.
@@ -412,21 +1232,43 @@ impl Table {
         self.delete_entry(key).map(|r| r.map(|e| e.value))
     }
 
+    /// Deletes the entry with the given key and returns an owned copy of its value
+    ///
+    /// In contrast to [`Table::delete`], the returned value stays valid when the table is modified afterwards.
+    /// If the key is not found in the table, `None` is returned.
+    ///
+    /// ```
+    /// use rust_persist::Table;
+    ///
+    /// let mut table = Table::create("example_take.tbl").unwrap();
+    /// table.set("hello".as_bytes(), "world".as_bytes()).unwrap();
+    /// let value = table.take("hello".as_bytes()).unwrap();
+    /// table.set("other".as_bytes(), "value".as_bytes()).unwrap();
+    /// assert_eq!(value, Some("world".as_bytes().to_vec()));
+    /// assert_eq!(table.take("hello".as_bytes()).unwrap(), None);
+    /// ```
+    #[inline]
+    pub fn take(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        self.delete(key).map(|r| r.map(|value| value.to_vec()))
+    }
+
     #[inline]
     pub(crate) fn delete_entry_no_shrink<'a>(&'a mut self, key: &[u8]) -> Option<EntryMut<'a>> {
-        let hash = hash_key(key);
+        self.delete_index_entry(hash_key(key), key).map(move |old| self.entry_mut_from_index_data(old))
+    }
+
+    #[inline]
+    fn delete_index_entry(&mut self, hash: Hash, key: &[u8]) -> Option<IndexEntryData> {
         let result = {
             let data = &self.data;
             let data_start = self.data_start;
             self.index.index_delete(hash, |e| match_key(e, data, data_start, key))
         };
-        match result {
-            Some(old) => {
-                self.free_data(old.position);
-                Some(self.entry_mut_from_index_data(old))
-            }
-            None => None,
+        if let Some(old) = result {
+            self.free_data(old.position());
+            self.run_hooks(|hooks, tbl| hooks.delete(key, tbl.entry_from_index_data(old).value));
         }
+        result
     }
 
     /// Deletes all entries in the table
@@ -434,11 +1276,26 @@ impl Table {
     /// This method essentially resets the table to its state after creation.
     #[inline]
     pub fn clear(&mut self) -> Result<(), Error> {
-        self.resize_fd(INITIAL_INDEX_CAPACITY, INITIAL_DATA_SIZE as u64)?;
+        self.check_writable()?;
+        let deleted: Vec<_> = if self.hooks.is_empty() {
+            Vec::new()
+        } else {
+            self.iter().map(|entry| (entry.key.to_vec(), entry.value.to_vec())).collect()
+        };
+        if self.wal.is_some() {
+            // Changes in the write-ahead log must not be replayed onto the cleared table
+            self.sync()?;
+        }
+        if self.options.shred {
+            self.data.fill(0);
+        }
+        self.resize_fd(self.options.index_capacity, self.options.data_size)?;
         self.index.clear();
-        self.mem.clear();
-        self.header.index_capacity = INITIAL_INDEX_CAPACITY as u32;
-        Ok(())
+        self.mem = MemoryManagment::new(self.data_start, self.data_start + self.data.len() as u64);
+        self.unindexed = 0;
+        self.header.index_capacity = self.options.index_capacity as u32;
+        self.run_hooks(|hooks, _| deleted.iter().for_each(|(key, value)| hooks.delete(key, value)));
+        self.maybe_flush()
     }
 
     /// Explicitly closes the table.
@@ -450,55 +1307,94 @@ impl Table {
     }
 
     pub(crate) fn is_valid(&self) -> bool {
-        let mut valid = true;
-        valid &= self.index.is_valid();
-        valid &= self.mem.is_valid();
-        if self.mem.start() < self.data_start {
-            println!("Data begins before data start: {} vs {}", self.mem.start(), self.data_start);
-            valid = false;
-        }
-        if self.mem.end() > self.data_start + self.data.len() as u64 {
-            println!("Data ends after data end: {} vs {}", self.mem.end(), self.data_start + self.data.len() as u64);
-            valid = false;
-        }
-        let used = self.mem.get_used();
-        for entry in self.index.get_entries() {
-            if entry.is_used()
-                && entry.data.size > 0
-                && !used.contains(&Used {
-                    start: entry.data.position,
-                    size: cmp::max(entry.data.size, 1),
-                    hash: entry.hash,
-                })
-            {
-                println!("Index entry at {} does not exist in mem", entry.data.position);
-                valid = false;
-            }
-        }
-        if used.len() != self.index.len() {
-            println!("Index and data disagree about entry count: {} vs {}", self.index.len(), used.len());
-            valid = false;
+        let report = self.verify(CheckLevel::Quick);
+        for finding in &report.findings {
+            println!("Table error: {:?}", finding);
         }
-        valid
+        report.is_ok()
     }
 
     /// Return a statistics struct
     pub fn stats(&self) -> Stats {
         Stats {
-            valid: self.is_valid(),
-            entries: self.len(),
+            valid: self.verify(CheckLevel::Quick).is_ok(),
+            entries: self.index.len(),
             size: self.size(),
             hash_size: self.index.capacity() as u64 * mem::size_of::<IndexEntry>() as u64,
             hash_free: (self.index.capacity() - self.index.len()) as u64 * mem::size_of::<IndexEntry>() as u64,
             data_size: self.mem.end() - self.mem.start(),
             data_free: self.mem.end() - self.mem.start() - self.mem.used_size(),
-            avg_size: if self.is_empty() { 0 } else { self.mem.used_size() / self.len() as u64 },
+            avg_size: if self.index.len() == 0 { 0 } else { self.mem.used_size() / self.index.len() as u64 },
             biggest_gap: self.mem.biggest_gap(),
+            pinned: self.index.pinned(),
             overhead: (self.size() - self.mem.used_size()) as f32 / self.size() as f32,
         }
     }
+
+    /// Returns statistics that are computed in constant time.
+    ///
+    /// In contrast to [`Table::stats`], the table is not verified, so this method can be called frequently, e.g.
+    /// to monitor a table.
+    pub fn quick_stats(&self) -> QuickStats {
+        let data_size = self.mem.end() - self.mem.start();
+        let data_used = self.mem.used_size();
+        QuickStats {
+            entries: self.index.len(),
+            index_capacity: self.index.capacity(),
+            load_factor: self.index.len() as f64 / self.index.capacity() as f64,
+            avg_displacement: self.index.avg_displacement(),
+            data_size,
+            data_used,
+            fragmentation: if data_size == 0 { 0.0 } else { (data_size - data_used) as f64 / data_size as f64 },
+        }
+    }
 }
 
+impl Drop for Table {
+    fn drop(&mut self) {
+        if self.is_read_only() {
+            return;
+        }
+        // Shared readers are released when the files are closed, after the table lock
+        self.lock_readers().ok();
+        self.shred_freed();
+        self.header.last_close = self.now();
+        self.header.set_open(false);
+        self.header.set_shared_writer(false);
+        if self.options.flush != FlushMode::Manual {
+            self.sync().ok();
+        }
+    }
+}
+
+
+/// Audit information stored in the table header
+#[derive(Debug, Clone, Serialize)]
+pub struct TableInfo {
+    /// Time of the table creation in seconds since the UNIX epoch
+    pub created: u64,
+
+    /// Version of this crate that created the table
+    pub version: String,
+
+    /// Time of the last clean close in seconds since the UNIX epoch, `None` if the table has never been closed
+    pub last_close: Option<u64>,
+
+    /// Whether the table was not closed cleanly the last time it was used
+    pub unclean_shutdown: bool,
+
+    /// Whether an interrupted resize of the index has been completed or rolled back when opening the table
+    pub interrupted_resize: bool,
+
+    /// Memory page size of the system using the table
+    pub page_size: u32,
+
+    /// Whether the table has been used on a system with a different page size before
+    pub page_size_changed: bool,
+
+    /// Version of the file format: 1, or 2 if the table contains or contained entries larger than 4 GiB
+    pub format_version: u8,
+}
 
 /// Struct containing table statistics
 #[derive(Debug, Serialize)]
@@ -506,7 +1402,7 @@ pub struct Stats {
     /// Whether the table is valid/consistent
     pub valid: bool,
 
-    /// Entries contained in the table
+    /// Entries contained in the table, including the entries of namespaces
     pub entries: usize,
 
     /// Total byte size of the table
@@ -528,8 +1424,36 @@ pub struct Stats {
     pub avg_size: u64,
 
     /// Biggest gap in data part
-    pub biggest_gap: u32,
+    pub biggest_gap: u64,
+
+    /// Entries pinned to the front of the data section via [`Table::pin_front`]
+    pub pinned: usize,
 
     /// Overhead fraction
     pub overhead: f32
 }
+
+/// Table statistics that are cheap to compute, see [`Table::quick_stats`]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct QuickStats {
+    /// Entries contained in the table, including the entries of namespaces
+    pub entries: usize,
+
+    /// Number of slots in the index
+    pub index_capacity: usize,
+
+    /// Fraction of the index slots that are used
+    pub load_factor: f64,
+
+    /// Average distance of the index entries from their ideal slots, i.e. the extra probes of a lookup
+    pub avg_displacement: f64,
+
+    /// Total size of the data part
+    pub data_size: u64,
+
+    /// Used size of the data part
+    pub data_used: u64,
+
+    /// Fraction of the data part that is free
+    pub fragmentation: f64,
+}
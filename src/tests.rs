@@ -1,13 +1,14 @@
-use std::{cmp, collections::HashMap, mem};
+use std::{cmp, collections::HashMap, fs, mem, path::Path};
 
 use rand::prelude::*;
 use rand_chacha::ChaCha8Rng;
 
 use crate::{
-    index::IndexEntry,
+    evict::FLAG_REFERENCED,
+    index::{IndexEntry, IndexEntryData, MAX_BLOCK_SIZE, MAX_POSITION},
     mmap::open_fd,
-    table::{hash_key, Header},
-    Table,
+    table::{entry_size, hash_key, total_size, Header},
+    Entry, Error, Table, FLAG_CHECKSUM, FLAG_PINNED, INDEX_HEADER, RESERVED_FLAGS,
 };
 
 type Rand = ChaCha8Rng;
@@ -27,7 +28,7 @@ fn random_data(rand: &mut Rand, max_size: usize) -> Vec<u8> {
 
 #[test]
 fn test_size() {
-    assert_eq!(36, mem::size_of::<Header>());
+    assert_eq!(72, mem::size_of::<Header>());
     assert_eq!(24, mem::size_of::<IndexEntry>());
     assert_eq!(24576, mem::size_of::<[IndexEntry; 1024]>());
 }
@@ -37,6 +38,35 @@ fn test_hash() {
     assert_eq!(16183295663280961421, hash_key("test".as_bytes()));
 }
 
+#[test]
+fn test_total_size_overflow() {
+    let mut rand = seeded_rng(42);
+    let header = mem::size_of::<Header>() as u128;
+    let entry = mem::size_of::<IndexEntry>() as u128;
+    for _ in 0..10000 {
+        let capacity = rand.gen::<u64>() >> rand.gen_range(0..64);
+        let data_size = rand.gen::<u64>() >> rand.gen_range(0..64);
+        let expected = header + capacity as u128 * entry + data_size as u128;
+        match total_size(capacity as usize, data_size) {
+            Ok(size) => assert_eq!(size as u128, expected),
+            Err(Error::TooLarge) => assert!(expected > MAX_POSITION as u128),
+            Err(err) => panic!("Unexpected error: {}", err),
+        }
+    }
+}
+
+#[test]
+fn test_entry_size_limits() {
+    let key = vec![0; u16::MAX as usize + 1];
+    assert!(matches!(entry_size(&key, &[]), Err(Error::TooLarge)));
+    assert_eq!(entry_size(&key[1..], &key).unwrap(), 2 * u16::MAX as u64 + 1);
+    let file = tempfile::NamedTempFile::new().unwrap();
+    let mut tbl = Table::create(file.path()).unwrap();
+    assert!(matches!(tbl.set(&key, &[]), Err(Error::TooLarge)));
+    assert!(tbl.is_valid());
+    assert!(tbl.is_empty());
+}
+
 #[test]
 fn test_create_new() {
     let file = tempfile::NamedTempFile::new().unwrap();
@@ -67,6 +97,107 @@ fn test_normal_use() {
     assert_eq!(tbl.get("key2".as_bytes()), None);
 }
 
+#[test]
+fn test_take() {
+    let file = tempfile::NamedTempFile::new().unwrap();
+    let mut tbl = Table::create(file.path()).unwrap();
+    for i in 0u32..100 {
+        tbl.set(&i.to_le_bytes(), &i.to_be_bytes()).unwrap();
+    }
+    let taken: Vec<_> = (0u32..50).map(|i| tbl.take(&i.to_le_bytes()).unwrap().unwrap()).collect();
+    // The values are still intact after the table has shrunk
+    for (i, value) in taken.iter().enumerate() {
+        assert_eq!(value, &(i as u32).to_be_bytes());
+    }
+    assert_eq!(tbl.take(&0u32.to_le_bytes()).unwrap(), None);
+    assert_eq!(tbl.len(), 50);
+    assert!(tbl.is_valid());
+}
+
+#[test]
+fn test_reserved_flags() {
+    let file = tempfile::NamedTempFile::new().unwrap();
+    let mut tbl = Table::options().checksums(true).max_data_size(Some(100_000)).create(file.path()).unwrap();
+    tbl.set_entry(Entry { key: "key".as_bytes(), value: "value".as_bytes(), flags: 0xffff }).unwrap();
+    assert_eq!(tbl.get_entry("key".as_bytes()).unwrap().flags, FLAG_PINNED | FLAG_CHECKSUM | 0xff);
+    assert_eq!(tbl.get_raw_entry("key".as_bytes()).unwrap().flags & RESERVED_FLAGS, FLAG_REFERENCED);
+    // Internal flags of the table are never returned
+    tbl.namespace("a").unwrap().set("key".as_bytes(), "value".as_bytes()).unwrap();
+    tbl.post("topic", "message".as_bytes()).unwrap();
+    tbl.record_stats().unwrap();
+    assert!(tbl.iter().all(|entry| entry.flags & RESERVED_FLAGS == 0));
+    assert!(tbl.iter_mut().all(|entry| entry.flags & RESERVED_FLAGS == 0));
+    let old = tbl.set_entry(Entry { key: "key".as_bytes(), value: "value2".as_bytes(), flags: 1 }).unwrap();
+    assert_eq!(old.unwrap().flags, FLAG_PINNED | FLAG_CHECKSUM | 0xff);
+    assert_eq!(tbl.delete_entry("key".as_bytes()).unwrap().unwrap().flags, FLAG_CHECKSUM | 1);
+}
+
+#[test]
+fn test_get_many() {
+    let file = tempfile::NamedTempFile::new().unwrap();
+    let mut tbl = Table::create(file.path()).unwrap();
+    for i in 0u32..1000 {
+        tbl.set(&i.to_le_bytes(), &i.to_be_bytes()).unwrap();
+    }
+    let keys: Vec<_> = (500u32..1500).rev().map(|i| i.to_le_bytes()).collect();
+    let keys: Vec<&[u8]> = keys.iter().map(|k| &k[..]).collect();
+    let values = tbl.get_many(&keys);
+    assert_eq!(values.len(), keys.len());
+    for (key, value) in keys.iter().zip(values) {
+        assert_eq!(value, tbl.get(key));
+    }
+    assert!(tbl.get_many(&[]).is_empty());
+}
+
+/// Panicking calls that are allowed outside of `invariant.rs`: the explicit test helper, the fixed Arrow schema and
+/// the `Extend` implementation, which cannot return errors and is documented to panic with the `no-panic` feature
+const ALLOWED_PANICS: &[&str] =
+    &["Table invariants violated", "Columns must match the schema", "Failed to extend table"];
+
+#[test]
+fn test_no_panic_paths() {
+    // The `no-panic` feature relies on internal code only failing via the macros in `invariant.rs`
+    let patterns = ["panic!(", ".expect(", ".unwrap()", "assert!(", "assert_eq!(", "assert_ne!(", "unreachable!("];
+    let mut found = vec![];
+    for file in fs::read_dir(Path::new(env!("CARGO_MANIFEST_DIR")).join("src")).unwrap() {
+        let path = file.unwrap().path();
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        if !name.ends_with(".rs") || name == "tests.rs" || name == "invariant.rs" {
+            continue;
+        }
+        let source = fs::read_to_string(&path).unwrap();
+        let lines: Vec<_> = source.lines().collect();
+        let mut nr = 0;
+        while nr < lines.len() {
+            let line = lines[nr];
+            let code = line.trim_start();
+            nr += 1;
+            if code == "#[cfg(test)]" && nr < lines.len() && lines[nr].ends_with('{') {
+                // Skip the test item up to its closing brace
+                let end = format!("{}}}", &line[..line.len() - code.len()]);
+                nr += lines[nr..].iter().position(|l| *l == end).unwrap() + 1;
+                continue;
+            }
+            let allowed = code.contains("debug_assert") || ALLOWED_PANICS.iter().any(|a| code.contains(a));
+            if !code.starts_with("//") && !allowed && patterns.iter().any(|p| code.contains(p)) {
+                found.push(format!("{}:{}: {}", name, nr, code));
+            }
+        }
+    }
+    assert!(found.is_empty(), "Panicking calls outside of invariant.rs:\n{}", found.join("\n"));
+}
+
+#[test]
+#[cfg(feature = "no-panic")]
+fn test_no_panic_violation() {
+    let file = tempfile::NamedTempFile::new().unwrap();
+    let mut tbl = Table::create(file.path()).unwrap();
+    tbl.set("key1".as_bytes(), "value1".as_bytes()).unwrap();
+    tbl.mem.check_invariant(false, "injected violation");
+    assert!(matches!(tbl.set("key2".as_bytes(), &[]), Err(Error::Corrupt("injected violation"))));
+    assert_eq!(tbl.get("key1".as_bytes()), Some("value1".as_bytes()));
+}
+
 #[test]
 fn test_zero_size() {
     let file = tempfile::NamedTempFile::new().unwrap();
@@ -91,7 +222,7 @@ fn test_endianness() {
     let hash = tbl.index.get_entries()[index].hash;
     tbl.close();
     {
-        let mut tbl = open_fd(file.path(), false).unwrap();
+        let tbl = open_fd(file.path(), false, &Default::default()).unwrap();
         tbl.header.flags[0] = if tbl.header.flags[0] > 0 { 0 } else { 2 };
         tbl.header.fix_endianness();
         tbl.index_entries[index].fix_endianness();
@@ -102,6 +233,295 @@ fn test_endianness() {
     assert_eq!(tbl.get("key1".as_bytes()), Some("value1".as_bytes()));
 }
 
+#[test]
+fn test_upgrade_v1() {
+    // Table of format 01: magic, flags and index capacity followed by the index and the data section
+    let capacity = 128usize;
+    let data_start = 36 + capacity * mem::size_of::<IndexEntry>();
+    let mut bytes = vec![0u8; data_start + 10];
+    bytes[..16].copy_from_slice(b"rust-persist-01\n");
+    bytes[16] = if 1u16.to_be() == 1 { 2 } else { 0 };
+    bytes[32..36].copy_from_slice(&(capacity as u32).to_ne_bytes());
+    let hash = hash_key("key1".as_bytes());
+    let slot = 36 + (hash as usize & (capacity - 1)) * mem::size_of::<IndexEntry>();
+    bytes[slot..slot + 8].copy_from_slice(&hash.to_ne_bytes());
+    bytes[slot + 8..slot + 16].copy_from_slice(&(data_start as u64).to_ne_bytes());
+    bytes[slot + 16..slot + 20].copy_from_slice(&10u32.to_ne_bytes());
+    bytes[slot + 20..slot + 22].copy_from_slice(&4u16.to_ne_bytes());
+    bytes[data_start..].copy_from_slice(b"key1value1");
+    let file = tempfile::NamedTempFile::new().unwrap();
+    fs::write(file.path(), &bytes).unwrap();
+    let tbl = Table::open_read_only(file.path()).unwrap();
+    assert_eq!(tbl.get("key1".as_bytes()), Some("value1".as_bytes()));
+    tbl.close();
+    assert_eq!(fs::read(file.path()).unwrap(), bytes);
+    let mut tbl = Table::open(file.path()).unwrap();
+    // The converted table replaces the file
+    assert_eq!(fs::read(file.path()).unwrap()[..16], INDEX_HEADER);
+    assert!(!crate::batch::sibling_path(file.path(), ".upgrade").exists());
+    assert!(tbl.is_valid());
+    assert_eq!(tbl.get("key1".as_bytes()), Some("value1".as_bytes()));
+    assert!(!tbl.info().page_size_changed);
+    tbl.set("key2".as_bytes(), "value2".as_bytes()).unwrap();
+    tbl.close();
+    let tbl = Table::open(file.path()).unwrap();
+    assert!(tbl.is_valid());
+    assert_eq!(tbl.len(), 2);
+    assert_eq!(tbl.get("key1".as_bytes()), Some("value1".as_bytes()));
+}
+
+#[test]
+fn test_info() {
+    let file = tempfile::NamedTempFile::new().unwrap();
+    let tbl = Table::create(file.path()).unwrap();
+    let info = tbl.info();
+    assert!(info.created > 0);
+    assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+    assert_eq!(info.last_close, None);
+    assert!(!info.unclean_shutdown);
+    tbl.close();
+    let tbl = Table::open(file.path()).unwrap();
+    let info = tbl.info();
+    assert!(info.last_close.unwrap() >= info.created);
+    assert!(!info.unclean_shutdown);
+    tbl.close();
+    {
+        let tbl = open_fd(file.path(), false, &Default::default()).unwrap();
+        tbl.header.set_open(true);
+    }
+    let tbl = Table::open(file.path()).unwrap();
+    assert!(tbl.info().unclean_shutdown);
+    assert!(!tbl.info().page_size_changed);
+}
+
+#[test]
+fn test_page_size_changed() {
+    let file = tempfile::NamedTempFile::new().unwrap();
+    let tbl = Table::create(file.path()).unwrap();
+    let page_size = tbl.info().page_size;
+    assert!(page_size > 0);
+    tbl.close();
+    {
+        let tbl = open_fd(file.path(), false, &Default::default()).unwrap();
+        tbl.header.page_size = page_size * 4;
+    }
+    let tbl = Table::open(file.path()).unwrap();
+    assert!(tbl.is_valid());
+    assert!(tbl.info().page_size_changed);
+    assert_eq!(tbl.info().page_size, page_size);
+    tbl.close();
+    let tbl = Table::open(file.path()).unwrap();
+    assert!(!tbl.info().page_size_changed);
+}
+
+#[test]
+fn test_set_many() {
+    let file = tempfile::NamedTempFile::new().unwrap();
+    let mut tbl = Table::create(file.path()).unwrap();
+    tbl.set(&0u32.to_ne_bytes(), &[]).unwrap();
+    tbl.set_many((0u32..10_000).map(|i| (i.to_ne_bytes(), i.to_be_bytes()))).unwrap();
+    assert_eq!(tbl.len(), 10_000);
+    assert_eq!(tbl.index.capacity(), 16_384);
+    for i in 0u32..10_000 {
+        assert_eq!(tbl.get(&i.to_ne_bytes()), Some(&i.to_be_bytes() as &[u8]));
+    }
+    assert!(tbl.is_valid());
+    let key = [0; u16::MAX as usize + 1];
+    assert!(matches!(tbl.set_many(vec![(&[1u8] as &[u8], &[] as &[u8]), (&key, &[])]), Err(Error::TooLarge)));
+    assert!(tbl.get(&[1]).is_none());
+}
+
+#[test]
+fn test_open_untrusted() {
+    let file = tempfile::NamedTempFile::new().unwrap();
+    let mut tbl = Table::create(file.path()).unwrap();
+    tbl.set("key1".as_bytes(), "value1".as_bytes()).unwrap();
+    tbl.close();
+    let tbl = Table::open_untrusted(file.path()).unwrap();
+    assert_eq!(tbl.get("key1".as_bytes()), Some("value1".as_bytes()));
+    tbl.close();
+    let offset = {
+        let tbl = Table::open(file.path()).unwrap();
+        mem::size_of::<Header>() + tbl.index.get_entries().iter().position(|e| e.is_used()).unwrap() * 24
+    };
+    let bytes = fs::read(file.path()).unwrap();
+    // Entry pointing behind the end of the data section
+    let mut broken = bytes.clone();
+    broken[offset + 8..offset + 16].copy_from_slice(&u64::MAX.to_ne_bytes());
+    fs::write(file.path(), &broken).unwrap();
+    assert!(matches!(Table::open_untrusted(file.path()), Err(Error::Corrupt(_))));
+    // Entry at the wrong position in the index
+    let mut broken = bytes;
+    let mut hash = [0; 8];
+    hash.copy_from_slice(&broken[offset..offset + 8]);
+    broken[offset..offset + 8].copy_from_slice(&(u64::from_ne_bytes(hash) + 1).to_ne_bytes());
+    fs::write(file.path(), &broken).unwrap();
+    assert!(matches!(Table::open_untrusted(file.path()), Err(Error::Corrupt(_))));
+}
+
+#[test]
+fn test_from_map() {
+    let file = tempfile::NamedTempFile::new().unwrap();
+    let map: HashMap<_, _> = (0u32..1000).map(|i| (i.to_ne_bytes(), i.to_be_bytes())).collect();
+    let tbl = Table::from_map(file.path(), &map).unwrap();
+    assert_eq!(tbl.len(), 1000);
+    assert_eq!(tbl.index.capacity(), 2048);
+    assert_eq!(tbl.mem.free_tail(), 0);
+    for (key, value) in &map {
+        assert_eq!(tbl.get(key), Some(&value[..]));
+    }
+    assert!(tbl.is_valid());
+}
+
+#[test]
+fn test_open_corrupt() {
+    let file = tempfile::NamedTempFile::new().unwrap();
+    let mut tbl = Table::create(file.path()).unwrap();
+    tbl.set("key1".as_bytes(), "value1".as_bytes()).unwrap();
+    let offset = mem::size_of::<Header>() + tbl.index.get_entries().iter().position(|e| e.is_used()).unwrap() * 24;
+    tbl.close();
+    let bytes = fs::read(file.path()).unwrap();
+    let mut broken = bytes.clone();
+    broken[offset + 16..offset + 20].copy_from_slice(&u32::MAX.to_ne_bytes());
+    fs::write(file.path(), &broken).unwrap();
+    assert!(matches!(Table::open(file.path()), Err(Error::Corrupt("entry outside of the data section"))));
+    fs::write(file.path(), &bytes[..mem::size_of::<Header>() + 100]).unwrap();
+    assert!(matches!(Table::open(file.path()), Err(Error::Corrupt("file is smaller than the index"))));
+}
+
+#[test]
+fn test_wide_sizes() {
+    for &(position, size) in &[(0, 0), (72, u32::MAX as u64), (MAX_POSITION, 1 << 32), (1 << 40, MAX_BLOCK_SIZE)] {
+        let data = IndexEntryData::new(position, size, 3, FLAG_CHECKSUM);
+        assert_eq!((data.position(), data.size(), data.key_size, data.flags), (position, size, 3, FLAG_CHECKSUM));
+    }
+    let file = tempfile::NamedTempFile::new().unwrap();
+    let mut tbl = Table::create(file.path()).unwrap();
+    tbl.set("key1".as_bytes(), "value1".as_bytes()).unwrap();
+    assert_eq!(tbl.info().format_version, 1);
+    let offset = mem::size_of::<Header>() + tbl.index.get_entries().iter().position(|e| e.is_used()).unwrap() * 24;
+    tbl.close();
+    let mut bytes = fs::read(file.path()).unwrap();
+    let mut buf = [0; 8];
+    buf.copy_from_slice(&bytes[offset + 8..offset + 16]);
+    let raw = u64::from_ne_bytes(buf);
+    // Sets bit 32 of the size, which is stored above the 48 position bits
+    bytes[offset + 8..offset + 16].copy_from_slice(&(raw | 1 << 48).to_ne_bytes());
+    fs::write(file.path(), &bytes).unwrap();
+    assert!(matches!(Table::open(file.path()), Err(Error::Corrupt("entry size requires format v2"))));
+    bytes[16] |= 1 << 4;
+    fs::write(file.path(), &bytes).unwrap();
+    assert!(matches!(Table::open(file.path()), Err(Error::Corrupt("entry outside of the data section"))));
+    bytes[offset + 8..offset + 16].copy_from_slice(&raw.to_ne_bytes());
+    fs::write(file.path(), &bytes).unwrap();
+    let tbl = Table::open(file.path()).unwrap();
+    assert_eq!(tbl.info().format_version, 2);
+    assert_eq!(tbl.get("key1".as_bytes()), Some("value1".as_bytes()));
+}
+
+#[test]
+fn test_read_only() {
+    let file = tempfile::NamedTempFile::new().unwrap();
+    let mut tbl = Table::create(file.path()).unwrap();
+    tbl.set("key1".as_bytes(), "value1".as_bytes()).unwrap();
+    assert!(matches!(Table::open_read_only(file.path()), Err(Error::TableLocked)));
+    tbl.close();
+    let reader1 = Table::open_read_only(file.path()).unwrap();
+    let reader2 = Table::options().read_only(true).open(file.path()).unwrap();
+    assert!(matches!(Table::open(file.path()), Err(Error::TableLocked)));
+    assert!(reader1.is_read_only());
+    assert_eq!(reader1.get("key1".as_bytes()), Some("value1".as_bytes()));
+    reader1.close();
+    let mut reader1 = reader2;
+    assert!(matches!(reader1.set("key2".as_bytes(), &[]), Err(Error::ReadOnly)));
+    assert!(matches!(reader1.delete("key1".as_bytes()), Err(Error::ReadOnly)));
+    assert!(matches!(reader1.clear(), Err(Error::ReadOnly)));
+    reader1.get_mut("key1".as_bytes()).unwrap()[0] = b'X';
+    reader1.close();
+    let tbl = Table::open(file.path()).unwrap();
+    assert_eq!(tbl.get("key1".as_bytes()), Some("value1".as_bytes()));
+}
+
+#[test]
+fn test_resident_bytes() {
+    let file = tempfile::NamedTempFile::new().unwrap();
+    let mut tbl = Table::create(file.path()).unwrap();
+    tbl.set("key1".as_bytes(), &[1u8; 10000]).unwrap();
+    tbl.advise_shared().unwrap();
+    assert_eq!(tbl.get("key1".as_bytes()), Some(&[1u8; 10000] as &[u8]));
+    let resident = tbl.resident_bytes().unwrap();
+    assert!(resident > 0);
+    assert!(resident <= tbl.size());
+}
+
+#[test]
+fn test_get_range() {
+    let file = tempfile::NamedTempFile::new().unwrap();
+    let mut tbl = Table::options().checksums(true).create(file.path()).unwrap();
+    let value: Vec<u8> = (0..=255).collect();
+    tbl.set("key".as_bytes(), &value).unwrap();
+    assert_eq!(tbl.get_range("key".as_bytes(), 0, 256), Some(&value[..]));
+    assert_eq!(tbl.get_range("key".as_bytes(), 10, 5), Some(&value[10..15]));
+    assert_eq!(tbl.get_range("key".as_bytes(), 256, 0), Some(&[][..]));
+    assert_eq!(tbl.get_range("key".as_bytes(), 250, 7), None);
+    assert_eq!(tbl.get_range("key".as_bytes(), 1, u64::MAX), None);
+    assert_eq!(tbl.get_range("missing".as_bytes(), 0, 0), None);
+    tbl.set(&[], &[]).unwrap();
+    assert_eq!(tbl.get_range(&[], 0, 0), Some(&[][..]));
+}
+
+#[test]
+fn test_update() {
+    let file = tempfile::NamedTempFile::new().unwrap();
+    let mut tbl = Table::options().checksums(true).create(file.path()).unwrap();
+    for i in 0u16..1000 {
+        let key = (i % 200).to_ne_bytes();
+        tbl.update(&key, |old| Some([old.unwrap_or_default(), &i.to_ne_bytes()].concat())).unwrap();
+    }
+    assert_eq!(tbl.len(), 200);
+    let expected: Vec<u8> = [7u16, 207, 407, 607, 807].iter().flat_map(|i| i.to_ne_bytes().to_vec()).collect();
+    assert_eq!(tbl.get(&7u16.to_ne_bytes()), Some(&expected[..]));
+    for i in 0u16..150 {
+        tbl.update(&i.to_ne_bytes(), |old| {
+            assert!(old.is_some());
+            None
+        })
+        .unwrap();
+    }
+    tbl.update("missing".as_bytes(), |old| {
+        assert!(old.is_none());
+        None
+    })
+    .unwrap();
+    assert_eq!(tbl.len(), 50);
+    assert!(tbl.is_valid());
+    assert!(tbl.verify_checksums().is_empty());
+    tbl.close();
+    let tbl = Table::open(file.path()).unwrap();
+    assert_eq!(tbl.len(), 50);
+    assert!(tbl.get(&7u16.to_ne_bytes()).is_none());
+    assert_eq!(tbl.get(&199u16.to_ne_bytes()).unwrap().len(), 10);
+}
+
+#[test]
+fn test_quick_stats() {
+    let file = tempfile::NamedTempFile::new().unwrap();
+    let mut tbl = Table::create(file.path()).unwrap();
+    assert_eq!(tbl.quick_stats().fragmentation, 0.0);
+    for i in 0u16..100 {
+        tbl.set(&i.to_ne_bytes(), &[0; 98]).unwrap();
+    }
+    for i in 0u16..10 {
+        tbl.delete_entry_no_shrink(&i.to_ne_bytes());
+    }
+    let stats = tbl.quick_stats();
+    let full = tbl.stats();
+    assert_eq!(stats.entries, 90);
+    assert_eq!(stats.load_factor, 90.0 / stats.index_capacity as f64);
+    assert_eq!((stats.data_size, stats.data_size - stats.data_used), (full.data_size, full.data_free));
+    assert!(stats.fragmentation >= 0.1);
+}
+
 fn test_one_seed(seed: u64) {
     let mut rand = seeded_rng(seed);
     let mut data = HashMap::new();
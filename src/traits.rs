@@ -0,0 +1,298 @@
+use std::borrow::Cow;
+
+use crate::{DegradedTable, Error, ReadOnlyTable, Table};
+
+/// Boxed iterator over all entries of a table as returned by [`TableRead::entries`]
+pub type Entries<'a, K, V> = Box<dyn Iterator<Item = Result<(K, V), Error>> + 'a>;
+
+/// Boxed iterator over all entries of a table as returned by [`TableRead::entries_borrowed`]
+pub type BorrowedEntries<'a, K, V> = Box<dyn Iterator<Item = Result<(Cow<'a, K>, Cow<'a, V>), Error>> + 'a>;
+
+/// Read access to a table
+///
+/// This trait is implemented by [`Table`], [`ReadOnlyTable`], [`DegradedTable`], [`ShardedTable`](crate::ShardedTable),
+/// [`ConcurrentTable`](crate::ConcurrentTable), [`Namespace`](crate::Namespace) and the typed wrappers, so code that
+/// only reads can be generic over all of them.
+///
+/// ```
+/// use rust_persist::{Table, TableRead};
+///
+/// fn count_present<T: TableRead<Key = [u8]>>(table: &T, keys: &[&[u8]]) -> usize {
+///     keys.iter().filter(|key| table.contains(key).unwrap()).count()
+/// }
+///
+/// let mut table = Table::create("example_traits.tbl").unwrap();
+/// table.set("key1".as_bytes(), "value1".as_bytes()).unwrap();
+/// assert_eq!(count_present(&table, &["key1".as_bytes(), "key2".as_bytes()]), 1);
+/// ```
+pub trait TableRead {
+    /// Type of the keys as they are passed to the methods
+    type Key: ?Sized;
+    /// Type of the keys as they are returned by [`TableRead::entries`]
+    type OwnedKey;
+    /// Type of the values as they are passed to [`TableWrite::set`]
+    type Value: ?Sized;
+    /// Type of the values as they are returned by [`TableRead::get`]
+    type OwnedValue;
+
+    /// Returns whether an entry is associated with the given key.
+    fn contains(&self, key: &Self::Key) -> Result<bool, Error>;
+
+    /// Loads and returns the value stored with the given key.
+    fn get(&self, key: &Self::Key) -> Result<Option<Self::OwnedValue>, Error>;
+
+    /// Returns an iterator over all entries in no particular order
+    fn entries(&self) -> Entries<'_, Self::OwnedKey, Self::OwnedValue>;
+
+    /// Returns an iterator over all entries in no particular order, borrowing keys and values where possible
+    ///
+    /// Tables that keep their data in memory return references into it, so iterating does not copy every entry. The
+    /// default implementation returns the owned entries of [`TableRead::entries`].
+    #[inline]
+    fn entries_borrowed(&self) -> BorrowedEntries<'_, Self::Key, Self::Value>
+    where
+        Self::Key: ToOwned<Owned = Self::OwnedKey>,
+        Self::Value: ToOwned<Owned = Self::OwnedValue>, {
+        Box::new(self.entries().map(|entry| entry.map(|(key, value)| (Cow::Owned(key), Cow::Owned(value)))))
+    }
+
+    /// Returns the number of entries in the table
+    fn len(&self) -> usize;
+
+    /// Returns whether the table is empty
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Layers that are backed by a single [`Table`]
+///
+/// This trait is not exported, it only enables methods of the wrappers that need the underlying table, e.g.
+/// [`TypedTable::stats`](crate::TypedTable::stats).
+pub trait AsTable {
+    /// Returns the underlying table
+    ///
+    /// Beware that the underlying table exposes the raw data as stored by all layers.
+    fn table(&self) -> &Table;
+}
+
+/// Write access to a table
+///
+/// This trait is implemented by [`Table`], [`ShardedTable`](crate::ShardedTable),
+/// [`ConcurrentTable`](crate::ConcurrentTable), [`Namespace`](crate::Namespace) and the typed wrappers, but not by
+/// the read-only handles.
+pub trait TableWrite: TableRead {
+    /// Stores the given key/value pair and returns whether a value has been replaced.
+    fn set(&mut self, key: &Self::Key, value: &Self::Value) -> Result<bool, Error>;
+
+    /// Deletes the entry with the given key and returns whether an entry has been deleted.
+    fn delete(&mut self, key: &Self::Key) -> Result<bool, Error>;
+
+    /// Deletes and returns the entry with the given key.
+    #[inline]
+    fn take(&mut self, key: &Self::Key) -> Result<Option<Self::OwnedValue>, Error> {
+        let value = self.get(key)?;
+        if value.is_some() {
+            self.delete(key)?;
+        }
+        Ok(value)
+    }
+
+    /// Deletes all entries
+    fn clear(&mut self) -> Result<(), Error>;
+
+    /// Forces to write all pending changes to disk
+    fn flush(&mut self) -> Result<(), Error>;
+
+    /// Forces defragmentation of the data section of the underlying table, see [`Table::defragment`].
+    fn defragment(&mut self) -> Result<(), Error>;
+}
+
+impl TableRead for Table {
+    type Key = [u8];
+    type OwnedKey = Vec<u8>;
+    type Value = [u8];
+    type OwnedValue = Vec<u8>;
+
+    #[inline]
+    fn contains(&self, key: &[u8]) -> Result<bool, Error> {
+        Ok(Table::contains(self, key))
+    }
+
+    #[inline]
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        Ok(Table::get(self, key).map(|value| value.to_vec()))
+    }
+
+    #[inline]
+    fn entries(&self) -> Entries<'_, Vec<u8>, Vec<u8>> {
+        Box::new(self.iter_owned().map(Ok))
+    }
+
+    #[inline]
+    fn entries_borrowed(&self) -> BorrowedEntries<'_, [u8], [u8]> {
+        Box::new(self.iter().map(|entry| Ok((Cow::Borrowed(entry.key), Cow::Borrowed(entry.value)))))
+    }
+
+    #[inline]
+    fn len(&self) -> usize {
+        Table::len(self)
+    }
+}
+
+impl AsTable for Table {
+    #[inline]
+    fn table(&self) -> &Table {
+        self
+    }
+}
+
+impl TableWrite for Table {
+    #[inline]
+    fn set(&mut self, key: &[u8], value: &[u8]) -> Result<bool, Error> {
+        Table::set(self, key, value).map(|old| old.is_some())
+    }
+
+    #[inline]
+    fn delete(&mut self, key: &[u8]) -> Result<bool, Error> {
+        Table::delete(self, key).map(|old| old.is_some())
+    }
+
+    #[inline]
+    fn take(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        Table::take(self, key)
+    }
+
+    #[inline]
+    fn clear(&mut self) -> Result<(), Error> {
+        Table::clear(self)
+    }
+
+    #[inline]
+    fn flush(&mut self) -> Result<(), Error> {
+        Table::flush(self)
+    }
+
+    #[inline]
+    fn defragment(&mut self) -> Result<(), Error> {
+        Table::defragment(self)
+    }
+}
+
+/// Implements [`TableRead`] for the read-only handles by delegating to the table they dereference to
+macro_rules! impl_read_only {
+    ($handle:ty) => {
+        impl TableRead for $handle {
+            type Key = [u8];
+            type OwnedKey = Vec<u8>;
+            type Value = [u8];
+            type OwnedValue = Vec<u8>;
+
+            #[inline]
+            fn contains(&self, key: &[u8]) -> Result<bool, Error> {
+                TableRead::contains(&**self, key)
+            }
+
+            #[inline]
+            fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+                TableRead::get(&**self, key)
+            }
+
+            #[inline]
+            fn entries(&self) -> Entries<'_, Vec<u8>, Vec<u8>> {
+                TableRead::entries(&**self)
+            }
+
+            #[inline]
+            fn entries_borrowed(&self) -> BorrowedEntries<'_, [u8], [u8]> {
+                TableRead::entries_borrowed(&**self)
+            }
+
+            #[inline]
+            fn len(&self) -> usize {
+                Table::len(self)
+            }
+        }
+
+        impl AsTable for $handle {
+            #[inline]
+            fn table(&self) -> &Table {
+                self
+            }
+        }
+    };
+}
+
+impl_read_only!(ReadOnlyTable);
+impl_read_only!(DegradedTable);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn copy_all<R: TableRead<Key = [u8], OwnedValue = Vec<u8>>, W: TableWrite<Key = [u8], Value = [u8]>>(
+        from: &R, to: &mut W, keys: &[&[u8]],
+    ) {
+        for key in keys {
+            if let Some(value) = from.get(key).unwrap() {
+                to.set(key, &value).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn test_generic_access() {
+        let file1 = tempfile::NamedTempFile::new().unwrap();
+        let file2 = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::create(file1.path()).unwrap();
+        assert!(TableRead::is_empty(&tbl));
+        assert!(!TableWrite::set(&mut tbl, "key1".as_bytes(), "value1".as_bytes()).unwrap());
+        assert!(TableWrite::set(&mut tbl, "key1".as_bytes(), "value2".as_bytes()).unwrap());
+        TableWrite::set(&mut tbl, "key2".as_bytes(), "value3".as_bytes()).unwrap();
+        tbl.close();
+        let ro = Table::open_read_only(file1.path()).unwrap();
+        assert_eq!(TableRead::len(&ro), 2);
+        let mut tbl = Table::create(file2.path()).unwrap();
+        copy_all(&ro, &mut tbl, &["key1".as_bytes(), "key2".as_bytes(), "key3".as_bytes()]);
+        assert_eq!(tbl.len(), 2);
+        assert_eq!(TableRead::get(&tbl, "key1".as_bytes()).unwrap(), Some("value2".as_bytes().to_vec()));
+        assert_eq!(ro.entries().count(), 2);
+        assert!(TableWrite::delete(&mut tbl, "key2".as_bytes()).unwrap());
+        assert!(!TableWrite::delete(&mut tbl, "key2".as_bytes()).unwrap());
+        assert_eq!(TableWrite::take(&mut tbl, "key1".as_bytes()).unwrap(), Some("value2".as_bytes().to_vec()));
+        assert!(TableRead::is_empty(&tbl));
+    }
+
+    fn fill<W: TableWrite<Key = [u8], OwnedKey = Vec<u8>, Value = [u8], OwnedValue = Vec<u8>>>(tbl: &mut W) {
+        for i in 0u32..100 {
+            assert!(!tbl.set(&i.to_le_bytes(), &[i as u8; 10]).unwrap());
+        }
+        assert_eq!(tbl.len(), 100);
+        assert!(tbl.delete(&5u32.to_le_bytes()).unwrap());
+        assert_eq!(tbl.take(&6u32.to_le_bytes()).unwrap(), Some(vec![6; 10]));
+        assert_eq!(tbl.get(&7u32.to_le_bytes()).unwrap(), Some(vec![7; 10]));
+        assert!(!tbl.contains(&5u32.to_le_bytes()).unwrap());
+        assert_eq!(tbl.entries().count(), 98);
+        assert_eq!(tbl.entries_borrowed().filter(|entry| entry.as_ref().unwrap().1[0] == 8).count(), 1);
+        tbl.defragment().unwrap();
+        tbl.flush().unwrap();
+    }
+
+    #[test]
+    fn test_layers() {
+        let dir = tempfile::tempdir().unwrap();
+        fill(&mut crate::ShardedTable::create(dir.path().join("sharded"), 4).unwrap());
+        let mut tbl = crate::ConcurrentTable::create(dir.path().join("concurrent"), 4).unwrap();
+        fill(&mut tbl);
+        tbl.clear().unwrap();
+        assert!(TableRead::is_empty(&tbl));
+        let mut tbl = Table::create(dir.path().join("namespace")).unwrap();
+        tbl.set("plain".as_bytes(), "value".as_bytes()).unwrap();
+        fill(&mut tbl.namespace("a").unwrap());
+        tbl.close();
+        let tbl = Table::open_degraded(dir.path().join("namespace")).unwrap();
+        assert_eq!(TableRead::get(&tbl, "plain".as_bytes()).unwrap(), Some("value".as_bytes().to_vec()));
+        assert!(AsTable::table(&tbl).size() > 0);
+    }
+}
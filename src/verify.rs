@@ -0,0 +1,246 @@
+use std::cmp;
+
+use crate::{
+    guard::{GUARD_HASH, GUARD_SIZE},
+    memmngr::Used,
+    table::check_key_hash,
+    Table,
+};
+
+/// How thoroughly [`Table::verify`] checks the table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckLevel {
+    /// Only checks the index and the block accounting, the data of the entries is not read
+    Quick,
+    /// Additionally reads all entries and verifies their key hashes and checksums
+    Full,
+}
+
+/// A single inconsistency found by [`Table::verify`]
+///
+/// Slots are positions in the index, all other positions are relative to the start of the data section.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Finding {
+    /// An index entry is not at the slot where lookups search for it
+    MisplacedIndexEntry {
+        /// Slot of the entry
+        slot: usize,
+        /// Slot where lookups find the entry, if at all
+        expected: Option<usize>,
+    },
+    /// The entry count stored in the index does not match the number of used slots
+    IndexCountMismatch {
+        /// Stored entry count
+        stored: usize,
+        /// Number of used slots
+        actual: usize,
+    },
+    /// The key of an index entry is larger than its data
+    KeyLargerThanEntry {
+        /// Slot of the entry
+        slot: usize,
+    },
+    /// The data of an index entry is not within the data section
+    EntryOutOfBounds {
+        /// Slot of the entry
+        slot: usize,
+    },
+    /// The data of an index entry is not registered as a used block
+    MissingBlock {
+        /// Slot of the entry
+        slot: usize,
+        /// Position of the data of the entry
+        position: u64,
+    },
+    /// The index and the data section disagree about the number of entries
+    EntryCountMismatch {
+        /// Number of entries in the index (including entries not indexed yet)
+        index: usize,
+        /// Number of used blocks in the data section
+        data: usize,
+    },
+    /// A block starts before the previous block ends
+    OverlappingBlocks {
+        /// Start of the block
+        position: u64,
+        /// End of the previous block
+        previous_end: u64,
+    },
+    /// A range of the data section is neither used nor free
+    UntrackedSpace {
+        /// Start of the range
+        start: u64,
+        /// End of the range
+        end: u64,
+    },
+    /// Two free blocks follow each other without being merged
+    AdjacentFreeBlocks {
+        /// Start of the second block
+        position: u64,
+    },
+    /// A block has a size of zero
+    ZeroSizeBlock {
+        /// Start of the block
+        position: u64,
+    },
+    /// The last block ends after the end of the data section
+    BlockBeyondEnd {
+        /// End of the last block
+        end: u64,
+    },
+    /// The stored size of all used blocks does not match their actual size
+    UsedSizeMismatch {
+        /// Stored size of all used blocks
+        stored: u64,
+        /// Actual size of all used blocks
+        actual: u64,
+    },
+    /// The managed blocks lie outside of the data section
+    DataOutOfBounds,
+    /// The key of an entry does not hash to the hash stored in the index (only checked by [`CheckLevel::Full`])
+    HashMismatch {
+        /// Slot of the entry
+        slot: usize,
+    },
+    /// The data of an entry does not match its checksum (only checked by [`CheckLevel::Full`])
+    ChecksumMismatch {
+        /// Slot of the entry
+        slot: usize,
+    },
+    /// The guard bytes behind a data block have been overwritten (only checked by [`CheckLevel::Full`]), see
+    /// [`TableOptions::guard_bytes`](crate::TableOptions::guard_bytes)
+    GuardOverwritten {
+        /// Start of the guard bytes
+        position: u64,
+    },
+}
+
+/// Result of [`Table::verify`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IntegrityReport {
+    /// The level the table has been checked with
+    pub level: CheckLevel,
+    /// All inconsistencies that have been found
+    pub findings: Vec<Finding>,
+}
+
+impl IntegrityReport {
+    /// Returns whether no inconsistencies have been found
+    #[inline]
+    pub fn is_ok(&self) -> bool {
+        self.findings.is_empty()
+    }
+}
+
+impl Table {
+    /// Checks the table for inconsistencies and returns a report listing all of them.
+    ///
+    /// In contrast to the checks when opening a table, this does not stop at the first problem, so it can be used
+    /// for periodic health checks. See [`CheckLevel`] for what is checked.
+    pub fn verify(&self, level: CheckLevel) -> IntegrityReport {
+        let mut findings = Vec::new();
+        let data_start = self.data_start;
+        let data_end = data_start + self.data.len() as u64;
+        self.index.check(&mut findings);
+        self.mem.check(data_start, &mut findings);
+        if self.mem.start() < data_start || self.mem.end() > data_end {
+            findings.push(Finding::DataOutOfBounds);
+        }
+        let used = self.mem.get_used();
+        for (slot, entry) in self.index.get_entries().iter().enumerate() {
+            if !entry.is_used() {
+                continue;
+            }
+            let block = &entry.data;
+            let size = cmp::max(block.size(), 1);
+            if block.position() < data_start
+                || !matches!(block.position().checked_add(size as u64), Some(end) if end <= data_end)
+            {
+                findings.push(Finding::EntryOutOfBounds { slot });
+                continue;
+            }
+            if block.size() > 0 && !used.contains(&Used { start: block.position(), size, hash: entry.hash }) {
+                findings.push(Finding::MissingBlock { slot, position: block.position() - data_start });
+            }
+            if level == CheckLevel::Full && block.key_size as u64 <= block.size() {
+                if !check_key_hash(self.header, self.get_data(block.position(), block.key_size as u64), entry.hash) {
+                    findings.push(Finding::HashMismatch { slot });
+                } else if self.verify_block(block).is_err() {
+                    findings.push(Finding::ChecksumMismatch { slot });
+                }
+            }
+        }
+        if level == CheckLevel::Full {
+            for block in used.iter().filter(|b| b.hash == GUARD_HASH && b.size == GUARD_SIZE) {
+                if !self.guard_intact(block.start) {
+                    findings.push(Finding::GuardOverwritten { position: block.start - data_start });
+                }
+            }
+        }
+        if used.len() != self.index.len() + self.unindexed {
+            findings.push(Finding::EntryCountMismatch { index: self.index.len() + self.unindexed, data: used.len() });
+        }
+        IntegrityReport { level, findings }
+    }
+
+    /// Panics if the table violates any of its invariants, checked via [`Table::verify`] with [`CheckLevel::Full`].
+    ///
+    /// This is meant for tests of crates that build on this crate: call it after exercising an abstraction layer to
+    /// make sure the layer left the table consistent.
+    ///
+    /// This method is only available with the `test-utils` feature.
+    #[cfg(feature = "test-utils")]
+    #[track_caller]
+    pub fn assert_invariants(&self) {
+        let report = self.verify(CheckLevel::Full);
+        assert!(report.is_ok(), "Table invariants violated: {:?}", report.findings);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::options().checksums(true).create(file.path()).unwrap();
+        for i in 0u16..10 {
+            tbl.set(&i.to_ne_bytes(), &i.to_be_bytes()).unwrap();
+        }
+        assert!(tbl.verify(CheckLevel::Quick).is_ok());
+        assert!(tbl.verify(CheckLevel::Full).is_ok());
+        tbl.get_mut(&3u16.to_ne_bytes()).unwrap()[0] ^= 1;
+        assert!(tbl.verify(CheckLevel::Quick).is_ok());
+        let (slot, position) = tbl
+            .index
+            .get_entries()
+            .iter()
+            .enumerate()
+            .find(|(_, e)| e.is_used() && tbl.get_data(e.data.position(), 2) == 3u16.to_ne_bytes())
+            .map(|(slot, e)| (slot, e.data.position()))
+            .unwrap();
+        assert_eq!(tbl.verify(CheckLevel::Full).findings, vec![Finding::ChecksumMismatch { slot }]);
+        tbl.get_data_mut(position, 1)[0] ^= 1;
+        assert_eq!(tbl.verify(CheckLevel::Full).findings, vec![Finding::HashMismatch { slot }]);
+        tbl.get_data_mut(position, 1)[0] ^= 1;
+        tbl.unindexed += 1;
+        assert_eq!(tbl.verify(CheckLevel::Quick).findings, vec![Finding::EntryCountMismatch { index: 11, data: 10 }]);
+        assert!(!tbl.is_valid());
+        tbl.unindexed -= 1;
+    }
+
+    #[cfg(feature = "test-utils")]
+    #[test]
+    #[should_panic(expected = "ChecksumMismatch")]
+    fn test_assert_invariants() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::options().checksums(true).create(file.path()).unwrap();
+        for i in 0u16..10 {
+            tbl.set(&i.to_ne_bytes(), &i.to_be_bytes()).unwrap();
+        }
+        tbl.assert_invariants();
+        tbl.get_mut(&3u16.to_ne_bytes()).unwrap()[0] ^= 1;
+        tbl.assert_invariants();
+    }
+}
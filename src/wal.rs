@@ -0,0 +1,186 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    batch::{check_record_size, encode_ops, is_rejected, sibling_path, Op},
+    Error, Table, WriteBatch,
+};
+
+/// The log is checkpointed when it grows larger than this
+const WAL_CHECKPOINT_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Returns the path of the write-ahead log that belongs to the table at the given path
+#[inline]
+pub(crate) fn wal_path(path: &Path) -> PathBuf {
+    sibling_path(path, ".wal")
+}
+
+impl Table {
+    /// Replays the write-ahead log of the table (if any) and opens it for appending if enabled.
+    ///
+    /// Replaying stops at the first incomplete record, as the table has not been touched for this record. Records that
+    /// are rejected when applying them are skipped.
+    pub(crate) fn open_wal(&mut self, path: &Path) -> Result<(), Error> {
+        let wal = wal_path(path);
+        let data = match fs::read(&wal) {
+            Ok(data) => data,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => vec![],
+            Err(err) => return Err(Error::Io(err)),
+        };
+        let mut records = &data[..];
+        while let Some(batch) = WriteBatch::decode(&mut records) {
+            match self.apply_ops(&batch)? {
+                // Records of changes that are rejected have failed when they were logged as well
+                Err(err) if !is_rejected(&err) => return Err(err),
+                _ => (),
+            }
+        }
+        if !data.is_empty() {
+            self.sync()?;
+        }
+        if self.options.wal {
+            let fd = OpenOptions::new().create(true).append(true).open(&wal).map_err(Error::Io)?;
+            fd.set_len(0).map_err(Error::Io)?;
+            self.wal = Some(fd);
+        } else if !data.is_empty() {
+            fs::remove_file(&wal).map_err(Error::Io)?;
+        }
+        Ok(())
+    }
+
+    fn log_record(&self, record: &[u8]) -> Result<(), Error> {
+        if let Some(wal) = &self.wal {
+            let mut wal: &File = wal;
+            wal.write_all(record).map_err(Error::Io)?;
+            wal.sync_data().map_err(Error::Io)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the given key/value pair with the flags of its entry to the write-ahead log (if enabled)
+    ///
+    /// The flags are replayed as they are, so they must include the internal flags of the entry.
+    pub(crate) fn log_set(&self, key: &[u8], value: &[u8], flags: u16) -> Result<(), Error> {
+        if self.wal.is_none() {
+            return Ok(());
+        }
+        check_record_size(key, value)?;
+        self.log_record(&encode_ops(Some((key, Some((value, flags)))).into_iter()))
+    }
+
+    /// Writes the deletion of the given key to the write-ahead log (if enabled)
+    pub(crate) fn log_delete(&self, key: &[u8]) -> Result<(), Error> {
+        if self.wal.is_none() {
+            return Ok(());
+        }
+        self.log_record(&encode_ops(Some((key, None)).into_iter()))
+    }
+
+    /// Writes all changes of the batch as a single record to the write-ahead log (if enabled)
+    pub(crate) fn log_batch(&self, batch: &WriteBatch) -> Result<(), Error> {
+        if self.wal.is_none() {
+            return Ok(());
+        }
+        self.log_record(&batch.encode())
+    }
+
+    /// Writes all key/value pairs as entries without flags in a single record to the write-ahead log (if enabled)
+    pub(crate) fn log_entries<K: AsRef<[u8]>, V: AsRef<[u8]>>(&self, entries: &[(K, V)]) -> Result<(), Error> {
+        if self.wal.is_none() {
+            return Ok(());
+        }
+        for (key, value) in entries {
+            check_record_size(key.as_ref(), value.as_ref())?;
+        }
+        self.log_record(&encode_ops(entries.iter().map(|(key, value)| (key.as_ref(), Some((value.as_ref(), 0))))))
+    }
+
+    /// Writes the given operations as a single record to the write-ahead log (if enabled)
+    pub(crate) fn log_ops<'a, I: ExactSizeIterator<Item = Op<'a>>>(&self, ops: I) -> Result<(), Error> {
+        if self.wal.is_none() {
+            return Ok(());
+        }
+        self.log_record(&encode_ops(ops))
+    }
+
+    /// Truncates the write-ahead log, all changes must have been flushed before.
+    pub(crate) fn truncate_wal(&self) -> Result<(), Error> {
+        if let Some(wal) = &self.wal {
+            wal.set_len(0).map_err(Error::Io)?;
+        }
+        Ok(())
+    }
+
+    /// Flushes the table when the write-ahead log has grown too large.
+    pub(crate) fn maybe_checkpoint(&self) -> Result<(), Error> {
+        if let Some(wal) = &self.wal {
+            if wal.metadata().map_err(Error::Io)?.len() > WAL_CHECKPOINT_SIZE {
+                self.sync()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs the given function with the write-ahead log disabled
+    pub(crate) fn without_wal<R, F: FnOnce(&mut Self) -> R>(&mut self, f: F) -> R {
+        let wal = self.wal.take();
+        let result = f(self);
+        self.wal = wal;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Entry, FLAG_PINNED};
+
+    #[test]
+    fn test_wal_replay() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::options().wal(true).create(file.path()).unwrap();
+        tbl.set("key1".as_bytes(), "value1".as_bytes()).unwrap();
+        tbl.flush().unwrap();
+        assert_eq!(fs::metadata(wal_path(file.path())).unwrap().len(), 0);
+        tbl.set("key2".as_bytes(), "value2".as_bytes()).unwrap();
+        tbl.delete("key1".as_bytes()).unwrap();
+        let mut batch = WriteBatch::new();
+        batch.set("key3".as_bytes(), "value3".as_bytes());
+        tbl.apply(batch).unwrap();
+        let wal = fs::read(wal_path(file.path())).unwrap();
+        tbl.close();
+        // Simulate a crash where none of the changes reached the table file
+        let mut tbl = Table::create(file.path()).unwrap();
+        tbl.set("key1".as_bytes(), "value1".as_bytes()).unwrap();
+        tbl.close();
+        fs::write(wal_path(file.path()), &wal[..wal.len() - 1]).unwrap();
+        let tbl = Table::options().wal(true).open(file.path()).unwrap();
+        assert!(tbl.get("key1".as_bytes()).is_none());
+        assert_eq!(tbl.get("key2".as_bytes()), Some("value2".as_bytes()));
+        // The last record is incomplete
+        assert!(tbl.get("key3".as_bytes()).is_none());
+        assert_eq!(fs::metadata(wal_path(file.path())).unwrap().len(), 0);
+    }
+
+    #[test]
+    fn test_wal_replay_flags() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::options().wal(true).create(file.path()).unwrap();
+        tbl.namespace("ns").unwrap().set("key".as_bytes(), "value1".as_bytes()).unwrap();
+        tbl.set_entry(Entry { key: "key".as_bytes(), value: "value2".as_bytes(), flags: FLAG_PINNED | 3 }).unwrap();
+        let wal = fs::read(wal_path(file.path())).unwrap();
+        tbl.close();
+        // Simulate a crash where none of the changes reached the table file
+        Table::create(file.path()).unwrap().close();
+        fs::write(wal_path(file.path()), &wal).unwrap();
+        let mut tbl = Table::options().wal(true).open(file.path()).unwrap();
+        assert_eq!(tbl.namespaces(), ["ns"]);
+        assert_eq!(tbl.namespace("ns").unwrap().get("key".as_bytes()), Some("value1".as_bytes()));
+        assert_eq!(tbl.get_entry("key".as_bytes()).unwrap().flags, FLAG_PINNED | 3);
+        assert_eq!(tbl.len(), 1);
+        assert!(tbl.is_valid());
+    }
+}
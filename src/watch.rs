@@ -0,0 +1,121 @@
+use std::sync::mpsc::{self, Receiver, Sender};
+
+use crate::Table;
+
+/// A change of an entry delivered to the receivers returned by [`Table::watch`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChangeEvent {
+    /// An entry has been inserted or overwritten with the given value
+    Set {
+        /// Key of the entry
+        key: Vec<u8>,
+        /// New value of the entry
+        value: Vec<u8>,
+    },
+    /// An entry has been deleted
+    Delete {
+        /// Key of the entry
+        key: Vec<u8>,
+    },
+}
+
+impl ChangeEvent {
+    /// Returns the key of the changed entry
+    #[inline]
+    pub fn key(&self) -> &[u8] {
+        match self {
+            ChangeEvent::Set { key, .. } | ChangeEvent::Delete { key } => key,
+        }
+    }
+}
+
+/// A subscription to the changes of all keys with a given prefix
+pub(crate) struct Watcher {
+    prefix: Vec<u8>,
+    sender: Sender<ChangeEvent>,
+}
+
+impl Watcher {
+    /// Sends the event created by the closure if the key matches the prefix and returns whether the receiver still
+    /// exists
+    #[inline]
+    pub(crate) fn notify<F: FnOnce() -> ChangeEvent>(&self, key: &[u8], event: F) -> bool {
+        !key.starts_with(&self.prefix) || self.sender.send(event()).is_ok()
+    }
+}
+
+impl Table {
+    /// Returns a receiver of the changes of all entries whose key starts with the given prefix
+    ///
+    /// An empty prefix watches the whole table, a full key watches the entry and all entries whose key extends it.
+    /// Changes are reported by the same methods that call the hooks registered via [`Table::on_set`] and
+    /// [`Table::on_delete`], after the table has been modified. The events are buffered without limit until they are
+    /// received, the subscription ends when the receiver is dropped.
+    ///
+    /// ```
+    /// use rust_persist::{ChangeEvent, Table};
+    ///
+    /// let mut table = Table::create("example_watch.tbl").unwrap();
+    /// let changes = table.watch("user/".as_bytes());
+    /// table.set("user/1".as_bytes(), "alice".as_bytes()).unwrap();
+    /// table.set("group/1".as_bytes(), "admins".as_bytes()).unwrap();
+    /// table.delete("user/1".as_bytes()).unwrap();
+    /// let key = "user/1".as_bytes().to_vec();
+    /// assert_eq!(changes.try_recv(), Ok(ChangeEvent::Set { key: key.clone(), value: "alice".as_bytes().to_vec() }));
+    /// assert_eq!(changes.try_recv(), Ok(ChangeEvent::Delete { key }));
+    /// assert!(changes.try_recv().is_err());
+    /// ```
+    pub fn watch(&mut self, prefix: &[u8]) -> Receiver<ChangeEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.add_watcher(prefix, sender);
+        receiver
+    }
+
+    /// Sends the changes of all entries whose key starts with the given prefix to the given sender
+    #[inline]
+    pub(crate) fn add_watcher(&mut self, prefix: &[u8], sender: Sender<ChangeEvent>) {
+        self.hooks.watchers.push(Watcher { prefix: prefix.to_vec(), sender })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ConcurrentTable;
+    use std::thread;
+
+    #[test]
+    fn test_watch() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::create(file.path()).unwrap();
+        let all = tbl.watch(&[]);
+        let single = tbl.watch(b"a");
+        tbl.set(b"a", b"1").unwrap();
+        tbl.set(b"b", b"2").unwrap();
+        tbl.update(b"a", |_| Some(b"3".to_vec())).unwrap();
+        tbl.clear().unwrap();
+        let set = |key: &[u8], value: &[u8]| ChangeEvent::Set { key: key.to_vec(), value: value.to_vec() };
+        let delete = |key: &[u8]| ChangeEvent::Delete { key: key.to_vec() };
+        assert_eq!(single.try_iter().collect::<Vec<_>>(), vec![set(b"a", b"1"), set(b"a", b"3"), delete(b"a")]);
+        let mut events: Vec<_> = all.try_iter().collect();
+        events[3..].sort_by(|a, b| a.key().cmp(b.key()));
+        assert_eq!(events, vec![set(b"a", b"1"), set(b"b", b"2"), set(b"a", b"3"), delete(b"a"), delete(b"b")]);
+        drop(single);
+        tbl.set(b"a", b"4").unwrap();
+        assert_eq!(tbl.hooks.watchers.len(), 1);
+        assert_eq!(all.try_recv(), Ok(set(b"a", b"4")));
+    }
+
+    #[test]
+    fn test_watch_concurrent() {
+        let dir = tempfile::tempdir().unwrap();
+        let tbl = ConcurrentTable::create(dir.path(), 4).unwrap();
+        let changes = tbl.watch(b"job/").unwrap();
+        let worker = thread::spawn(move || changes.iter().take(100).filter(|e| e.key().ends_with(b"0")).count());
+        for i in 0..100 {
+            tbl.set(format!("job/{}", i).as_bytes(), b"queued").unwrap();
+            tbl.set(format!("other/{}", i).as_bytes(), b"ignored").unwrap();
+        }
+        assert_eq!(worker.join().unwrap(), 10);
+    }
+}
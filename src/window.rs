@@ -0,0 +1,248 @@
+use std::{
+    cell::RefCell,
+    fs::{File, OpenOptions},
+    io::{Read, Seek, SeekFrom},
+    mem,
+    path::Path,
+    rc::Rc,
+};
+
+use memmap::{Mmap, MmapOptions};
+
+use crate::{
+    checksum::CHECKSUM_SIZE,
+    index::{is_namespaced, Index, IndexEntryData},
+    mmap::{self, MMap, OpenFdResult},
+    table::{hash_key, total_size, Header},
+    Error, TableOptions, FLAG_CHECKSUM,
+};
+
+/// A table that has been opened read-only with a bounded memory map, see [`TableOptions::open_windowed`]
+///
+/// Only the header and the index are mapped completely. The data section is accessed through a window of limited
+/// size that is moved to the data blocks as they are read, so tables larger than
+/// [`TableOptions::max_map_size`] can be read. Blocks larger than the window are read from the file directly.
+///
+/// As the window moves, keys and values can not be borrowed from the table and are returned as owned copies.
+pub struct WindowedTable {
+    fd: File,
+    index: Index,
+    _index_map: MMap,
+    data_start: u64,
+    data_end: u64,
+    window_size: usize,
+    window: RefCell<Option<Rc<(u64, Mmap)>>>,
+}
+
+impl WindowedTable {
+    pub(crate) fn open(path: &Path, options: TableOptions) -> Result<Self, Error> {
+        options.validate()?;
+        let limit = match options.max_map_size {
+            Some(limit) => limit,
+            None => return Err(Error::InvalidOptions("windowed access requires a maximum map size")),
+        };
+        let fd = OpenOptions::new().read(true).open(path).map_err(Error::Io)?;
+        mmap::lock_fd(&fd, true, options.lock)?;
+        let file_size = fd.metadata().map_err(Error::Io)?.len();
+        if file_size < mem::size_of::<Header>() as u64 {
+            return Err(Error::WrongHeader);
+        }
+        let index_capacity = {
+            let mut header_map = map_private(&fd, 0, mem::size_of::<Header>())?;
+            let (header, ..) = unsafe { mmap::mmap_as_ref(&mut header_map, 0)? };
+            mmap::check_header(header, file_size)?
+        };
+        let data_start = total_size(index_capacity as usize, 0)?;
+        // The window needs at least two pages, as the mapping is aligned to a page boundary before the window
+        let page_size = mmap::page_size() as u64;
+        mmap::check_map_size(&options, data_start + 2 * page_size)?;
+        let mut index_map = map_private(&fd, 0, data_start as usize)?;
+        let (header, index_entries, _, data) =
+            unsafe { mmap::mmap_as_ref(&mut index_map, index_capacity as usize)? };
+        mmap::check_header(header, file_size)?;
+        if header.has_hash_keys() {
+            return Err(Error::InvalidOptions("operation is not supported for hash key tables"));
+        }
+        let mut opened =
+            OpenFdResult { fd: None, mmap: index_map, header, index_entries, data_start: data_start as usize, data };
+        // The index is mapped copy-on-write, so converting and reordering it does not modify the file
+        opened.fix_endianness();
+        let count = opened.index_entries.iter().filter(|entry| entry.is_used()).count();
+        let mut index = Index::new(opened.index_entries, count);
+        if opened.header.is_dirty() {
+            index.reinsert_all();
+        }
+        Ok(Self {
+            fd,
+            index,
+            _index_map: opened.mmap,
+            data_start,
+            data_end: file_size,
+            window_size: (limit - data_start - page_size) as usize,
+            window: RefCell::new(None),
+        })
+    }
+
+    /// Returns the number of key/value pairs stored in the table, like [`Table::len`](crate::Table::len).
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.index.len() - self.index.namespaced()
+    }
+
+    /// Returns whether the table is empty
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of bytes of the data section that are mapped at a time
+    #[inline]
+    pub fn window_size(&self) -> usize {
+        self.window_size
+    }
+
+    /// Returns whether an entry is associated with the given key.
+    pub fn contains(&self, key: &[u8]) -> Result<bool, Error> {
+        Ok(self.find(key)?.is_some())
+    }
+
+    /// Retrieves and returns a copy of the value associated with the given key.
+    /// If no entry with the given key is stored in the table, `None` is returned.
+    pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        match self.find(key)? {
+            Some(entry) => self.with_block(&entry, |_, value| value.to_vec()).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Calls the given function for every entry of the table.
+    ///
+    /// The entries are visited in the order of their positions in the file, so the window moves through the data
+    /// section only once.
+    pub fn for_each<F: FnMut(&[u8], &[u8])>(&self, mut f: F) -> Result<(), Error> {
+        let mut entries: Vec<_> = self
+            .index
+            .get_entries()
+            .iter()
+            .filter(|entry| entry.is_used() && !is_namespaced(&entry.data))
+            .map(|entry| entry.data)
+            .collect();
+        entries.sort_unstable_by_key(|entry| entry.position());
+        for entry in entries {
+            self.with_block(&entry, &mut f)?;
+        }
+        Ok(())
+    }
+
+    /// Explicitly closes the table.
+    #[inline]
+    pub fn close(self) {
+        // nothing to do, just drop self
+    }
+
+    fn find(&self, key: &[u8]) -> Result<Option<IndexEntryData>, Error> {
+        let mut error = None;
+        let entry = self.index.index_get(hash_key(key), |entry| {
+            if entry.key_size as usize != key.len() || error.is_some() {
+                return false;
+            }
+            match self.with_block(entry, |entry_key, _| entry_key == key) {
+                Ok(matches) => matches,
+                Err(err) => {
+                    error = Some(err);
+                    false
+                }
+            }
+        });
+        match error {
+            Some(err) => Err(err),
+            None => Ok(entry.filter(|entry| !is_namespaced(entry))),
+        }
+    }
+
+    /// Calls the given function with the key and the value of the given entry
+    fn with_block<R, F: FnOnce(&[u8], &[u8]) -> R>(&self, entry: &IndexEntryData, f: F) -> Result<R, Error> {
+        let checksum_size = if entry.flags & FLAG_CHECKSUM > 0 { CHECKSUM_SIZE as u64 } else { 0 };
+        let (pos, size) = (entry.position(), entry.size());
+        if pos < self.data_start
+            || entry.key_size as u64 + checksum_size > size
+            || !matches!(pos.checked_add(size), Some(end) if end <= self.data_end)
+        {
+            return Err(Error::Corrupt("entry outside of the data section"));
+        }
+        let split = |data: &[u8]| {
+            let data = &data[..data.len() - checksum_size as usize];
+            let (key, value) = data.split_at(entry.key_size as usize);
+            f(key, value)
+        };
+        if size > self.window_size as u64 {
+            let mut data = vec![0; size as usize];
+            let mut fd = &self.fd;
+            fd.seek(SeekFrom::Start(pos)).map_err(Error::Io)?;
+            fd.read_exact(&mut data).map_err(Error::Io)?;
+            return Ok(split(&data));
+        }
+        let window = {
+            let mut window = self.window.borrow_mut();
+            match &*window {
+                Some(current) if current.0 <= pos && pos + size <= current.0 + current.1.len() as u64 => current.clone(),
+                _ => {
+                    *window = None;
+                    let len = (self.data_end - pos).min(self.window_size as u64) as usize;
+                    let map = unsafe { MmapOptions::new().offset(pos).len(len).map(&self.fd) }.map_err(Error::Io)?;
+                    window.insert(Rc::new((pos, map))).clone()
+                }
+            }
+        };
+        // The window is not borrowed while calling the function, so the function can read from the table as well
+        let (start, map) = &*window;
+        let offset = (pos - start) as usize;
+        Ok(split(&map[offset..offset + size as usize]))
+    }
+}
+
+/// Maps the given region of the file copy-on-write
+fn map_private(fd: &File, offset: u64, len: usize) -> Result<MMap, Error> {
+    unsafe { MmapOptions::new().offset(offset).len(len).map_copy(fd) }.map_err(Error::Io)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Table;
+
+    #[test]
+    fn test_windowed() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut tbl = Table::create(file.path()).unwrap();
+        for i in 0u32..2000 {
+            tbl.set(&i.to_le_bytes(), &[i as u8; 1000]).unwrap();
+        }
+        tbl.set("big".as_bytes(), &[7; 200_000]).unwrap();
+        let data_start = tbl.data_start;
+        tbl.close();
+        let options = Table::options().max_map_size(Some(data_start + 64 * 1024));
+        assert!(matches!(options.clone().open(file.path()), Err(Error::MapLimit { .. })));
+        let tbl = options.open_windowed(file.path()).unwrap();
+        assert_eq!(tbl.len(), 2001);
+        assert!(tbl.window_size() < 64 * 1024);
+        for i in (0u32..2000).rev() {
+            assert_eq!(tbl.get(&i.to_le_bytes()).unwrap(), Some(vec![i as u8; 1000]));
+        }
+        assert_eq!(tbl.get("big".as_bytes()).unwrap(), Some(vec![7; 200_000]));
+        assert!(!tbl.contains("missing".as_bytes()).unwrap());
+        let mut count = 0;
+        tbl.for_each(|key, value| {
+            if key != "big".as_bytes() {
+                assert_eq!(value, &[key[0]; 1000][..]);
+            }
+            count += 1;
+        })
+        .unwrap();
+        assert_eq!(count, 2001);
+        // Other readers can use the table at the same time, but it cannot be opened for writing
+        assert!(Table::open_read_only(file.path()).is_ok());
+        assert!(matches!(Table::open(file.path()), Err(Error::TableLocked)));
+        assert!(matches!(Table::options().open_windowed(file.path()), Err(Error::InvalidOptions(_))));
+    }
+}